@@ -0,0 +1,150 @@
+use crate::types::config::dataset::{Dataset, License};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// License and attribution of a dataset. Every result that is based on the dataset has to carry
+/// this, so that users can see where the data comes from and under which terms it may be used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatasetAttribution {
+    pub dataset_id: String,
+    /// SPDX identifier of the license, missing for unknown and custom licenses
+    pub license: Option<String>,
+    pub license_url: Option<Url>,
+    /// Text that has to be shown wherever the data is used. Missing if the license doesn't
+    /// require attribution.
+    pub attribution: Option<String>,
+    /// The publisher of the feed (`feed_info.txt` in GTFS)
+    pub publisher: Option<Organization>,
+    /// Organizations that were involved in creating the data (`attributions.txt` in GTFS)
+    #[serde(default)]
+    pub organizations: Vec<Organization>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Organization {
+    pub name: String,
+    pub url: Option<String>,
+    /// E.g. `producer`, `operator` or `authority`
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl DatasetAttribution {
+    /// Builds the attribution of a dataset from its config and the organizations named in the
+    /// feed. The attribution text from the config takes precedence.
+    pub fn new(dataset: &Dataset, publisher: Option<Organization>, organizations: Vec<Organization>) -> Self {
+        let license = dataset.license.as_ref();
+        let attribution = dataset.attribution.clone().or_else(|| {
+            let requires_attribution = license.is_none_or(License::requires_attribution);
+            requires_attribution.then(|| Self::attribution_text(&dataset.id, license, publisher.as_ref(), &organizations))
+        });
+
+        Self {
+            dataset_id: dataset.id.clone(),
+            license: license.and_then(License::spdx_id).map(str::to_string),
+            license_url: license.and_then(License::url),
+            attribution,
+            publisher,
+            organizations,
+        }
+    }
+
+    /// For datasets whose attribution is not known, e.g. if it was not stored with the data
+    pub fn unknown(dataset_id: &str) -> Self {
+        Self {
+            dataset_id: dataset_id.to_string(),
+            license: None,
+            license_url: None,
+            attribution: None,
+            publisher: None,
+            organizations: vec![],
+        }
+    }
+
+    /// Names the provider of the data, followed by the license and a link to it. This is what
+    /// DL-DE-BY-2.0 and the CC BY licenses demand. Without any named organization, the dataset ID
+    /// stands in for the provider.
+    fn attribution_text(
+        dataset_id: &str,
+        license: Option<&License>,
+        publisher: Option<&Organization>,
+        organizations: &[Organization],
+    ) -> String {
+        let mut providers: Vec<&Organization> = vec![];
+        for organization in publisher.into_iter().chain(organizations) {
+            if !providers.iter().any(|provider| provider.name == organization.name) {
+                providers.push(organization);
+            }
+        }
+
+        let mut text = match providers.is_empty() {
+            true => dataset_id.to_string(),
+            false => providers.iter()
+                .map(|provider| match &provider.url {
+                    Some(url) => format!("{} ({url})", provider.name),
+                    None => provider.name.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+
+        if let Some(license) = license {
+            text.push_str(", ");
+            text.push_str(license.name());
+            if let Some(url) = license.url() {
+                text.push_str(&format!(" ({url})"));
+            }
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::config::dataset::{DataSource, DatasetFormat};
+
+    fn dataset(license: Option<License>, attribution: Option<&str>) -> Dataset {
+        Dataset {
+            id: "vbn".into(),
+            src: DataSource::File { path: "vbn.zip".into() },
+            format: DatasetFormat::Gtfs,
+            license,
+            attribution: attribution.map(str::to_string),
+            group_ids: vec![],
+            clip: None,
+        }
+    }
+
+    fn organization(name: &str, url: Option<&str>) -> Organization {
+        Organization { name: name.into(), url: url.map(str::to_string), roles: vec![] }
+    }
+
+    #[test]
+    fn test_dl_de_by() {
+        let publisher = organization("VBN", Some("https://www.vbn.de"));
+        let organizations = vec![organization("VBN", None), organization("BSAG", None)];
+        let attribution = DatasetAttribution::new(&dataset(Some(License::DlDeBy2_0), None), Some(publisher), organizations);
+
+        assert_eq!(attribution.license.as_deref(), Some("DL-DE-BY-2.0"));
+        assert_eq!(
+            attribution.attribution.as_deref(),
+            Some("VBN (https://www.vbn.de), BSAG, Datenlizenz Deutschland – Namensnennung – Version 2.0 (https://www.govdata.de/dl-de/by-2-0)")
+        );
+    }
+
+    #[test]
+    fn test_attribution_text() {
+        // Public domain data doesn't need to be attributed
+        let attribution = DatasetAttribution::new(&dataset(Some(License::Cc0_1_0), None), None, vec![]);
+        assert_eq!(attribution.attribution, None);
+
+        // Without a license, the terms are unknown
+        let attribution = DatasetAttribution::new(&dataset(None, None), None, vec![]);
+        assert_eq!(attribution.attribution.as_deref(), Some("vbn"));
+
+        let attribution = DatasetAttribution::new(&dataset(Some(License::CcBy4_0), Some("© VBN")), None, vec![]);
+        assert_eq!(attribution.attribution.as_deref(), Some("© VBN"));
+    }
+}
@@ -1,19 +1,69 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use crate::types::config::dataset::{Dataset, DatasetGroup};
 use crate::types::config::features::FeatureConfig;
+use crate::types::config::servers::ServersConfig;
 
 pub mod dataset;
 pub mod features;
+pub mod servers;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Where the data is written to, unless `data_dir` is set in the config
+pub const DEFAULT_DATA_DIR: &str = "./data";
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(tag = "version")]
 pub enum Config {
     #[serde(rename = "1")]
-    Version1 {
-        datasets: Vec<Dataset>,
-        #[serde(default)]
-        dataset_groups: Vec<DatasetGroup>,
-        #[serde(default)]
-        features: FeatureConfig,
+    Version1(ConfigV1),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ConfigV1 {
+    pub datasets: Vec<Dataset>,
+    #[serde(default)]
+    pub dataset_groups: Vec<DatasetGroup>,
+    #[serde(default)]
+    pub features: FeatureConfig,
+    #[serde(default)]
+    pub servers: ServersConfig,
+    /// Directory for the downloaded datasets, their validation reports and the artifacts of the
+    /// preprocessing (in `tmp`)
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+}
+
+fn default_data_dir() -> PathBuf {
+    DEFAULT_DATA_DIR.into()
+}
+
+/// Where the preprocessing writes its artifacts to, within the data dir
+pub fn work_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("tmp")
+}
+
+impl Config {
+    pub fn servers(&self) -> &ServersConfig {
+        match self {
+            Config::Version1(ConfigV1 { servers, .. }) => servers,
+        }
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        match self {
+            Config::Version1(ConfigV1 { data_dir, .. }) => data_dir,
+        }
+    }
+
+    pub fn servers_mut(&mut self) -> &mut ServersConfig {
+        match self {
+            Config::Version1(ConfigV1 { servers, .. }) => servers,
+        }
+    }
+
+    /// JSON Schema of the config file, e.g. for completion and validation in editors
+    pub fn json_schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(Config)
     }
-}
\ No newline at end of file
+}
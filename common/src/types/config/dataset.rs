@@ -1,15 +1,20 @@
 use crate::util::distance::{Distance, Radius};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use chrono::{Days, NaiveDate};
+use geo::{Coord, LineString, Polygon, Rect};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
 use url::Url;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct DatasetGroup {
     pub id: String,
     pub consistency: DatasetConsistency
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct DatasetConsistency {
     #[serde(default)]
     pub stop_ids: IdConsistency,
@@ -19,7 +24,7 @@ pub struct DatasetConsistency {
     pub trip_ids: IdConsistency,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(untagged)]
 pub enum IdConsistency {
     Fully(bool),
@@ -32,7 +37,7 @@ impl Default for IdConsistency {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(
     untagged,
     expecting = "Invalid or missing consistency definition. Specify either a hard cutoff radius with `radius: 42m` or an attenuation with `equality_radius:` and `inequality_radius:`"
@@ -54,18 +59,95 @@ impl Default for GeoPointConsistency {
 }
 
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct Dataset {
     pub id: String,
     pub src: DataSource,
     pub format: DatasetFormat,
     pub license: Option<License>,
+    /// Attribution to show for this dataset instead of the one that is built from the license and
+    /// the publisher of the feed, e.g. if the publisher asks for a specific wording
+    pub attribution: Option<String>,
     #[serde(default, rename = "groups")]
     pub group_ids: Vec<String>,
-    // TODO: Fetch interval et al
+    /// Only keep the part of the dataset that is needed, e.g. of a national feed for a region
+    #[serde(default)]
+    pub clip: Option<ClipConfig>,
+}
+
+/// Reduces a dataset to an area and a date window while preprocessing
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ClipConfig {
+    /// Stops outside the area are removed. Trips that leave the area are cut at its boundary, so
+    /// only their stops within the area are kept.
+    pub area: Option<Area>,
+    /// Services are shortened to the dates within the window
+    pub dates: Option<DateWindow>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(
+    untagged,
+    expecting = "Invalid area. Specify either `bbox: [min_lon, min_lat, max_lon, max_lat]` or `polygon:` with a list of `[lon, lat]` points"
+)]
+pub enum Area {
+    BoundingBox {
+        /// `[min_lon, min_lat, max_lon, max_lat]`, like in GeoJSON
+        bbox: [f64; 4],
+    },
+    Polygon {
+        /// `[lon, lat]` points of the exterior ring. The ring is closed automatically.
+        polygon: Vec<[f64; 2]>,
+    },
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+impl Area {
+    pub fn to_polygon(&self) -> Polygon<f64> {
+        match self {
+            Area::BoundingBox { bbox: [min_lon, min_lat, max_lon, max_lat] } => {
+                Rect::new(Coord { x: *min_lon, y: *min_lat }, Coord { x: *max_lon, y: *max_lat }).to_polygon()
+            }
+            Area::Polygon { polygon } => Polygon::new(
+                LineString::from(polygon.iter().map(|[lon, lat]| Coord { x: *lon, y: *lat }).collect::<Vec<_>>()),
+                vec![],
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(
+    untagged,
+    expecting = "Invalid date window. Specify either a duration from the day of preprocessing with `next: 14days` or dates with `from:` and `to:`"
+)]
+pub enum DateWindow {
+    Relative {
+        /// E.g. `14days`, starting on the day of preprocessing
+        #[serde(with = "humantime_serde")]
+        #[schemars(with = "String")]
+        next: Duration,
+    },
+    Absolute {
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+}
+
+impl DateWindow {
+    /// First and last day of the window (both inclusive), `None` if the window is open on that side
+    pub fn resolve(&self, today: NaiveDate) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        match self {
+            DateWindow::Relative { next } => {
+                // `next: 14days` covers today and the 13 days after it
+                let days = next.as_secs().div_ceil(24 * 60 * 60);
+                (Some(today), (today + Days::new(days)).pred_opt())
+            }
+            DateWindow::Absolute { from, to } => (*from, *to),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub enum DatasetFormat {
     #[serde(rename = "gtfs")]
     Gtfs,
@@ -73,7 +155,7 @@ pub enum DatasetFormat {
     GtfsRt,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(
     untagged,
     expecting = "Invalid or missing data source. Specify either a remote source with `url:` and `headers:` or a local path with `file:` under `src:` of this dataset")
@@ -81,19 +163,56 @@ pub enum DatasetFormat {
 pub enum DataSource {
     URL {
         url: Url,
-        #[serde(default)]
+        /// Often hold secrets such as API keys, so their values are never serialized or printed
+        #[serde(default, serialize_with = "serialize_redacted")]
         headers: HashMap<String, String>,
+        /// Minimum time between two downloads, e.g. `12h` or `7days`. Until it has passed, the
+        /// last import is used. Without an interval, the source is checked on every fetch.
+        #[serde(default, with = "humantime_serde")]
+        #[schemars(with = "Option<String>")]
+        fetch_interval: Option<Duration>,
+        /// Number of imports that are kept on disk
+        #[serde(default = "default_retention")]
+        retention: usize,
     },
     File {
         path: String
     }
 }
 
+fn default_retention() -> usize {
+    3
+}
+
+const REDACTED: &str = "<redacted>";
+
+fn redacted(headers: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+    headers.keys().map(|name| (name.as_str(), REDACTED)).collect()
+}
+
+fn serialize_redacted<S: Serializer>(headers: &HashMap<String, String>, serializer: S) -> Result<S::Ok, S::Error> {
+    redacted(headers).serialize(serializer)
+}
+
+impl Debug for DataSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSource::URL { url, headers, fetch_interval, retention } => f.debug_struct("URL")
+                .field("url", url)
+                .field("headers", &redacted(headers))
+                .field("fetch_interval", fetch_interval)
+                .field("retention", retention)
+                .finish(),
+            DataSource::File { path } => f.debug_struct("File").field("path", path).finish(),
+        }
+    }
+}
+
 // Identifiers: https://spdx.org/licenses/
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub enum License {
     Custom { src: DataSource },
-    #[serde(rename = " CC0-1.0")]
+    #[serde(rename = "CC0-1.0")]
     Cc0_1_0,
     #[serde(rename = "CC-BY-4.0")]
     CcBy4_0,
@@ -111,4 +230,96 @@ pub enum License {
     DlDeBy2_0,
     #[serde(rename = "DL-DE-ZERO-2.0")]
     DlDeZero2_0,
-}
\ No newline at end of file
+}
+
+impl License {
+    /// The SPDX identifier, `None` for custom licenses
+    pub fn spdx_id(&self) -> Option<&'static str> {
+        match self {
+            License::Custom { .. } => None,
+            License::Cc0_1_0 => Some("CC0-1.0"),
+            License::CcBy4_0 => Some("CC-BY-4.0"),
+            License::CcByNc4_0 => Some("CC-BY-NC-4.0"),
+            License::CcByNd4_0 => Some("CC-BY-ND-4.0"),
+            License::CcBySa4_0 => Some("CC-BY-SA-4.0"),
+            License::CcByNcNd4_0 => Some("CC-BY-NC-ND-4.0"),
+            License::CcByNcSa4_0 => Some("CC-BY-NC-SA-4.0"),
+            License::DlDeBy2_0 => Some("DL-DE-BY-2.0"),
+            License::DlDeZero2_0 => Some("DL-DE-ZERO-2.0"),
+        }
+    }
+
+    /// The name of the license as it should be given in attributions
+    pub fn name(&self) -> &'static str {
+        match self {
+            License::Custom { .. } => "Custom license",
+            License::Cc0_1_0 => "CC0 1.0",
+            License::CcBy4_0 => "CC BY 4.0",
+            License::CcByNc4_0 => "CC BY-NC 4.0",
+            License::CcByNd4_0 => "CC BY-ND 4.0",
+            License::CcBySa4_0 => "CC BY-SA 4.0",
+            License::CcByNcNd4_0 => "CC BY-NC-ND 4.0",
+            License::CcByNcSa4_0 => "CC BY-NC-SA 4.0",
+            License::DlDeBy2_0 => "Datenlizenz Deutschland – Namensnennung – Version 2.0",
+            License::DlDeZero2_0 => "Datenlizenz Deutschland – Zero – Version 2.0",
+        }
+    }
+
+    /// Where the license text can be found. Custom licenses only have one if they are read from a
+    /// URL.
+    pub fn url(&self) -> Option<Url> {
+        let url = match self {
+            License::Custom { src: DataSource::URL { url, .. } } => return Some(url.clone()),
+            License::Custom { src: DataSource::File { .. } } => return None,
+            License::Cc0_1_0 => "https://creativecommons.org/publicdomain/zero/1.0/",
+            License::CcBy4_0 => "https://creativecommons.org/licenses/by/4.0/",
+            License::CcByNc4_0 => "https://creativecommons.org/licenses/by-nc/4.0/",
+            License::CcByNd4_0 => "https://creativecommons.org/licenses/by-nd/4.0/",
+            License::CcBySa4_0 => "https://creativecommons.org/licenses/by-sa/4.0/",
+            License::CcByNcNd4_0 => "https://creativecommons.org/licenses/by-nc-nd/4.0/",
+            License::CcByNcSa4_0 => "https://creativecommons.org/licenses/by-nc-sa/4.0/",
+            License::DlDeBy2_0 => "https://www.govdata.de/dl-de/by-2-0",
+            License::DlDeZero2_0 => "https://www.govdata.de/dl-de/zero-2-0",
+        };
+        Some(Url::parse(url).expect("license URLs are valid"))
+    }
+
+    /// Whether users of the data have to be told where it comes from. The terms of custom licenses
+    /// are unknown, so they are assumed to require attribution.
+    pub fn requires_attribution(&self) -> bool {
+        !matches!(self, License::Cc0_1_0 | License::DlDeZero2_0)
+    }
+
+    /// Whether the data must not be used commercially
+    pub fn is_non_commercial(&self) -> bool {
+        matches!(self, License::CcByNc4_0 | License::CcByNcNd4_0 | License::CcByNcSa4_0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_are_redacted() {
+        let source = DataSource::URL {
+            url: Url::parse("https://example.com/gtfs.zip").unwrap(),
+            headers: HashMap::from([("Authorization".to_string(), "secret".to_string())]),
+            fetch_interval: None,
+            retention: 3,
+        };
+
+        let json = serde_json::to_string(&source).unwrap();
+        assert!(json.contains("\"Authorization\":\"<redacted>\""));
+        assert!(!json.contains("secret"));
+        assert!(!format!("{source:?}").contains("secret"));
+    }
+
+    #[test]
+    fn test_relative_date_window() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let window = DateWindow::Relative { next: Duration::from_secs(14 * 24 * 60 * 60) };
+
+        assert_eq!(window.resolve(today), (Some(today), NaiveDate::from_ymd_opt(2025, 1, 14)));
+    }
+}
@@ -1,20 +1,88 @@
 use either::Either;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, JsonSchema)]
 pub struct FeatureConfig {
-    pub preprocessing: PreprocessingConfig
+    #[serde(default)]
+    pub preprocessing: PreprocessingConfig,
+    #[serde(default)]
+    pub reload: ReloadConfig,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+/// Reloading of the timetable data while the server is running
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ReloadConfig {
+    /// Off by default, so that the server keeps serving the data it was started with
+    #[serde(default = "default_reload_enabled")]
+    pub enabled: bool,
+    /// How often to check the datasets for changes. URL datasets are only downloaded again if
+    /// their `fetch_interval` has passed.
+    #[serde(default = "default_check_interval", with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub check_interval: Duration,
+}
+
+fn default_reload_enabled() -> bool {
+    false
+}
+
+fn default_check_interval() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_reload_enabled(),
+            check_interval: default_check_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, JsonSchema)]
 pub struct PreprocessingConfig {
+    #[serde(default)]
     validation: ValidationConfigOrBool,
+    #[serde(default)]
+    repair: RepairConfigOrBool,
+    /// How stops are clustered for Scalable Transfer Patterns
+    #[serde(default)]
+    pub clustering: ClusteringConfig,
+    /// How the transfer patterns within the clusters are calculated
+    #[serde(default)]
+    pub local_transfer_patterns: LocalTransferPatternsConfig,
+    /// How the long-distance layer between the clusters is searched
+    #[serde(default)]
+    pub long_distance: LongDistanceConfig,
+}
+
+impl PreprocessingConfig {
+    /// The validation rules to check, `None` if validation is disabled
+    pub fn validation(&self) -> Option<ValidationConfig> {
+        match &self.validation.inner {
+            Either::Left(true) => Some(ValidationConfig::default()),
+            Either::Left(false) => None,
+            Either::Right(config) => Some(config.clone()),
+        }
+    }
+
+    /// The repairs to apply, `None` if repairing is disabled
+    pub fn repair(&self) -> Option<RepairConfig> {
+        match &self.repair.inner {
+            Either::Left(true) => Some(RepairConfig::default()),
+            Either::Left(false) => None,
+            Either::Right(config) => Some(config.clone()),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(transparent)]
 struct ValidationConfigOrBool {
     #[serde(with = "either::serde_untagged")]
+    #[schemars(with = "Either<bool, ValidationConfig>")]
     inner: Either<bool, ValidationConfig>
 }
 
@@ -24,7 +92,203 @@ impl Default for ValidationConfigOrBool {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Toggles for the validation rules. All rules are checked by default.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(default)]
 pub struct ValidationConfig {
-    
-}
\ No newline at end of file
+    /// IDs that refer to rows of other files must exist, e.g. the trip of a stop time
+    pub referential_integrity: bool,
+    /// Times along a trip must not decrease
+    pub time_monotonicity: bool,
+    /// Coordinates of stops must be valid and not (0, 0)
+    pub coordinate_sanity: bool,
+    /// IDs must be unique within their file
+    pub duplicate_ids: bool,
+    /// Services must run on at least one day that has not passed, and trips must have a service
+    pub calendar_coverage: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            referential_integrity: true,
+            time_monotonicity: true,
+            coordinate_sanity: true,
+            duplicate_ids: true,
+            calendar_coverage: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(transparent)]
+struct RepairConfigOrBool {
+    #[serde(with = "either::serde_untagged")]
+    #[schemars(with = "Either<bool, RepairConfig>")]
+    inner: Either<bool, RepairConfig>
+}
+
+impl Default for RepairConfigOrBool {
+    fn default() -> Self {
+        Self { inner: Either::Left(false) }
+    }
+}
+
+/// Toggles for the repairs of datasets. If repairing is enabled, all repairs are applied by
+/// default.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(default)]
+pub struct RepairConfig {
+    /// Remove stop times whose trip or stop does not exist
+    pub drop_orphan_stop_times: bool,
+    /// Merge stops with the same ID, as well as stops with the same name, coordinates and properties
+    pub merge_duplicate_stops: bool,
+    /// Interpolate missing times of intermediate stops linearly between the surrounding stops
+    pub interpolate_missing_times: bool,
+    /// Raise times that are earlier than the time at a previous stop of the trip
+    pub fix_non_increasing_times: bool,
+    /// Remove trips with fewer than two stops
+    pub remove_short_trips: bool,
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        Self {
+            drop_orphan_stop_times: true,
+            merge_duplicate_stops: true,
+            interpolate_missing_times: true,
+            fix_non_increasing_times: true,
+            remove_short_trips: true,
+        }
+    }
+}
+
+/// Clusters are processed in parallel, as long as their estimated memory usage fits into the
+/// budget. Results are saved per cluster, so that an interrupted preprocessing can resume.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(default)]
+pub struct LocalTransferPatternsConfig {
+    /// Memory that the clusters processed at the same time may use together, in MiB. A cluster
+    /// that needs more than this is processed alone.
+    pub memory_budget_mib: u64,
+    /// Maximum number of clusters that are processed at the same time. Defaults to the number of
+    /// cores.
+    pub max_parallel_clusters: Option<usize>,
+    /// Reuse the results of clusters that were finished by an earlier, interrupted run with the
+    /// same input and clustering
+    pub resume: bool,
+}
+
+impl Default for LocalTransferPatternsConfig {
+    fn default() -> Self {
+        Self {
+            memory_budget_mib: 4 * 1024,
+            max_parallel_clusters: None,
+            resume: true,
+        }
+    }
+}
+
+/// Arc flags speed up searches in the long-distance layer, at the cost of one shortest path tree
+/// per boundary station of each cluster during preprocessing. Disabled by default.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(default)]
+pub struct LongDistanceConfig {
+    /// Compute arc flags over the clusters for the long-distance layer
+    pub arc_flags: bool,
+    /// Number of queries that are run with and without arc flags to compare them. 0 skips the
+    /// benchmark.
+    pub benchmark_queries: usize,
+}
+
+impl Default for LongDistanceConfig {
+    fn default() -> Self {
+        Self {
+            arc_flags: false,
+            benchmark_queries: 1_000,
+        }
+    }
+}
+
+/// The clustering strategy and its parameters. The quality of the clusters determines how fast
+/// queries with Scalable Transfer Patterns are, so the best strategy depends on the network.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum ClusteringConfig {
+    /// Clusters stops by location into a fixed number of clusters
+    KMeans {
+        #[serde(default = "default_num_clusters")]
+        num_clusters: u32,
+    },
+    /// Clusters dense areas of stops. Stops outside of those areas are added to the cluster of
+    /// their nearest stop.
+    Dbscan {
+        /// Minimum number of stops within `tolerance` of a stop for it to be in a dense area
+        #[serde(default = "default_dbscan_min_points")]
+        min_points: usize,
+        /// In degrees
+        #[serde(default = "default_dbscan_tolerance")]
+        tolerance: f32,
+    },
+    /// Fits a gaussian mixture model with a number of clusters that depends on the number of stops
+    Gmm {
+        #[serde(default = "default_gmm_stops_per_cluster")]
+        stops_per_cluster: u32,
+    },
+    /// OPTICS with geographic distances
+    OpticsGeo {
+        #[serde(default = "default_optics_min_points")]
+        min_points: usize,
+        /// Search radius in meters. Smaller radii are faster.
+        #[serde(default = "default_optics_tolerance")]
+        tolerance: f32,
+        /// Maximum reachability distance within a cluster, in meters
+        #[serde(default = "default_optics_eps")]
+        eps: f32,
+    },
+    /// Merges clusters that are connected by many trips, as described in section 3.1.2 of
+    /// "Scalable Transfer Patterns"
+    Merging {
+        /// Clusters are not merged if the result would have more stops (or stations) than this
+        #[serde(default = "default_max_cluster_size")]
+        max_cluster_size: u32,
+    },
+}
+
+fn default_num_clusters() -> u32 {
+    8
+}
+
+fn default_dbscan_min_points() -> usize {
+    500
+}
+
+fn default_dbscan_tolerance() -> f32 {
+    0.5
+}
+
+fn default_gmm_stops_per_cluster() -> u32 {
+    850
+}
+
+fn default_optics_min_points() -> usize {
+    8
+}
+
+fn default_optics_tolerance() -> f32 {
+    2_000.0
+}
+
+fn default_optics_eps() -> f32 {
+    300.0
+}
+
+fn default_max_cluster_size() -> u32 {
+    1_500
+}
+
+impl Default for ClusteringConfig {
+    fn default() -> Self {
+        Self::KMeans { num_clusters: default_num_clusters() }
+    }
+}
@@ -0,0 +1,121 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, JsonSchema)]
+pub struct ServersConfig {
+    /// The routing API
+    #[serde(default)]
+    pub api: ApiServerConfig,
+    /// The dashboard for inspecting data and preprocessing
+    #[serde(default)]
+    pub visualization: VisualizationServerConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ApiServerConfig {
+    #[serde(default = "default_api_listen")]
+    pub listen: SocketAddr,
+    /// Serve HTTPS instead of HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Origins that browsers may call the server from, e.g. `https://example.org`. `*` allows all
+    /// origins.
+    #[serde(default = "default_api_cors_origins")]
+    pub cors_origins: Vec<String>,
+    /// The API is used commercially. The server refuses to start with datasets whose license
+    /// forbids this (CC-BY-NC-*).
+    #[serde(default)]
+    pub commercial: bool,
+}
+
+fn default_api_listen() -> SocketAddr {
+    (Ipv4Addr::UNSPECIFIED, 8080).into()
+}
+
+fn default_api_cors_origins() -> Vec<String> {
+    // The dashboard runs queries on the API
+    vec!["http://localhost:3001".into(), "http://localhost:5173".into()]
+}
+
+impl Default for ApiServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: default_api_listen(),
+            tls: None,
+            cors_origins: default_api_cors_origins(),
+            commercial: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct VisualizationServerConfig {
+    #[serde(default = "default_visualization_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_visualization_listen")]
+    pub listen: SocketAddr,
+    /// Serve HTTPS instead of HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Origins that browsers may call the server from, e.g. `https://example.org`. `*` allows all
+    /// origins.
+    #[serde(default = "default_visualization_cors_origins")]
+    pub cors_origins: Vec<String>,
+}
+
+fn default_visualization_enabled() -> bool {
+    true
+}
+
+fn default_visualization_listen() -> SocketAddr {
+    (Ipv4Addr::LOCALHOST, 3001).into()
+}
+
+fn default_visualization_cors_origins() -> Vec<String> {
+    // The development server of the dashboard and an API client for testing
+    vec!["http://localhost:5173".into(), "https://hoppscotch.io".into()]
+}
+
+impl Default for VisualizationServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_visualization_enabled(),
+            listen: default_visualization_listen(),
+            tls: None,
+            cors_origins: default_visualization_cors_origins(),
+        }
+    }
+}
+
+/// Whether `origin` may be used in `cors_origins`: `*` or an origin like `https://example.org`, which
+/// is what browsers send in the `Origin` header (no path, no trailing slash, no default port)
+pub fn is_valid_cors_origin(origin: &str) -> bool {
+    origin == "*" || Url::parse(origin).is_ok_and(|url| url.origin().ascii_serialization() == origin)
+}
+
+/// PEM files for serving HTTPS
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct TlsConfig {
+    /// Certificate chain, starting with the certificate of the server
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cors_origins() {
+        assert!(is_valid_cors_origin("*"));
+        assert!(is_valid_cors_origin("https://example.org"));
+        assert!(is_valid_cors_origin("http://localhost:5173"));
+        assert!(!is_valid_cors_origin("https://example.org/"));
+        assert!(!is_valid_cors_origin("https://example.org/path"));
+        assert!(!is_valid_cors_origin("https://example.org\n"));
+        assert!(!is_valid_cors_origin("example.org"));
+    }
+}
@@ -5,8 +5,11 @@ use std::num::ParseIntError;
 use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 
+pub mod attribution;
 pub mod config;
 pub mod errors;
+pub mod route;
+pub mod station;
 pub mod trip;
 
 pub fn u32_from_any_value(value: AnyValue) -> Result<u32, ()> {
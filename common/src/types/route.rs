@@ -0,0 +1,81 @@
+use std::fmt::{Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+/// A GTFS route type. Feeds may either use the basic route types (0-12) of the GTFS reference or
+/// the extended route types (100-1702) that are based on the Hierarchical Vehicle Type (HVT) codes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct RouteType(pub u32);
+
+impl RouteType {
+    pub const TRAM: RouteType = RouteType(0);
+    pub const SUBWAY: RouteType = RouteType(1);
+    pub const RAIL: RouteType = RouteType(2);
+    pub const BUS: RouteType = RouteType(3);
+    pub const FERRY: RouteType = RouteType(4);
+    pub const CABLE_TRAM: RouteType = RouteType(5);
+    pub const AERIAL_LIFT: RouteType = RouteType(6);
+    pub const FUNICULAR: RouteType = RouteType(7);
+    pub const TROLLEYBUS: RouteType = RouteType(11);
+    pub const MONORAIL: RouteType = RouteType(12);
+
+    /// Maps an extended route type to the basic route type it is a specialization of. Basic route
+    /// types are returned as they are. Returns `None` for extended types that have no basic
+    /// equivalent (like taxis).
+    pub fn basic(&self) -> Option<RouteType> {
+        let basic = match self.0 {
+            0..=12 => *self,
+            100..=199 => Self::RAIL,
+            200..=299 => Self::BUS, // Coach services
+            405 => Self::MONORAIL,
+            400..=499 => Self::SUBWAY, // Urban railway
+            700..=799 => Self::BUS,
+            800..=899 => Self::TROLLEYBUS,
+            900..=999 => Self::TRAM,
+            1000..=1099 | 1200..=1299 => Self::FERRY,
+            1300..=1399 => Self::AERIAL_LIFT,
+            1400..=1499 => Self::FUNICULAR,
+            _ => return None,
+        };
+        Some(basic)
+    }
+
+    /// Whether this route type is `other` or a specialization of it. For example, a long distance
+    /// train (102) matches rail (2), but not the other way round.
+    pub fn matches(&self, other: &RouteType) -> bool {
+        self == other || self.basic().as_ref() == Some(other)
+    }
+}
+
+impl Display for RouteType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for RouteType {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse::<u32>()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        // Long distance trains
+        assert!(RouteType(102).matches(&RouteType::RAIL));
+        assert!(RouteType(102).matches(&RouteType(102)));
+        assert!(!RouteType::RAIL.matches(&RouteType(102)));
+        assert!(!RouteType(102).matches(&RouteType(103)));
+
+        assert!(RouteType(700).matches(&RouteType::BUS));
+        assert!(RouteType::BUS.matches(&RouteType::BUS));
+        assert!(!RouteType(1500).matches(&RouteType::BUS));
+    }
+}
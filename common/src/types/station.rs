@@ -0,0 +1,138 @@
+use crate::types::{u32_from_any_value, StopId};
+use polars::datatypes::AnyValue;
+use std::fmt::{Debug, Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A continuous station id, like [`StopId`]. A station groups the platforms (stops) that
+/// passengers perceive as one place, e.g. all platforms of a central station.
+#[derive(Serialize, Deserialize, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct StationId(pub u32);
+
+impl Display for StationId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "st:{}", self.0)
+    }
+}
+
+impl Debug for StationId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "st:{}", self.0)
+    }
+}
+
+impl<'a> TryFrom<AnyValue<'a>> for StationId {
+    type Error = ();
+
+    fn try_from(value: AnyValue<'a>) -> Result<Self, Self::Error> {
+        u32_from_any_value(value).map(Self)
+    }
+}
+
+impl From<u32> for StationId {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+/// Where a journey can start: either a single stop or all platforms of a station
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Place {
+    Stop(StopId),
+    Station(StationId),
+}
+
+impl From<StopId> for Place {
+    fn from(value: StopId) -> Self {
+        Self::Stop(value)
+    }
+}
+
+impl From<StationId> for Place {
+    fn from(value: StationId) -> Self {
+        Self::Station(value)
+    }
+}
+
+impl Display for Place {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Place::Stop(stop) => write!(f, "{}", stop.0),
+            Place::Station(station) => write!(f, "{station}"),
+        }
+    }
+}
+
+impl FromStr for Place {
+    type Err = ParseIntError;
+
+    /// Parses `<stop_id>` or `st:<station_id>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("st:") {
+            Some(station) => Ok(Self::Station(StationId(station.parse()?))),
+            None => Ok(Self::Stop(s.parse()?)),
+        }
+    }
+}
+
+impl Serialize for Place {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Place {
+    /// Accepts a bare stop id, like [`StopId`] does, or a string that [`Place::from_str`] parses
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PlaceVisitor;
+
+        impl Visitor<'_> for PlaceVisitor {
+            type Value = Place;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                write!(f, "a stop id or `st:<station_id>`")
+            }
+
+            fn visit_u64<E: Error>(self, value: u64) -> Result<Self::Value, E> {
+                u32::try_from(value)
+                    .map(|stop| Place::Stop(StopId(stop)))
+                    .map_err(|_| E::custom(format!("stop id {value} is out of range")))
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+                Place::from_str(value).map_err(|e| E::custom(format!("invalid place `{value}`: {e}")))
+            }
+        }
+
+        deserializer.deserialize_any(PlaceVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_place() {
+        assert_eq!(Place::from_str("42").unwrap(), Place::Stop(StopId(42)));
+        assert_eq!(Place::from_str("st:7").unwrap(), Place::Station(StationId(7)));
+        assert!(Place::from_str("st:").is_err());
+        assert!(Place::from_str("s:42").is_err());
+
+        let station = Place::Station(StationId(7));
+        assert_eq!(Place::from_str(&station.to_string()).unwrap(), station);
+    }
+
+    #[test]
+    fn test_deserialize_place() {
+        assert_eq!(serde_json::from_str::<Place>("42").unwrap(), Place::Stop(StopId(42)));
+        assert_eq!(serde_json::from_str::<Place>("\"42\"").unwrap(), Place::Stop(StopId(42)));
+        assert_eq!(serde_json::from_str::<Place>("\"st:7\"").unwrap(), Place::Station(StationId(7)));
+        assert!(serde_json::from_str::<Place>("\"st:\"").is_err());
+
+        let station = Place::Station(StationId(7));
+        assert_eq!(serde_json::from_str::<Place>(&serde_json::to_string(&station).unwrap()).unwrap(), station);
+    }
+}
@@ -29,6 +29,20 @@ pub struct RecurringTripId {
     starting_day: NaiveDate,
 }
 
+impl RecurringTripId {
+    pub fn new(base_id: u32, starting_day: NaiveDate) -> Self {
+        Self { base_id, starting_day }
+    }
+
+    pub fn base_id(&self) -> u32 {
+        self.base_id
+    }
+
+    pub fn starting_day(&self) -> NaiveDate {
+        self.starting_day
+    }
+}
+
 pub struct OneOff;
 
 impl TripType for OneOff {
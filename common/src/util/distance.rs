@@ -3,6 +3,9 @@ use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use either::Either;
 use regex::Regex;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 
 /// Distance in meters
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -56,4 +59,15 @@ impl TryFrom<SerializedDistance> for Distance {
 }
 
 
-pub type Radius = Distance;
\ No newline at end of file
+pub type Radius = Distance;
+
+/// Same as the serialized representation: meters, or a string with an optional `m` suffix
+impl JsonSchema for Distance {
+    fn schema_name() -> String {
+        "Distance".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        <Either<f32, String>>::json_schema(generator)
+    }
+}
\ No newline at end of file
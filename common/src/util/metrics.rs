@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::fs::{create_dir_all, File};
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the memory usage is sampled while a stage runs
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Latest measurement of each stage, in the order in which the stages were first measured
+static STAGES: Mutex<Vec<StageMetrics>> = Mutex::new(Vec::new());
+
+/// Duration and memory usage of a stage of the preprocessing
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StageMetrics {
+    pub stage: String,
+    #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
+    pub duration: Duration,
+    /// Highest physical memory usage of the whole process while the stage ran, in bytes. Not
+    /// available on all platforms.
+    pub peak_memory: Option<usize>,
+}
+
+/// Samples the memory usage on a separate thread, until it is stopped
+struct PeakMemorySampler {
+    peak: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl PeakMemorySampler {
+    fn start() -> Option<Self> {
+        let peak = Arc::new(AtomicUsize::new(current_memory()?));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let peak = Arc::clone(&peak);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("memory sampler".into())
+                .spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        if let Some(memory) = current_memory() {
+                            peak.fetch_max(memory, Ordering::Relaxed);
+                        }
+                        thread::sleep(SAMPLE_INTERVAL);
+                    }
+                })
+                .ok()?
+        };
+
+        Some(Self { peak, stop, handle })
+    }
+
+    fn finish(self) -> usize {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+
+        // Also include the memory at the end, in case the stage was shorter than one interval
+        let end = current_memory().unwrap_or(0);
+        self.peak.load(Ordering::Relaxed).max(end)
+    }
+}
+
+fn current_memory() -> Option<usize> {
+    memory_stats::memory_stats().map(|stats| stats.physical_mem)
+}
+
+/// Runs `f` as `stage` and records how long it took and how much memory was used
+pub fn measure<Out>(stage: &str, f: impl FnOnce() -> Out) -> Out {
+    let sampler = PeakMemorySampler::start();
+    let start = Instant::now();
+
+    let result = f();
+
+    record(stage, start.elapsed(), sampler.map(PeakMemorySampler::finish));
+    result
+}
+
+/// Like [`measure`], for futures
+pub async fn measure_async<Out>(stage: &str, f: impl Future<Output = Out>) -> Out {
+    let sampler = PeakMemorySampler::start();
+    let start = Instant::now();
+
+    let result = f.await;
+
+    record(stage, start.elapsed(), sampler.map(PeakMemorySampler::finish));
+    result
+}
+
+fn record(stage: &str, duration: Duration, peak_memory: Option<usize>) {
+    let metrics = StageMetrics { stage: stage.to_string(), duration, peak_memory };
+
+    let mut stages = STAGES.lock().unwrap();
+    match stages.iter_mut().find(|existing| existing.stage == stage) {
+        Some(existing) => *existing = metrics,
+        None => stages.push(metrics),
+    }
+}
+
+/// Latest measurement of each stage
+pub fn stages() -> Vec<StageMetrics> {
+    STAGES.lock().unwrap().clone()
+}
+
+/// Writes the latest measurement of each stage to `path` as JSON
+pub fn save(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    serde_json::to_writer_pretty(File::create(path)?, &stages())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let result = measure("test_measure", || {
+            thread::sleep(Duration::from_millis(30));
+            42
+        });
+        assert_eq!(result, 42);
+        measure("test_measure", || thread::sleep(Duration::from_millis(5)));
+
+        // Only the latest measurement is kept
+        let stages: Vec<StageMetrics> = stages().into_iter()
+            .filter(|stage| stage.stage == "test_measure")
+            .collect();
+        assert_eq!(stages.len(), 1);
+        assert!(stages[0].duration < Duration::from_millis(30));
+        if cfg!(target_os = "linux") {
+            assert!(stages[0].peak_memory.unwrap() > 0);
+        }
+    }
+}
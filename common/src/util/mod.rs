@@ -4,4 +4,7 @@ pub mod time;
 pub mod duration;
 pub mod df;
 pub mod distance;
-pub mod geoarrow_lines;
\ No newline at end of file
+pub mod geoarrow_lines;
+pub mod metrics;
+pub mod progress;
+pub mod tls;
\ No newline at end of file
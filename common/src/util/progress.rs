@@ -0,0 +1,186 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::LazyLock;
+use tokio::sync::watch;
+
+/// Progress of the preprocessing pipeline, which is shown in the dashboard. The pipeline
+/// reports to it through the functions in this module, the dashboard [`subscribe`]s to it.
+static STATE: LazyLock<watch::Sender<PipelineState>> =
+    LazyLock::new(|| watch::Sender::new(PipelineState::default()));
+
+#[derive(Serialize, Debug, Hash, Clone, Copy, Eq, PartialEq)]
+pub enum Job {
+    HarvestData,
+    ImportData,
+    ValidateData,
+    PreprocessingClustering,
+    PreprocessingLocalTransferPatterns,
+    PreprocessingLongDistanceTransferPatterns,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum JobStatus {
+    #[default]
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct JobState {
+    pub status: JobStatus,
+    /// Percentage of the job that is done, if the job reports it
+    pub progress: Option<f32>,
+    /// Only for jobs that process clusters one after another
+    pub clusters: Option<ClusterProgress>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClusterProgress {
+    /// Number of clusters that are done
+    pub done: u32,
+    pub total: u32,
+    /// ID of the cluster that is currently processed
+    pub current: Option<u32>,
+    /// Percentage of the current cluster that is done
+    pub current_progress: f32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PipelineState(HashMap<Job, JobState>);
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (Job::HarvestData, JobState::default()),
+            (Job::ImportData, JobState::default()),
+            (Job::ValidateData, JobState::default()),
+            (Job::PreprocessingClustering, JobState::default()),
+            (Job::PreprocessingLocalTransferPatterns, JobState::default()),
+            (Job::PreprocessingLongDistanceTransferPatterns, JobState::default()),
+        ]))
+    }
+}
+
+impl PipelineState {
+    pub fn get(&self, job: Job) -> &JobState {
+        &self.0[&job]
+    }
+}
+
+/// Receives the state of the pipeline whenever it changes
+pub fn subscribe() -> watch::Receiver<PipelineState> {
+    STATE.subscribe()
+}
+
+fn update(job: Job, f: impl FnOnce(&mut JobState)) {
+    STATE.send_modify(|state| f(state.0.entry(job).or_default()));
+}
+
+pub fn set_status(job: Job, status: JobStatus) {
+    update(job, |state| {
+        state.status = status;
+        match status {
+            JobStatus::Queued | JobStatus::Running => {
+                state.progress = None;
+                state.clusters = None;
+            }
+            JobStatus::Succeeded => state.progress = Some(100.0),
+            JobStatus::Failed => {}
+        }
+    });
+}
+
+pub fn set_progress(job: Job, done: u64, total: u64) {
+    update(job, |state| state.progress = Some(percentage(done, total)));
+}
+
+/// Marks `cluster_id` as the cluster that `job` currently processes, after `done` of `total`
+/// clusters are finished
+pub fn start_cluster(job: Job, cluster_id: u32, done: u32, total: u32) {
+    update(job, |state| {
+        state.progress = Some(percentage(done as u64, total as u64));
+        state.clusters = Some(ClusterProgress { done, total, current: Some(cluster_id), current_progress: 0.0 });
+    });
+}
+
+/// Marks another cluster of `job` as finished, of which `done` are finished now. The clusters
+/// can be processed in parallel, so this doesn't change which cluster is the current one.
+pub fn finish_cluster(job: Job, done: u32) {
+    update(job, |state| {
+        if let Some(clusters) = &mut state.clusters {
+            clusters.done = done;
+            state.progress = Some(percentage(done as u64, clusters.total as u64));
+        }
+    });
+}
+
+/// Progress within the cluster that `job` currently processes. If the job doesn't process
+/// clusters, this is the progress of the job itself. If it processes several clusters at the same
+/// time, this is the progress of the one that reported last.
+pub fn set_cluster_progress(job: Job, done: u64, total: u64) {
+    update(job, |state| match &mut state.clusters {
+        Some(clusters) => {
+            clusters.current_progress = percentage(done, total);
+            let clusters_done = clusters.done as f32 + clusters.current_progress / 100.0;
+            state.progress = Some(100.0 * clusters_done / clusters.total.max(1) as f32);
+        }
+        None => state.progress = Some(percentage(done, total)),
+    });
+}
+
+fn percentage(done: u64, total: u64) -> f32 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * done as f32 / total as f32
+    }
+}
+
+/// Runs `f` as `job`, which is reported as running and then as succeeded or failed
+pub fn track<T, E>(job: Job, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    set_status(job, JobStatus::Running);
+    let result = f();
+    set_status(job, if result.is_ok() { JobStatus::Succeeded } else { JobStatus::Failed });
+    result
+}
+
+/// Like [`track`], for futures
+pub async fn track_async<T, E>(job: Job, f: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    set_status(job, JobStatus::Running);
+    let result = f.await;
+    set_status(job, if result.is_ok() { JobStatus::Succeeded } else { JobStatus::Failed });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_progress() {
+        let mut receiver = subscribe();
+        let job = Job::PreprocessingLocalTransferPatterns;
+
+        let result = track(job, || {
+            start_cluster(job, 7, 1, 4);
+            set_cluster_progress(job, 1, 2);
+
+            let state = receiver.borrow_and_update().get(job).clone();
+            assert_eq!(state.status, JobStatus::Running);
+            assert_eq!(state.progress, Some(37.5));
+            assert_eq!(state.clusters.unwrap().current, Some(7));
+
+            finish_cluster(job, 2);
+            assert_eq!(receiver.borrow_and_update().get(job).progress, Some(50.0));
+
+            Err::<(), ()>(())
+        });
+
+        assert!(result.is_err());
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(receiver.borrow().get(job).status, JobStatus::Failed);
+    }
+}
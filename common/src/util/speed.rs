@@ -5,12 +5,19 @@ pub struct Speed(pub f64); // in km/h
 
 pub const MAX_WALKING_SPEED: Speed = Speed(7f64);
 pub const MAX_WALKING_DURATION: Duration = Duration::minutes(15);
+// Manual wheelchairs are usually a good bit slower than pedestrians
+pub const MAX_WHEELCHAIR_SPEED: Speed = Speed(4f64);
 
 impl Speed {
     pub fn time_to_travel_distance(&self, meters: f32) -> Duration {
         let hours = (1.0 / self.0) * (meters as f64 / 1_000.0);
         TimeDelta::milliseconds((hours * 60.0 * 60.0 * 1_000.0) as i64)
     }
+
+    pub fn distance_traveled_in(&self, duration: Duration) -> f32 {
+        let hours = duration.num_milliseconds() as f64 / (60.0 * 60.0 * 1_000.0);
+        (self.0 * hours * 1_000.0) as f32
+    }
 }
 
 #[cfg(test)]
@@ -21,5 +28,6 @@ mod tests {
     fn test_speed_to_distance() {
         assert_eq!(Duration::seconds(36), Speed(10.0).time_to_travel_distance(100.));
         assert_eq!(Duration::seconds(18), Speed(200.0).time_to_travel_distance(1_000.));
+        assert_eq!(100., Speed(10.0).distance_traveled_in(Duration::seconds(36)));
     }
 }
\ No newline at end of file
@@ -1,3 +1,122 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::de::{Error, Visitor};
+use serde::{Deserializer, Serializer};
+use serde_with::{DeserializeAs, SerializeAs};
+use std::fmt;
 
-pub const INFINITY: DateTime<Utc> = DateTime::<Utc>::MAX_UTC;
\ No newline at end of file
+pub const INFINITY: DateTime<Utc> = DateTime::<Utc>::MAX_UTC;
+
+/// The point in time that GTFS times of a service day are relative to: noon minus 12 hours in the
+/// timezone of the agency. This is midnight, except for days on which daylight saving time starts
+/// or ends. On these days, "00:00:00" is one hour before or after midnight.
+pub fn service_day_start(day: NaiveDate, timezone: Tz) -> DateTime<Utc> {
+    let noon = day.and_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap());
+    // Noon is never skipped or repeated by a daylight saving time change
+    let noon = timezone.from_local_datetime(&noon).earliest()
+        .unwrap_or_else(|| panic!("Noon of {day} must exist in {timezone}"));
+
+    noon.with_timezone(&Utc) - Duration::hours(12)
+}
+
+/// Outputs whose times are presented in a timezone, e.g. the one of the timetable. Until it is
+/// set, times are in UTC.
+pub trait InTimezone {
+    fn in_timezone(self, timezone: Tz) -> Self;
+}
+
+/// Local time with an offset for the API:
+/// - Serializes times as RFC 3339 with the offset of their timezone
+/// - Deserializes RFC 3339 times with an offset, as well as unix timestamps in seconds. Timestamps
+///   may be numbers or strings, since query parameters are always strings.
+pub struct LocalTime;
+
+impl<T: TimeZone> SerializeAs<DateTime<T>> for LocalTime where T::Offset: fmt::Display {
+    fn serialize_as<S: Serializer>(source: &DateTime<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&source.to_rfc3339())
+    }
+}
+
+impl<'de> DeserializeAs<'de, DateTime<Utc>> for LocalTime {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        deserializer.deserialize_any(LocalTimeVisitor)
+    }
+}
+
+struct LocalTimeVisitor;
+
+impl Visitor<'_> for LocalTimeVisitor {
+    type Value = DateTime<Utc>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an RFC 3339 time with offset or a unix timestamp in seconds")
+    }
+
+    fn visit_i64<E: Error>(self, seconds: i64) -> Result<Self::Value, E> {
+        DateTime::from_timestamp(seconds, 0)
+            .ok_or_else(|| E::custom(format!("Timestamp {seconds} is out of range")))
+    }
+
+    fn visit_u64<E: Error>(self, seconds: u64) -> Result<Self::Value, E> {
+        let seconds = i64::try_from(seconds)
+            .map_err(|_| E::custom(format!("Timestamp {seconds} is out of range")))?;
+        self.visit_i64(seconds)
+    }
+
+    fn visit_str<E: Error>(self, time: &str) -> Result<Self::Value, E> {
+        if let Ok(seconds) = time.parse::<i64>() {
+            return self.visit_i64(seconds);
+        }
+
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|err| E::custom(format!("Invalid time {time}: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use serde_with::serde_as;
+
+    #[serde_as]
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Wrapper(#[serde_as(as = "LocalTime")] DateTime<Utc>);
+
+    #[serde_as]
+    #[derive(Serialize)]
+    struct LocalWrapper(#[serde_as(as = "LocalTime")] DateTime<Tz>);
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_service_day_start() {
+        let berlin = chrono_tz::Europe::Berlin;
+
+        // Regular days start at midnight
+        assert_eq!(service_day_start(NaiveDate::from_ymd_opt(2024, 3, 30).unwrap(), berlin), utc("2024-03-30T00:00:00+01:00"));
+        assert_eq!(service_day_start(NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(), berlin), utc("2024-04-01T00:00:00+02:00"));
+        // Daylight saving time starts at 02:00, so the service day starts at 23:00 the day before
+        assert_eq!(service_day_start(NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(), berlin), utc("2024-03-30T23:00:00+01:00"));
+        // Daylight saving time ends at 03:00, so the service day starts at 01:00
+        assert_eq!(service_day_start(NaiveDate::from_ymd_opt(2024, 10, 27).unwrap(), berlin), utc("2024-10-27T01:00:00+02:00"));
+    }
+
+    #[test]
+    fn test_local_time() {
+        let parse = |json: &str| serde_json::from_str::<Wrapper>(json).unwrap().0;
+
+        assert_eq!(parse("\"2024-03-31T08:00:00+02:00\""), utc("2024-03-31T06:00:00Z"));
+        assert_eq!(parse("\"60\""), utc("1970-01-01T00:01:00Z"));
+        assert_eq!(parse("60"), utc("1970-01-01T00:01:00Z"));
+        assert!(serde_json::from_str::<Wrapper>("\"tomorrow\"").is_err());
+
+        let time = Wrapper(utc("2024-03-31T06:00:00Z"));
+        assert_eq!(serde_json::to_string(&time).unwrap(), "\"2024-03-31T06:00:00+00:00\"");
+        let local = LocalWrapper(time.0.with_timezone(&chrono_tz::Europe::Berlin));
+        assert_eq!(serde_json::to_string(&local).unwrap(), "\"2024-03-31T08:00:00+02:00\"");
+    }
+}
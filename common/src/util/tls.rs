@@ -0,0 +1,76 @@
+use crate::types::config::servers::TlsConfig;
+use rustls::pki_types::CertificateDer;
+use rustls::ServerConfig;
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Reads the certificate chain and private key for serving HTTPS. Both HTTP/2 and HTTP/1.1 are
+/// offered to clients.
+pub fn load_server_config(config: &TlsConfig) -> Result<ServerConfig, TlsError> {
+    let certificates = rustls_pemfile::certs(&mut open(&config.certificate)?)
+        .collect::<Result<Vec<CertificateDer>, io::Error>>()
+        .map_err(|err| TlsError::Read(config.certificate.clone(), err))?;
+    if certificates.is_empty() {
+        return Err(TlsError::NoCertificate(config.certificate.clone()));
+    }
+
+    let private_key = rustls_pemfile::private_key(&mut open(&config.private_key)?)
+        .map_err(|err| TlsError::Read(config.private_key.clone(), err))?
+        .ok_or_else(|| TlsError::NoPrivateKey(config.private_key.clone()))?;
+
+    let mut server_config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(server_config)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| TlsError::Read(path.to_path_buf(), err))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TlsError {
+    Read(PathBuf, io::Error),
+    NoCertificate(PathBuf),
+    NoPrivateKey(PathBuf),
+    Rustls(#[from] rustls::Error),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::Read(path, err) => write!(f, "Unable to read {path:?}: {err}"),
+            TlsError::NoCertificate(path) => write!(f, "No certificate found in {path:?}"),
+            TlsError::NoPrivateKey(path) => write!(f, "No private key found in {path:?}"),
+            TlsError::Rustls(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_server_config_errors() {
+        let dir = TempDir::new().unwrap();
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+
+        let missing = TlsConfig { certificate: dir.path().join("missing.pem"), private_key: empty.clone() };
+        assert!(matches!(load_server_config(&missing), Err(TlsError::Read(..))));
+
+        let no_certificate = TlsConfig { certificate: empty.clone(), private_key: empty };
+        assert!(matches!(load_server_config(&no_certificate), Err(TlsError::NoCertificate(..))));
+    }
+}
@@ -1,6 +1,6 @@
 use polars::datatypes::{AnyValue, DataType};
 use polars::error::{ErrString, PolarsError};
-use polars::prelude::{Column, Field, StrptimeOptions};
+use polars::prelude::{col, Column, Expr, Field, Schema, StrptimeOptions};
 use polars::series::Series;
 
 pub const GTFS_REQUIRED_FILES: [&str; 5] = [
@@ -29,8 +29,10 @@ pub const GTFS_OTHER_FILES: [&str; 21] = [
     "feed_info.txt",
     "attributions.txt",
 ];
-pub const GTFS_FILES_TO_IMPORT: [&str; 4] = [
+pub const GTFS_FILES_TO_IMPORT: [&str; 6] = [
+    "agency.txt",
     "calendar.txt",
+    "routes.txt",
     "stops.txt",
     "trips.txt",
     "stop_times.txt"
@@ -45,6 +47,7 @@ pub fn gtfs_date_format() -> StrptimeOptions {
     }
 }
 
+/// Times may be missing (e.g. for intermediate stops of a trip), these stay null
 #[inline]
 pub fn gtfs_time_to_ms(times: Column) -> Result<Column, PolarsError> {
     let strings = times.as_materialized_series().rechunk().iter()
@@ -64,12 +67,13 @@ pub fn gtfs_time_to_ms(times: Column) -> Result<Column, PolarsError> {
                         })
                         .collect::<Result<Vec<u32>, PolarsError>>()?.into_iter()
                         .sum::<u32>() * 1000;
-                    Ok(result)
+                    Ok(Some(result))
                 }
+                AnyValue::Null => Ok(None),
                 _ => Err(PolarsError::SchemaMismatch(ErrString::from("Expected string")))
             }
         })
-        .collect::<Result<Vec<u32>, PolarsError>>()?;
+        .collect::<Result<Vec<Option<u32>>, PolarsError>>()?;
     let series: Series = strings.into_iter().collect();
     Ok(series.into())
 }
@@ -77,6 +81,35 @@ pub fn gtfs_time_to_ms(times: Column) -> Result<Column, PolarsError> {
 #[derive(Debug)]
 pub struct GtfsFile {
     pub required_fields: Vec<Field>,
+    /// Fields that drino makes use of, but which feeds are not required to provide
+    pub optional_fields: Vec<Field>,
+}
+
+impl GtfsFile {
+    /// Add the expected data types of optional fields to a schema read from a file. Optional fields
+    /// that are not in the file are not added, since the CSV reader would then expect them.
+    pub fn apply_optional_fields(&self, schema: &mut Schema) {
+        for field in &self.optional_fields {
+            if schema.contains(field.name()) {
+                schema.with_column(field.name().clone(), field.dtype().clone());
+            }
+        }
+    }
+
+    /// Select an optional field, or a column filled with `default` if the feed doesn't provide it.
+    /// Missing values in a provided column are also replaced by `default`.
+    pub fn optional_field(&self, schema: &Schema, name: &str, default: Expr) -> Expr {
+        debug_assert!(
+            self.optional_fields.iter().any(|field| field.name() == name),
+            "{name} is not an optional field of this file"
+        );
+
+        if schema.contains(name) {
+            col(name).fill_null(default)
+        } else {
+            default.alias(name)
+        }
+    }
 }
 
 pub struct GtfsDataset {
@@ -92,9 +125,14 @@ pub fn gtfs_schemas() -> GtfsDataset {
     GtfsDataset {
         agency: GtfsFile {
             required_fields: vec![
-                Field { name: "agency_id".into(), dtype: DataType::String },
+                // All agencies of a feed must have the same timezone. Times in stop_times.txt are
+                // relative to service days in this timezone.
                 Field { name: "agency_timezone".into(), dtype: DataType::String },
             ],
+            optional_fields: vec![
+                // Only required if there are multiple agencies in the feed
+                Field { name: "agency_id".into(), dtype: DataType::String },
+            ],
         },
         calendar: GtfsFile {
             required_fields: vec![
@@ -109,10 +147,16 @@ pub fn gtfs_schemas() -> GtfsDataset {
                 Field { name: "start_date".into(), dtype: DataType::String },
                 Field { name: "end_date".into(), dtype: DataType::String },
             ],
+            optional_fields: vec![],
         },
         routes: GtfsFile {
             required_fields: vec![
                 Field { name: "route_id".into(), dtype: DataType::String },
+                // Basic (0-12) or extended (100-1702) route type
+                Field { name: "route_type".into(), dtype: DataType::UInt32 },
+            ],
+            optional_fields: vec![
+                // Only required if there are multiple agencies in the feed
                 Field { name: "agency_id".into(), dtype: DataType::String },
             ],
         },
@@ -123,6 +167,7 @@ pub fn gtfs_schemas() -> GtfsDataset {
                 Field { name: "departure_time".into(), dtype: DataType::String },
                 Field { name: "stop_sequence".into(), dtype: DataType::UInt32 },
             ],
+            optional_fields: vec![],
         },
         stops: GtfsFile {
             required_fields: vec![
@@ -130,6 +175,17 @@ pub fn gtfs_schemas() -> GtfsDataset {
                 Field { name: "stop_lat".into(), dtype: DataType::Float32 }, // f32 for coordinates might be too little (~2m precision?)
                 Field { name: "stop_lon".into(), dtype: DataType::Float32 }, // f32 for coordinates might be too little (~2m precision?)
             ],
+            optional_fields: vec![
+                // 0 or empty: no information, 1: boarding is possible, 2: boarding is not possible
+                Field { name: "wheelchair_boarding".into(), dtype: DataType::UInt32 },
+                // Only for presenting times at the stop, times of trips are never relative to it
+                Field { name: "stop_timezone".into(), dtype: DataType::String },
+                Field { name: "stop_name".into(), dtype: DataType::String },
+                // 0 or empty: stop or platform, 1: station, 2-4: entrances, nodes and boarding areas
+                Field { name: "location_type".into(), dtype: DataType::UInt32 },
+                // The station that a platform belongs to
+                Field { name: "parent_station".into(), dtype: DataType::String },
+            ],
         },
         trips: GtfsFile {
             required_fields: vec![
@@ -137,6 +193,10 @@ pub fn gtfs_schemas() -> GtfsDataset {
                 Field { name: "service_id".into(), dtype: DataType::String },
                 Field { name: "trip_id".into(), dtype: DataType::String },
             ],
+            optional_fields: vec![
+                // 0 or empty: no information, 1: at least one wheelchair fits, 2: no wheelchairs
+                Field { name: "wheelchair_accessible".into(), dtype: DataType::UInt32 },
+            ],
         },
     }
 }
\ No newline at end of file
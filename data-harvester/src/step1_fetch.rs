@@ -1,29 +1,32 @@
-use std::fmt;
+use std::{fmt, io};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::io::{Cursor};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use common::types::config::dataset::{Dataset, DataSource};
-use std::fs::{create_dir_all, File};
+use std::fs::{create_dir_all, read_dir, remove_file, File};
 use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
 
-pub async fn fetch_dataset(
-    dataset: &Dataset
-) -> Result<FetchStepOutput, FetchError> {
-    match dataset.clone().src {
-        DataSource::URL { url, .. } => {
-            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
-            let path_str = format!("./data/datasets/{}/imports/{}", dataset.id, timestamp);
-            let path = Path::new(&path_str);
-            create_dir_all(path.parent().unwrap())?;
-            let mut file = File::create(&path)?;
-
-            let response = reqwest::get(url.clone()).await?;
-            let mut content = Cursor::new(response.bytes().await?);
-            std::io::copy(&mut content, &mut file)?;
+/// Downloads datasets from URLs into `data_dir`. Local files are used where they are.
+pub async fn fetch_dataset<'a>(
+    dataset: &'a Dataset,
+    data_dir: &Path,
+) -> Result<FetchStepOutput<'a>, FetchError> {
+    match &dataset.src {
+        DataSource::URL { url, headers, fetch_interval, retention } => {
+            let imports_dir = data_dir.join("datasets").join(&dataset.id).join("imports");
+            let path = fetch_url(url, headers, *fetch_interval, *retention, &imports_dir, SystemTime::now()).await?;
 
             Ok(FetchStepOutput {
                 dataset,
-                path: path.to_path_buf(),
+                path,
             })
         },
         DataSource::File { path } => {
@@ -35,17 +38,151 @@ pub async fn fetch_dataset(
     }
 }
 
+/// Stored next to the imports, so that the next fetch can check whether the source has changed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ImportMetadata {
+    /// File name of the last import in the imports folder
+    file_name: String,
+    fetched_at: SystemTime,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Hex encoded SHA-256 of the import
+    checksum: String,
+}
+
+const METADATA_FILE_NAME: &str = "last_import.json";
+
+/// Downloads the dataset into `imports_dir`, unless the last import can be used instead. This is
+/// the case if
+/// - it is newer than `fetch_interval`,
+/// - the server responds that it has not been modified (using ETag and Last-Modified), or
+/// - the downloaded content is the same (by checksum).
+///
+/// Only the newest `retention` imports are kept. `now` is the time of the fetch, which new imports
+/// are named by. Returns the path of the import to use.
+async fn fetch_url(
+    url: &Url,
+    headers: &HashMap<String, String>,
+    fetch_interval: Option<Duration>,
+    retention: usize,
+    imports_dir: &Path,
+    now: SystemTime,
+) -> Result<PathBuf, FetchError> {
+    create_dir_all(imports_dir)?;
+
+    // The previous import is of no use if its file has been deleted in the meantime
+    let previous = read_metadata(imports_dir)
+        .filter(|previous| imports_dir.join(&previous.file_name).exists());
+
+    if let (Some(previous), Some(fetch_interval)) = (&previous, fetch_interval) {
+        if now.duration_since(previous.fetched_at).is_ok_and(|elapsed| elapsed < fetch_interval) {
+            debug!(target: "fetch", "Last import of {url} is recent enough, not fetching again");
+            return Ok(imports_dir.join(&previous.file_name));
+        }
+    }
+
+    let mut request = reqwest::Client::new().get(url.clone());
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    if let Some(previous) = &previous {
+        if let Some(etag) = &previous.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?;
+    let fetched_at = now;
+
+    if let (StatusCode::NOT_MODIFIED, Some(previous)) = (response.status(), &previous) {
+        info!(target: "fetch", "{url} has not been modified since the last import");
+        let metadata = ImportMetadata { fetched_at, ..previous.clone() };
+        write_metadata(imports_dir, &metadata)?;
+        return Ok(imports_dir.join(metadata.file_name));
+    }
+
+    let response = response.error_for_status()?;
+    let etag = header_value(response.headers(), ETAG);
+    let last_modified = header_value(response.headers(), LAST_MODIFIED);
+    let content = response.bytes().await?;
+    let checksum = hex::encode(Sha256::digest(&content));
+
+    let file_name = match previous.filter(|previous| previous.checksum == checksum) {
+        Some(previous) => {
+            info!(target: "fetch", "Content of {url} is the same as in the last import");
+            previous.file_name
+        }
+        None => {
+            let timestamp = fetched_at.duration_since(UNIX_EPOCH).unwrap().as_millis();
+            let file_name = timestamp.to_string();
+            let mut file = File::create(imports_dir.join(&file_name))?;
+            std::io::copy(&mut Cursor::new(content), &mut file)?;
+            file_name
+        }
+    };
+
+    let path = imports_dir.join(&file_name);
+    write_metadata(imports_dir, &ImportMetadata { file_name, fetched_at, etag, last_modified, checksum })?;
+    remove_old_imports(imports_dir, retention)?;
+
+    Ok(path)
+}
+
+fn header_value(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+fn read_metadata(imports_dir: &Path) -> Option<ImportMetadata> {
+    let file = File::open(imports_dir.join(METADATA_FILE_NAME)).ok()?;
+
+    serde_json::from_reader(file)
+        .inspect_err(|err| warn!(target: "fetch", "Ignoring unreadable metadata of last import: {err}"))
+        .ok()
+}
+
+fn write_metadata(imports_dir: &Path, metadata: &ImportMetadata) -> Result<(), FetchError> {
+    let file = File::create(imports_dir.join(METADATA_FILE_NAME))?;
+    serde_json::to_writer_pretty(file, metadata)?;
+    Ok(())
+}
+
+/// Deletes all but the newest `retention` imports (at least the newest one is kept). Imports are
+/// named by the timestamp of their download.
+fn remove_old_imports(imports_dir: &Path, retention: usize) -> io::Result<()> {
+    let imports = read_dir(imports_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let timestamp = entry.file_name().to_str()?.parse::<u128>().ok()?;
+            Some((timestamp, entry.path()))
+        })
+        .sorted_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+
+    for (_, path) in imports.skip(retention.max(1)) {
+        debug!(target: "fetch", "Removing old import {path:?}");
+        remove_file(path)?;
+    }
+
+    Ok(())
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum FetchError {
     Reqwest(#[from] reqwest::Error),
-    File(#[from] std::io::Error)
+    File(#[from] std::io::Error),
+    Metadata(#[from] serde_json::Error),
 }
 
 impl Display for FetchError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let err: &dyn Display = match self {
             FetchError::Reqwest(err) => err,
-            FetchError::File(err) => err
+            FetchError::File(err) => err,
+            FetchError::Metadata(err) => err,
         };
         write!(f, "{}", err)
     }
@@ -56,4 +193,152 @@ impl Display for FetchError {
 pub struct FetchStepOutput<'a> {
     pub(crate) dataset: &'a Dataset,
     pub(crate) path: PathBuf
-}
\ No newline at end of file
+}
+
+impl FetchStepOutput<'_> {
+    /// The fetched file of the dataset
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn dataset(&self) -> &Dataset {
+        self.dataset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::http::HeaderMap;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+
+    /// Local stand-in for a dataset server. Responds with `content` and, if set, `etag`.
+    #[derive(Default)]
+    struct Stub {
+        content: Mutex<Vec<u8>>,
+        etag: Option<String>,
+        requests: AtomicUsize,
+    }
+
+    async fn serve(State(stub): State<Arc<Stub>>, headers: HeaderMap) -> Response {
+        stub.requests.fetch_add(1, Ordering::SeqCst);
+
+        if headers.get("x-api-key").is_none_or(|key| key != "secret") {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+
+        match &stub.etag {
+            Some(etag) if headers.get(IF_NONE_MATCH).is_some_and(|value| value == etag) => {
+                StatusCode::NOT_MODIFIED.into_response()
+            }
+            Some(etag) => ([(ETAG, etag.clone())], stub.content.lock().unwrap().clone()).into_response(),
+            None => stub.content.lock().unwrap().clone().into_response(),
+        }
+    }
+
+    async fn start(stub: Arc<Stub>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route("/feed.zip", get(serve)).with_state(stub);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Url::parse(&format!("http://{address}/feed.zip")).unwrap()
+    }
+
+    fn headers() -> HashMap<String, String> {
+        HashMap::from([("x-api-key".into(), "secret".into())])
+    }
+
+    /// Time of a fetch, `minutes` after the first one
+    fn at(minutes: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + minutes * 60)
+    }
+
+    fn imports(dir: &TempDir) -> Vec<PathBuf> {
+        read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| !path.ends_with(METADATA_FILE_NAME))
+            .sorted()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_not_modified() {
+        let stub = Arc::new(Stub { content: Mutex::new(b"v1".to_vec()), etag: Some("\"v1\"".into()), ..Default::default() });
+        let url = start(stub.clone()).await;
+        let dir = TempDir::new().unwrap();
+
+        let first = fetch_url(&url, &headers(), None, 3, dir.path(), at(0)).await.unwrap();
+        let second = fetch_url(&url, &headers(), None, 3, dir.path(), at(1)).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(imports(&dir), vec![first]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_headers() {
+        let url = start(Arc::new(Stub::default())).await;
+        let dir = TempDir::new().unwrap();
+
+        let result = fetch_url(&url, &HashMap::new(), None, 3, dir.path(), at(0)).await;
+
+        assert!(matches!(result, Err(FetchError::Reqwest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_same_checksum() {
+        let stub = Arc::new(Stub { content: Mutex::new(b"v1".to_vec()), ..Default::default() });
+        let url = start(stub.clone()).await;
+        let dir = TempDir::new().unwrap();
+
+        let first = fetch_url(&url, &headers(), None, 3, dir.path(), at(0)).await.unwrap();
+        let second = fetch_url(&url, &headers(), None, 3, dir.path(), at(1)).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(imports(&dir).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let stub = Arc::new(Stub::default());
+        let url = start(stub.clone()).await;
+        let dir = TempDir::new().unwrap();
+
+        let mut paths = vec![];
+        for version in 0..4 {
+            *stub.content.lock().unwrap() = format!("v{version}").into_bytes();
+            paths.push(fetch_url(&url, &headers(), None, 2, dir.path(), at(version)).await.unwrap());
+        }
+
+        // Only the two newest imports are kept
+        assert_eq!(imports(&dir), paths[2..]);
+        assert_eq!(std::fs::read(&paths[3]).unwrap(), b"v3");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_interval() {
+        let stub = Arc::new(Stub { content: Mutex::new(b"v1".to_vec()), ..Default::default() });
+        let url = start(stub.clone()).await;
+        let dir = TempDir::new().unwrap();
+        let interval = Some(Duration::from_secs(60 * 60));
+
+        let first = fetch_url(&url, &headers(), interval, 3, dir.path(), at(0)).await.unwrap();
+        let second = fetch_url(&url, &headers(), interval, 3, dir.path(), at(59)).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 1);
+
+        // Once the interval has passed, the source is checked again
+        fetch_url(&url, &headers(), interval, 3, dir.path(), at(60)).await.unwrap();
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 2);
+    }
+}
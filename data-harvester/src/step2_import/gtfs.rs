@@ -1,12 +1,16 @@
 use polars::datatypes::DataType;
-use polars::prelude::{col, GetOutput, LazyCsvReader, LazyFileListReader, Schema, TimeUnit};
+use polars::prelude::{col, lit, CsvReadOptions, DataFrame, GetOutput, LazyCsvReader, LazyFileListReader, SerReader, Schema, TimeUnit, NULL};
 use std::collections::HashMap;
 use std::fs::File;
+use std::io::{Cursor, Read};
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tempfile::NamedTempFile;
+use zip::result::ZipError;
 use zip::ZipArchive;
+use common::types::attribution::{DatasetAttribution, Organization};
+use common::types::config::dataset::Dataset;
 
 use crate::gtfs_file::*;
 use crate::step1_fetch::FetchStepOutput;
@@ -73,6 +77,24 @@ async fn import_gtfs_files<'lifetime>(
     }
 
 
+    let agency_reader = LazyCsvReader::new(
+        tmp_files.get("agency").expect("No agency file found").canonicalize()?.to_str().unwrap()
+    );
+
+    let mut agency_schema = agency_reader.clone().finish()?.collect_schema()?.deref().clone();
+    schema.agency.apply_optional_fields(&mut agency_schema);
+    let expected_agency_schema = Schema::from_iter(schema.agency.required_fields.clone());
+    agency_schema.merge(expected_agency_schema);
+
+    let agency = agency_reader
+        .with_schema(Some(Arc::new(Schema::from_iter(agency_schema.clone()))))
+        .finish()?
+        .select([
+            schema.agency.optional_field(&agency_schema, "agency_id", lit(NULL).cast(DataType::String)),
+            col("agency_timezone"),
+        ]);
+
+
     let calendar_reader = LazyCsvReader::new(
         tmp_files.get("calendar").expect("No calendar file found").canonicalize()?.to_str().unwrap()
     );
@@ -131,21 +153,46 @@ async fn import_gtfs_files<'lifetime>(
         ]);
 
 
+    let routes_reader = LazyCsvReader::new(
+        tmp_files.get("routes").expect("No routes file found").canonicalize()?.to_str().unwrap()
+    );
+
+    let mut routes_schema = routes_reader.clone().finish()?.collect_schema()?.deref().clone();
+    schema.routes.apply_optional_fields(&mut routes_schema);
+    let expected_routes_schema = Schema::from_iter(schema.routes.required_fields.clone());
+    routes_schema.merge(expected_routes_schema);
+
+    let routes = routes_reader
+        .with_schema(Some(Arc::new(Schema::from_iter(routes_schema.clone()))))
+        .finish()?
+        .select([
+            col("route_id"),
+            col("route_type"),
+            schema.routes.optional_field(&routes_schema, "agency_id", lit(NULL).cast(DataType::String)),
+        ]);
+
+
     let stops_reader = LazyCsvReader::new(
         tmp_files.get("stops").expect("No stop_times file found").canonicalize()?.to_str().unwrap()
     );
 
     let mut stops_schema = stops_reader.clone().finish()?.collect_schema()?.deref().clone();
-    let expected_stops_schema = Schema::from_iter(schema.stops.required_fields);
+    schema.stops.apply_optional_fields(&mut stops_schema);
+    let expected_stops_schema = Schema::from_iter(schema.stops.required_fields.clone());
     stops_schema.merge(expected_stops_schema);
 
     let stops = stops_reader
-        .with_schema(Some(Arc::new(Schema::from_iter(stops_schema))))
+        .with_schema(Some(Arc::new(Schema::from_iter(stops_schema.clone()))))
         .finish()?
         .select([
             col("stop_id"),
             col("stop_lat"),
             col("stop_lon"),
+            schema.stops.optional_field(&stops_schema, "wheelchair_boarding", lit(0u32)),
+            schema.stops.optional_field(&stops_schema, "stop_timezone", lit(NULL).cast(DataType::String)),
+            schema.stops.optional_field(&stops_schema, "stop_name", lit(NULL).cast(DataType::String)),
+            schema.stops.optional_field(&stops_schema, "location_type", lit(0u32)),
+            schema.stops.optional_field(&stops_schema, "parent_station", lit(NULL).cast(DataType::String)),
         ]);
 
 
@@ -154,23 +201,96 @@ async fn import_gtfs_files<'lifetime>(
     );
 
     let mut trips_schema = trips_reader.clone().finish()?.collect_schema()?.deref().clone();
-    let expected_trips_schema = Schema::from_iter(schema.trips.required_fields);
+    schema.trips.apply_optional_fields(&mut trips_schema);
+    let expected_trips_schema = Schema::from_iter(schema.trips.required_fields.clone());
     trips_schema.merge(expected_trips_schema);
 
     let trips = trips_reader
-        .with_schema(Some(Arc::new(Schema::from_iter(trips_schema))))
+        .with_schema(Some(Arc::new(Schema::from_iter(trips_schema.clone()))))
         .finish()?
         .select([
             col("route_id"),
             col("service_id"),
             col("trip_id"),
+            schema.trips.optional_field(&trips_schema, "wheelchair_accessible", lit(0u32)),
         ]);
 
     Ok(ImportStepExtra::Gtfs {
+        agency,
         calendar,
+        routes,
         stops,
         trips,
         stop_times,
         temporary_files: tmp_files.into_iter().map(|(_, path)| path).collect(),
     })
-}
\ No newline at end of file
+}
+
+/// Reads the publisher (`feed_info.txt`) and the organizations that should be attributed
+/// (`attributions.txt`) of a feed. Both files are optional.
+pub(crate) fn read_gtfs_attribution(path: &Path, dataset: &Dataset) -> Result<DatasetAttribution, ImportError> {
+    let mut zip_archive_file = File::open(path)?;
+    let mut zip_archive = ZipArchive::new(&mut zip_archive_file)?;
+
+    let publisher = read_optional_file(&mut zip_archive, "feed_info.txt")?
+        .and_then(|feed_info| {
+            Some(Organization {
+                name: string_at(&feed_info, "feed_publisher_name", 0)?,
+                url: string_at(&feed_info, "feed_publisher_url", 0),
+                roles: vec!["publisher".into()],
+            })
+        });
+
+    let mut organizations: Vec<Organization> = vec![];
+    if let Some(attributions) = read_optional_file(&mut zip_archive, "attributions.txt")? {
+        // Attributions may be given per agency, route or trip, so the same organization is usually
+        // named several times
+        for row in 0..attributions.height() {
+            let Some(name) = string_at(&attributions, "organization_name", row) else { continue };
+            let roles = ["producer", "operator", "authority"].into_iter()
+                .filter(|role| string_at(&attributions, &format!("is_{role}"), row).as_deref() == Some("1"))
+                .map(str::to_string);
+
+            match organizations.iter_mut().find(|organization| organization.name == name) {
+                Some(organization) => {
+                    for role in roles {
+                        if !organization.roles.contains(&role) {
+                            organization.roles.push(role);
+                        }
+                    }
+                    organization.url = organization.url.take().or_else(|| string_at(&attributions, "attribution_url", row));
+                }
+                None => organizations.push(Organization {
+                    name,
+                    url: string_at(&attributions, "attribution_url", row),
+                    roles: roles.collect(),
+                }),
+            }
+        }
+    }
+
+    Ok(DatasetAttribution::new(dataset, publisher, organizations))
+}
+
+/// Reads a small file of the feed with all columns as strings, `None` if the feed doesn't have it
+fn read_optional_file(zip_archive: &mut ZipArchive<&mut File>, filename: &str) -> Result<Option<DataFrame>, ImportError> {
+    let mut file = match zip_archive.by_name(filename) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+
+    let df = CsvReadOptions::default()
+        .with_infer_schema_length(Some(0))
+        .into_reader_with_file_handle(Cursor::new(bytes))
+        .finish()?;
+    Ok(Some(df))
+}
+
+/// A non-empty value of an optional column
+fn string_at(df: &DataFrame, column: &str, row: usize) -> Option<String> {
+    let value = df.column(column).ok()?.str().ok()?.get(row)?.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
@@ -1,7 +1,8 @@
 mod gtfs;
 
 use crate::step1_fetch::FetchStepOutput;
-use crate::step2_import::gtfs::import_gtfs;
+use crate::step2_import::gtfs::{import_gtfs, read_gtfs_attribution};
+use common::types::attribution::DatasetAttribution;
 use common::types::config::dataset::{Dataset, DatasetFormat};
 use polars::prelude::LazyFrame;
 use std::fmt::Display;
@@ -22,6 +23,16 @@ pub async fn import_data<'a>(
     }
 }
 
+/// Reads the license and attribution of a fetched dataset. These are kept separately from the
+/// timetable, since they are needed for every response that uses the dataset.
+pub fn read_attribution(fetched: &FetchStepOutput) -> Result<DatasetAttribution, ImportError> {
+    match fetched.dataset.format {
+        DatasetFormat::Gtfs => read_gtfs_attribution(&fetched.path, fetched.dataset),
+        // Realtime feeds don't name their publisher
+        DatasetFormat::GtfsRt => Ok(DatasetAttribution::new(fetched.dataset, None, vec![])),
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ImportError {
     Zip(#[from] zip::result::ZipError),
@@ -52,7 +63,9 @@ pub struct ImportStepOutput<'a> {
 #[derive(Clone)]
 pub enum ImportStepExtra {
     Gtfs {
+        agency: LazyFrame,
         calendar: LazyFrame,
+        routes: LazyFrame,
         stops: LazyFrame,
         trips: LazyFrame,
         stop_times: LazyFrame,
@@ -0,0 +1,331 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use common::types::config::features::ValidationConfig;
+use polars::datatypes::DataType;
+use polars::error::PolarsResult;
+use polars::prelude::{col, len, lit, Expr, IdxSize, JoinArgs, JoinType, LazyFrame, SortMultipleOptions};
+use crate::step2_import::ImportStepExtra;
+use crate::step3_validate::{Finding, Rule, SampleRow, Severity, MAX_ROWS_PER_FINDING, MAX_SAMPLES_PER_FINDING};
+
+/// Name of the column that holds the index of a row in its file
+pub(crate) const ROW: &str = "row";
+
+pub(crate) fn validate_gtfs(
+    extra: &ImportStepExtra,
+    config: &ValidationConfig,
+) -> PolarsResult<Vec<Finding>> {
+    let ImportStepExtra::Gtfs { agency, calendar, routes, stops, trips, stop_times, .. } = extra;
+
+    let agency = with_row_index(agency.clone())?;
+    let calendar = with_row_index(calendar.clone())?;
+    let routes = with_row_index(routes.clone())?;
+    let stops = with_row_index(stops.clone())?;
+    let trips = with_row_index(trips.clone())?;
+    let stop_times = with_row_index(stop_times.clone())?;
+
+    // Each check consists of the offending rows and a description of what is wrong with them
+    let mut checks: Vec<(Rule, Severity, &str, &str, LazyFrame)> = vec![];
+
+    if config.referential_integrity {
+        checks.extend([
+            (Rule::ReferentialIntegrity, Severity::Error, "stop_times.txt", "Trip does not exist",
+                missing_references(&stop_times, "trip_id", &trips, "trip_id")),
+            (Rule::ReferentialIntegrity, Severity::Error, "stop_times.txt", "Stop does not exist",
+                missing_references(&stop_times, "stop_id", &stops, "stop_id")),
+            (Rule::ReferentialIntegrity, Severity::Error, "trips.txt", "Route does not exist",
+                missing_references(&trips, "route_id", &routes, "route_id")),
+            (Rule::ReferentialIntegrity, Severity::Error, "routes.txt", "Agency does not exist",
+                missing_references(&routes, "agency_id", &agency, "agency_id")),
+        ]);
+    }
+
+    if config.time_monotonicity {
+        let stop_times = stop_times.clone()
+            .sort(["trip_id", "stop_sequence"], SortMultipleOptions::default())
+            .with_column(
+                col("arrival_time").shift(lit(-1)).over([col("trip_id")]).alias("next_arrival_time")
+            );
+
+        checks.extend([
+            // Times of intermediate stops may be missing in GTFS, but they are needed for routing
+            (Rule::TimeMonotonicity, Severity::Error, "stop_times.txt", "Time is missing",
+                stop_times.clone().filter(col("arrival_time").is_null().or(col("departure_time").is_null()))),
+            (Rule::TimeMonotonicity, Severity::Error, "stop_times.txt", "Departure is before arrival",
+                stop_times.clone().filter(col("departure_time").lt(col("arrival_time")))),
+            (Rule::TimeMonotonicity, Severity::Error, "stop_times.txt", "Departure is after arrival at the next stop",
+                stop_times.filter(col("departure_time").gt(col("next_arrival_time")))),
+        ]);
+    }
+
+    if config.coordinate_sanity {
+        let in_range = |column: &str, limit: f32| {
+            col(column).gt_eq(lit(-limit)).and(col(column).lt_eq(lit(limit)))
+        };
+        // Polars orders NaN above all numbers, so it is out of range as well
+        let out_of_range = in_range("stop_lat", 90.0).and(in_range("stop_lon", 180.0)).not();
+        let null_island = col("stop_lat").eq(lit(0.0)).and(col("stop_lon").eq(lit(0.0)));
+
+        checks.extend([
+            (Rule::CoordinateSanity, Severity::Error, "stops.txt", "Coordinates are out of range",
+                stops.clone().filter(out_of_range)),
+            (Rule::CoordinateSanity, Severity::Warning, "stops.txt", "Coordinates are (0, 0)",
+                stops.clone().filter(null_island)),
+        ]);
+    }
+
+    if config.duplicate_ids {
+        checks.extend([
+            (Rule::DuplicateIds, Severity::Error, "agency.txt", "Duplicate agency_id",
+                duplicates(&agency, &["agency_id"])),
+            (Rule::DuplicateIds, Severity::Error, "calendar.txt", "Duplicate service_id",
+                duplicates(&calendar, &["service_id"])),
+            (Rule::DuplicateIds, Severity::Error, "routes.txt", "Duplicate route_id",
+                duplicates(&routes, &["route_id"])),
+            (Rule::DuplicateIds, Severity::Error, "stops.txt", "Duplicate stop_id",
+                duplicates(&stops, &["stop_id"])),
+            (Rule::DuplicateIds, Severity::Error, "trips.txt", "Duplicate trip_id",
+                duplicates(&trips, &["trip_id"])),
+            (Rule::DuplicateIds, Severity::Error, "stop_times.txt", "Duplicate stop_sequence within trip",
+                duplicates(&stop_times, &["trip_id", "stop_sequence"])),
+        ]);
+    }
+
+    if config.calendar_coverage {
+        let today = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() / (24 * 60 * 60);
+        let weekdays = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+        let runs_on_no_weekday = weekdays.iter()
+            .map(|weekday| col(*weekday))
+            .reduce(|a, b| a.or(b))
+            .unwrap()
+            .not();
+
+        checks.extend([
+            (Rule::CalendarCoverage, Severity::Error, "calendar.txt", "Service ends before it starts",
+                calendar.clone().filter(col("start_date").gt(col("end_date")))),
+            (Rule::CalendarCoverage, Severity::Warning, "calendar.txt", "Service runs on no weekday",
+                calendar.clone().filter(runs_on_no_weekday)),
+            (Rule::CalendarCoverage, Severity::Warning, "calendar.txt", "Service has already ended",
+                calendar.clone().filter(col("end_date").lt(lit(today as i32).cast(DataType::Date)))),
+            // Services may also be defined in calendar_dates.txt, which is not imported
+            (Rule::CalendarCoverage, Severity::Warning, "trips.txt", "Service is not in calendar.txt",
+                missing_references(&trips, "service_id", &calendar, "service_id")),
+        ]);
+    }
+
+    checks.into_iter()
+        .filter_map(|(rule, severity, file, message, offending)| {
+            finding(rule, severity, file, message, offending).transpose()
+        })
+        .collect()
+}
+
+/// Adds the index of each row in its file, unless the frame already has it. This is the case
+/// for repaired frames, so that findings refer to the rows of the original file.
+pub(crate) fn with_row_index(mut frame: LazyFrame) -> PolarsResult<LazyFrame> {
+    if frame.collect_schema()?.contains(ROW) {
+        Ok(frame)
+    } else {
+        Ok(frame.with_row_index(ROW, None))
+    }
+}
+
+/// Number of rows in `frame` and the first [`MAX_ROWS_PER_FINDING`] of their indices
+pub(crate) fn collect_rows(frame: LazyFrame) -> PolarsResult<(usize, Vec<IdxSize>)> {
+    let rows = frame
+        .select([col(ROW)])
+        .sort([ROW], SortMultipleOptions::default())
+        .collect()?;
+    let rows = rows.column(ROW)?.idx()?;
+
+    Ok((rows.len(), rows.into_no_null_iter().take(MAX_ROWS_PER_FINDING).collect()))
+}
+
+/// Contents of the first offending rows in `frame`
+fn collect_samples(file: &str, frame: LazyFrame) -> PolarsResult<Vec<SampleRow>> {
+    let samples = frame
+        .sort([ROW], SortMultipleOptions::default())
+        .limit(MAX_SAMPLES_PER_FINDING as IdxSize)
+        .collect()?;
+    let rows = samples.column(ROW)?.idx()?;
+
+    let samples = rows.into_no_null_iter()
+        .enumerate()
+        .map(|(idx, row)| {
+            let values = samples.get_columns().iter()
+                .filter(|column| column.name() != ROW)
+                .map(|column| {
+                    let value = column.get(idx)?;
+                    let value = value.get_str().map(String::from).unwrap_or_else(|| value.to_string());
+                    Ok((column.name().to_string(), value))
+                })
+                .collect::<PolarsResult<_>>()?;
+
+            // Lines start at 1 and the first one is the header
+            Ok(SampleRow { location: format!("{file}:{}", row + 2), values })
+        })
+        .collect::<PolarsResult<_>>()?;
+
+    Ok(samples)
+}
+
+/// Creates a finding from the offending rows, `None` if there aren't any
+fn finding(
+    rule: Rule,
+    severity: Severity,
+    file: &str,
+    message: &str,
+    offending: LazyFrame,
+) -> PolarsResult<Option<Finding>> {
+    let (count, rows) = collect_rows(offending.clone())?;
+
+    if count == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(Finding {
+        rule,
+        severity,
+        file: file.to_string(),
+        message: message.to_string(),
+        count,
+        rows,
+        samples: collect_samples(file, offending)?,
+    }))
+}
+
+/// Rows of `frame` whose `key` is set, but not contained in `referenced_key` of `referenced`
+fn missing_references(
+    frame: &LazyFrame,
+    key: &str,
+    referenced: &LazyFrame,
+    referenced_key: &str,
+) -> LazyFrame {
+    // IDs are compared as strings, since the CSV reader might infer a number type for some files
+    frame.clone()
+        .filter(col(key).is_not_null())
+        .join(
+            referenced.clone().select([col(referenced_key)]),
+            [col(key).cast(DataType::String)],
+            [col(referenced_key).cast(DataType::String)],
+            JoinArgs::new(JoinType::Anti),
+        )
+}
+
+/// Rows of `frame` that have the same `keys` as another row. Rows without keys are ignored.
+fn duplicates(frame: &LazyFrame, keys: &[&str]) -> LazyFrame {
+    let keys: Vec<Expr> = keys.iter().map(|key| col(*key)).collect();
+    let has_keys = keys.iter()
+        .map(|key| key.clone().is_not_null())
+        .reduce(|a, b| a.and(b))
+        .unwrap();
+    let frame = frame.clone().filter(has_keys);
+
+    let duplicated_keys = frame.clone()
+        .group_by(keys.clone())
+        .agg([len().alias("count")])
+        .filter(col("count").gt(lit(1)))
+        .select(keys.clone());
+
+    frame.join(duplicated_keys, keys.clone(), keys, JoinArgs::new(JoinType::Semi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+    use polars::prelude::{DataFrame, IntoLazy, TimeUnit};
+
+    fn frame(frame: PolarsResult<DataFrame>) -> LazyFrame {
+        frame.unwrap().lazy()
+    }
+
+    fn dataset() -> ImportStepExtra {
+        let hours = |column: &str| (col(column) * lit(60 * 60 * 1000i64)).cast(DataType::Duration(TimeUnit::Milliseconds));
+
+        ImportStepExtra::Gtfs {
+            agency: frame(df!(
+                "agency_id" => [Some("a")],
+                "agency_timezone" => ["Europe/Berlin"],
+            )),
+            calendar: frame(df!(
+                "service_id" => ["s1", "s2"],
+                "monday" => [true, false],
+                "tuesday" => [true, false],
+                "wednesday" => [true, false],
+                "thursday" => [true, false],
+                "friday" => [true, false],
+                "saturday" => [false, false],
+                "sunday" => [false, false],
+                "start_date" => [0i32, 0],
+                "end_date" => [100_000i32, 100_000],
+            )).with_columns([col("start_date").cast(DataType::Date), col("end_date").cast(DataType::Date)]),
+            routes: frame(df!(
+                "route_id" => ["r1"],
+                "route_type" => [3u32],
+                "agency_id" => [Some("a")],
+            )),
+            stops: frame(df!(
+                "stop_id" => ["A", "B", "A"],
+                "stop_lat" => [48.78f32, 0.0, 48.77],
+                "stop_lon" => [9.18f32, 0.0, 9.17],
+            )),
+            trips: frame(df!(
+                "route_id" => ["r1", "r2"],
+                "service_id" => ["s1", "s1"],
+                "trip_id" => ["t1", "t2"],
+            )),
+            stop_times: frame(df!(
+                "trip_id" => ["t1", "t1", "t1", "t3"],
+                "stop_id" => ["A", "B", "C", "A"],
+                "arrival_hour" => [8i64, 9, 8, 8],
+                "departure_hour" => [8i64, 9, 8, 8],
+                "stop_sequence" => [1u32, 2, 3, 1],
+            )).select([
+                col("trip_id"),
+                col("stop_id"),
+                hours("arrival_hour").alias("arrival_time"),
+                hours("departure_hour").alias("departure_time"),
+                col("stop_sequence"),
+            ]),
+            temporary_files: vec![],
+        }
+    }
+
+    fn messages(findings: &[Finding]) -> Vec<(&str, usize, Vec<IdxSize>)> {
+        findings.iter()
+            .map(|finding| (finding.message.as_str(), finding.count, finding.rows.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_gtfs() {
+        let findings = validate_gtfs(&dataset(), &ValidationConfig::default()).unwrap();
+
+        assert_eq!(messages(&findings), vec![
+            ("Trip does not exist", 1, vec![3]),
+            ("Stop does not exist", 1, vec![2]),
+            ("Route does not exist", 1, vec![1]),
+            ("Departure is after arrival at the next stop", 1, vec![1]),
+            ("Coordinates are (0, 0)", 1, vec![1]),
+            ("Duplicate stop_id", 2, vec![0, 2]),
+            ("Service runs on no weekday", 1, vec![1]),
+        ]);
+        assert_eq!(findings[4].severity, Severity::Warning);
+        assert_eq!(findings[5].samples[1].location, "stops.txt:4");
+        assert_eq!(findings[5].samples[1].values["stop_id"], "A");
+    }
+
+    #[test]
+    fn test_rule_toggles() {
+        let config = ValidationConfig {
+            referential_integrity: false,
+            duplicate_ids: false,
+            ..Default::default()
+        };
+        let findings = validate_gtfs(&dataset(), &config).unwrap();
+
+        assert!(findings.iter().all(|finding| {
+            finding.rule != Rule::ReferentialIntegrity && finding.rule != Rule::DuplicateIds
+        }));
+        assert_eq!(findings.len(), 3);
+    }
+}
@@ -0,0 +1,160 @@
+mod gtfs;
+pub mod repair;
+pub mod report;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use common::types::config::dataset::{Dataset, DatasetFormat};
+use common::types::config::features::ValidationConfig;
+use itertools::Itertools;
+use log::{debug, error, info, warn};
+use polars::error::PolarsError;
+use polars::prelude::IdxSize;
+use serde::{Deserialize, Serialize};
+use crate::step2_import::{ImportStepExtra, ImportStepOutput};
+use crate::step3_validate::gtfs::validate_gtfs;
+use crate::step3_validate::repair::Repair;
+
+/// Checks the imported data for rule violations. Datasets with errors are skipped, warnings are
+/// only reported. If `config` is `None`, validation is disabled.
+pub async fn validate_data<'a>(
+    ImportStepOutput { dataset, extra }: ImportStepOutput<'a>,
+    config: Option<&ValidationConfig>,
+) -> Result<ValidateStepOutput<'a>, ValidateError> {
+    let Some(config) = config else {
+        debug!(target: "validation", "Validation is disabled, skipping it for dataset '{}'", dataset.id);
+        return Ok(ValidateStepOutput { dataset, extra, skip: false, findings: vec![], repairs: vec![] });
+    };
+
+    let findings = match (&dataset.format, &extra) {
+        (DatasetFormat::Gtfs, ImportStepExtra::Gtfs { .. }) => validate_gtfs(&extra, config)?,
+        _ => return Err(ValidateError::UnknownFormat),
+    };
+
+    for finding in &findings {
+        match finding.severity {
+            Severity::Error => error!(target: "validation", "Dataset '{}': {finding}", dataset.id),
+            Severity::Warning => warn!(target: "validation", "Dataset '{}': {finding}", dataset.id),
+        }
+    }
+
+    let skip = findings.iter().any(|finding| finding.severity == Severity::Error);
+    if skip {
+        warn!(target: "validation", "Skipping dataset '{}' because of validation errors", dataset.id);
+    } else {
+        info!(target: "validation", "Dataset '{}' is valid ({} warnings)", dataset.id, findings.len());
+    }
+
+    Ok(ValidateStepOutput {
+        dataset,
+        extra,
+        skip,
+        findings,
+        repairs: vec![],
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The dataset can't be used
+    Error,
+    /// The dataset can be used, but some of its data might be wrong
+    Warning,
+}
+
+/// The rules of [`ValidationConfig`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    ReferentialIntegrity,
+    TimeMonotonicity,
+    CoordinateSanity,
+    DuplicateIds,
+    CalendarCoverage,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rule::ReferentialIntegrity => "referential_integrity",
+            Rule::TimeMonotonicity => "time_monotonicity",
+            Rule::CoordinateSanity => "coordinate_sanity",
+            Rule::DuplicateIds => "duplicate_ids",
+            Rule::CalendarCoverage => "calendar_coverage",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A violation of a rule by some rows of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// File that contains the offending rows, e.g. `stop_times.txt`
+    pub file: String,
+    pub message: String,
+    /// Number of offending rows
+    pub count: usize,
+    /// Indices of the first [`MAX_ROWS_PER_FINDING`] offending rows. The first row after the
+    /// header has index 0.
+    pub rows: Vec<IdxSize>,
+    /// Contents of the first [`MAX_SAMPLES_PER_FINDING`] offending rows
+    pub samples: Vec<SampleRow>,
+}
+
+pub const MAX_ROWS_PER_FINDING: usize = 100;
+pub const MAX_SAMPLES_PER_FINDING: usize = 5;
+
+/// An offending row, as it was imported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleRow {
+    /// File and line of the row, e.g. `stop_times.txt:12`. The header is line 1.
+    pub location: String,
+    /// Imported columns of the row by name
+    pub values: BTreeMap<String, String>,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        let rows = self.rows.iter().take(5).join(", ");
+        write!(
+            f, "{severity} in {} ({}): {} in {} rows (e.g. rows {rows})",
+            self.file, self.rule, self.message, self.count
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ValidateError {
+    UnknownFormat,
+    Polars(#[from] PolarsError),
+    IO(#[from] std::io::Error),
+    Report(#[from] serde_json::Error),
+}
+
+impl Display for ValidateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidateError::UnknownFormat => write!(f, "Unknown format"),
+            ValidateError::Polars(err) => write!(f, "{err}"),
+            ValidateError::IO(err) => write!(f, "IO error: {err}"),
+            ValidateError::Report(err) => write!(f, "Unable to write report: {err}"),
+        }
+    }
+}
+
+pub struct ValidateStepOutput<'a> {
+    pub(crate) dataset: &'a Dataset,
+    pub extra: ImportStepExtra,
+    pub(crate) skip: bool,
+    pub findings: Vec<Finding>,
+    /// Filled by [`repair::repair_data`]
+    pub repairs: Vec<Repair>,
+}
@@ -0,0 +1,390 @@
+use std::fmt;
+use std::fmt::Display;
+use common::types::config::features::{RepairConfig, ValidationConfig};
+use log::{debug, info, warn};
+use polars::datatypes::DataType;
+use polars::error::PolarsResult;
+use polars::prelude::{col, len, lit, when, Expr, IdxSize, InterpolationMethod, IntoLazy, JoinArgs, JoinType, LazyFrame, SortMultipleOptions, TimeUnit, UniqueKeepStrategy};
+use serde::{Deserialize, Serialize};
+use crate::step2_import::ImportStepExtra;
+use crate::step3_validate::gtfs::{collect_rows, validate_gtfs, with_row_index, ROW};
+use crate::step3_validate::{Severity, ValidateError, ValidateStepOutput};
+
+/// Fixes common problems of a dataset, so that it doesn't have to be skipped because of them.
+/// Afterward, the dataset is validated again. If `config` is `None`, repairing is disabled.
+pub async fn repair_data<'a>(
+    validated: ValidateStepOutput<'a>,
+    config: Option<&RepairConfig>,
+    validation: Option<&ValidationConfig>,
+) -> Result<ValidateStepOutput<'a>, ValidateError> {
+    let Some(config) = config else {
+        debug!(target: "repair", "Repairing is disabled, skipping it for dataset '{}'", validated.dataset.id);
+        return Ok(validated);
+    };
+
+    let ValidateStepOutput { dataset, extra, skip, findings, .. } = validated;
+    let (extra, repairs) = repair_gtfs(extra, config)?;
+
+    for repair in &repairs {
+        info!(target: "repair", "Dataset '{}': {repair}", dataset.id);
+    }
+
+    let (skip, findings) = match validation {
+        Some(validation) if !repairs.is_empty() => {
+            let findings = validate_gtfs(&extra, validation)?;
+            let skip = findings.iter().any(|finding| finding.severity == Severity::Error);
+            if skip {
+                warn!(target: "repair", "Dataset '{}' still has validation errors after repairing it", dataset.id);
+            }
+            (skip, findings)
+        }
+        _ => (skip, findings),
+    };
+
+    // The row indices were only needed to refer to the original rows
+    let ImportStepExtra::Gtfs { agency, calendar, routes, stops, trips, stop_times, temporary_files } = extra;
+    let extra = ImportStepExtra::Gtfs {
+        agency,
+        calendar,
+        routes,
+        stops: stops.drop([ROW]),
+        trips: trips.drop([ROW]),
+        stop_times: stop_times.drop([ROW]),
+        temporary_files,
+    };
+
+    Ok(ValidateStepOutput {
+        dataset,
+        extra,
+        skip,
+        findings,
+        repairs,
+    })
+}
+
+/// The repairs of [`RepairConfig`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairKind {
+    DropOrphanStopTimes,
+    MergeDuplicateStops,
+    InterpolateMissingTimes,
+    FixNonIncreasingTimes,
+    RemoveShortTrips,
+}
+
+/// Changes to some rows of a file by a repair
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repair {
+    pub kind: RepairKind,
+    /// File that contains the changed rows, e.g. `stop_times.txt`
+    pub file: String,
+    pub message: String,
+    /// Number of changed rows
+    pub count: usize,
+    /// Indices of the first changed rows in the original file
+    pub rows: Vec<IdxSize>,
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {} rows of {}", self.message, self.count, self.file)
+    }
+}
+
+fn repair_gtfs(
+    extra: ImportStepExtra,
+    config: &RepairConfig,
+) -> PolarsResult<(ImportStepExtra, Vec<Repair>)> {
+    let ImportStepExtra::Gtfs { agency, calendar, routes, stops, trips, stop_times, temporary_files } = extra;
+
+    // The row indices are kept, so that repairs and findings refer to the rows of the files
+    let mut stops = with_row_index(stops)?;
+    let mut trips = with_row_index(trips)?;
+    let mut stop_times = with_row_index(stop_times)?;
+    let mut repairs = vec![];
+
+    if config.drop_orphan_stop_times {
+        let repaired = stop_times.clone()
+            .join(trips.clone().select([col("trip_id")]), [col("trip_id").cast(DataType::String)], [col("trip_id").cast(DataType::String)], JoinArgs::new(JoinType::Semi))
+            .join(stops.clone().select([col("stop_id")]), [col("stop_id").cast(DataType::String)], [col("stop_id").cast(DataType::String)], JoinArgs::new(JoinType::Semi))
+            .collect()?.lazy();
+
+        repairs.push(repair(
+            RepairKind::DropOrphanStopTimes, "stop_times.txt", "Removed stop times of missing trips or stops",
+            removed_rows(&stop_times, &repaired),
+        )?);
+        stop_times = repaired;
+    }
+
+    if config.merge_duplicate_stops {
+        // Stops are equivalent if they have the same name, are at the same location and have the
+        // same properties. All of them are merged into the first one. Stations often share the
+        // location of one of their platforms, but they are never equivalent to it. Different stops
+        // can share a location (e.g. platforms without their own coordinates), so stops without a
+        // name are never merged.
+        let equivalent = [
+            col("stop_name"), col("stop_lat"), col("stop_lon"), col("wheelchair_boarding"),
+            col("stop_timezone"), col("location_type"), col("parent_station"),
+        ];
+        let stops_with_merged_ids = stops.clone()
+            .with_column(
+                when(col("stop_name").is_not_null())
+                    .then(col("stop_id").first().over(equivalent))
+                    .otherwise(col("stop_id"))
+                    .alias("merged_stop_id")
+            );
+
+        let repaired_stops = stops_with_merged_ids.clone()
+            .filter(col("stop_id").eq(col("merged_stop_id")))
+            .unique_stable(Some(vec!["stop_id".into()]), UniqueKeepStrategy::First)
+            .drop(["merged_stop_id"])
+            .collect()?.lazy();
+        let merged_ids = stops_with_merged_ids
+            .select([col("stop_id"), col("merged_stop_id")])
+            .unique_stable(Some(vec!["stop_id".into()]), UniqueKeepStrategy::First);
+
+        let repaired_stop_times = stop_times.clone()
+            .with_column(col("stop_id").cast(DataType::String))
+            .join(merged_ids, [col("stop_id")], [col("stop_id")], JoinArgs::new(JoinType::Left))
+            .with_column(col("merged_stop_id").fill_null(col("stop_id")).alias("stop_id"))
+            .drop(["merged_stop_id"])
+            .collect()?.lazy();
+
+        repairs.push(repair(
+            RepairKind::MergeDuplicateStops, "stops.txt", "Merged duplicate stops",
+            removed_rows(&stops, &repaired_stops),
+        )?);
+        stops = repaired_stops;
+        stop_times = repaired_stop_times;
+    }
+
+    stop_times = stop_times.sort(["trip_id", "stop_sequence"], SortMultipleOptions::default());
+
+    if config.interpolate_missing_times {
+        let missing = col("arrival_time").is_null().or(col("departure_time").is_null());
+        let interpolated = |column: &str| {
+            col(column).cast(DataType::Int64)
+                .interpolate(InterpolationMethod::Linear)
+                .over([col("trip_id")])
+                .cast(DataType::Int64)
+                .cast(DataType::Duration(TimeUnit::Milliseconds))
+        };
+
+        let repaired = stop_times.clone()
+            .with_column(missing.alias("missing_time"))
+            // Stops with only one of both times are treated as if arrival and departure were equal
+            .with_columns([
+                col("arrival_time").fill_null(col("departure_time")),
+                col("departure_time").fill_null(col("arrival_time")),
+            ])
+            .with_columns([interpolated("arrival_time"), interpolated("departure_time")])
+            .collect()?.lazy();
+
+        // Times of the first and last stop of a trip can't be interpolated
+        let interpolated_rows = repaired.clone().filter(
+            col("missing_time")
+                .and(col("arrival_time").is_not_null())
+                .and(col("departure_time").is_not_null())
+        );
+        repairs.push(repair(
+            RepairKind::InterpolateMissingTimes, "stop_times.txt", "Interpolated missing times",
+            interpolated_rows,
+        )?);
+        stop_times = repaired.drop(["missing_time"]);
+    }
+
+    if config.fix_non_increasing_times {
+        // Latest time at any previous stop of the trip
+        let previous_max = max(col("arrival_time"), col("departure_time"))
+            .shift(lit(1))
+            .cum_max(false)
+            .over([col("trip_id")]);
+
+        let repaired = stop_times.clone()
+            .with_column(max(col("arrival_time"), previous_max).alias("fixed_arrival_time"))
+            .with_column(max(col("departure_time"), col("fixed_arrival_time")).alias("fixed_departure_time"))
+            .collect()?.lazy();
+
+        let fixed_rows = repaired.clone().filter(
+            col("fixed_arrival_time").neq(col("arrival_time"))
+                .or(col("fixed_departure_time").neq(col("departure_time")))
+        );
+        repairs.push(repair(
+            RepairKind::FixNonIncreasingTimes, "stop_times.txt", "Raised times that were earlier than at a previous stop",
+            fixed_rows,
+        )?);
+        stop_times = repaired
+            .drop(["arrival_time", "departure_time"])
+            .rename(["fixed_arrival_time", "fixed_departure_time"], ["arrival_time", "departure_time"], true);
+    }
+
+    if config.remove_short_trips {
+        let long_trips = stop_times.clone()
+            .group_by([col("trip_id")])
+            .agg([len().alias("num_stops")])
+            .filter(col("num_stops").gt_eq(lit(2)))
+            .select([col("trip_id")]);
+
+        let repaired_trips = trips.clone()
+            .join(long_trips.clone(), [col("trip_id")], [col("trip_id")], JoinArgs::new(JoinType::Semi))
+            .collect()?.lazy();
+        let repaired_stop_times = stop_times.clone()
+            .join(long_trips, [col("trip_id")], [col("trip_id")], JoinArgs::new(JoinType::Semi))
+            .collect()?.lazy();
+
+        repairs.push(repair(
+            RepairKind::RemoveShortTrips, "trips.txt", "Removed trips with fewer than two stops",
+            removed_rows(&trips, &repaired_trips),
+        )?);
+        trips = repaired_trips;
+        stop_times = repaired_stop_times;
+    }
+
+    let extra = ImportStepExtra::Gtfs {
+        agency,
+        calendar,
+        routes,
+        stops,
+        trips,
+        stop_times: stop_times.collect()?.lazy(),
+        temporary_files,
+    };
+
+    Ok((extra, repairs.into_iter().flatten().collect()))
+}
+
+/// The later of two times, ignoring missing times
+fn max(a: Expr, b: Expr) -> Expr {
+    when(b.clone().gt(a.clone())).then(b).otherwise(a)
+}
+
+/// Rows of `before` that are not in `after` anymore
+fn removed_rows(before: &LazyFrame, after: &LazyFrame) -> LazyFrame {
+    before.clone().join(
+        after.clone().select([col(ROW)]),
+        [col(ROW)],
+        [col(ROW)],
+        JoinArgs::new(JoinType::Anti),
+    )
+}
+
+/// Creates a repair from the changed rows, `None` if there aren't any
+fn repair(
+    kind: RepairKind,
+    file: &str,
+    message: &str,
+    changed: LazyFrame,
+) -> PolarsResult<Option<Repair>> {
+    let (count, rows) = collect_rows(changed)?;
+
+    if count == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(Repair {
+        kind,
+        file: file.to_string(),
+        message: message.to_string(),
+        count,
+        rows,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+    use polars::prelude::DataFrame;
+
+    fn frame(frame: PolarsResult<DataFrame>) -> LazyFrame {
+        frame.unwrap().lazy()
+    }
+
+    fn minutes(column: &str) -> Expr {
+        (col(column) * lit(60 * 1000i64)).cast(DataType::Duration(TimeUnit::Milliseconds))
+    }
+
+    fn dataset() -> ImportStepExtra {
+        ImportStepExtra::Gtfs {
+            agency: frame(df!("agency_id" => [Some("a")], "agency_timezone" => ["Europe/Berlin"])),
+            calendar: frame(df!("service_id" => ["s1"])),
+            routes: frame(df!("route_id" => ["r1"], "route_type" => [3u32], "agency_id" => [Some("a")])),
+            stops: frame(df!(
+                "stop_id" => ["A", "B", "C", "B2", "D", "D2"],
+                "stop_name" => [Some("A"), Some("B"), Some("C"), Some("B"), Some("D"), Some("Other")],
+                "stop_lat" => [48.1f32, 48.2, 48.3, 48.2, 48.4, 48.4],
+                "stop_lon" => [9.1f32, 9.2, 9.3, 9.2, 9.4, 9.4],
+                "wheelchair_boarding" => [0u32, 0, 0, 0, 0, 0],
+                "stop_timezone" => [None::<&str>, None, None, None, None, None],
+                "location_type" => [0u32, 0, 0, 0, 0, 0],
+                "parent_station" => [None::<&str>, None, None, None, None, None],
+            )),
+            trips: frame(df!(
+                "route_id" => ["r1", "r1", "r1"],
+                "service_id" => ["s1", "s1", "s1"],
+                "trip_id" => ["t1", "t2", "t3"],
+            )),
+            stop_times: frame(df!(
+                "trip_id" => ["t1", "t1", "t1", "t1", "t2", "t2", "t3"],
+                "stop_id" => ["A", "B2", "C", "B", "A", "X", "A"],
+                "arrival_minute" => [Some(0i64), None, Some(20), Some(15), Some(0), Some(5), Some(0)],
+                "departure_minute" => [Some(0i64), None, Some(20), Some(15), Some(0), Some(5), Some(0)],
+                "stop_sequence" => [1u32, 2, 3, 4, 1, 2, 1],
+            )).select([
+                col("trip_id"),
+                col("stop_id"),
+                minutes("arrival_minute").alias("arrival_time"),
+                minutes("departure_minute").alias("departure_time"),
+                col("stop_sequence"),
+            ]),
+            temporary_files: vec![],
+        }
+    }
+
+    #[test]
+    fn test_repair_gtfs() {
+        let (extra, repairs) = repair_gtfs(dataset(), &RepairConfig::default()).unwrap();
+
+        let changes: Vec<_> = repairs.iter()
+            .map(|repair| (repair.kind, repair.count, repair.rows.clone()))
+            .collect();
+        assert_eq!(changes, vec![
+            (RepairKind::DropOrphanStopTimes, 1, vec![5]),
+            (RepairKind::MergeDuplicateStops, 1, vec![3]),
+            (RepairKind::InterpolateMissingTimes, 1, vec![1]),
+            (RepairKind::FixNonIncreasingTimes, 1, vec![3]),
+            (RepairKind::RemoveShortTrips, 2, vec![1, 2]),
+        ]);
+
+        let ImportStepExtra::Gtfs { stop_times, .. } = extra;
+        let stop_times = stop_times
+            .select([
+                col("stop_id"),
+                (col("arrival_time").dt().total_minutes()).alias("arrival"),
+                (col("departure_time").dt().total_minutes()).alias("departure"),
+            ])
+            .collect()
+            .unwrap();
+
+        assert_eq!(stop_times, df!(
+            "stop_id" => ["A", "B", "C", "B"],
+            "arrival" => [0i64, 10, 20, 20],
+            "departure" => [0i64, 10, 20, 20],
+        ).unwrap());
+    }
+
+    #[test]
+    fn test_repair_toggles() {
+        let config = RepairConfig {
+            drop_orphan_stop_times: false,
+            remove_short_trips: false,
+            ..Default::default()
+        };
+        let (_, repairs) = repair_gtfs(dataset(), &config).unwrap();
+
+        assert!(repairs.iter().all(|repair| {
+            repair.kind != RepairKind::DropOrphanStopTimes && repair.kind != RepairKind::RemoveShortTrips
+        }));
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::step3_validate::repair::Repair;
+use crate::step3_validate::{Finding, Rule, Severity, ValidateError, ValidateStepOutput};
+
+/// Result of validating and repairing an import of a dataset. Reports are kept for every import,
+/// so that the quality of a feed can be followed over time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub dataset_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Whether the dataset was skipped because of errors
+    pub skipped: bool,
+    /// Number of offending rows by rule
+    pub counts: BTreeMap<Rule, RuleCounts>,
+    pub findings: Vec<Finding>,
+    pub repairs: Vec<Repair>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuleCounts {
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl ValidationReport {
+    pub fn new(output: &ValidateStepOutput) -> Self {
+        let mut counts: BTreeMap<Rule, RuleCounts> = BTreeMap::new();
+        for finding in &output.findings {
+            let rule_counts = counts.entry(finding.rule).or_default();
+            match finding.severity {
+                Severity::Error => rule_counts.errors += finding.count,
+                Severity::Warning => rule_counts.warnings += finding.count,
+            }
+        }
+
+        Self {
+            dataset_id: output.dataset.id.clone(),
+            created_at: Utc::now(),
+            skipped: output.skip,
+            counts,
+            findings: output.findings.clone(),
+            repairs: output.repairs.clone(),
+        }
+    }
+
+    /// Writes the report to `<data_dir>/datasets/<dataset_id>/validation`
+    pub fn save(&self, data_dir: &Path) -> Result<PathBuf, ValidateError> {
+        let reports_dir = data_dir.join("datasets").join(&self.dataset_id).join("validation");
+        self.save_to(&reports_dir)
+    }
+
+    /// Writes the report to `reports_dir`, named by the time it was created
+    pub fn save_to(&self, reports_dir: &Path) -> Result<PathBuf, ValidateError> {
+        create_dir_all(reports_dir)?;
+
+        let path = reports_dir.join(format!("{}.json", self.created_at.timestamp_millis()));
+        serde_json::to_writer_pretty(File::create(&path)?, self)?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_report() {
+        let finding = Finding {
+            rule: Rule::TimeMonotonicity,
+            severity: Severity::Error,
+            file: "stop_times.txt".into(),
+            message: "Time is missing".into(),
+            count: 3,
+            rows: vec![4, 8, 15],
+            samples: vec![],
+        };
+        let report = ValidationReport {
+            dataset_id: "de:vvs:gtfs".into(),
+            created_at: Utc::now(),
+            skipped: false,
+            counts: BTreeMap::from([(Rule::TimeMonotonicity, RuleCounts { errors: 3, warnings: 0 })]),
+            findings: vec![finding],
+            repairs: vec![],
+        };
+        let dir = TempDir::new().unwrap();
+
+        let path = report.save_to(dir.path()).unwrap();
+
+        let json: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(json["counts"]["time_monotonicity"]["errors"], 3);
+        assert_eq!(serde_json::from_value::<ValidationReport>(json).unwrap(), report);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::step2_import::ImportStepExtra;
 use crate::step3_validate::ValidateStepOutput;
-use polars::prelude::{lit, LazyFrame};
+use polars::prelude::{col, lit, LazyFrame};
 use std::fmt;
 use std::fmt::Display;
 
@@ -13,12 +13,21 @@ pub async fn merge<'a>(input: Vec<ValidateStepOutput<'a>>) -> Result<DatasetMerg
     let dataset_id = &first.dataset.id;
 
     match first.extra.clone() { ImportStepExtra::Gtfs {
-        calendar, stops, trips, stop_times, ..
+        agency, calendar, routes, stops, trips, stop_times, ..
     } => {
+        // GTFS requires all agencies of a feed to be in the same timezone
+        let timezone = agency.select([col("agency_timezone")]).first().collect()?
+            .column("agency_timezone")?.str()?
+            .get(0)
+            .ok_or(MergeError::MissingTimezone())?
+            .to_string();
+
         let services = calendar
             .with_columns([
                 lit(dataset_id.clone()).alias("dataset_id"),
+                lit(timezone).alias("timezone"),
             ]);
+        let routes = routes.with_column(lit(dataset_id.clone()).alias("dataset_id"));
         let stops = stops
             .with_columns([
                 lit(dataset_id.clone()).alias("dataset_id"),
@@ -27,14 +36,15 @@ pub async fn merge<'a>(input: Vec<ValidateStepOutput<'a>>) -> Result<DatasetMerg
         let stop_times = stop_times.with_column(lit(dataset_id.clone()).alias("dataset_id"));
 
         Ok(DatasetMergeOutput {
-            services, stops, trips, stop_times,
+            services, routes, stops, trips, stop_times,
             import_extra: first.extra
         })
     } }
 }
 
 pub struct DatasetMergeOutput {
-    pub services: LazyFrame, // corresponds to calendar.txt in GTFS
+    pub services: LazyFrame, // corresponds to calendar.txt in GTFS, plus the agency timezone
+    pub routes: LazyFrame,
     pub stops: LazyFrame,
     pub trips: LazyFrame,
     pub stop_times: LazyFrame,
@@ -45,6 +55,7 @@ pub struct DatasetMergeOutput {
 pub enum MergeError {
     Polars(#[from] polars::error::PolarsError),
     NoDatasets(),
+    MissingTimezone(),
 }
 
 impl Display for MergeError {
@@ -52,6 +63,7 @@ impl Display for MergeError {
         let err = match self {
             MergeError::Polars(err) => err.fmt(f),
             MergeError::NoDatasets { .. } => write!(f, "No datasets were provided"),
+            MergeError::MissingTimezone { .. } => write!(f, "No agency with a timezone was provided"),
         };
         err
     }
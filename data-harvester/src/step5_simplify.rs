@@ -1,12 +1,24 @@
+mod clip;
+mod stations;
+
 use crate::step4_merge::DatasetMergeOutput;
+use crate::step5_simplify::clip::{clip, Tables};
+use crate::step5_simplify::stations::group_into_stations;
+use chrono::Utc;
+use common::types::config::dataset::Dataset;
+use common::types::config::work_dir;
 use common::util::df::{write_df_to_file, FileType};
 use polars::frame::DataFrame;
-use polars::prelude::{col, Column, IntoLazy, JoinArgs, JoinType};
+use polars::prelude::{col, Column, IntoLazy, JoinArgs, JoinType, LazyFrame};
 use polars::series::Series;
 use std::fmt;
 use std::fmt::Display;
+use std::path::Path;
 use routing::algorithms::initialization::PreprocessingInput;
 
+/// Where the simplified tables are written to, relative to the work dir
+pub const SIMPLIFY_DIR: &str = "simplify";
+
 fn assign_new_ids(
     mut frame: DataFrame,
     name: &str
@@ -21,17 +33,30 @@ fn assign_new_ids(
     Ok(frame)
 }
 
+/// Clips the datasets to their configured areas and date windows, groups the stops into stations,
+/// assigns continuous IDs and writes the resulting tables to [`SIMPLIFY_DIR`] in the work dir of
+/// `data_dir`
 pub async fn simplify(
     DatasetMergeOutput {
         stops,
         trips,
         services,
+        routes,
         stop_times,
         ..
-    }: DatasetMergeOutput
+    }: DatasetMergeOutput,
+    datasets: &[Dataset],
+    data_dir: &Path,
 ) -> Result<PreprocessingInput, SimplifyError> {
+    let simplify_dir = work_dir(data_dir).join(SIMPLIFY_DIR);
+    let Tables { stops, trips, services, stop_times } =
+        clip(Tables { stops, trips, services, stop_times }, datasets, Utc::now().date_naive())?;
+
+    // Stations are not used in trips, so they have to be grouped before unused stops are removed
+    let (stops, stations) = group_into_stations(stops.collect()?)?;
+
     // Turn stop ids into integers
-    let stops = stops
+    let stops = stops.lazy()
         // Only include stops that are used in trips
         .semi_join(
             stop_times.clone(),
@@ -43,12 +68,33 @@ pub async fn simplify(
             col("dataset_id"),
             col("stop_lat").alias("lat"),
             col("stop_lon").alias("lon"),
+            col("wheelchair_boarding"),
+            col("stop_timezone"),
+            col("stop_name"),
+            col("station_key"),
         ]);
 
     // Generate a new stop_id
     let stops = assign_new_ids(stops.collect()?, "stop_id")?;
 
-    write_df_to_file("data/tmp/simplify/stops.parquet".into(), FileType::PARQUET, stops.clone())?;
+    // Only keep the stations that have got used stops, and give them continuous ids as well
+    let stations = stations.lazy()
+        .semi_join(stops.clone().lazy(), col("station_key"), col("station_key"))
+        .collect()?;
+    let stations = assign_new_ids(stations, "station_id")?;
+    let stops = stops.lazy()
+        .join(
+            stations.clone().lazy().select([col("station_key"), col("station_id")]),
+            [col("station_key")],
+            [col("station_key")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .drop(["station_key"])
+        .collect()?;
+
+    write_df_to_file(simplify_dir.join("stations.parquet"), FileType::PARQUET, stations.drop("station_key")?)?;
+
+    write_df_to_file(simplify_dir.join("stops.parquet"), FileType::PARQUET, stops.clone())?;
     let stops = stops.lazy();
 
     let trips = trips
@@ -57,11 +103,19 @@ pub async fn simplify(
             col("route_id").alias("route_id_in_dataset"),
             col("service_id").alias("service_id_in_dataset"),
             col("dataset_id"),
-        ]);
+            col("wheelchair_accessible"),
+        ])
+        // Add route information to the trips, so that routing can filter by them
+        .join(
+            routes.select([col("dataset_id"), col("route_id").alias("route_id_in_dataset"), col("route_type"), col("agency_id")]),
+            [col("dataset_id"), col("route_id_in_dataset")],
+            [col("dataset_id"), col("route_id_in_dataset")],
+            JoinArgs::new(JoinType::Left),
+        );
 
     let trips = assign_new_ids(trips.collect()?, "trip_id")?;
 
-    write_df_to_file("data/tmp/simplify/trips.parquet".into(), FileType::PARQUET, trips.clone())?;
+    write_df_to_file(simplify_dir.join("trips.parquet"), FileType::PARQUET, trips.clone())?;
     let trips = trips.lazy();
 
     let services = services
@@ -70,12 +124,13 @@ pub async fn simplify(
             col("service_id").alias("service_id_in_dataset"),
             col("monday"), col("tuesday"), col("wednesday"),
             col("thursday"), col("friday"), col("saturday"),
-            col("sunday"), col("start_date"), col("end_date")
+            col("sunday"), col("start_date"), col("end_date"),
+            col("timezone"),
         ]);
 
     let services = assign_new_ids(services.collect()?, "service_id")?;
 
-    write_df_to_file("data/tmp/simplify/services.parquet".into(), FileType::PARQUET, services.clone())?;
+    write_df_to_file(simplify_dir.join("services.parquet"), FileType::PARQUET, services.clone())?;
     let services = services.lazy();
 
     let stop_times = stop_times
@@ -102,8 +157,22 @@ pub async fn simplify(
             JoinArgs::new(JoinType::Inner),
         );
 
-    write_df_to_file("data/tmp/simplify/stop_times.parquet".into(), FileType::PARQUET, stop_times.clone().collect()?)?;
+    write_df_to_file(simplify_dir.join("stop_times.parquet"), FileType::PARQUET, stop_times.clone().collect()?)?;
+
+    Ok(finish(stops, trips, services, stop_times))
+}
 
+/// Reads the tables that [`simplify`] wrote to `dir`, e.g. to route on the data of an earlier
+/// preprocessing
+pub fn load(dir: &Path) -> Result<PreprocessingInput, SimplifyError> {
+    let read = |name: &str| LazyFrame::scan_parquet(dir.join(format!("{name}.parquet")), Default::default());
+
+    Ok(finish(read("stops")?, read("trips")?, read("services")?, read("stop_times")?))
+}
+
+/// Connects trips to their services and drops the ids of the datasets, which routing doesn't need.
+/// The written tables keep them, so that the entities can be traced back to their datasets.
+fn finish(stops: LazyFrame, trips: LazyFrame, services: LazyFrame, stop_times: LazyFrame) -> PreprocessingInput {
     let stop_times = stop_times.drop(["stop_id_in_dataset"])
         .drop(["dataset_id", "trip_id_in_dataset"]);
 
@@ -121,20 +190,21 @@ pub async fn simplify(
             "stop_id_in_dataset", "dataset_id"
         ]);
 
+    // The dataset of each trip is kept to attribute journeys to their datasets
     let trips = trips.drop([
-        "trip_id_in_dataset", "dataset_id"
+        "trip_id_in_dataset"
     ]);
 
     let services = services.drop([
         "service_id_in_dataset", "dataset_id"
     ]);
 
-    Ok(PreprocessingInput {
+    PreprocessingInput {
         services,
         stops,
         trips,
         stop_times,
-    })
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -0,0 +1,215 @@
+use crate::step5_simplify::SimplifyError;
+use chrono::{DateTime, NaiveDate};
+use common::types::config::dataset::{Dataset, DateWindow};
+use geo::{Intersects, Point};
+use itertools::izip;
+use log::debug;
+use polars::prelude::*;
+
+/// Tables of the merged datasets, as far as they are affected by clipping
+pub(super) struct Tables {
+    pub stops: LazyFrame,
+    pub trips: LazyFrame,
+    pub services: LazyFrame,
+    pub stop_times: LazyFrame,
+}
+
+/// Removes everything outside the areas and date windows that the datasets are clipped to.
+/// Datasets without a clip config are left as they are.
+pub(super) fn clip(tables: Tables, datasets: &[Dataset], today: NaiveDate) -> Result<Tables, SimplifyError> {
+    let tables = clip_dates(tables, datasets, today)?;
+    clip_areas(tables, datasets)
+}
+
+/// Matches the rows that belong to one of the datasets
+fn of_datasets<'a>(datasets: impl IntoIterator<Item = &'a Dataset>) -> Option<Expr> {
+    datasets.into_iter()
+        .map(|dataset| col("dataset_id").eq(lit(dataset.id.clone())))
+        .reduce(|a, b| a.or(b))
+}
+
+/// Days since the unix epoch, as polars stores dates
+fn date_lit(date: NaiveDate) -> Expr {
+    lit((date - DateTime::UNIX_EPOCH.date_naive()).num_days() as i32).cast(DataType::Date)
+}
+
+/// Shortens the services to the date windows. Services that don't run within their window and
+/// their trips are removed.
+fn clip_dates(Tables { stops, trips, services, stop_times }: Tables, datasets: &[Dataset], today: NaiveDate) -> Result<Tables, SimplifyError> {
+    let windows: Vec<(&Dataset, &DateWindow)> = datasets.iter()
+        .filter_map(|dataset| Some((dataset, dataset.clip.as_ref()?.dates.as_ref()?)))
+        .collect();
+    let Some(clipped) = of_datasets(windows.iter().map(|(dataset, _)| *dataset)) else {
+        return Ok(Tables { stops, trips, services, stop_times });
+    };
+
+    let mut services = services;
+    for (dataset, window) in &windows {
+        let (from, to) = window.resolve(today);
+        debug!(target: "simplify", "Clipping {} to the dates from {from:?} to {to:?}", dataset.id);
+
+        let of_dataset = col("dataset_id").eq(lit(dataset.id.clone()));
+        if let Some(from) = from {
+            services = services.with_column(
+                when(of_dataset.clone().and(col("start_date").lt(date_lit(from))))
+                    .then(date_lit(from))
+                    .otherwise(col("start_date"))
+                    .alias("start_date")
+            );
+        }
+        if let Some(to) = to {
+            services = services.with_column(
+                when(of_dataset.and(col("end_date").gt(date_lit(to))))
+                    .then(date_lit(to))
+                    .otherwise(col("end_date"))
+                    .alias("end_date")
+            );
+        }
+    }
+    // Only trips whose service was clipped away are removed. Trips of services that are only
+    // defined in calendar_dates.txt aren't in the services and are kept.
+    let outside = clipped.and(col("start_date").gt(col("end_date")));
+    let trips = trips.join(
+        services.clone().filter(outside.clone()).select([col("dataset_id"), col("service_id")]),
+        [col("dataset_id"), col("service_id")],
+        [col("dataset_id"), col("service_id")],
+        JoinArgs::new(JoinType::Anti),
+    );
+    let services = services.filter(outside.not());
+    let stop_times = stop_times.join(
+        trips.clone().select([col("dataset_id"), col("trip_id")]),
+        [col("dataset_id"), col("trip_id")],
+        [col("dataset_id"), col("trip_id")],
+        JoinArgs::new(JoinType::Semi),
+    );
+
+    Ok(Tables { stops, trips, services, stop_times })
+}
+
+/// Removes the stops outside the areas. Trips that leave an area keep their stops within it, trips
+/// with fewer than two of those are removed.
+fn clip_areas(Tables { stops, trips, services, stop_times }: Tables, datasets: &[Dataset]) -> Result<Tables, SimplifyError> {
+    let areas: Vec<(&Dataset, geo::Polygon<f64>)> = datasets.iter()
+        .filter_map(|dataset| Some((dataset, dataset.clip.as_ref()?.area.as_ref()?.to_polygon())))
+        .collect();
+    let Some(clipped) = of_datasets(areas.iter().map(|(dataset, _)| *dataset)) else {
+        return Ok(Tables { stops, trips, services, stop_times });
+    };
+
+    // Stops are few compared to stop times, so the coordinates are checked one by one
+    let stops = stops.collect()?;
+    let inside: BooleanChunked = izip!(
+        stops.column("dataset_id")?.str()?,
+        stops.column("stop_lat")?.f32()?,
+        stops.column("stop_lon")?.f32()?,
+    )
+        .map(|(dataset_id, lat, lon)| {
+            let Some((_, area)) = areas.iter().find(|(dataset, _)| Some(dataset.id.as_str()) == dataset_id) else {
+                return Some(true);
+            };
+            let (Some(lat), Some(lon)) = (lat, lon) else { return Some(false) };
+            Some(area.intersects(&Point::new(lon as f64, lat as f64)))
+        })
+        .collect();
+    let stops = stops.filter(&inside)?;
+    debug!(target: "simplify", "Clipping removed {} stops", inside.len() - stops.height());
+    let stops = stops.lazy();
+
+    // Stop times of trips that now only serve one stop can't be used for any ride
+    let stop_times = stop_times
+        .join(
+            stops.clone().select([col("dataset_id"), col("stop_id")]),
+            [col("dataset_id"), col("stop_id")],
+            [col("dataset_id"), col("stop_id")],
+            JoinArgs::new(JoinType::Semi),
+        )
+        .filter(clipped.not().or(len().over([col("dataset_id"), col("trip_id")]).gt_eq(lit(2))));
+    let trips = trips.join(
+        stop_times.clone().select([col("dataset_id"), col("trip_id")]).unique(None, UniqueKeepStrategy::First),
+        [col("dataset_id"), col("trip_id")],
+        [col("dataset_id"), col("trip_id")],
+        JoinArgs::new(JoinType::Semi),
+    );
+
+    Ok(Tables { stops, trips, services, stop_times })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::config::dataset::{Area, ClipConfig, DataSource, DatasetFormat};
+
+    fn dataset(clip: ClipConfig) -> Dataset {
+        Dataset {
+            id: "d".into(),
+            src: DataSource::File { path: "d.zip".into() },
+            format: DatasetFormat::Gtfs,
+            license: None,
+            attribution: None,
+            group_ids: vec![],
+            clip: Some(clip),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, day).unwrap()
+    }
+
+    fn tables() -> Tables {
+        Tables {
+            stops: df!(
+                "stop_id" => ["a", "b", "c"],
+                "dataset_id" => ["d", "d", "d"],
+                "stop_lat" => [48.5f32, 48.6, 52.5],
+                "stop_lon" => [9.0f32, 9.1, 13.4],
+            ).unwrap().lazy(),
+            trips: df!(
+                "trip_id" => ["inside", "leaving", "outside_dates", "exceptions_only"],
+                "service_id" => ["daily", "daily", "past", "holidays"],
+                "dataset_id" => ["d", "d", "d", "d"],
+            ).unwrap().lazy(),
+            services: df!(
+                "service_id" => ["daily", "past"],
+                "dataset_id" => ["d", "d"],
+                "start_date" => [date(1), date(1)],
+                "end_date" => [date(31), date(5)],
+            ).unwrap().lazy(),
+            stop_times: df!(
+                "trip_id" => ["inside", "inside", "leaving", "leaving", "outside_dates", "outside_dates", "exceptions_only", "exceptions_only"],
+                "stop_id" => ["a", "b", "b", "c", "a", "b", "a", "b"],
+                "dataset_id" => ["d", "d", "d", "d", "d", "d", "d", "d"],
+            ).unwrap().lazy(),
+        }
+    }
+
+    fn column(frame: LazyFrame, name: &str) -> Vec<String> {
+        let df = frame.sort([name], Default::default()).collect().unwrap();
+        df.column(name).unwrap().str().unwrap().into_no_null_iter().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_clip_area() {
+        let clip = ClipConfig { area: Some(Area::BoundingBox { bbox: [7.5, 47.5, 10.5, 49.8] }), dates: None };
+        let Tables { stops, trips, stop_times, .. } = clip_areas(tables(), &[dataset(clip)]).unwrap();
+
+        assert_eq!(column(stops, "stop_id"), ["a", "b"]);
+        // Only one stop of "leaving" is within the area, so it can't be ridden anymore
+        assert_eq!(column(trips, "trip_id"), ["exceptions_only", "inside", "outside_dates"]);
+        assert_eq!(column(stop_times.filter(col("trip_id").eq(lit("leaving"))), "stop_id"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_clip_dates() {
+        let clip = ClipConfig { area: None, dates: Some(DateWindow::Absolute { from: Some(date(10)), to: Some(date(20)) }) };
+        let Tables { trips, services, stop_times, .. } = clip_dates(tables(), &[dataset(clip)], date(1)).unwrap();
+
+        // "holidays" is only defined in calendar_dates.txt, so it can't be clipped
+        assert_eq!(column(trips, "trip_id"), ["exceptions_only", "inside", "leaving"]);
+        assert_eq!(column(stop_times, "trip_id"), ["exceptions_only", "exceptions_only", "inside", "inside", "leaving", "leaving"]);
+
+        let services = services.collect().unwrap();
+        assert_eq!(services.height(), 1);
+        assert_eq!(services.column("start_date").unwrap().date().unwrap().as_date_iter().next(), Some(Some(date(10))));
+        assert_eq!(services.column("end_date").unwrap().date().unwrap().as_date_iter().next(), Some(Some(date(20))));
+    }
+}
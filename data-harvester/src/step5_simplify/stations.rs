@@ -0,0 +1,215 @@
+use crate::step5_simplify::SimplifyError;
+use geo::{Distance, Haversine, Point};
+use std::collections::HashMap;
+use itertools::izip;
+use log::debug;
+use polars::prelude::*;
+
+/// Stops without a parent station are grouped with stations of the same name within this
+/// distance. In meters.
+const MAX_STATION_RADIUS: f64 = 300.0;
+
+/// Words that introduce the platform within a station, like in "Stuttgart Hbf Gleis 3" or
+/// "Central Station Bay B"
+const PLATFORM_WORDS: [&str; 12] = [
+    "gleis", "bahnsteig", "bstg", "steig", "bussteig", "platform", "track", "bay", "stand", "quai",
+    "voie", "binario",
+];
+
+const LOCATION_TYPE_STATION: u32 = 1;
+
+struct Station {
+    name: Option<String>,
+    lat: f64,
+    lon: f64,
+    /// Number of stops whose mean location this is. Stations of the feed keep their own location,
+    /// so they don't count any.
+    num_located_stops: u32,
+}
+
+impl Station {
+    fn location(&self) -> Point<f64> {
+        Point::new(self.lon, self.lat)
+    }
+
+    fn add_stop(&mut self, lat: f64, lon: f64) {
+        if self.num_located_stops == 0 {
+            return;
+        }
+        self.num_located_stops += 1;
+        self.lat += (lat - self.lat) / self.num_located_stops as f64;
+        self.lon += (lon - self.lon) / self.num_located_stops as f64;
+    }
+}
+
+/// Groups the stops into stations and adds the station of each stop in the column
+/// `station_key`. Platforms belong to their `parent_station`. Stops without one are grouped with
+/// stations of the same name nearby, which also merges stations of different datasets.
+///
+/// Returns the stops and the stations, with the columns `station_key`, `station_name`, `lat` and
+/// `lon`. Station keys are continuous, but stations without used stops aren't removed yet.
+pub(super) fn group_into_stations(stops: DataFrame) -> Result<(DataFrame, DataFrame), SimplifyError> {
+    let dataset_ids = stops.column("dataset_id")?.str()?;
+    let stop_ids = stops.column("stop_id")?.str()?;
+    let names = stops.column("stop_name")?.str()?;
+    let lats = stops.column("stop_lat")?.f32()?;
+    let lons = stops.column("stop_lon")?.f32()?;
+    let location_types = stops.column("location_type")?.u32()?;
+    let parent_stations = stops.column("parent_station")?.str()?;
+
+    let mut stations: Vec<Station> = vec![];
+    // Stations of the feeds, by dataset and stop id
+    let mut feed_stations: HashMap<(&str, &str), usize> = HashMap::new();
+    // Stations by their normalized name, to find the station of stops without a parent
+    let mut stations_by_name: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for (dataset_id, stop_id, name, lat, lon, location_type) in
+        izip!(dataset_ids, stop_ids, names, lats, lons, location_types)
+    {
+        let (Some(dataset_id), Some(stop_id)) = (dataset_id, stop_id) else { continue };
+        if location_type != Some(LOCATION_TYPE_STATION) {
+            continue;
+        }
+
+        let station_key = stations.len();
+        stations.push(Station {
+            name: name.map(String::from),
+            lat: lat.unwrap_or_default() as f64,
+            lon: lon.unwrap_or_default() as f64,
+            num_located_stops: 0,
+        });
+        feed_stations.insert((dataset_id, stop_id), station_key);
+        if let Some(name) = name.and_then(normalize_name) {
+            stations_by_name.entry(name).or_default().push(station_key);
+        }
+    }
+    let num_feed_stations = stations.len();
+
+    let mut station_keys: Vec<u32> = Vec::with_capacity(stops.height());
+    for (dataset_id, stop_id, name, lat, lon, parent_station) in
+        izip!(dataset_ids, stop_ids, names, lats, lons, parent_stations)
+    {
+        let own_station = dataset_id.zip(stop_id)
+            .and_then(|key| feed_stations.get(&key));
+        let parent_station = dataset_id.zip(parent_station)
+            .and_then(|key| feed_stations.get(&key));
+        if let Some(station_key) = own_station.or(parent_station) {
+            station_keys.push(*station_key as u32);
+            continue;
+        }
+
+        let lat = lat.unwrap_or_default() as f64;
+        let lon = lon.unwrap_or_default() as f64;
+        let location = Point::new(lon, lat);
+        let normalized_name = name.and_then(normalize_name);
+
+        let nearby_station = normalized_name.as_ref()
+            .and_then(|name| stations_by_name.get(name))
+            .and_then(|candidates| {
+                candidates.iter().copied()
+                    .map(|station_key| (station_key, Haversine::distance(stations[station_key].location(), location)))
+                    .filter(|(_, distance)| *distance <= MAX_STATION_RADIUS)
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            });
+
+        let station_key = match nearby_station {
+            Some((station_key, _)) => {
+                stations[station_key].add_stop(lat, lon);
+                station_key
+            }
+            None => {
+                let station_key = stations.len();
+                stations.push(Station {
+                    name: name.map(String::from),
+                    lat,
+                    lon,
+                    num_located_stops: 1,
+                });
+                if let Some(name) = normalized_name {
+                    stations_by_name.entry(name).or_default().push(station_key);
+                }
+                station_key
+            }
+        };
+        station_keys.push(station_key as u32);
+    }
+
+    debug!(
+        target: "simplify",
+        "Grouped {} stops into {} stations, {} of them from parent stations",
+        stops.height(), stations.len(), num_feed_stations
+    );
+
+    let mut stops = stops;
+    stops.with_column(Column::new("station_key".into(), station_keys))?;
+
+    let stations = df!(
+        "station_key" => (0..stations.len() as u32).collect::<Vec<u32>>(),
+        "station_name" => stations.iter().map(|station| station.name.clone()).collect::<Vec<Option<String>>>(),
+        "lat" => stations.iter().map(|station| station.lat as f32).collect::<Vec<f32>>(),
+        "lon" => stations.iter().map(|station| station.lon as f32).collect::<Vec<f32>>(),
+    )?;
+
+    Ok((stops, stations))
+}
+
+/// Lowercase words of a stop name without the platform, so that "Stuttgart Hbf (Gleis 3)" and
+/// "Stuttgart Hbf" match. `None` for names without any words.
+fn normalize_name(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    let mut words: Vec<&str> = name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    if let Some(platform_idx) = words.iter().rposition(|word| PLATFORM_WORDS.contains(word)) {
+        // The platform word must be followed by the platform only and not be the whole name
+        if platform_idx > 0 && words.len() - platform_idx <= 2 {
+            words.truncate(platform_idx);
+        }
+    }
+
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Stuttgart Hbf"), Some("stuttgart hbf".into()));
+        assert_eq!(normalize_name("Stuttgart Hbf (Gleis 3)"), Some("stuttgart hbf".into()));
+        assert_eq!(normalize_name("Stuttgart Hbf, Bstg. 12"), Some("stuttgart hbf".into()));
+        assert_eq!(normalize_name("Central Station Bay"), Some("central station".into()));
+        // Only a platform word, which then is the name
+        assert_eq!(normalize_name("Platform"), Some("platform".into()));
+        assert_eq!(normalize_name(" - "), None);
+    }
+
+    #[test]
+    fn test_group_into_stations() {
+        let stops = df!(
+            "dataset_id" => ["a", "a", "a", "b", "b", "b", "b", "b"],
+            "stop_id" => ["hbf", "hbf_1", "hbf_2", "hbf_9", "markt_1", "markt_2", "markt_far", "unnamed"],
+            "stop_name" => [
+                Some("Stuttgart Hbf"), Some("Stuttgart Hbf"), Some("Stuttgart Hbf"),
+                Some("Stuttgart Hbf Gleis 9"), Some("Marktplatz"), Some("Marktplatz"), Some("Marktplatz"), None,
+            ],
+            "stop_lat" => [48.7840f32, 48.7841, 48.7839, 48.7845, 48.7750, 48.7753, 48.8200, 48.7700],
+            "stop_lon" => [9.1820f32, 9.1821, 9.1819, 9.1815, 9.1790, 9.1792, 9.2500, 9.1700],
+            "location_type" => [1u32, 0, 0, 0, 0, 0, 0, 0],
+            "parent_station" => [None, Some("hbf"), Some("hbf"), None, None, None, None, None],
+        ).unwrap();
+
+        let (stops, stations) = group_into_stations(stops).unwrap();
+        let station_keys: Vec<u32> = stops.column("station_key").unwrap().u32().unwrap()
+            .into_no_null_iter().collect();
+
+        // The platform of the other dataset is close enough to the main station
+        assert_eq!(station_keys, vec![0, 0, 0, 0, 1, 1, 2, 3]);
+        assert_eq!(stations.height(), 4);
+        // The feed's station keeps its location
+        assert_eq!(stations.column("lat").unwrap().f32().unwrap().get(0), Some(48.7840));
+        assert_eq!(stations.column("station_name").unwrap().str().unwrap().get(3), None);
+    }
+}
@@ -12,6 +12,7 @@ pub enum QueryError {
     NoRouteFound,
     TransferError(#[from] TransferError),
     InvalidTargetCardinality,
+    InvalidInput(String),
 }
 
 impl Display for QueryError {
@@ -21,6 +22,7 @@ impl Display for QueryError {
             QueryError::NoRouteFound => &"No route found",
             QueryError::TransferError(err) => err,
             &QueryError::InvalidTargetCardinality => &"Target cardinality incompatible with query type",
+            QueryError::InvalidInput(reason) => reason,
         };
         write!(f, "{}", err)
     }
@@ -1,10 +1,24 @@
 use crate::algorithms::RoutingAlgorithm;
+use common::types::config::features::PreprocessingConfig;
 use polars::prelude::LazyFrame;
 use std::fmt;
 use std::fmt::Display;
+use std::path::Path;
 
 pub trait ByPreprocessing: RoutingAlgorithm {
     fn preprocess(input: PreprocessingInput, save_to_disk: bool) -> PreprocessingResult<Self>;
+
+    /// Like [`ByPreprocessing::preprocess`], for algorithms that can be tuned in the config.
+    /// Algorithms without options ignore the config. Artifacts that are saved to disk go to the
+    /// work dir of `data_dir`.
+    fn preprocess_with_config(
+        input: PreprocessingInput,
+        _config: &PreprocessingConfig,
+        _data_dir: &Path,
+        save_to_disk: bool,
+    ) -> PreprocessingResult<Self> {
+        Self::preprocess(input, save_to_disk)
+    }
 }
 
 pub trait FromDisk: RoutingAlgorithm {
@@ -13,7 +27,7 @@ pub trait FromDisk: RoutingAlgorithm {
 
 #[derive(Clone)]
 pub struct PreprocessingInput {
-    // corresponds to calendar.txt in GTFS
+    // corresponds to calendar.txt in GTFS, with the timezone of the agency in "timezone"
     pub services: LazyFrame,
     pub stops: LazyFrame,
     pub trips: LazyFrame,
@@ -26,10 +40,12 @@ pub type PreprocessingResult<T> = Result<T, PreprocessingError>;
 pub enum PreprocessingError {
     Polars(#[from] polars::error::PolarsError),
     KMeans(#[from] linfa_clustering::KMeansError),
+    Clustering(String),
     IO(#[from] std::io::Error),
     GeoArrow(#[from] geoarrow::error::GeoArrowError),
     Arrow(#[from] arrow_schema::ArrowError),
     BuildLines(#[from] common::util::geoarrow_lines::Error),
+    InvalidTimezone(String),
 }
 
 impl Display for PreprocessingError {
@@ -41,6 +57,12 @@ impl Display for PreprocessingError {
             PreprocessingError::GeoArrow(err) => err,
             PreprocessingError::Arrow(err) => err,
             PreprocessingError::BuildLines(err) => err,
+            PreprocessingError::InvalidTimezone(timezone) => {
+                return write!(f, "Unknown timezone {timezone}");
+            }
+            PreprocessingError::Clustering(message) => {
+                return write!(f, "Clustering failed: {message}");
+            }
         };
         write!(f, "{}", err)
     }
@@ -1,5 +1,6 @@
 use serde_with::DisplayFromStr;
 use crate::algorithms::queries::QueryType;
+use common::types::station::Place;
 use common::types::StopId;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
@@ -12,8 +13,9 @@ pub trait TargetCardinality<QT: QueryType>: Sized {
 #[serde_as]
 #[derive(Deserialize)]
 pub struct Single {
+    /// A stop ID, or `st:<station_id>` to arrive at any stop of a station
     #[serde_as(as = "DisplayFromStr")]
-    pub target: StopId,
+    pub target: Place,
 }
 
 #[derive(Deserialize)]
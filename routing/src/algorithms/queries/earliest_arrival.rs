@@ -1,8 +1,11 @@
 use crate::algorithms::queries::cardinality::{All, Multiple, Single, TargetCardinality};
+use crate::algorithms::queries::options::QueryOptions;
 use crate::algorithms::queries::QueryType;
 use crate::journey::Journey;
 use chrono::{DateTime, Utc};
-use common::types::StopId;
+use chrono_tz::Tz;
+use common::types::station::Place;
+use common::util::time::InTimezone;
 use serde::Deserialize;
 use serde_with::serde_derive::Serialize;
 
@@ -17,7 +20,9 @@ impl QueryType for EarliestArrival {
 #[derive(Deserialize)]
 pub struct EarliestArrivalInput {
     pub(crate) earliest_departure: DateTime<Utc>,
-    pub(crate) start: StopId,
+    pub(crate) start: Place,
+    #[serde(flatten)]
+    pub(crate) options: QueryOptions,
 }
 
 #[derive(Serialize, Debug, Eq, PartialEq)]
@@ -25,6 +30,12 @@ pub struct EarliestArrivalOutput {
     pub journey: Journey,
 }
 
+impl InTimezone for EarliestArrivalOutput {
+    fn in_timezone(self, timezone: Tz) -> Self {
+        Self { journey: self.journey.in_timezone(timezone) }
+    }
+}
+
 // Allow all target cardinalities for earliest arrival
 impl TargetCardinality<EarliestArrival> for Single {
     type Output = EarliestArrivalOutput;
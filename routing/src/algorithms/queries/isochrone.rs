@@ -0,0 +1,200 @@
+use crate::algorithms::queries::cardinality::{All, TargetCardinality};
+use crate::algorithms::queries::options::QueryOptions;
+use crate::algorithms::queries::QueryType;
+use common::util::time::{InTimezone, LocalTime};
+use chrono::{DateTime, Duration, TimeDelta, Utc};
+use chrono_tz::Tz;
+use common::types::station::Place;
+use common::types::StopId;
+use common::util::speed::{MAX_WALKING_DURATION, MAX_WALKING_SPEED};
+use geo::{Destination, Haversine, LineString, Point, Polygon};
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_with::serde_as;
+use serde_with::DisplayFromStr;
+
+/// The isochrone query asks for the earliest arrival at every stop that can be reached within a
+/// maximum travel time. In contrast to an earliest arrival query for all targets, no journeys are
+/// reconstructed, which makes it a lot cheaper.
+pub struct Isochrone {}
+impl QueryType for Isochrone {
+    type Input = IsochroneInput;
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+pub struct IsochroneInput {
+    #[serde_as(as = "LocalTime")]
+    pub(crate) earliest_departure: DateTime<Utc>,
+    /// A stop ID, or `st:<station_id>` to start at all stops of a station
+    #[serde_as(as = "DisplayFromStr")]
+    pub(crate) start: Place,
+    #[serde_as(as = "serde_with::DurationSeconds<String>")]
+    pub(crate) max_duration: TimeDelta,
+    #[serde(flatten)]
+    pub(crate) options: QueryOptions,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReachableStop {
+    pub stop: StopId,
+    pub arrival: DateTime<Utc>,
+    pub duration: Duration,
+    /// Number of changes between vehicles that are needed to reach this stop as early as possible
+    pub transfers: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct IsochroneOutput {
+    pub departure: DateTime<Utc>,
+    pub max_duration: Duration,
+    /// Sorted by arrival
+    pub stops: Vec<ReachableStop>,
+    /// Timezone in which the times are serialized
+    pub(crate) timezone: Tz,
+}
+
+impl InTimezone for IsochroneOutput {
+    fn in_timezone(self, timezone: Tz) -> Self {
+        Self { timezone, ..self }
+    }
+}
+
+/// How a reachable stop is serialized, with the arrival in the timezone of the isochrone
+#[serde_as]
+#[derive(Serialize)]
+struct LocalReachableStop {
+    stop: StopId,
+    #[serde_as(as = "LocalTime")]
+    arrival: DateTime<Tz>,
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    duration: Duration,
+    transfers: u32,
+}
+
+impl Serialize for IsochroneOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[serde_as]
+        #[derive(Serialize)]
+        struct LocalIsochroneOutput {
+            #[serde_as(as = "LocalTime")]
+            departure: DateTime<Tz>,
+            #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+            max_duration: Duration,
+            stops: Vec<LocalReachableStop>,
+        }
+
+        let stops = self.stops.iter()
+            .map(|reachable| LocalReachableStop {
+                stop: reachable.stop,
+                arrival: reachable.arrival.with_timezone(&self.timezone),
+                duration: reachable.duration,
+                transfers: reachable.transfers,
+            })
+            .collect();
+
+        LocalIsochroneOutput {
+            departure: self.departure.with_timezone(&self.timezone),
+            max_duration: self.max_duration,
+            stops,
+        }.serialize(serializer)
+    }
+}
+
+// Isochrones always cover all stops
+impl TargetCardinality<Isochrone> for All {
+    type Output = IsochroneOutput;
+}
+
+/// Number of points used to approximate the circle of a walking buffer
+const BUFFER_RESOLUTION: usize = 32;
+
+impl IsochroneOutput {
+    /// Turns the reachable stops into GeoJSON. Each stop is represented by the area that can be
+    /// reached by walking from it in the time that is left until `max_duration` runs out (but at
+    /// most [MAX_WALKING_DURATION]). Stops without a known location are left out.
+    pub fn to_geojson(&self, location: impl Fn(StopId) -> Option<Point<f64>>) -> FeatureCollection {
+        let features = self.stops.iter()
+            .filter_map(|reachable| {
+                let center = location(reachable.stop)?;
+                let walking_time = (self.max_duration - reachable.duration).min(MAX_WALKING_DURATION);
+                let radius = MAX_WALKING_SPEED.distance_traveled_in(walking_time) as f64;
+
+                let mut properties = JsonObject::new();
+                properties.insert("stop_id".into(), reachable.stop.0.into());
+                properties.insert("arrival".into(), reachable.arrival.with_timezone(&self.timezone).to_rfc3339().into());
+                properties.insert("duration".into(), reachable.duration.num_seconds().into());
+                properties.insert("transfers".into(), reachable.transfers.into());
+
+                Some(Feature {
+                    geometry: Some(Geometry::from(&walking_buffer(center, radius))),
+                    properties: Some(properties),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        FeatureCollection { features, bbox: None, foreign_members: None }
+    }
+}
+
+/// Approximates a circle with `radius` meters around `center`
+fn walking_buffer(center: Point<f64>, radius: f64) -> Polygon<f64> {
+    let ring = (0..=BUFFER_RESOLUTION)
+        .map(|i| {
+            let bearing = 360.0 * i as f64 / BUFFER_RESOLUTION as f64;
+            Haversine::destination(center, bearing, radius)
+        })
+        .collect::<LineString<f64>>();
+
+    Polygon::new(ring, vec![])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Distance;
+
+    #[test]
+    fn test_to_geojson() {
+        let departure = DateTime::UNIX_EPOCH;
+        let output = IsochroneOutput {
+            departure,
+            max_duration: Duration::minutes(30),
+            stops: vec![
+                ReachableStop { stop: StopId(0), arrival: departure, duration: Duration::zero(), transfers: 0 },
+                ReachableStop {
+                    stop: StopId(1),
+                    arrival: departure + Duration::minutes(25),
+                    duration: Duration::minutes(25),
+                    transfers: 1,
+                },
+                ReachableStop {
+                    stop: StopId(2),
+                    arrival: departure + Duration::minutes(30),
+                    duration: Duration::minutes(30),
+                    transfers: 0,
+                },
+            ],
+            timezone: chrono_tz::Europe::Berlin,
+        };
+
+        let center = Point::new(9.18, 48.78);
+        let geojson = output.to_geojson(|stop| (stop != StopId(2)).then_some(center));
+
+        // Stop 2 has no location
+        assert_eq!(geojson.features.len(), 2);
+        // Times are in the timezone of the output
+        assert_eq!(geojson.features[0].property("arrival"), Some(&"1970-01-01T01:00:00+01:00".into()));
+
+        // Stop 0 can walk for the maximum walking duration, stop 1 only for the remaining 5 minutes
+        let radii = geojson.features.iter()
+            .map(|feature| {
+                let polygon: Polygon<f64> = feature.geometry.clone().unwrap().try_into().unwrap();
+                Haversine::distance(center, polygon.exterior().0[0].into())
+            })
+            .collect::<Vec<_>>();
+        assert!((radii[0] - MAX_WALKING_SPEED.distance_traveled_in(MAX_WALKING_DURATION) as f64).abs() < 1.0);
+        assert!((radii[1] - MAX_WALKING_SPEED.distance_traveled_in(Duration::minutes(5)) as f64).abs() < 1.0);
+    }
+}
@@ -2,7 +2,9 @@ use crate::algorithms::queries::cardinality::{Single, TargetCardinality};
 use crate::algorithms::queries::QueryType;
 use crate::journey::Journey;
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use common::types::StopId;
+use common::util::time::InTimezone;
 use serde::Deserialize;
 use serde_with::serde_derive::Serialize;
 
@@ -25,6 +27,12 @@ pub struct LatestDepartureOutput {
     pub(crate) journey: Journey,
 }
 
+impl InTimezone for LatestDepartureOutput {
+    fn in_timezone(self, timezone: Tz) -> Self {
+        Self { journey: self.journey.in_timezone(timezone) }
+    }
+}
+
 impl TargetCardinality<LatestDeparture> for Single {
     type Output = LatestDepartureOutput;
 }
@@ -0,0 +1,252 @@
+use crate::algorithms::errors::{QueryError, QueryResult};
+use crate::algorithms::queries::cardinality::{All, Multiple, TargetCardinality};
+use crate::algorithms::queries::isochrone::{Isochrone, IsochroneInput};
+use crate::algorithms::queries::options::QueryOptions;
+use crate::algorithms::queries::{QueryType, Queryable};
+use arrow_array::{RecordBatch, UInt32Array};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use common::util::time::LocalTime;
+use chrono::{DateTime, Duration, TimeDelta, Utc};
+use common::types::StopId;
+use hashbrown::HashMap;
+use itertools::Itertools;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Deserialize;
+use serde_with::formats::Flexible;
+use serde_with::serde_as;
+use serde_with::serde_derive::Serialize;
+use std::sync::Arc;
+
+/// The matrix query asks for the travel times between each of the origins and each of the targets.
+/// Instead of a single departure, a departure window can be given. Departures are then sampled
+/// every `interval` within the window, and the median travel time of these departures is used.
+pub struct Matrix {}
+impl QueryType for Matrix {
+    type Input = MatrixInput;
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+pub struct MatrixInput {
+    pub(crate) origins: Vec<StopId>,
+    #[serde_as(as = "LocalTime")]
+    pub(crate) earliest_departure: DateTime<Utc>,
+    /// Length of the departure window. Without it, only `earliest_departure` is used.
+    #[serde_as(as = "Option<serde_with::DurationSeconds<String, Flexible>>")]
+    #[serde(default)]
+    pub(crate) range: Option<TimeDelta>,
+    /// Time between sampled departures within the window
+    #[serde_as(as = "serde_with::DurationSeconds<String, Flexible>")]
+    #[serde(default = "default_interval")]
+    pub(crate) interval: TimeDelta,
+    /// Destinations that take longer to reach are reported as unreachable
+    #[serde_as(as = "serde_with::DurationSeconds<String, Flexible>")]
+    #[serde(default = "default_max_duration")]
+    pub(crate) max_duration: TimeDelta,
+    #[serde(flatten)]
+    pub(crate) options: QueryOptions,
+}
+
+/// Most departures that are sampled within the departure window
+pub const MAX_SAMPLES: usize = 120;
+
+/// Most pairs of origins and destinations in a matrix
+pub const MAX_CELLS: usize = 1_000_000;
+
+fn default_interval() -> TimeDelta {
+    Duration::minutes(1)
+}
+
+fn default_max_duration() -> TimeDelta {
+    Duration::hours(6)
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct MatrixOutput {
+    pub origins: Vec<StopId>,
+    pub destinations: Vec<StopId>,
+    /// One row per origin, one column per destination. Travel times are in minutes, rounded up.
+    /// Unreachable destinations are `None`.
+    pub travel_times: Vec<Vec<Option<u32>>>,
+}
+
+impl TargetCardinality<Matrix> for Multiple<'_> {
+    type Output = MatrixOutput;
+}
+/// With all targets, the destinations are all stops that can be reached from any origin
+impl TargetCardinality<Matrix> for All {
+    type Output = MatrixOutput;
+}
+
+/// Every algorithm that can calculate isochrones can also calculate travel time matrices.
+impl<'a, A> Queryable<Matrix, Multiple<'a>> for A
+where
+    A: Queryable<Isochrone, All> + Sync,
+{
+    fn query(&self, input: MatrixInput, Multiple { targets }: Multiple<'a>) -> QueryResult<MatrixOutput> {
+        check_cells(input.origins.len(), targets.len())?;
+        let travel_times = travel_times_by_origin(self, &input)?;
+        Ok(MatrixOutput::new(input.origins, targets.into_owned(), &travel_times))
+    }
+}
+
+impl<A> Queryable<Matrix, All> for A
+where
+    A: Queryable<Isochrone, All> + Sync,
+{
+    fn query(&self, input: MatrixInput, _: All) -> QueryResult<MatrixOutput> {
+        let travel_times = travel_times_by_origin(self, &input)?;
+        let destinations = travel_times.iter()
+            .flat_map(|travel_times| travel_times.keys().copied())
+            .unique()
+            .sorted()
+            .collect_vec();
+        check_cells(input.origins.len(), destinations.len())?;
+
+        Ok(MatrixOutput::new(input.origins, destinations, &travel_times))
+    }
+}
+
+/// Calculates the median travel time from each origin (in the same order) to every reachable stop.
+fn travel_times_by_origin<A>(algorithm: &A, input: &MatrixInput) -> QueryResult<Vec<HashMap<StopId, Duration>>>
+where
+    A: Queryable<Isochrone, All> + Sync,
+{
+    let last_departure = input.earliest_departure + input.range.unwrap_or_default();
+    if input.interval <= TimeDelta::zero() {
+        return Err(QueryError::InvalidInput("interval must be positive".into()));
+    }
+    let departures = std::iter::successors(Some(input.earliest_departure), |departure| {
+        Some(*departure + input.interval).filter(|next| next <= &last_departure)
+    }).take(MAX_SAMPLES + 1).collect_vec();
+    if departures.len() > MAX_SAMPLES {
+        return Err(QueryError::InvalidInput(format!(
+            "The departure window has more than {MAX_SAMPLES} departures, use a shorter range or a longer interval"
+        )));
+    }
+
+    input.origins.clone().into_par_iter()
+        .map(|origin| {
+            // Travel times for each departure, unreachable stops are missing
+            let mut samples: HashMap<StopId, Vec<Duration>> = HashMap::new();
+            for departure in &departures {
+                let isochrone = algorithm.query(IsochroneInput {
+                    earliest_departure: *departure,
+                    start: origin.into(),
+                    max_duration: input.max_duration,
+                    options: input.options.clone(),
+                }, All)?;
+
+                for reachable in isochrone.stops {
+                    samples.entry(reachable.stop).or_default().push(reachable.duration);
+                }
+            }
+
+            let medians = samples.into_iter()
+                .filter_map(|(stop, durations)| median(durations, departures.len()).map(|m| (stop, m)))
+                .collect();
+            Ok(medians)
+        })
+        .collect()
+}
+
+fn check_cells(num_origins: usize, num_destinations: usize) -> QueryResult<()> {
+    if num_origins.saturating_mul(num_destinations) > MAX_CELLS {
+        return Err(QueryError::InvalidInput(format!(
+            "The matrix has more than {MAX_CELLS} pairs of origins and destinations"
+        )));
+    }
+    Ok(())
+}
+
+/// The median of `total` samples, where only the reachable ones are in `durations`. The missing
+/// samples count as infinitely long, so the median is `None` if most samples are missing.
+fn median(mut durations: Vec<Duration>, total: usize) -> Option<Duration> {
+    durations.sort();
+    durations.get(total / 2).copied()
+}
+
+impl MatrixOutput {
+    fn new(origins: Vec<StopId>, destinations: Vec<StopId>, travel_times: &[HashMap<StopId, Duration>]) -> Self {
+        let travel_times = travel_times.iter()
+            .map(|travel_times| {
+                destinations.iter()
+                    .map(|destination| {
+                        travel_times.get(destination).map(|duration| {
+                            (duration.num_seconds() as u32).div_ceil(60)
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { origins, destinations, travel_times }
+    }
+
+    /// Serializes the matrix as an Arrow IPC stream. The matrix is in long format, with one row for
+    /// each pair of origin and destination.
+    pub fn to_arrow_ipc(&self) -> Result<Vec<u8>, ArrowError> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("origin", DataType::UInt32, false),
+            Field::new("destination", DataType::UInt32, false),
+            Field::new("travel_time_minutes", DataType::UInt32, true),
+        ]));
+
+        let pairs = self.origins.iter().zip(&self.travel_times)
+            .flat_map(|(origin, row)| {
+                self.destinations.iter().zip(row)
+                    .map(move |(destination, travel_time)| (origin.0, destination.0, *travel_time))
+            });
+        let (origins, destinations, travel_times): (Vec<u32>, Vec<u32>, Vec<Option<u32>>) =
+            pairs.multiunzip();
+
+        let batch = RecordBatch::try_new(schema.clone(), vec![
+            Arc::new(UInt32Array::from(origins)),
+            Arc::new(UInt32Array::from(destinations)),
+            Arc::new(UInt32Array::from(travel_times)),
+        ])?;
+
+        let mut buffer = vec![];
+        let mut writer = StreamWriter::try_new(&mut buffer, &schema)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        drop(writer);
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_ipc::reader::StreamReader;
+
+    #[test]
+    fn test_median() {
+        let minutes = |m: Vec<i64>| m.into_iter().map(Duration::minutes).collect_vec();
+
+        assert_eq!(median(minutes(vec![3, 1, 2]), 3), Some(Duration::minutes(2)));
+        // Two of four departures can't reach the stop
+        assert_eq!(median(minutes(vec![1, 2]), 4), None);
+        assert_eq!(median(minutes(vec![1, 2, 3]), 4), Some(Duration::minutes(3)));
+    }
+
+    #[test]
+    fn test_arrow_ipc() {
+        let output = MatrixOutput {
+            origins: vec![StopId(0), StopId(1)],
+            destinations: vec![StopId(2), StopId(3)],
+            travel_times: vec![vec![Some(5), None], vec![Some(7), Some(8)]],
+        };
+
+        let ipc = output.to_arrow_ipc().unwrap();
+        let batches = StreamReader::try_new(ipc.as_slice(), None).unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 4);
+        let travel_times = batches[0].column(2).as_any().downcast_ref::<UInt32Array>().unwrap();
+        assert_eq!(travel_times.iter().collect_vec(), vec![Some(5), None, Some(7), Some(8)]);
+    }
+}
@@ -5,8 +5,12 @@ use serde::de::DeserializeOwned;
 
 pub mod cardinality;
 pub mod earliest_arrival;
+pub mod isochrone;
 pub mod latest_departure;
+pub mod matrix;
+pub mod options;
 pub mod range;
+pub mod via;
 
 pub trait Queryable<QT: QueryType, TC: TargetCardinality<QT>>: RoutingAlgorithm {
     fn query(&self, input: QT::Input, target_cardinality: TC) -> QueryResult<TC::Output>;
@@ -0,0 +1,132 @@
+use crate::direct_connections::RouteInfo;
+use common::types::route::RouteType;
+use hashbrown::HashSet;
+use serde::Deserialize;
+use serde_with::formats::CommaSeparator;
+use serde_with::{serde_as, DisplayFromStr, StringWithSeparator};
+
+/// Options that restrict which journeys are acceptable, independent of the query type
+#[serde_as]
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct QueryOptions {
+    /// Only use stops, trips and transfers that are usable with a wheelchair. Stops and trips
+    /// without any accessibility information are assumed to be accessible, since excluding them
+    /// would make most feeds unusable.
+    #[serde_as(as = "DisplayFromStr")]
+    #[serde(default)]
+    pub(crate) wheelchair_accessible: bool,
+
+    // ROUTE FILTERS
+    // All of them are passed as comma separated lists, e.g. `route_types=0,3`. Route types also
+    // match their extended route types, so excluding rail (2) also excludes long distance trains (102).
+    /// If set, only use routes of these types
+    #[serde_as(as = "Option<StringWithSeparator::<CommaSeparator, RouteType>>")]
+    #[serde(default)]
+    pub(crate) route_types: Option<HashSet<RouteType>>,
+    /// Don't use any routes of these types
+    #[serde_as(as = "StringWithSeparator::<CommaSeparator, RouteType>")]
+    #[serde(default)]
+    pub(crate) excluded_route_types: HashSet<RouteType>,
+    /// If set, only use routes that are operated by these agencies (GTFS `agency_id`)
+    #[serde_as(as = "Option<StringWithSeparator::<CommaSeparator, String>>")]
+    #[serde(default)]
+    pub(crate) agencies: Option<HashSet<String>>,
+    /// Don't use any routes operated by these agencies
+    #[serde_as(as = "StringWithSeparator::<CommaSeparator, String>")]
+    #[serde(default)]
+    pub(crate) excluded_agencies: HashSet<String>,
+    /// If set, only use these routes (GTFS `route_id`)
+    #[serde_as(as = "Option<StringWithSeparator::<CommaSeparator, String>>")]
+    #[serde(default)]
+    pub(crate) routes: Option<HashSet<String>>,
+    /// Don't use any of these routes
+    #[serde_as(as = "StringWithSeparator::<CommaSeparator, String>")]
+    #[serde(default)]
+    pub(crate) excluded_routes: HashSet<String>,
+}
+
+impl QueryOptions {
+    /// Whether any of the route filters is set
+    pub(crate) fn filters_routes(&self) -> bool {
+        self.route_types.is_some() || !self.excluded_route_types.is_empty()
+            || self.agencies.is_some() || !self.excluded_agencies.is_empty()
+            || self.routes.is_some() || !self.excluded_routes.is_empty()
+    }
+
+    /// Checks if a route may be used. If a filter restricts to some values (e.g. "bus only"),
+    /// routes without the respective information are not allowed, as we can't know if they fit.
+    /// Exclusions on the other hand only apply to routes where the information is known.
+    pub(crate) fn route_allowed(&self, route: &RouteInfo) -> bool {
+        fn included<T>(value: Option<&T>, included: &Option<HashSet<T>>, matches: impl Fn(&T, &T) -> bool) -> bool {
+            match (included, value) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(included), Some(value)) => included.iter().any(|inc| matches(value, inc)),
+            }
+        }
+        fn excluded<T>(value: Option<&T>, excluded: &HashSet<T>, matches: impl Fn(&T, &T) -> bool) -> bool {
+            value.is_some_and(|value| excluded.iter().any(|exc| matches(value, exc)))
+        }
+
+        let route_type = route.route_type.as_ref();
+        let agency_id = route.agency_id.as_ref();
+        let route_id = route.route_id.as_ref();
+
+        included(route_type, &self.route_types, RouteType::matches)
+            && !excluded(route_type, &self.excluded_route_types, RouteType::matches)
+            && included(agency_id, &self.agencies, String::eq)
+            && !excluded(agency_id, &self.excluded_agencies, String::eq)
+            && included(route_id, &self.routes, String::eq)
+            && !excluded(route_id, &self.excluded_routes, String::eq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_allowed() {
+        let long_distance_train = RouteInfo {
+            route_id: Some("ICE 42".into()),
+            route_type: Some(RouteType(102)),
+            agency_id: Some("db".into()),
+        };
+        let bus = RouteInfo {
+            route_id: Some("42".into()),
+            route_type: Some(RouteType::BUS),
+            agency_id: Some("vvs".into()),
+        };
+        let unknown = RouteInfo::default();
+
+        let no_trains = QueryOptions {
+            excluded_route_types: HashSet::from([RouteType::RAIL]),
+            ..Default::default()
+        };
+        assert!(!no_trains.route_allowed(&long_distance_train));
+        assert!(no_trains.route_allowed(&bus));
+        assert!(no_trains.route_allowed(&unknown));
+
+        let bus_only = QueryOptions {
+            route_types: Some(HashSet::from([RouteType::BUS])),
+            ..Default::default()
+        };
+        assert!(!bus_only.route_allowed(&long_distance_train));
+        assert!(bus_only.route_allowed(&bus));
+        assert!(!bus_only.route_allowed(&unknown));
+
+        let no_db = QueryOptions {
+            excluded_agencies: HashSet::from(["db".to_string()]),
+            ..Default::default()
+        };
+        assert!(!no_db.route_allowed(&long_distance_train));
+        assert!(no_db.route_allowed(&bus));
+
+        let only_route_42 = QueryOptions {
+            routes: Some(HashSet::from(["42".to_string()])),
+            ..Default::default()
+        };
+        assert!(!only_route_42.route_allowed(&long_distance_train));
+        assert!(only_route_42.route_allowed(&bus));
+    }
+}
@@ -1,12 +1,14 @@
 use serde_with::DisplayFromStr;
 use crate::algorithms::queries::cardinality::{All, Multiple, Single, TargetCardinality};
+use crate::algorithms::queries::options::QueryOptions;
 use crate::algorithms::queries::QueryType;
 use crate::journey::Journey;
+use common::util::time::{InTimezone, LocalTime};
 use chrono::{DateTime, TimeDelta, Utc};
-use common::types::StopId;
+use chrono_tz::Tz;
+use common::types::station::Place;
 use hashbrown::HashSet;
 use serde::Deserialize;
-use serde_with::formats::Flexible;
 use serde_with::serde_as;
 use serde_with::serde_derive::Serialize;
 
@@ -20,20 +22,24 @@ impl QueryType for Range {
 #[serde_as]
 #[derive(Deserialize)]
 pub struct RangeInput {
-    #[serde_as(as = "serde_with::TimestampSeconds<String, Flexible>")]
+    #[serde_as(as = "LocalTime")]
     pub(crate) earliest_departure: DateTime<Utc>,
     #[serde_as(as = "serde_with::DurationSeconds<String>")]
     pub(crate) range: TimeDelta,
+    /// A stop ID, or `st:<station_id>` to start at all stops of a station
     #[serde_as(as = "DisplayFromStr")]
-    pub(crate) start: StopId,
+    pub(crate) start: Place,
+    #[serde(flatten)]
+    pub(crate) options: QueryOptions,
 }
 
 impl RangeInput {
-    pub fn from_absolute(earliest: DateTime<Utc>, latest: DateTime<Utc>, start: StopId) -> Self {
+    pub fn from_absolute(earliest: DateTime<Utc>, latest: DateTime<Utc>, start: impl Into<Place>) -> Self {
         Self {
             earliest_departure: earliest,
             range: latest - earliest,
-            start,
+            start: start.into(),
+            options: QueryOptions::default(),
         }
     }
 }
@@ -43,6 +49,18 @@ pub struct RangeOutput {
     pub(crate) journeys: HashSet<Journey>,
 }
 
+impl InTimezone for RangeOutput {
+    fn in_timezone(self, timezone: Tz) -> Self {
+        Self { journeys: self.journeys.into_iter().map(|journey| journey.in_timezone(timezone)).collect() }
+    }
+}
+
+impl RangeOutput {
+    pub fn journeys(&self) -> impl Iterator<Item = &Journey> {
+        self.journeys.iter()
+    }
+}
+
 impl TargetCardinality<Range> for Single {
     type Output = RangeOutput;
 }
@@ -0,0 +1,118 @@
+use crate::algorithms::queries::cardinality::{Single, TargetCardinality};
+use crate::algorithms::queries::options::QueryOptions;
+use crate::algorithms::queries::QueryType;
+use crate::journey::Journey;
+use common::util::time::{InTimezone, LocalTime};
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use common::types::station::Place;
+use serde::Deserialize;
+use serde_with::formats::CommaSeparator;
+use serde_with::serde_derive::Serialize;
+use serde_with::{serde_as, DisplayFromStr, StringWithSeparator};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// The via query asks for the earliest arrival at the target when departing at or after a
+/// specified point in time, while visiting all via stops in the given order. At each via stop,
+/// the journey can demand a minimum stay before continuing.
+pub struct Via {}
+impl QueryType for Via {
+    type Input = ViaInput;
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+pub struct ViaInput {
+    #[serde_as(as = "LocalTime")]
+    pub(crate) earliest_departure: DateTime<Utc>,
+    /// A stop ID, or `st:<station_id>` to start at all stops of a station
+    #[serde_as(as = "DisplayFromStr")]
+    pub(crate) start: Place,
+    /// Comma separated list of stops (or `st:<station_id>`) that have to be visited in this order.
+    /// A stop may be followed by the minimum dwell time in seconds, e.g. `via=42:1800,st:7` (stay
+    /// 30 minutes at 42, then pass through any stop of station 7).
+    #[serde_as(as = "StringWithSeparator::<CommaSeparator, ViaStop>")]
+    pub(crate) via: Vec<ViaStop>,
+    #[serde(flatten)]
+    pub(crate) options: QueryOptions,
+}
+
+impl ViaInput {
+    pub fn new(earliest_departure: DateTime<Utc>, start: impl Into<Place>, via: Vec<ViaStop>) -> Self {
+        Self {
+            earliest_departure,
+            start: start.into(),
+            via,
+            options: QueryOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ViaStop {
+    pub(crate) place: Place,
+    pub(crate) min_dwell: TimeDelta,
+}
+
+impl FromStr for ViaStop {
+    type Err = ParseIntError;
+
+    /// Parses a [`Place`], optionally followed by `:<min_dwell_seconds>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, rest) = match s.strip_prefix("st:") {
+            Some(station) => ("st:", station),
+            None => ("", s),
+        };
+        let (place, min_dwell) = match rest.split_once(':') {
+            Some((place, min_dwell)) => (place, TimeDelta::seconds(min_dwell.parse()?)),
+            None => (rest, TimeDelta::zero()),
+        };
+
+        Ok(Self { place: format!("{prefix}{place}").parse()?, min_dwell })
+    }
+}
+
+/// The journey is split into sections at each via stop. The time between the arrival at a via stop
+/// and the departure of the next section is the dwell time there (at least the minimum dwell).
+#[derive(Serialize, Debug, Eq, PartialEq)]
+pub struct ViaOutput {
+    pub sections: Vec<Journey>,
+}
+
+impl InTimezone for ViaOutput {
+    fn in_timezone(self, timezone: Tz) -> Self {
+        Self { sections: self.sections.into_iter().map(|section| section.in_timezone(timezone)).collect() }
+    }
+}
+
+// The via stops are already multiple targets, so only allow a single final target
+impl TargetCardinality<Via> for Single {
+    type Output = ViaOutput;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::station::StationId;
+    use common::types::StopId;
+
+    #[test]
+    fn test_parse_via_stop() {
+        assert_eq!(
+            ViaStop::from_str("42").unwrap(),
+            ViaStop { place: StopId(42).into(), min_dwell: TimeDelta::zero() }
+        );
+        assert_eq!(
+            ViaStop::from_str("42:1800").unwrap(),
+            ViaStop { place: StopId(42).into(), min_dwell: TimeDelta::minutes(30) }
+        );
+        assert_eq!(
+            ViaStop::from_str("st:7:60").unwrap(),
+            ViaStop { place: StationId(7).into(), min_dwell: TimeDelta::minutes(1) }
+        );
+        assert!(ViaStop::from_str("42:").is_err());
+        assert!(ViaStop::from_str("st:").is_err());
+        assert!(ViaStop::from_str("s42").is_err());
+    }
+}
@@ -4,10 +4,16 @@ use polars::frame::DataFrame;
 use polars::prelude::*;
 use polars::series::IntoSeries;
 
-use common::types::StopId;
+use common::types::route::RouteType;
+use common::types::{LineId, StopId};
+use hashbrown::HashMap;
+use itertools::izip;
 use common::util::df;
 use common::util::geoarrow_lines::build_geoarrow_lines;
+use common::types::trip::OneOffTripId;
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
 use crate::algorithms::initialization::{PreprocessingError, PreprocessingInput};
+use crate::journey::Leg;
 
 /// In the transfer patterns paper, lines are represented like this:
 ///
@@ -30,8 +36,15 @@ use crate::algorithms::initialization::{PreprocessingError, PreprocessingInput};
 /// | 0       | ...          | ...     | ...     | ...       |           ... |
 /// | 1       | ...          | ...     | ...     | ...       |           ... |
 /// | ...     | ...          | ...     | ...     | ...       |           ... |
+///
+/// If the trips contain route information (see [ROUTE_COLUMNS]), these columns are also included.
+/// Trips of different routes are then never part of the same line, so that each line belongs to
+/// exactly one route.
 pub type ExpandedLinesFrame = DataFrame;
 
+/// Columns of the trips frame that describe the route of a trip
+pub const ROUTE_COLUMNS: [&str; 3] = ["route_id_in_dataset", "route_type", "agency_id"];
+
 /// | line_id | stop_id  | stop_sequence   |
 /// | ------- | -------- | --------------- |
 /// | 0       | 5        | 0               |
@@ -62,15 +75,33 @@ impl TryFrom<PreprocessingInput> for DirectConnections {
     fn try_from(input: PreprocessingInput) -> Result<Self, Self::Error> {
         let (expanded_lines, line_progressions) = {
             // TODO: For now, this completely ignores traffic days. Therefore, computed transfer patterns might include some patterns that are never possible and might not include some optimal ones (when mixture of days is better than whats possible on an actual day)!
-            let mut lines = input.stop_times
-                .clone()
+            // Route information is optional, so only use the route columns that actually exist
+            let trips_schema = input.trips.clone().collect_schema()?;
+            let route_columns = ROUTE_COLUMNS.into_iter()
+                .filter(|column| trips_schema.contains(column))
+                .map(col)
+                .collect_vec();
+
+            let stop_times = if route_columns.is_empty() {
+                input.stop_times.clone()
+            } else {
+                input.stop_times.clone().join(
+                    input.trips.clone().select([&[col("trip_id")], route_columns.as_slice()].concat()),
+                    [col("trip_id")],
+                    [col("trip_id")],
+                    JoinArgs::new(JoinType::Left),
+                )
+            };
+
+            let mut lines = stop_times
                 // Sort the stop sequence, so that list of stop_ids are identical once aggregated
                 .sort(["stop_sequence"], Default::default())
                 // Turn the stop_ids into a list per each trip
-                .group_by([col("trip_id")])
+                .group_by([&[col("trip_id")], route_columns.as_slice()].concat())
                 .agg([col("stop_id").alias("stop_ids"), col("arrival_time"), col("departure_time"), col("stop_sequence")])
                 // Group by the sequence of stop_ids, to identify lines (aka unique sequences of stops)
-                .group_by([col("stop_ids"), col("stop_sequence")])
+                // Lines are also split by route, so that route information is unique per line
+                .group_by([&[col("stop_ids"), col("stop_sequence")], route_columns.as_slice()].concat())
                 .agg([col("trip_id").alias("trip_ids"), col("arrival_time"), col("departure_time")])
                 .collect()?;
 
@@ -130,7 +161,58 @@ impl PartialEq for DirectConnections {
 }
 
 
+/// Information about the route that a line belongs to. Everything is optional, since the input
+/// data might not contain it.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RouteInfo {
+    pub(crate) route_id: Option<String>,
+    pub(crate) route_type: Option<RouteType>,
+    pub(crate) agency_id: Option<String>,
+}
+
 impl DirectConnections {
+    /// The route information of each line. Lines are missing if there is no route information.
+    pub(crate) fn route_info(&self) -> Result<HashMap<LineId, RouteInfo>, PreprocessingError> {
+        let schema = self.expanded_lines.schema();
+        if !ROUTE_COLUMNS.iter().any(|column| schema.contains(column)) {
+            return Ok(HashMap::new());
+        }
+
+        let lines = self.expanded_lines.clone().lazy()
+            .select([
+                col("line_id"),
+                Self::route_column(&schema, "route_id_in_dataset", DataType::String),
+                Self::route_column(&schema, "route_type", DataType::UInt32),
+                Self::route_column(&schema, "agency_id", DataType::String),
+            ])
+            .unique(Some(vec!["line_id".into()]), UniqueKeepStrategy::Any)
+            .collect()?;
+
+        let [line_ids, route_ids, route_types, agency_ids] = lines.get_columns()
+        else { unreachable!("we selected exactly four columns") };
+
+        let route_info = izip!(line_ids.u32()?, route_ids.str()?, route_types.u32()?, agency_ids.str()?)
+            .filter_map(|(line_id, route_id, route_type, agency_id)| {
+                Some((LineId(line_id?), RouteInfo {
+                    route_id: route_id.map(String::from),
+                    route_type: route_type.map(RouteType),
+                    agency_id: agency_id.map(String::from),
+                }))
+            })
+            .collect();
+
+        Ok(route_info)
+    }
+
+    /// Selects a route column, or a column of nulls if it does not exist
+    fn route_column(schema: &Schema, name: &str, dtype: DataType) -> Expr {
+        if schema.contains(name) {
+            col(name).cast(dtype)
+        } else {
+            lit(NULL).cast(dtype).alias(name)
+        }
+    }
+
     pub(crate) fn query_direct(&self, from: StopId, to: StopId) -> Result<LazyFrame, PreprocessingError> {
         // Utility function to filter for incidences whose stop_id matches
         fn filter_and_unpack_incidences(StopId(id): StopId, stop_incidence: &StopIncidenceFrame) -> Result<LazyFrame, PreprocessingError> {
@@ -181,6 +263,64 @@ impl DirectConnections {
         Ok(earliest)
     }
 
+    /// The ride from `from` to `to` that arrives first, departing at `departure` or later. Like the
+    /// rest of the direct connections, this ignores traffic days: stop times are taken as times of
+    /// the day of `departure`, or of the next day if no trip departs later that day.
+    pub(crate) fn earliest_ride(
+        &self, from: StopId, to: StopId, departure: DateTime<Utc>,
+    ) -> PolarsResult<Option<Leg>> {
+        let day_start = departure.date_naive().and_time(NaiveTime::MIN).and_utc();
+        let time_of_day = (departure - day_start).num_milliseconds();
+
+        match self.earliest_ride_on_day(from, to, day_start, time_of_day)? {
+            Some(ride) => Ok(Some(ride)),
+            None => self.earliest_ride_on_day(from, to, day_start + TimeDelta::days(1), 0),
+        }
+    }
+
+    fn earliest_ride_on_day(
+        &self, from: StopId, to: StopId, day_start: DateTime<Utc>, min_departure: i64,
+    ) -> PolarsResult<Option<Leg>> {
+        // Stop times are durations in milliseconds since the start of the day
+        let stop_times = self.expanded_lines.clone().lazy()
+            .select([
+                col("trip_id"),
+                col("stop_id"),
+                col("stop_sequence"),
+                col("arrival_time").cast(DataType::Int64),
+                col("departure_time").cast(DataType::Int64),
+            ]);
+        let boardings = stop_times.clone()
+            .filter(col("stop_id").eq(lit(from.0)).and(col("departure_time").gt_eq(lit(min_departure))))
+            .select([col("trip_id"), col("stop_sequence").alias("boarding_sequence"), col("departure_time")]);
+        let alightings = stop_times
+            .filter(col("stop_id").eq(lit(to.0)))
+            .select([col("trip_id"), col("stop_sequence").alias("alight_sequence"), col("arrival_time")]);
+
+        let ride = boardings
+            .inner_join(alightings, col("trip_id"), col("trip_id"))
+            .filter(col("boarding_sequence").lt(col("alight_sequence")))
+            .sort(["arrival_time", "departure_time"], Default::default())
+            .first()
+            .collect()?;
+
+        let (Some(trip_id), Some(departure_time), Some(arrival_time)) = (
+            ride.column("trip_id")?.u32()?.get(0),
+            ride.column("departure_time")?.i64()?.get(0),
+            ride.column("arrival_time")?.i64()?.get(0),
+        ) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Leg::Ride {
+            trip: OneOffTripId(trip_id).into(),
+            boarding_stop: from,
+            alight_stop: to,
+            boarding_time: day_start + TimeDelta::milliseconds(departure_time),
+            alight_time: day_start + TimeDelta::milliseconds(arrival_time),
+        }))
+    }
+
     pub fn to_geoarrow_lines(
         &self,
         stops_df: LazyFrame,
@@ -215,7 +355,7 @@ impl DirectConnections {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tests::case_1;
+    use crate::tests::{case_1, case_2};
     use polars::datatypes::AnyValue::List;
 
     #[test]
@@ -258,4 +398,35 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_route_info() {
+        let mut input = case_2::generate_preprocessing_input().unwrap();
+        input.trips = df![
+            "trip_id" => [0u32, 1],
+            "service_id" => [0u32, 0],
+            "route_id_in_dataset" => ["S1", "42"],
+            "route_type" => [109u32, 3],
+            "agency_id" => [Some("db"), None],
+        ].unwrap().lazy();
+
+        let direct_connections = DirectConnections::try_from(input).unwrap();
+        let mut route_info = direct_connections.route_info().unwrap()
+            .into_values()
+            .collect_vec();
+        route_info.sort_by_key(|route| route.route_type);
+
+        assert_eq!(route_info, vec![
+            RouteInfo { route_id: Some("42".into()), route_type: Some(RouteType::BUS), agency_id: None },
+            RouteInfo { route_id: Some("S1".into()), route_type: Some(RouteType(109)), agency_id: Some("db".into()) },
+        ]);
+    }
+
+    #[test]
+    fn test_route_info_missing() {
+        let input = case_2::generate_preprocessing_input().unwrap();
+        let direct_connections = DirectConnections::try_from(input).unwrap();
+
+        assert!(direct_connections.route_info().unwrap().is_empty());
+    }
 }
@@ -1,26 +1,44 @@
+use common::util::time::{InTimezone, LocalTime};
 use chrono::{DateTime, Duration, TimeDelta, Utc};
-use chrono::serde::ts_seconds;
+use chrono_tz::Tz;
 use common::types::StopId;
 #[cfg(debug_assertions)] use itertools::Itertools;
 use std::fmt::{Debug, Formatter};
 use std::slice::Iter;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use serde_with::serde_as;
 use common::types::trip::AnyTripId;
 
-#[serde_as]
-#[derive(Serialize, Clone, Eq, PartialEq, Hash)]
+#[derive(Clone, Eq, PartialEq, Hash)]
 pub enum Leg {
-    #[serde(rename = "ride")]
     Ride {
         trip: AnyTripId,
         boarding_stop: StopId,
         alight_stop: StopId,
-        #[serde(with = "ts_seconds")]
         boarding_time: DateTime<Utc>,
-        #[serde(with = "ts_seconds")]
         alight_time: DateTime<Utc>,
     },
+    Transfer {
+        start: StopId,
+        end: StopId,
+        duration: Duration,
+    },
+}
+
+/// How a leg is serialized, with the times in the timezone of its journey
+#[serde_as]
+#[derive(Serialize)]
+enum LocalLeg<'a> {
+    #[serde(rename = "ride")]
+    Ride {
+        trip: &'a AnyTripId,
+        boarding_stop: StopId,
+        alight_stop: StopId,
+        #[serde_as(as = "LocalTime")]
+        boarding_time: DateTime<Tz>,
+        #[serde_as(as = "LocalTime")]
+        alight_time: DateTime<Tz>,
+    },
     #[serde(rename = "transfer")]
     Transfer {
         start: StopId,
@@ -79,9 +97,41 @@ impl Debug for Leg {
     }
 }
 
-#[derive(Serialize, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Journey {
     pub legs: Vec<Leg>,
+    /// Timezone in which the times are serialized
+    timezone: Tz,
+}
+
+impl InTimezone for Journey {
+    fn in_timezone(self, timezone: Tz) -> Self {
+        Self { timezone, ..self }
+    }
+}
+
+impl Serialize for Journey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct LocalJourney<'a> {
+            legs: Vec<LocalLeg<'a>>,
+        }
+
+        let legs = self.legs.iter()
+            .map(|leg| match leg {
+                Leg::Ride { trip, boarding_stop, alight_stop, boarding_time, alight_time } => LocalLeg::Ride {
+                    trip,
+                    boarding_stop: *boarding_stop,
+                    alight_stop: *alight_stop,
+                    boarding_time: boarding_time.with_timezone(&self.timezone),
+                    alight_time: alight_time.with_timezone(&self.timezone),
+                },
+                Leg::Transfer { start, end, duration } => LocalLeg::Transfer { start: *start, end: *end, duration: *duration },
+            })
+            .collect();
+
+        LocalJourney { legs }.serialize(serializer)
+    }
 }
 
 impl Journey {
@@ -112,7 +162,7 @@ impl Journey {
             );
         }
 
-        Self { legs }
+        Self { legs, timezone: Tz::UTC }
     }
 
     pub(crate) fn legs(&self) -> Iter<Leg> {
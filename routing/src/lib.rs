@@ -4,5 +4,6 @@ pub mod tp;
 pub mod transfers;
 pub mod direct_connections;
 pub mod journey;
+pub mod stations;
 pub mod algorithms;
 #[cfg(test)] mod tests;
\ No newline at end of file
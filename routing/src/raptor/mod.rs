@@ -1,10 +1,19 @@
+use crate::algorithms::errors::{QueryError, QueryResult};
+use crate::algorithms::queries::options::QueryOptions;
 use crate::algorithms::RoutingAlgorithm;
-use crate::journey::Journey;
+use crate::direct_connections::RouteInfo;
+use crate::journey::{Journey, Leg};
+use crate::stations::Stations;
 use crate::transfers::TransferProvider;
-use chrono::{DateTime, Duration, Utc};
-use common::types::trip::{AnyTripId, OneOff, Recurring, TripType};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+use common::types::trip::{AnyTripId, OneOff, OneOffTripId, TripType};
+use common::util::time::service_day_start;
+use common::types::station::Place;
 use common::types::{LineId, SeqNum, StopId};
+use geo::Point;
 use hashbrown::{HashMap, HashSet};
+use std::sync::Arc;
 
 mod preprocessing;
 mod routing;
@@ -18,16 +27,27 @@ type LocalStopId = StopId;
 pub type TripsByLineAndStopMap<TT: TripType> =
     HashMap<(LineId, LocalStopId), Vec<(DateTime<Utc>, TT::Id)>>;
 
+/// Like [`TripsByLineAndStopMap`], but with departures relative to the start of the service day and
+/// the base ID of the recurring trip
+pub type RecurringTripsByLineAndStopMap = HashMap<(LineId, LocalStopId), Vec<(TimeDelta, u32)>>;
+
 pub type StopsByLineMap = HashMap<LineId, Vec<(LocalStopId, u32)>>;
 pub type LinesByStopMap = HashMap<LocalStopId, HashSet<(LineId, SeqNum)>>;
 
 pub struct RaptorAlgorithm {
     pub(crate) stop_mapping: StopMapping,
+    // Location of each stop with known coordinates, by global stop ID. Only used for output, not
+    // for routing.
+    pub(crate) stop_coords: HashMap<GlobalStopId, Point<f64>>,
+    // Stops of each station, with global stop IDs. Only used to resolve where queries start.
+    pub(crate) stations: Stations,
 
     // STOPS AND LINES
     // <line_id, [stop_id, visit_idx]>
     pub(crate) stops_by_line: StopsByLineMap,
     pub(crate) lines_by_stops: LinesByStopMap,
+    // Route information of lines, only used for filtering. Lines without information are missing.
+    pub(crate) line_routes: HashMap<LineId, RouteInfo>,
 
     // ARRIVALS & DEPARTURES
     pub(crate) arrivals: AnyTripAtStopTime,
@@ -37,10 +57,31 @@ pub struct RaptorAlgorithm {
     // Vec has to be sorted from earliest to latest
     // DateTime is departure at the stop
     pub(crate) one_off_trips_by_line_and_stop: TripsByLineAndStopMap<OneOff>,
-    pub(crate) recurring_trips_by_line_and_stop: TripsByLineAndStopMap<Recurring>,
+    pub(crate) recurring_trips_by_line_and_stop: RecurringTripsByLineAndStopMap,
+    // Days on which the recurring trips run
+    pub(crate) calendar: ServiceCalendar,
+    // Dataset that each trip comes from, by (base) trip ID. Only used for attributing journeys.
+    pub(crate) trip_datasets: HashMap<u32, Arc<str>>,
 
     // TRANSFERS
     pub(crate) transfer_provider: Box<dyn TransferProvider + Send + Sync>,
+
+    // ACCESSIBILITY
+    pub(crate) accessibility: Accessibility,
+}
+
+/// Everything that is needed to answer queries in wheelchair accessible mode
+#[derive(Default)]
+pub struct Accessibility {
+    /// Stops where boarding or alighting with a wheelchair is explicitly impossible
+    pub(crate) inaccessible_stops: HashSet<LocalStopId>,
+    /// Trips that explicitly can't accommodate any wheelchairs. Recurring trips are contained with
+    /// their base ID.
+    pub(crate) inaccessible_trips: HashSet<OneOffTripId>,
+    /// Provides transfers at wheelchair speed. Only present if the stops contain accessibility
+    /// information. If this is `None`, the regular transfer provider is used, but transfers to
+    /// inaccessible stops are still excluded.
+    pub(crate) transfer_provider: Option<Box<dyn TransferProvider + Send + Sync>>,
 }
 
 /// <(trip_id, stop_id, visit_idx), time>
@@ -48,9 +89,13 @@ pub struct RaptorAlgorithm {
 /// - time: is either arrival or departure
 pub type TripAtStopTimeMap<TT: TripType> = HashMap<(TT::Id, LocalStopId, u32), DateTime<Utc>>;
 
+/// <(base_id, stop_id, visit_idx), time>
+/// - time: is relative to the start of the service day on which the recurring trip starts
+pub type RecurringTripAtStopTimeMap = HashMap<(u32, LocalStopId, u32), TimeDelta>;
+
 pub struct AnyTripAtStopTime {
     one_off: TripAtStopTimeMap<OneOff>,
-    recurring: TripAtStopTimeMap<Recurring>,
+    recurring: RecurringTripAtStopTimeMap,
 }
 
 impl AnyTripAtStopTime {
@@ -59,14 +104,74 @@ impl AnyTripAtStopTime {
         trip_id: &AnyTripId,
         stop_id: &LocalStopId,
         visit_idx: &u32,
-    ) -> Option<&DateTime<Utc>> {
+        calendar: &ServiceCalendar,
+    ) -> Option<DateTime<Utc>> {
         match trip_id {
-            AnyTripId::Recurring(trip_id) => self.recurring.get(&(*trip_id, *stop_id, *visit_idx)),
-            AnyTripId::OneOff(trip_id) => self.one_off.get(&(*trip_id, *stop_id, *visit_idx)),
+            AnyTripId::Recurring(trip_id) => {
+                let offset = self.recurring.get(&(trip_id.base_id(), *stop_id, *visit_idx))?;
+                let day_start = calendar.service_day_start(trip_id.base_id(), trip_id.starting_day())?;
+                Some(day_start + *offset)
+            }
+            AnyTripId::OneOff(trip_id) => self.one_off.get(&(*trip_id, *stop_id, *visit_idx)).copied(),
         }
     }
 }
 
+/// Corresponds to a row of calendar.txt in GTFS
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    /// Whether the service runs on a weekday, starting with monday
+    pub(crate) weekdays: [bool; 7],
+    pub(crate) start_date: NaiveDate,
+    pub(crate) end_date: NaiveDate,
+    /// Timezone of the agency, in which the service days start
+    pub(crate) timezone: Tz,
+}
+
+impl Service {
+    fn runs_on(&self, day: NaiveDate) -> bool {
+        self.start_date <= day && day <= self.end_date
+            && self.weekdays[day.weekday().num_days_from_monday() as usize]
+    }
+}
+
+/// Days on which recurring trips run
+#[derive(Default)]
+pub struct ServiceCalendar {
+    pub(crate) services: HashMap<u32, Service>,
+    /// <base_id, service_id>
+    pub(crate) trip_services: HashMap<u32, u32>,
+    /// Number of days the longest recurring trip reaches past its service day. For a trip that
+    /// departs at 25:00:00, this is 1.
+    pub(crate) max_days_after_service_day: u32,
+}
+
+impl ServiceCalendar {
+    /// Start of the service day `day` of a recurring trip. `None` if the trip doesn't run that day.
+    fn service_day_start(&self, base_id: u32, day: NaiveDate) -> Option<DateTime<Utc>> {
+        let service = self.trip_services.get(&base_id)
+            .and_then(|service_id| self.services.get(service_id))?;
+
+        service.runs_on(day).then(|| service_day_start(day, service.timezone))
+    }
+
+    /// The timezone that times should be presented in. If the services are in different
+    /// timezones, this is the timezone of the service with the lowest ID.
+    pub(crate) fn timezone(&self) -> Tz {
+        self.services.iter()
+            .min_by_key(|(service_id, _)| **service_id)
+            .map(|(_, service)| service.timezone)
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// Start of the first service day of any service
+    pub(crate) fn first_service_day_start(&self) -> Option<DateTime<Utc>> {
+        self.services.values()
+            .map(|service| service_day_start(service.start_date, service.timezone))
+            .min()
+    }
+}
+
 impl RoutingAlgorithm for RaptorAlgorithm {}
 
 impl RaptorAlgorithm {
@@ -74,6 +179,55 @@ impl RaptorAlgorithm {
         (0..self.num_stops()).map(|x| StopId(x as u32))
     }
 
+    /// The transfer provider that is suitable for the given query options
+    pub(crate) fn transfer_provider(&self, options: &QueryOptions) -> &(dyn TransferProvider + Send + Sync) {
+        match &self.accessibility.transfer_provider {
+            Some(transfer_provider) if options.wheelchair_accessible => transfer_provider.as_ref(),
+            _ => self.transfer_provider.as_ref(),
+        }
+    }
+
+    /// Local IDs of the stops of `place`, where a journey can start or arrive
+    pub(crate) fn place_stops(&self, place: &Place) -> QueryResult<Vec<LocalStopId>> {
+        self.stations.stops(place)?.into_iter()
+            .map(|stop| {
+                self.stop_mapping.try_translate_to_local(stop)
+                    .ok_or_else(|| QueryError::InvalidInput(format!("Unknown stop {}", stop.0)))
+            })
+            .collect()
+    }
+
+    /// The timezone of the timetable, in which times should be presented
+    pub fn timezone(&self) -> Tz {
+        self.calendar.timezone()
+    }
+
+    /// The location of a stop, if it is known
+    pub fn stop_location(&self, stop: GlobalStopId) -> Option<Point<f64>> {
+        self.stop_coords.get(&stop).copied()
+    }
+
+    /// IDs of the datasets that the rides of the journeys come from, in order of their first use.
+    /// Trips without a known dataset are skipped.
+    pub fn datasets<'a>(&self, journeys: impl IntoIterator<Item = &'a Journey>) -> Vec<&str> {
+        let mut datasets: Vec<&str> = vec![];
+        let trips = journeys.into_iter()
+            .flat_map(|journey| journey.legs.iter())
+            .filter_map(|leg| match leg {
+                Leg::Ride { trip: AnyTripId::Recurring(trip), .. } => Some(trip.base_id()),
+                Leg::Ride { trip: AnyTripId::OneOff(OneOffTripId(trip)), .. } => Some(*trip),
+                Leg::Transfer { .. } => None,
+            });
+        for trip in trips {
+            if let Some(dataset) = self.trip_datasets.get(&trip) {
+                if !datasets.contains(&dataset.as_ref()) {
+                    datasets.push(dataset);
+                }
+            }
+        }
+        datasets
+    }
+
     pub(crate) fn num_stops(&self) -> usize {
         // Since each stop has also got a global ID, use the number of those IDs to determine how many
         // stops there are.
@@ -94,10 +248,15 @@ impl StopMapping {
     }
 
     /// Translates a global stop ID into a local stop ID
-    // TODO: Return the result, maybe use a separate hash map to speed up the lookup?
+    // TODO: Maybe use a separate hash map to speed up the lookup?
     fn translate_to_local(&self, global_stop_id: GlobalStopId) -> LocalStopId {
-        let idx = self.0.iter().position(|stop_id| stop_id == &global_stop_id);
+        self.try_translate_to_local(global_stop_id).unwrap()
+    }
+
+    /// Translates a global stop ID into a local stop ID, `None` if the stop is unknown
+    fn try_translate_to_local(&self, global_stop_id: GlobalStopId) -> Option<LocalStopId> {
+        let idx = self.0.iter().position(|stop_id| stop_id == &global_stop_id)?;
 
-        StopId(idx.unwrap() as u32)
+        Some(StopId(idx as u32))
     }
 }
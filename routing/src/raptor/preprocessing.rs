@@ -1,8 +1,12 @@
 use crate::algorithms::initialization::{ByPreprocessing, PreprocessingError, PreprocessingInput, PreprocessingResult};
 use crate::direct_connections::DirectConnections;
-use crate::raptor::{AnyTripAtStopTime, GlobalStopId, LinesByStopMap, RaptorAlgorithm, StopMapping, StopsByLineMap, TripAtStopTimeMap, TripsByLineAndStopMap};
+use crate::raptor::{Accessibility, AnyTripAtStopTime, GlobalStopId, LinesByStopMap, RaptorAlgorithm, RecurringTripAtStopTimeMap, RecurringTripsByLineAndStopMap, Service, ServiceCalendar, StopMapping, StopsByLineMap, TripAtStopTimeMap, TripsByLineAndStopMap};
+use crate::stations::Stations;
 use crate::transfers::crow_fly::CrowFlyTransferProvider;
+use crate::transfers::TransferProvider;
 use chrono::DateTime;
+use chrono_tz::Tz;
+use geo::Point;
 use common::types::{LineId, StopId};
 #[cfg(debug_assertions)]
 use common::util::time::INFINITY;
@@ -13,9 +17,11 @@ use polars::error::PolarsError;
 use polars::prelude::*;
 #[cfg(debug_assertions)]
 use std::ops::{BitAnd, BitOr};
-use hashbrown::HashMap;
+use common::util::speed::MAX_WHEELCHAIR_SPEED;
+use hashbrown::{HashMap, HashSet};
 use log::warn;
 use common::types::trip::{OneOff, OneOffTripId};
+use std::sync::Arc;
 
 impl ByPreprocessing for RaptorAlgorithm {
     fn preprocess(
@@ -33,13 +39,12 @@ impl ByPreprocessing for RaptorAlgorithm {
 
 impl RaptorAlgorithm {
     pub fn preprocess_with_direct_connections(
-        PreprocessingInput { stops, .. }: PreprocessingInput,
-        DirectConnections {
-            expanded_lines,
-            line_progressions,
-            ..
-        }: DirectConnections,
+        PreprocessingInput { stops, trips, services, .. }: PreprocessingInput,
+        direct_connections: DirectConnections,
     ) -> PreprocessingResult<RaptorAlgorithm> {
+        let line_routes = direct_connections.route_info()?;
+        let DirectConnections { expanded_lines, line_progressions, .. } = direct_connections;
+
         let stops_vec: Vec<GlobalStopId> = stops.clone()
             .select(&[col("stop_id")]).collect()?
             .column("stop_id")?.u32()?
@@ -49,6 +54,18 @@ impl RaptorAlgorithm {
 
         let stop_mapping = StopMapping(stops_vec);
 
+        let stop_coords = {
+            let coords = stops.clone().select([col("stop_id"), col("lat"), col("lon")]).collect()?;
+            let [stop_ids, lats, lons] = coords.get_columns() else { unreachable!("we selected three columns") };
+
+            // Stops without coordinates are left out instead of being placed at (0, 0)
+            izip!(stop_ids.u32()?, lats.f32()?, lons.f32()?)
+                .filter_map(|(stop_id, lat, lon)| {
+                    Some((StopId(stop_id?), Point::new(lon? as f64, lat? as f64)))
+                })
+                .collect()
+        };
+
         let (stops_by_line, lines_by_stops) = {
             let mut stops_by_line = HashMap::default();
             let mut lines_by_stops = HashMap::default();
@@ -133,7 +150,7 @@ impl RaptorAlgorithm {
                 let arrival_time = arrival_time.unwrap();
                 let departure_time = departure_time.unwrap();
 
-                // TODO: Fix date time handling
+                // Relative to the unix epoch for now, see the conversion for recurring trips below
                 let arrival_time = DateTime::from_timestamp_millis(arrival_time).unwrap();
                 let departure_time = DateTime::from_timestamp_millis(departure_time).unwrap();
 
@@ -215,7 +232,6 @@ impl RaptorAlgorithm {
                 .zip(trips.u32()?)
                 .filter_map(|(departure, trip)| {
                     departure.map(|departure| {
-                        // TODO: Fix date conversion
                         (
                             DateTime::from_timestamp_millis(departure).unwrap(),
                             OneOffTripId(trip.unwrap()),
@@ -279,17 +295,206 @@ impl RaptorAlgorithm {
             });
         }
 
+        let stations = Stations::from_stops(&stops)?;
+        let transfer_provider = CrowFlyTransferProvider::from_stops(stops.clone())?;
+        let accessibility = Self::accessibility(&stops, &trips, &stop_mapping, &transfer_provider)?;
+        let trip_datasets = Self::trip_datasets(&trips)?;
+
+        // Times were read relative to the unix epoch. If the trips run on service days, the times
+        // are relative to the start of the service day instead.
+        let (arrivals, departures, one_off_trips_by_line_and_stop, recurring_trips_by_line_and_stop, calendar) =
+            match Self::calendar(&services, &trips)? {
+                Some(mut calendar) => {
+                    let recurring_trips_by_line_and_stop: RecurringTripsByLineAndStopMap = trips_by_line_and_stop
+                        .into_iter()
+                        .map(|(key, departures)| {
+                            let departures = departures.into_iter()
+                                .map(|(departure, OneOffTripId(trip))| (departure - DateTime::UNIX_EPOCH, trip))
+                                .collect();
+                            (key, departures)
+                        })
+                        .collect();
+
+                    calendar.max_days_after_service_day = arrivals.one_off.values()
+                        .map(|arrival| (*arrival - DateTime::UNIX_EPOCH).num_days() as u32)
+                        .max()
+                        .unwrap_or_default();
+
+                    let arrivals = AnyTripAtStopTime {
+                        one_off: HashMap::new(),
+                        recurring: relative_to_service_day(arrivals.one_off),
+                    };
+                    let departures = AnyTripAtStopTime {
+                        one_off: HashMap::new(),
+                        recurring: relative_to_service_day(departures.one_off),
+                    };
+
+                    (arrivals, departures, HashMap::new(), recurring_trips_by_line_and_stop, calendar)
+                }
+                None => (arrivals, departures, trips_by_line_and_stop, HashMap::new(), ServiceCalendar::default()),
+            };
+
         Ok(Self {
             stop_mapping,
+            stop_coords,
+            stations,
             stops_by_line,
             lines_by_stops,
+            line_routes,
             arrivals,
             departures,
-            one_off_trips_by_line_and_stop: trips_by_line_and_stop,
-            recurring_trips_by_line_and_stop: HashMap::new(), // TODO
-            transfer_provider: Box::new(CrowFlyTransferProvider::from_stops(stops)?),
+            one_off_trips_by_line_and_stop,
+            recurring_trips_by_line_and_stop,
+            calendar,
+            trip_datasets,
+            transfer_provider: Box::new(transfer_provider),
+            accessibility,
+        })
+    }
+
+    /// Reads the service calendar. Trips only recur if both the trips and the services have got
+    /// service IDs. Otherwise, `None` is returned and trips are treated as one-off trips.
+    /// Services without a timezone start their days in UTC.
+    fn calendar(services: &LazyFrame, trips: &LazyFrame) -> PreprocessingResult<Option<ServiceCalendar>> {
+        let services_schema = services.clone().collect_schema()?;
+        if !services_schema.contains("service_id") || !trips.clone().collect_schema()?.contains("service_id") {
+            return Ok(None);
+        }
+
+        let timezone = if services_schema.contains("timezone") {
+            col("timezone")
+        } else {
+            lit("UTC").alias("timezone")
+        };
+        let services_df = services.clone()
+            .select([
+                col("service_id"),
+                col("monday"), col("tuesday"), col("wednesday"), col("thursday"),
+                col("friday"), col("saturday"), col("sunday"),
+                col("start_date"), col("end_date"),
+                timezone,
+            ])
+            .collect()?;
+
+        let weekdays = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"]
+            .iter()
+            .map(|weekday| Ok(services_df.column(weekday)?.bool()?.clone()))
+            .collect::<PolarsResult<Vec<_>>>()?;
+
+        let mut services = HashMap::new();
+        for (idx, (service_id, start_date, end_date, timezone)) in izip!(
+            services_df.column("service_id")?.u32()?,
+            services_df.column("start_date")?.date()?.as_date_iter(),
+            services_df.column("end_date")?.date()?.as_date_iter(),
+            services_df.column("timezone")?.str()?,
+        ).enumerate() {
+            let (Some(service_id), Some(start_date), Some(end_date)) = (service_id, start_date, end_date) else {
+                continue;
+            };
+            let timezone = timezone.unwrap_or("UTC");
+            let timezone = timezone.parse::<Tz>()
+                .map_err(|_| PreprocessingError::InvalidTimezone(timezone.to_string()))?;
+            let weekdays = std::array::from_fn(|day| weekdays[day].get(idx).unwrap_or(false));
+
+            services.insert(service_id, Service { weekdays, start_date, end_date, timezone });
+        }
+
+        let trips_df = trips.clone().select([col("trip_id"), col("service_id")]).collect()?;
+        let trip_services = izip!(trips_df.column("trip_id")?.u32()?, trips_df.column("service_id")?.u32()?)
+            .filter_map(|(trip_id, service_id)| Some((trip_id?, service_id?)))
+            .collect();
+
+        Ok(Some(ServiceCalendar {
+            services,
+            trip_services,
+            max_days_after_service_day: 0,
+        }))
+    }
+
+    /// Collects stops and trips that are explicitly marked as not wheelchair accessible (value 2
+    /// in GTFS). If the input doesn't contain accessibility information at all, nothing is excluded
+    /// and wheelchair queries use the regular transfers.
+    fn accessibility(
+        stops: &LazyFrame,
+        trips: &LazyFrame,
+        stop_mapping: &StopMapping,
+        transfer_provider: &CrowFlyTransferProvider,
+    ) -> PreprocessingResult<Accessibility> {
+        const NO_INFORMATION: u32 = 0;
+        const NOT_ACCESSIBLE: u32 = 2;
+
+        let has_stop_information = stops.clone().collect_schema()?.contains("wheelchair_boarding")
+            && stops.clone()
+                .filter(col("wheelchair_boarding").neq(lit(NO_INFORMATION)))
+                .limit(1)
+                .collect()?
+                .height() > 0;
+
+        let inaccessible_stops = if stops.clone().collect_schema()?.contains("wheelchair_boarding") {
+            stops.clone()
+                .filter(col("wheelchair_boarding").eq(lit(NOT_ACCESSIBLE)))
+                .select([col("stop_id")])
+                .collect()?
+                .column("stop_id")?.u32()?
+                .into_iter()
+                .flatten()
+                .map(|stop_id| stop_mapping.translate_to_local(StopId(stop_id)))
+                .collect()
+        } else { HashSet::new() };
+
+        let inaccessible_trips = if trips.clone().collect_schema()?.contains("wheelchair_accessible") {
+            trips.clone()
+                .filter(col("wheelchair_accessible").eq(lit(NOT_ACCESSIBLE)))
+                .select([col("trip_id")])
+                .collect()?
+                .column("trip_id")?.u32()?
+                .into_iter()
+                .flatten()
+                .map(OneOffTripId)
+                .collect()
+        } else { HashSet::new() };
+
+        // Slower transfers only make sense where the feed actually describes which stops can be
+        // used with a wheelchair. The coordinates are shared with the regular transfer provider.
+        let transfer_provider = has_stop_information.then(|| {
+            Box::new(transfer_provider.clone().with_speed(MAX_WHEELCHAIR_SPEED))
+                as Box<dyn TransferProvider + Send + Sync>
+        });
+
+        Ok(Accessibility {
+            inaccessible_stops,
+            inaccessible_trips,
+            transfer_provider,
         })
     }
+
+    /// Reads which dataset each trip comes from. Inputs without dataset IDs (e.g. in tests) have
+    /// no datasets to attribute.
+    fn trip_datasets(trips: &LazyFrame) -> PreprocessingResult<HashMap<u32, Arc<str>>> {
+        if !trips.clone().collect_schema()?.contains("dataset_id") {
+            return Ok(HashMap::new());
+        }
+
+        let trips_df = trips.clone().select([col("trip_id"), col("dataset_id")]).collect()?;
+        // Share the ID between all trips of a dataset
+        let mut dataset_ids: HashMap<&str, Arc<str>> = HashMap::new();
+        let trip_datasets = izip!(trips_df.column("trip_id")?.u32()?, trips_df.column("dataset_id")?.str()?)
+            .filter_map(|(trip_id, dataset_id)| Some((trip_id?, dataset_id?)))
+            .map(|(trip_id, dataset_id)| {
+                let dataset_id = dataset_ids.entry(dataset_id).or_insert_with(|| Arc::from(dataset_id));
+                (trip_id, dataset_id.clone())
+            })
+            .collect();
+
+        Ok(trip_datasets)
+    }
+}
+
+/// Turns times that were read relative to the unix epoch into times relative to the service day
+fn relative_to_service_day(times: TripAtStopTimeMap<OneOff>) -> RecurringTripAtStopTimeMap {
+    times.into_iter()
+        .map(|((OneOffTripId(trip), stop, visit_idx), time)| ((trip, stop, visit_idx), time - DateTime::UNIX_EPOCH))
+        .collect()
 }
 
 #[cfg(test)]
@@ -1,40 +1,127 @@
 use crate::algorithms::errors::{MultiQueryResult, QueryError, QueryResult};
-use crate::algorithms::queries::cardinality::All;
+use crate::algorithms::queries::cardinality::{All, Single};
 use crate::algorithms::queries::earliest_arrival::{EarliestArrival, EarliestArrivalInput, EarliestArrivalOutput};
+use crate::algorithms::queries::options::QueryOptions;
 use crate::algorithms::queries::range::{Range, RangeInput, RangeOutput};
+use crate::algorithms::queries::isochrone::{Isochrone, IsochroneInput, IsochroneOutput, ReachableStop};
+use crate::algorithms::queries::via::{Via, ViaInput, ViaOutput, ViaStop};
 use crate::algorithms::queries::Queryable;
+use crate::direct_connections::RouteInfo;
 use crate::journey::Journey;
 use crate::raptor::state::RaptorState;
 use crate::raptor::{LocalStopId, RaptorAlgorithm};
 use crate::transfers::TransferError;
-use chrono::{DateTime, Duration, TimeDelta, Utc};
-use common::types::trip::AnyTripId;
+use chrono::{DateTime, Days, Duration, NaiveTime, TimeDelta, Utc};
+use common::types::trip::{AnyTripId, OneOffTripId, RecurringTripId};
 use common::types::{LineId, SeqNum, StopId};
 use common::util::time::INFINITY;
 use hashbrown::HashSet;
 use itertools::Itertools;
 
+/// Number of days after the departure of a query in which recurring trips are looked for
+const MAX_DAYS_AHEAD: u64 = 7;
+
 impl RaptorAlgorithm {
-    /// Selects the earliest trip of a line, that departs at `stop` after a given time
-    fn earliest_trip(&self, line: LineId, stop: StopId, after: DateTime<Utc>) -> Option<AnyTripId> {
-        self.one_off_trips_by_line_and_stop
+    /// Selects the earliest trip of a line, that departs at `stop` after a given time and is
+    /// allowed by the query options
+    fn earliest_trip(&self, line: LineId, stop: StopId, after: DateTime<Utc>, options: &QueryOptions) -> Option<AnyTripId> {
+        let one_off = self.one_off_trips_by_line_and_stop
             .get(&(line, stop))
             .and_then(|trips| {
-                trips.iter().find_map(|(departure, trip)| {
-                    if *departure >= after {
-                        Some(AnyTripId::OneOff(*trip))
-                    } else { None }
+                trips.iter().find(|(departure, OneOffTripId(trip))| {
+                    *departure >= after && self.trip_accessible(*trip, options)
                 })
             })
+            .map(|(departure, trip)| (*departure, AnyTripId::OneOff(*trip)));
+
+        let recurring = self.earliest_recurring_trip(line, stop, after, options);
+
+        [one_off, recurring].into_iter()
+            .flatten()
+            .min_by_key(|(departure, _)| *departure)
+            .map(|(_, trip)| trip)
+    }
+
+    /// Selects the earliest recurring trip like [`Self::earliest_trip`], together with its
+    /// departure. Only looks [`MAX_DAYS_AHEAD`] days into the future.
+    fn earliest_recurring_trip(
+        &self,
+        line: LineId,
+        stop: StopId,
+        after: DateTime<Utc>,
+        options: &QueryOptions,
+    ) -> Option<(DateTime<Utc>, AnyTripId)> {
+        let trips = self.recurring_trips_by_line_and_stop.get(&(line, stop))?;
+
+        // Trips of service days that started before `after` might still depart after it. The
+        // additional day accounts for local dates that differ from the date in UTC.
+        // Checked, since `after` can be infinity for stops that have not been reached
+        let first_day = after.date_naive().checked_sub_days(Days::new(self.calendar.max_days_after_service_day as u64 + 1))?;
+        let last_day = after.date_naive().checked_add_days(Days::new(MAX_DAYS_AHEAD))?;
+
+        let mut earliest: Option<(DateTime<Utc>, AnyTripId)> = None;
+        for day in first_day.iter_days().take_while(|day| day <= &last_day) {
+            // Service days start no earlier than 14 hours before midnight UTC (the largest offset)
+            let earliest_day_start = day.and_time(NaiveTime::MIN).and_utc() - Duration::hours(14);
+            if earliest.is_some_and(|(departure, _)| departure < earliest_day_start) {
+                break;
+            }
+
+            // Trips are sorted by their departure within the service day. Trips of a line are all
+            // from the same agency, so they share the timezone and the first match is the earliest.
+            let trip_on_day = trips.iter().find_map(|(offset, base_id)| {
+                let departure = self.calendar.service_day_start(*base_id, day)? + *offset;
+
+                (departure >= after && self.trip_accessible(*base_id, options)).then(|| {
+                    (departure, AnyTripId::Recurring(RecurringTripId::new(*base_id, day)))
+                })
+            });
+
+            if let Some((departure, trip)) = trip_on_day {
+                if earliest.is_none_or(|(earliest_departure, _)| departure < earliest_departure) {
+                    earliest = Some((departure, trip));
+                }
+            }
+        }
+
+        earliest
+    }
+
+    /// Whether a trip (or the base ID of a recurring trip) can be used with the query options
+    fn trip_accessible(&self, trip: u32, options: &QueryOptions) -> bool {
+        !options.wheelchair_accessible
+            || !self.accessibility.inaccessible_trips.contains(&OneOffTripId(trip))
+    }
+
+    /// Whether passengers can board or alight at `stop` with the given query options
+    fn stop_allowed(&self, stop: &LocalStopId, options: &QueryOptions) -> bool {
+        !options.wheelchair_accessible || !self.accessibility.inaccessible_stops.contains(stop)
+    }
+
+    /// Whether the route filters of the query options allow using `line`
+    fn line_allowed(&self, line: &LineId, options: &QueryOptions) -> bool {
+        if !options.filters_routes() {
+            return true;
+        }
+
+        match self.line_routes.get(line) {
+            Some(route) => options.route_allowed(route),
+            None => options.route_allowed(&RouteInfo::default()),
+        }
     }
 
-    fn build_queue(&self, marked_stops: &HashSet<LocalStopId>) -> HashSet<(LineId, (LocalStopId, u32))> {
+    fn build_queue(&self, marked_stops: &HashSet<LocalStopId>, options: &QueryOptions) -> HashSet<(LineId, (LocalStopId, u32))> {
         let mut queue: HashSet<(LineId, (LocalStopId, u32))> = HashSet::new();
 
-        for stop_a in marked_stops {
+        // Lines can't be boarded at stops that aren't accessible
+        for stop_a in marked_stops.iter().filter(|stop| self.stop_allowed(stop, options)) {
             if let Some(lines_serving_stop) = self.lines_by_stops.get(stop_a) {
                 // foreach line serving marked_stop (stop a)
                 for (line, seq_num_a) in lines_serving_stop {
+                    if !self.line_allowed(line, options) {
+                        continue;
+                    }
+
                     let other_stops = self.stops_by_line.get(line)
                         .unwrap_or_else(|| panic!(
                             "Line {line:?} is in lines_by_stops, so it must also be in stops_by_line."
@@ -78,13 +165,15 @@ impl RaptorAlgorithm {
         stops_on_line_after
     }
 
+    /// Runs RAPTOR from all of the `starts` at once, like from a single stop
     fn run(
         &self,
-        start: LocalStopId,
+        starts: &[LocalStopId],
         departure: DateTime<Utc>,
+        options: &QueryOptions,
     ) -> QueryResult<RaptorState> {
-        let mut state = RaptorState::init(self.num_stops(), start, departure, &self.stop_mapping);
-        let mut marked_stops: HashSet<LocalStopId> = HashSet::from([start]);
+        let mut state = RaptorState::init(self.num_stops(), starts, departure, &self.stop_mapping);
+        let mut marked_stops: HashSet<LocalStopId> = starts.iter().copied().collect();
 
         // Increase the number of legs per round
         // foreach k <- 1,2,... do
@@ -95,8 +184,15 @@ impl RaptorAlgorithm {
 
             // FIRST STAGE: Build queue of lines and stops to scan
             // queue is called "Q" in the original paper
-            let queue = self.build_queue(&marked_stops);
-            debug_assert!(!queue.is_empty(), "Queue must not be empty, since termination condition was not met");
+            let queue = self.build_queue(&marked_stops, options);
+            if queue.is_empty() {
+                // Can only happen if the query options excluded all lines at the marked stops
+                debug_assert!(
+                    options.wheelchair_accessible || options.filters_routes(),
+                    "Queue must not be empty, since termination condition was not met"
+                );
+                break;
+            }
 
             // unmark previously marked stops
             // In the original paper, this is done for each element of marked_stops individually
@@ -112,10 +208,9 @@ impl RaptorAlgorithm {
                 let mut trip: Option<AnyTripId> = None;
 
                 for (b_stop, b_visit_idx) in self.stops_on_line_after(line, a_stop, a_visit_idx) {
-                    // TODO: Fix funky date problems
                     // if t != ⊥ and ...
                     if let Some(trip) = trip {
-                        let b_arrival = self.arrivals.get(&trip, b_stop, b_visit_idx)
+                        let b_arrival = self.arrivals.get(&trip, b_stop, b_visit_idx, &self.calendar)
                             .unwrap_or_else(|| panic!(
                                 "Expected arrival for stop {b_stop:?} (visit {b_visit_idx}) to exist on trip {trip:?}"
                             ));
@@ -125,30 +220,30 @@ impl RaptorAlgorithm {
 
                         // taking the trip to b it is faster than not taking it
                         // ...and arr(t, pᵢ) < τ*(pᵢ)
-                        if b_arrival < best_b_arrival {
+                        if &b_arrival < best_b_arrival && self.stop_allowed(b_stop, options) {
                             let (boarding_stop, boarding_visit_idx) = boarding.expect("Boarding stop must not be None");
-                            let boarding_departure = self.departures.get(&trip, &boarding_stop, &boarding_visit_idx)
+                            let boarding_departure = self.departures.get(&trip, &boarding_stop, &boarding_visit_idx, &self.calendar)
                                 .unwrap_or_else(|| panic!(
                                     "Expected departure for stop {a_stop:?} (visit {boarding_visit_idx}) to exist on trip {trip:?}"
                                 ));
                             
                             //println!("boarding departure: {boarding_departure:?}");
 
-                            state.set_ride(boarding_stop, *b_stop, *boarding_departure, *b_arrival, trip.clone());
+                            state.set_ride(boarding_stop, *b_stop, boarding_departure, b_arrival, trip.clone());
                             marked_stops.insert(*b_stop);
                         }
                     }
 
                     let b_departure = trip.and_then(|trip| {
-                        self.departures.get(&trip, b_stop, b_visit_idx)
-                    }).unwrap_or(&INFINITY);
+                        self.departures.get(&trip, b_stop, b_visit_idx, &self.calendar)
+                    }).unwrap_or(INFINITY);
 
                     let prev_b_arrival = state.previous_tau(b_stop);
 
                     // Initialize trip if its None. Also execute when we can catch an earlier trip
                     // of the same line at stop b.
-                    if prev_b_arrival <= b_departure {
-                        trip = self.earliest_trip(*line, *b_stop, *prev_b_arrival);
+                    if prev_b_arrival <= &b_departure && self.stop_allowed(b_stop, options) {
+                        trip = self.earliest_trip(*line, *b_stop, *prev_b_arrival, options);
 
                         if trip.is_some() {
                             boarding = Some((*b_stop, *b_visit_idx));
@@ -160,11 +255,13 @@ impl RaptorAlgorithm {
             // THIRD STAGE: Scan transfers
             // Look at individual station-to-station transfers (like footpaths) and update
             // best_arrival when walking to a stop is faster than taking transit
-            let transfer_provider = &self.transfer_provider;
+            let transfer_provider = self.transfer_provider(options);
             // foreach marked stop p
             for start in marked_stops.clone() {
                 // foreach footpath (p, p') ∈ F
-                for end in transfer_provider.transfers_from(&start) {
+                let transfers = transfer_provider.transfers_from(&start).into_iter()
+                    .filter(|end| self.stop_allowed(end, options));
+                for end in transfers {
                     // This is the maximum amount of time a transfer will have to take in order to
                     // be faster
                     let max_duration = *state.tau(&end).unwrap_or(&INFINITY) - *state.tau(&start)
@@ -209,9 +306,10 @@ impl RaptorAlgorithm {
 
     fn run_range(
         &self,
-        start: StopId,
+        starts: &[LocalStopId],
         earliest_departure: DateTime<Utc>,
         range: TimeDelta,
+        options: &QueryOptions,
     ) -> QueryResult<RangeOutput> {
         let last_departure = earliest_departure + range;
 
@@ -221,7 +319,7 @@ impl RaptorAlgorithm {
         let mut departure = earliest_departure;
         while departure <= last_departure {
             //println!("departure: {}", departure);
-            let res_after_departure = self.run(start, departure);
+            let res_after_departure = self.run(starts, departure, options);
 
             match res_after_departure {
                 // There is a valid output of the earliest arrival query
@@ -280,12 +378,12 @@ impl RaptorAlgorithm {
 impl Queryable<EarliestArrival, All> for RaptorAlgorithm {
     fn query(
         &self,
-        EarliestArrivalInput { earliest_departure, start }: EarliestArrivalInput,
+        EarliestArrivalInput { earliest_departure, start, options }: EarliestArrivalInput,
         _: All
     ) -> MultiQueryResult<EarliestArrivalOutput> {
-        let start = self.stop_mapping.translate_to_local(start);
+        let starts = self.place_stops(&start)?;
 
-        let res_state = self.run(start, earliest_departure)?;
+        let res_state = self.run(&starts, earliest_departure, &options)?;
         let journeys = self.backtrace_all(res_state, earliest_departure)?;
         let result = journeys.into_iter()
             .map(|journey| EarliestArrivalOutput { journey })
@@ -298,30 +396,115 @@ impl Queryable<EarliestArrival, All> for RaptorAlgorithm {
 impl Queryable<Range, All> for RaptorAlgorithm {
     fn query(
         &self,
-        RangeInput { earliest_departure, range, start }: RangeInput,
+        RangeInput { earliest_departure, range, start, options }: RangeInput,
         _: All
     ) -> QueryResult<RangeOutput> {
-        let start = self.stop_mapping.translate_to_local(start);
-        Ok(self.run_range(start, earliest_departure, range)?)
+        let starts = self.place_stops(&start)?;
+        Ok(self.run_range(&starts, earliest_departure, range, &options)?)
+    }
+}
+
+impl Queryable<Via, Single> for RaptorAlgorithm {
+    /// Chains earliest arrival queries from via stop to via stop. Since arriving earlier at a via
+    /// stop never leads to a later arrival at the target, this results in the earliest arrival.
+    fn query(
+        &self,
+        ViaInput { earliest_departure, start, via, options }: ViaInput,
+        Single { target }: Single,
+    ) -> QueryResult<ViaOutput> {
+        let stops = via.into_iter()
+            .chain(std::iter::once(ViaStop { place: target, min_dwell: TimeDelta::zero() }));
+
+        let mut sections = vec![];
+        let mut current_stops = self.place_stops(&start)?;
+        let mut departure = earliest_departure;
+
+        for ViaStop { place, min_dwell } in stops {
+            let place_stops = self.place_stops(&place)?;
+            // Consecutive via stops can be the same stop, then there's nothing to route
+            if let Some(stop) = place_stops.iter().find(|stop| current_stops.contains(stop)) {
+                current_stops = vec![*stop];
+            } else {
+                let state = self.run(&current_stops, departure, &options)?;
+                // A station is reached at the stop that the journey arrives at first
+                let local_stop = place_stops.into_iter()
+                    .min_by_key(|stop| *state.best_arrival(stop))
+                    .ok_or(QueryError::NoRouteFound)?;
+                let section = state.backtrace(self.stop_mapping.translate_to_global(local_stop), departure)?;
+
+                departure = section.arrival_when_starting_at(departure)
+                    .ok_or(QueryError::NoRouteFound)?;
+                sections.push(section);
+                current_stops = vec![local_stop];
+            }
+
+            departure += min_dwell;
+        }
+
+        Ok(ViaOutput { sections })
     }
 }
+impl Queryable<Isochrone, All> for RaptorAlgorithm {
+    fn query(
+        &self,
+        IsochroneInput { earliest_departure, start, max_duration, options }: IsochroneInput,
+        _: All,
+    ) -> QueryResult<IsochroneOutput> {
+        let starts = self.place_stops(&start)?;
+        let state = self.run(&starts, earliest_departure, &options)?;
+
+        let stops = self.local_stop_ids()
+            .filter_map(|stop| {
+                let arrival = *state.best_arrival(&stop);
+                let duration = arrival - earliest_departure;
+                if arrival == INFINITY || duration > max_duration {
+                    return None;
+                }
+
+                // The first round that reached the best arrival is the minimum number of rides
+                let rides = state.k_arrivals.iter()
+                    .position(|arrivals| arrivals[stop.0 as usize] == arrival)
+                    .unwrap_or(0) as u32;
 
+                Some(ReachableStop {
+                    stop: self.stop_mapping.translate_to_global(stop),
+                    arrival,
+                    duration,
+                    transfers: rides.saturating_sub(1),
+                })
+            })
+            .sorted_by_key(|reachable| reachable.arrival)
+            .collect();
+
+        Ok(IsochroneOutput { departure: earliest_departure, max_duration, stops, timezone: chrono_tz::Tz::UTC })
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::algorithms::queries::cardinality::Multiple;
+    use crate::algorithms::queries::matrix::{Matrix, MatrixInput};
     use crate::journey::Leg;
+    use crate::algorithms::initialization::{ByPreprocessing, PreprocessingInput};
+    use chrono::NaiveDate;
+    use polars::df;
+    use polars::prelude::{AnyValue, IntoLazy, TimeUnit};
+    use std::borrow::Cow;
     use crate::raptor::tests::generate_case_4;
     use crate::raptor::{AnyTripAtStopTime, StopMapping};
     use crate::transfers::fixed_time::FixedTimeTransferProvider;
     use common::util::duration;
     use hashbrown::{HashMap, HashSet};
     use ndarray::array;
+    use common::types::route::RouteType;
     use common::types::trip::OneOffTripId;
 
     fn case1() -> RaptorAlgorithm {
         RaptorAlgorithm {
             stop_mapping: StopMapping(vec![0, 1].into_iter().map(|x| StopId(x)).collect()),
+            stop_coords: HashMap::new(),
+            stations: Default::default(),
             stops_by_line: HashMap::from([
                 (LineId(0), vec![(StopId(0), 0), (StopId(1), 0)])
             ]),
@@ -329,6 +512,7 @@ mod tests {
                 (StopId(0), HashSet::from([(LineId(0), SeqNum(0))])),
                 (StopId(1), HashSet::from([(LineId(0), SeqNum(1))])),
             ]),
+            line_routes: HashMap::new(),
             arrivals: AnyTripAtStopTime {
                 one_off: HashMap::from([
                     ((OneOffTripId(0), StopId(1), 0), DateTime::<Utc>::from_timestamp(500, 0).unwrap())
@@ -345,18 +529,23 @@ mod tests {
                 ((LineId(0), StopId(0)), vec![(DateTime::<Utc>::from_timestamp(100, 0).unwrap(), OneOffTripId(0))]),
             ]),
             recurring_trips_by_line_and_stop: HashMap::new(),
+            calendar: Default::default(),
+            trip_datasets: HashMap::new(),
             transfer_provider: Box::new(FixedTimeTransferProvider {
                 duration_matrix: array![
                     [Duration::zero(), Duration::max_value(),],
                     [Duration::max_value(), Duration::zero(),],
                 ]
             }),
+            accessibility: Default::default(),
         }
     }
 
     fn case2() -> RaptorAlgorithm {
         RaptorAlgorithm {
             stop_mapping: StopMapping(vec![0, 1, 2].into_iter().map(|x| StopId(x)).collect()),
+            stop_coords: HashMap::new(),
+            stations: Default::default(),
             stops_by_line: HashMap::from([
                 (LineId(0), vec![(StopId(0), 0), (StopId(1), 0)]),
                 (LineId(1), vec![(StopId(1), 0), (StopId(2), 0)]),
@@ -366,6 +555,7 @@ mod tests {
                 (StopId(1), HashSet::from([(LineId(0), SeqNum(1)), (LineId(1), SeqNum(0))])),
                 (StopId(2), HashSet::from([(LineId(1), SeqNum(1))])),
             ]),
+            line_routes: HashMap::new(),
             arrivals: AnyTripAtStopTime {
                 one_off: HashMap::from([
                     ((OneOffTripId(0), StopId(1), 0), DateTime::<Utc>::from_timestamp(500, 0).unwrap()),
@@ -385,6 +575,8 @@ mod tests {
                 ((LineId(1), StopId(1)), vec![(DateTime::<Utc>::from_timestamp(1000, 0).unwrap(), OneOffTripId(1))]),
             ]),
             recurring_trips_by_line_and_stop: HashMap::new(),
+            calendar: Default::default(),
+            trip_datasets: HashMap::new(),
             transfer_provider: Box::new(FixedTimeTransferProvider {
                 duration_matrix: array![
                     [Duration::zero(), duration::INFINITY, duration::INFINITY,],
@@ -392,6 +584,7 @@ mod tests {
                     [duration::INFINITY, duration::INFINITY, Duration::zero(),],
                 ]
             }),
+            accessibility: Default::default(),
         }
     }
 
@@ -410,26 +603,26 @@ mod tests {
         let raptor = case2();
 
         assert_eq!(
-            raptor.earliest_trip(LineId(0), StopId(0), DateTime::<Utc>::from_timestamp(0, 0).unwrap()),
+            raptor.earliest_trip(LineId(0), StopId(0), DateTime::<Utc>::from_timestamp(0, 0).unwrap(), &QueryOptions::default()),
             Some(OneOffTripId(0).into())
         );
         assert_eq!(
-            raptor.earliest_trip(LineId(0), StopId(0), DateTime::<Utc>::from_timestamp(100, 0).unwrap()),
+            raptor.earliest_trip(LineId(0), StopId(0), DateTime::<Utc>::from_timestamp(100, 0).unwrap(), &QueryOptions::default()),
             Some(OneOffTripId(0).into())
         );
         assert_eq!(
-            raptor.earliest_trip(LineId(0), StopId(0), DateTime::<Utc>::from_timestamp(100, 1).unwrap()),
+            raptor.earliest_trip(LineId(0), StopId(0), DateTime::<Utc>::from_timestamp(100, 1).unwrap(), &QueryOptions::default()),
             None
         );
 
         // Stop 2 is not served by Line 0
         assert_eq!(
-            raptor.earliest_trip(LineId(0), StopId(2), DateTime::<Utc>::from_timestamp(0, 1).unwrap()),
+            raptor.earliest_trip(LineId(0), StopId(2), DateTime::<Utc>::from_timestamp(0, 1).unwrap(), &QueryOptions::default()),
             None
         );
         // Stop 2 is the terminus of Line 1, so there is no trip departing from there at any time
         assert_eq!(
-            raptor.earliest_trip(LineId(1), StopId(2), DateTime::<Utc>::from_timestamp(0, 1).unwrap()),
+            raptor.earliest_trip(LineId(1), StopId(2), DateTime::<Utc>::from_timestamp(0, 1).unwrap(), &QueryOptions::default()),
             None
         );
     }
@@ -439,7 +632,7 @@ mod tests {
         let dep0 = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
 
         let raptor = generate_case_4();
-        let res = raptor.run(StopId(0), dep0).unwrap();
+        let res = raptor.run(&[StopId(0)], dep0, &QueryOptions::default()).unwrap();
 
         // The k value that is reached after finding a way to all other stops
         // It's 3 since going to 1 or 4 takes two legs, going to 2 or 3 just takes one leg, and we
@@ -469,6 +662,159 @@ mod tests {
         // TODO: Test connection index
     }
 
+    #[test]
+    fn test_final_state_without_rail() {
+        let dep0 = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+
+        // Make the express line a long distance train
+        let mut raptor = generate_case_4();
+        raptor.line_routes = HashMap::from([
+            (LineId(130), RouteInfo { route_type: Some(RouteType(102)), ..Default::default() }),
+        ]);
+        let no_rail = QueryOptions {
+            excluded_route_types: HashSet::from([RouteType::RAIL]),
+            ..Default::default()
+        };
+
+        let res = raptor.run(&[StopId(0)], dep0, &no_rail).unwrap();
+
+        // Stop 3: Without the express line, the fastest way is 0 --100_1--> 3
+        assert_eq!(res.best_arrivals[3], DateTime::<Utc>::from_timestamp(300, 0).unwrap());
+        // Stop 4: 0 --100_1--> 2 --120_2--> 4, since walking from 3 now arrives too late
+        assert_eq!(res.best_arrivals[4], DateTime::<Utc>::from_timestamp(700, 0).unwrap());
+    }
+
+    #[test]
+    fn test_query_isochrone() {
+        let raptor = generate_case_4();
+        let departure = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        let input = IsochroneInput {
+            earliest_departure: departure,
+            start: StopId(0).into(),
+            max_duration: Duration::seconds(300),
+            options: QueryOptions::default(),
+        };
+
+        let actual = Queryable::<Isochrone, All>::query(&raptor, input, All {}).unwrap();
+
+        let reachable = |stop: u32, seconds: i64, transfers: u32| ReachableStop {
+            stop: StopId(stop),
+            arrival: DateTime::<Utc>::from_timestamp(seconds, 0).unwrap(),
+            duration: Duration::seconds(seconds),
+            transfers,
+        };
+        // Stop 4 is only reached after 660s
+        assert_eq!(actual.stops, vec![
+            reachable(0, 0, 0),
+            reachable(2, 100, 0),
+            reachable(1, 150, 1),
+            reachable(3, 250, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_query_matrix() {
+        let raptor = generate_case_4();
+        let input = MatrixInput {
+            origins: vec![StopId(0), StopId(3)],
+            earliest_departure: DateTime::<Utc>::from_timestamp(0, 0).unwrap(),
+            range: None,
+            interval: Duration::minutes(1),
+            max_duration: Duration::seconds(300),
+            options: QueryOptions::default(),
+        };
+        let targets = Multiple { targets: Cow::Owned(vec![StopId(2), StopId(3), StopId(4)]) };
+
+        let actual = Queryable::<Matrix, Multiple>::query(&raptor, input, targets).unwrap();
+
+        // Stop 4 can't be reached within 300s from either origin
+        assert_eq!(actual.travel_times, vec![
+            vec![Some(2), Some(5), None],
+            vec![Some(2), Some(0), None],
+        ]);
+    }
+
+    #[test]
+    fn test_query_matrix_invalid_input() {
+        let raptor = generate_case_4();
+        let input = |origins: Vec<StopId>, range: Duration| MatrixInput {
+            origins,
+            earliest_departure: DateTime::<Utc>::from_timestamp(0, 0).unwrap(),
+            range: Some(range),
+            interval: Duration::minutes(1),
+            max_duration: Duration::seconds(300),
+            options: QueryOptions::default(),
+        };
+        let targets = || Multiple { targets: Cow::Owned(vec![StopId(2)]) };
+
+        let unknown_origin = Queryable::<Matrix, Multiple>::query(&raptor, input(vec![StopId(42)], Duration::zero()), targets());
+        assert!(matches!(unknown_origin, Err(QueryError::InvalidInput(_))));
+
+        let too_many_samples = Queryable::<Matrix, Multiple>::query(&raptor, input(vec![StopId(0)], Duration::days(1)), targets());
+        assert!(matches!(too_many_samples, Err(QueryError::InvalidInput(_))));
+    }
+
+    /// A single service on the day daylight saving time starts in Berlin, so the service day
+    /// starts at 23:00 the day before
+    fn case_dst() -> RaptorAlgorithm {
+        let time = |hours: i64, minutes: i64| AnyValue::Duration((hours * 60 + minutes) * 60_000, TimeUnit::Milliseconds);
+        let day = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+
+        let input = PreprocessingInput {
+            services: df![
+                "service_id" => [0u32],
+                "monday" => [true], "tuesday" => [true], "wednesday" => [true], "thursday" => [true],
+                "friday" => [true], "saturday" => [true], "sunday" => [true],
+                "start_date" => [day],
+                "end_date" => [day],
+                "timezone" => ["Europe/Berlin"],
+            ].unwrap().lazy(),
+            stops: df![
+                "stop_id" => [0u32, 1],
+                "lat" => [0f32, 45.0],
+                "lon" => [0f32, 45.0],
+            ].unwrap().lazy(),
+            trips: df![
+                "trip_id" => [0u32, 1],
+                "service_id" => [0u32, 0],
+            ].unwrap().lazy(),
+            stop_times: df![
+                "trip_id" => [0u32, 0, 1, 1],
+                "stop_id" => [0u32, 1, 0, 1],
+                "arrival_time" => [time(8, 0), time(9, 0), time(25, 0), time(25, 30)],
+                "departure_time" => [time(8, 0), time(9, 0), time(25, 0), time(25, 30)],
+                "stop_sequence" => [0u32, 1, 0, 1],
+            ].unwrap().lazy(),
+        };
+
+        RaptorAlgorithm::preprocess(input, false).unwrap()
+    }
+
+    #[test]
+    fn test_query_recurring_across_dst() {
+        let raptor = case_dst();
+        let utc = |time: &str| DateTime::parse_from_rfc3339(time).unwrap().to_utc();
+        let arrival_at_1 = |departure: &str| {
+            let input = IsochroneInput {
+                earliest_departure: utc(departure),
+                start: StopId(0).into(),
+                max_duration: Duration::hours(3),
+                options: QueryOptions::default(),
+            };
+            Queryable::<Isochrone, All>::query(&raptor, input, All {}).unwrap().stops.into_iter()
+                .find(|reachable| reachable.stop == StopId(1))
+                .map(|reachable| reachable.arrival)
+        };
+
+        assert_eq!(raptor.timezone(), chrono_tz::Europe::Berlin);
+        // 09:00 is relative to 23:00 of the previous day, which happens to be 09:00 local time
+        assert_eq!(arrival_at_1("2024-03-31T07:30:00+02:00"), Some(utc("2024-03-31T09:00:00+02:00")));
+        // 25:30 of the service day is at 01:30 on the next day
+        assert_eq!(arrival_at_1("2024-03-31T23:30:00+02:00"), Some(utc("2024-04-01T01:30:00+02:00")));
+        // The service doesn't run on the next day
+        assert_eq!(arrival_at_1("2024-04-01T07:30:00+02:00"), None);
+    }
+
     #[test]
     fn test_backtrace_all() {
         let state = RaptorState {
@@ -515,7 +861,7 @@ mod tests {
         // Query a too short range starting from 0
         let res = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(98), start: StopId(0) },
+            RangeInput { earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(98), start: StopId(0).into(), options: QueryOptions::default() },
             All {}
         );
         assert!(matches!(res, Err(QueryError::NoRouteFound)));
@@ -523,7 +869,7 @@ mod tests {
         // Query a longer range starting from 0
         let res = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(101), start: StopId(0) },
+            RangeInput { earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(101), start: StopId(0).into(), options: QueryOptions::default() },
             All {}
         ).unwrap();
         assert_eq!(res.journeys, HashSet::from([Journey::from( vec![case1_trip0_leg0()] )]));
@@ -531,7 +877,7 @@ mod tests {
         // query later, after missing the only connection there is
         let res = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { earliest_departure: DateTime::<Utc>::from_timestamp(300, 0).unwrap(), range: Duration::weeks(42), start: StopId(0) },
+            RangeInput { earliest_departure: DateTime::<Utc>::from_timestamp(300, 0).unwrap(), range: Duration::weeks(42), start: StopId(0).into(), options: QueryOptions::default() },
             All {}
         );
         assert!(matches!(res, Err(QueryError::NoRouteFound)));
@@ -545,7 +891,7 @@ mod tests {
 
         let actual = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { start: StopId(0), earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(100) },
+            RangeInput { start: StopId(0).into(), earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(100), options: QueryOptions::default() },
             All {}
         ).unwrap();
 
@@ -578,6 +924,84 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    ///   0 ---Ride--> 1 ---Ride--> 2
+    /// where either the second trip or stop 1 can't be used with a wheelchair
+    #[test]
+    fn test_query_range_wheelchair_accessible() {
+        let accessible = QueryOptions { wheelchair_accessible: true, ..Default::default() };
+        let input = |options: QueryOptions| RangeInput {
+            start: StopId(0).into(),
+            earliest_departure: DateTime::UNIX_EPOCH,
+            range: Duration::seconds(100),
+            options,
+        };
+
+        let mut raptor = case2();
+        raptor.accessibility.inaccessible_trips = HashSet::from([OneOffTripId(1)]);
+
+        // Without the accessibility flag, nothing changes
+        let actual = Queryable::<Range, All>::query(&raptor, input(QueryOptions::default()), All {}).unwrap();
+        assert_eq!(actual.journeys.len(), 2);
+
+        // The second trip must not be used
+        let actual = Queryable::<Range, All>::query(&raptor, input(accessible.clone()), All {}).unwrap();
+        assert_eq!(actual.journeys, HashSet::from([Journey::from(vec![case1_trip0_leg0()])]));
+
+        // Stop 1 can neither be used for alighting nor for changing trips
+        let mut raptor = case2();
+        raptor.accessibility.inaccessible_stops = HashSet::from([StopId(1)]);
+
+        let actual = Queryable::<Range, All>::query(&raptor, input(accessible), All {});
+        assert!(matches!(actual, Err(QueryError::NoRouteFound)));
+    }
+
+    ///   0 ---Ride--> 1 ---Ride--> 2
+    /// where no line at the start matches the route filter
+    #[test]
+    fn test_query_range_all_lines_filtered() {
+        let raptor = case2();
+        let rail_only = QueryOptions { route_types: Some(HashSet::from([RouteType::RAIL])), ..Default::default() };
+        let input = RangeInput {
+            start: StopId(0).into(),
+            earliest_departure: DateTime::UNIX_EPOCH,
+            range: Duration::seconds(100),
+            options: rail_only,
+        };
+
+        let actual = Queryable::<Range, All>::query(&raptor, input, All {});
+        assert!(matches!(actual, Err(QueryError::NoRouteFound)));
+    }
+
+    ///   0 ---Ride--> 1 ---Ride--> 2
+    /// with a stay at 1 in between
+    #[test]
+    fn test_query_via() {
+        let raptor = case2();
+        let input = |min_dwell: TimeDelta| ViaInput {
+            earliest_departure: DateTime::UNIX_EPOCH,
+            start: StopId(0).into(),
+            via: vec![ViaStop { place: StopId(1).into(), min_dwell }],
+            options: QueryOptions::default(),
+        };
+
+        // Trip 1 departs 500s after trip 0 arrives at 1
+        let actual = Queryable::<Via, Single>::query(&raptor, input(Duration::seconds(300)), Single { target: StopId(2).into() }).unwrap();
+        assert_eq!(actual, ViaOutput { sections: vec![
+            Journey::from(vec![case1_trip0_leg0()]),
+            Journey::from(vec![Leg::Ride {
+                trip: OneOffTripId(1).into(),
+                boarding_stop: StopId(1),
+                alight_stop: StopId(2),
+                boarding_time: DateTime::<Utc>::from_timestamp(1000, 0).unwrap(),
+                alight_time: DateTime::<Utc>::from_timestamp(1500, 0).unwrap(),
+            }]),
+        ] });
+
+        // Staying at 1 for too long misses trip 1
+        let actual = Queryable::<Via, Single>::query(&raptor, input(Duration::seconds(600)), Single { target: StopId(2).into() });
+        assert!(matches!(actual, Err(QueryError::NoRouteFound)));
+    }
+
     ///   0 ---Ride--> 1
     ///   0 ---Ride--> 1 ---Transfer--> 2
     ///   0 ---Ride--> 1 ---Transfer--> 2 ---Ride--> 3
@@ -587,6 +1011,8 @@ mod tests {
 
         let raptor = RaptorAlgorithm {
             stop_mapping: StopMapping(vec![0, 1, 2, 3].into_iter().map(|x| StopId(x)).collect()),
+            stop_coords: HashMap::new(),
+            stations: Default::default(),
             stops_by_line: HashMap::from([
                 (LineId(0), vec![(StopId(0), 0), (StopId(1), 0)]),
                 (LineId(1), vec![(StopId(2), 0), (StopId(3), 0)]),
@@ -597,6 +1023,7 @@ mod tests {
                 (StopId(2), HashSet::from([(LineId(1), SeqNum(0))])),
                 (StopId(3), HashSet::from([(LineId(1), SeqNum(1))])),
             ]),
+            line_routes: HashMap::new(),
             arrivals: AnyTripAtStopTime {
                 one_off: HashMap::from([
                     ((OneOffTripId(0), StopId(1), 0), DateTime::<Utc>::from_timestamp(500, 0).unwrap()),
@@ -616,6 +1043,8 @@ mod tests {
                 ((LineId(1), StopId(2)), vec![(DateTime::<Utc>::from_timestamp(1000, 0).unwrap(), OneOffTripId(1))]),
             ]),
             recurring_trips_by_line_and_stop: HashMap::new(),
+            calendar: Default::default(),
+            trip_datasets: HashMap::new(),
             transfer_provider: Box::new(FixedTimeTransferProvider {
                 duration_matrix: array![
                         [Duration::zero(),   duration::INFINITY, duration::INFINITY, duration::INFINITY],
@@ -624,11 +1053,12 @@ mod tests {
                         [duration::INFINITY, duration::INFINITY, duration::INFINITY, Duration::zero()  ],
                     ]
             }),
+            accessibility: Default::default(),
         };
 
         let actual = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { start: StopId(0), earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(101) },
+            RangeInput { start: StopId(0).into(), earliest_departure: DateTime::UNIX_EPOCH, range: Duration::seconds(101), options: QueryOptions::default() },
             All {}
         ).unwrap();
 
@@ -683,7 +1113,7 @@ mod tests {
         // Takes 250s + 410s = 660s
         let actual = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { start: StopId(0), earliest_departure: dep0, range: Duration::seconds(1) },
+            RangeInput { start: StopId(0).into(), earliest_departure: dep0, range: Duration::seconds(1), options: QueryOptions::default() },
             All {}
         ).unwrap();
 
@@ -716,7 +1146,7 @@ mod tests {
         // 0@20s   ---Ride(100_1)-->   3@300s   ---Transfer-->   4@710s
         let actual = Queryable::<Range, All>::query(
             &raptor,
-            RangeInput { start: StopId(0), earliest_departure: DateTime::<Utc>::from_timestamp(1, 0).unwrap(), range: Duration::seconds(20) },
+            RangeInput { start: StopId(0).into(), earliest_departure: DateTime::<Utc>::from_timestamp(1, 0).unwrap(), range: Duration::seconds(20), options: QueryOptions::default() },
             All {}
         ).unwrap();
 
@@ -19,14 +19,15 @@ pub struct RaptorState<'a> {
 }
 
 impl <'a> RaptorState<'a> {
-    pub fn init(num_stops: usize, start: LocalStopId, departure: DateTime<Utc>, stop_mapping: &'a StopMapping) -> Self {
+    /// Journeys can start at any of the `starts`, e.g. at all platforms of a station
+    pub fn init(num_stops: usize, starts: &[LocalStopId], departure: DateTime<Utc>, stop_mapping: &'a StopMapping) -> Self {
         let initial_taus = (0..num_stops)
             .map(|idx|
-                if start.0 as usize != idx {
+                if !starts.iter().any(|start| start.0 as usize == idx) {
                     // Set initial earliest arrivals to "infinity"
                     DateTime::<Utc>::MAX_UTC
                 } else {
-                    // Set the departure node to instant departure
+                    // Set the departure nodes to instant departure
                     departure
                 }
             )
@@ -234,7 +235,7 @@ mod tests {
     fn test_init() {
         let departure = DateTime::from_str("2042-06-24T12:00:00Z").unwrap();
         let stop_mapping = StopMapping(vec![StopId(0), StopId(1), StopId(2), StopId(3)]);
-        let mut res = RaptorState::init(4, StopId(2), departure, &stop_mapping);
+        let mut res = RaptorState::init(4, &[StopId(2)], departure, &stop_mapping);
 
         assert_eq!(res.k, 0);
         res.new_round();
@@ -258,7 +259,7 @@ mod tests {
     fn test_new_round() {
         let departure = DateTime::from_str("2042-06-24T12:00:00Z").unwrap();
         let stop_mapping = StopMapping(vec![StopId(42), StopId(31)]);
-        let mut state = RaptorState::init(2, StopId(0), departure, &stop_mapping);
+        let mut state = RaptorState::init(2, &[StopId(0)], departure, &stop_mapping);
 
         assert_eq!(state.tau(&StopId(0)), Some(&departure));
         assert_eq!(state.tau(&StopId(1)), Some(&DateTime::<Utc>::MAX_UTC));
@@ -34,6 +34,8 @@ pub(crate) fn generate_case_4() -> RaptorAlgorithm {
 
     RaptorAlgorithm {
         stop_mapping: StopMapping(vec![0, 1, 2, 3, 4].into_iter().map(StopId).collect()),
+        stop_coords: HashMap::new(),
+        stations: Default::default(),
         stops_by_line: HashMap::from([
             // Line 100: 0 --> 2 --> 3
             (LineId(100), vec![(StopId(0), 0), (StopId(2), 0), (StopId(3), 0)]),
@@ -51,6 +53,7 @@ pub(crate) fn generate_case_4() -> RaptorAlgorithm {
             (StopId(3), HashSet::from([(LineId(100), SeqNum(2)), (LineId(101), SeqNum(0)), (LineId(130), SeqNum(1))])),
             (StopId(4), HashSet::from([(LineId(120), SeqNum(2))])),
         ]),
+        line_routes: HashMap::new(),
         arrivals: AnyTripAtStopTime {
             one_off: HashMap::from([
                 // Line 100
@@ -105,6 +108,8 @@ pub(crate) fn generate_case_4() -> RaptorAlgorithm {
             ((LineId(130), StopId(0)), vec![(dep0, OneOffTripId(130_1))]),
         ]),
         recurring_trips_by_line_and_stop: HashMap::new(),
+        calendar: Default::default(),
+        trip_datasets: HashMap::new(),
         transfer_provider: Box::new(FixedTimeTransferProvider {
             duration_matrix: array![
                 [Duration::zero(), INFINITY, INFINITY,  INFINITY, INFINITY],
@@ -113,6 +118,7 @@ pub(crate) fn generate_case_4() -> RaptorAlgorithm {
                 [INFINITY, INFINITY, INFINITY, Duration::zero(), duration_3_to_4  ],
                 [INFINITY, INFINITY, INFINITY, duration_3_to_4,  Duration::zero()  ],
             ]
-        })
+        }),
+        accessibility: Default::default(),
     }
 }
\ No newline at end of file
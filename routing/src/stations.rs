@@ -0,0 +1,43 @@
+use crate::algorithms::errors::{QueryError, QueryResult};
+use common::types::station::{Place, StationId};
+use common::types::StopId;
+use hashbrown::HashMap;
+use itertools::izip;
+use polars::error::PolarsResult;
+use polars::prelude::{col, LazyFrame};
+
+/// The stops of each station, so that queries can start at all platforms of a station at once
+#[derive(Default, Debug)]
+pub struct Stations {
+    pub(crate) stops_by_station: HashMap<StationId, Vec<StopId>>,
+}
+
+impl Stations {
+    /// Reads the station of each stop from the column `station_id`. Stops that were not grouped
+    /// into stations (e.g. in tests) have no stations.
+    pub fn from_stops(stops: &LazyFrame) -> PolarsResult<Self> {
+        if !stops.clone().collect_schema()?.contains("station_id") {
+            return Ok(Self::default());
+        }
+
+        let stops_df = stops.clone().select([col("stop_id"), col("station_id")]).collect()?;
+        let mut stops_by_station: HashMap<StationId, Vec<StopId>> = HashMap::new();
+        for (stop_id, station_id) in izip!(stops_df.column("stop_id")?.u32()?, stops_df.column("station_id")?.u32()?) {
+            if let (Some(stop_id), Some(station_id)) = (stop_id, station_id) {
+                stops_by_station.entry(StationId(station_id)).or_default().push(StopId(stop_id));
+            }
+        }
+
+        Ok(Self { stops_by_station })
+    }
+
+    /// The stops of `place`, where a journey can start or arrive
+    pub fn stops(&self, place: &Place) -> QueryResult<Vec<StopId>> {
+        match place {
+            Place::Stop(stop) => Ok(vec![*stop]),
+            Place::Station(station) => self.stops_by_station.get(station)
+                .cloned()
+                .ok_or_else(|| QueryError::InvalidInput(format!("Unknown station {station}"))),
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use crate::stp::ScalableTransferPatternsAlgorithm;
+use crate::tp::transfer_pattern_ds::dag::QueryGraph;
+use common::types::StopId;
+use geo::{Distance, Haversine, Point};
+use hashbrown::HashMap;
+use itertools::{izip, Itertools};
+use polars::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSecondsWithFrac};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
+
+/// The long-distance layer of Scalable Transfer Patterns: Long-distance stations are connected if
+/// a line goes from one to the other without another long-distance station in between. Edges are
+/// weighted by their distance in meters.
+///
+/// Nodes are numbered continuously. The edges of node `n` are
+/// `edge_offsets[n]..edge_offsets[n + 1]`.
+#[derive(Debug, Clone)]
+pub struct LongDistanceLayer {
+    stations: Vec<StopId>,
+    nodes: HashMap<StopId, u32>,
+    /// Cluster of each node
+    clusters: Vec<u32>,
+    num_clusters: u32,
+    edge_offsets: Vec<u32>,
+    edge_targets: Vec<u32>,
+    edge_weights: Vec<u32>,
+}
+
+/// Arc flags over the clusters: An edge has the flag of a cluster if it is on a shortest path to
+/// any station of that cluster. Searches towards a station only need to follow the edges with
+/// the flag of its cluster, which keeps cross-country searches from exploring the whole layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArcFlags {
+    words_per_edge: usize,
+    /// `words_per_edge` words per edge, with one bit per cluster
+    flags: Vec<u64>,
+}
+
+/// Result of a search in the long-distance layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct LongDistanceSearch {
+    /// In meters, `None` if the target can't be reached
+    pub distance: Option<u32>,
+    /// The connections that the search looked at
+    pub query_graph: QueryGraph,
+}
+
+/// Compares long-distance queries of Scalable Transfer Patterns with and without arc flags
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArcFlagsBenchmark {
+    pub num_queries: usize,
+    /// Queries whose distance differs with arc flags. Anything but zero is a bug.
+    pub num_different_distances: usize,
+    pub avg_query_graph_edges_without: f32,
+    pub avg_query_graph_edges_with: f32,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub duration_without: Duration,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub duration_with: Duration,
+}
+
+impl LongDistanceLayer {
+    /// Builds the layer from the lines (see [`crate::direct_connections::LineProgressionFrame`]),
+    /// the long-distance stations (column `stop_id`) and the cluster of each stop. Stations without
+    /// a cluster or location are left out.
+    pub fn new(
+        line_progressions: &DataFrame,
+        long_distance_stations: &DataFrame,
+        stops: &LazyFrame,
+        stop_ids_with_clusters: &DataFrame,
+    ) -> PolarsResult<Self> {
+        let long_distance_stations = long_distance_stations.clone().lazy()
+            .select([col("stop_id")])
+            .inner_join(stop_ids_with_clusters.clone().lazy(), col("stop_id"), col("stop_id"))
+            .inner_join(stops.clone().select([col("stop_id"), col("lat"), col("lon")]), col("stop_id"), col("stop_id"))
+            .sort(["stop_id"], Default::default())
+            .collect()?;
+
+        let mut stations = vec![];
+        let mut nodes = HashMap::with_capacity(long_distance_stations.height());
+        let mut clusters = vec![];
+        let mut locations = vec![];
+        for (stop_id, cluster_id, lat, lon) in izip!(
+            long_distance_stations.column("stop_id")?.u32()?,
+            long_distance_stations.column("cluster_id")?.u32()?,
+            long_distance_stations.column("lat")?.f32()?,
+            long_distance_stations.column("lon")?.f32()?,
+        ) {
+            let (Some(stop_id), Some(cluster_id), Some(lat), Some(lon)) = (stop_id, cluster_id, lat, lon) else {
+                continue;
+            };
+            nodes.insert(StopId(stop_id), stations.len() as u32);
+            stations.push(StopId(stop_id));
+            clusters.push(cluster_id);
+            locations.push(Point::new(lon as f64, lat as f64));
+        }
+        let num_clusters = clusters.iter().max().map_or(0, |max| max + 1);
+
+        // Connect consecutive long-distance stations of each line
+        let line_progressions = line_progressions.clone().lazy()
+            .sort(["line_id", "stop_sequence"], Default::default())
+            .collect()?;
+        let mut weights: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut previous: Option<(u32, u32)> = None;
+        for (line_id, stop_id) in izip!(
+            line_progressions.column("line_id")?.u32()?,
+            line_progressions.column("stop_id")?.u32()?,
+        ) {
+            let (Some(line_id), Some(stop_id)) = (line_id, stop_id) else { continue };
+            let Some(node) = nodes.get(&StopId(stop_id)).copied() else { continue };
+
+            if let Some((previous_line_id, previous_node)) = previous {
+                if previous_line_id == line_id && previous_node != node {
+                    let distance = Haversine::distance(locations[previous_node as usize], locations[node as usize]);
+                    let weight = weights.entry((previous_node, node)).or_insert(u32::MAX);
+                    *weight = (*weight).min(distance.round() as u32);
+                }
+            }
+            previous = Some((line_id, node));
+        }
+
+        let mut edges: Vec<((u32, u32), u32)> = weights.into_iter().collect();
+        edges.sort_unstable();
+        let mut edge_offsets = vec![0u32; stations.len() + 1];
+        for ((from, _), _) in &edges {
+            edge_offsets[*from as usize + 1] += 1;
+        }
+        for node in 0..stations.len() {
+            edge_offsets[node + 1] += edge_offsets[node];
+        }
+
+        Ok(Self {
+            stations,
+            nodes,
+            clusters,
+            num_clusters,
+            edge_offsets,
+            edge_targets: edges.iter().map(|((_, to), _)| *to).collect(),
+            edge_weights: edges.iter().map(|(_, weight)| *weight).collect(),
+        })
+    }
+
+    pub fn num_stations(&self) -> usize {
+        self.stations.len()
+    }
+
+    pub fn num_edges(&self) -> usize {
+        self.edge_targets.len()
+    }
+
+    fn edges(&self, node: u32) -> std::ops::Range<usize> {
+        self.edge_offsets[node as usize] as usize..self.edge_offsets[node as usize + 1] as usize
+    }
+
+    /// The long-distance stations of a cluster
+    pub fn stations_of_cluster(&self, cluster_id: u32) -> impl Iterator<Item = StopId> + '_ {
+        self.stations.iter().zip(&self.clusters)
+            .filter(move |(_, cluster)| **cluster == cluster_id)
+            .map(|(station, _)| *station)
+    }
+
+    /// Searches the shortest path from `source` to `target` with Dijkstra's algorithm. With
+    /// `arc_flags`, only edges towards the cluster of `target` are followed.
+    pub fn search(&self, source: StopId, target: StopId, arc_flags: Option<&ArcFlags>) -> Option<LongDistanceSearch> {
+        let (distance, edges) = self.search_between(&[source], &[target], arc_flags)?;
+        Some(LongDistanceSearch { distance, query_graph: QueryGraph::from_edges(source, target, edges) })
+    }
+
+    /// Searches the shortest paths from any of `sources` until all `targets` are reached, and
+    /// returns the distance to the closest target along with the edges that were looked at. The
+    /// targets have to be in the same cluster: with `arc_flags`, only edges towards that cluster
+    /// are followed. `None` if none of the sources or targets are long-distance stations.
+    pub(crate) fn search_between(
+        &self,
+        sources: &[StopId],
+        targets: &[StopId],
+        arc_flags: Option<&ArcFlags>,
+    ) -> Option<(Option<u32>, Vec<(StopId, StopId)>)> {
+        let source_nodes = sources.iter().filter_map(|source| self.nodes.get(source).copied()).collect::<Vec<_>>();
+        let mut target_nodes = targets.iter().filter_map(|target| self.nodes.get(target).copied()).collect::<Vec<_>>();
+        if source_nodes.is_empty() || target_nodes.is_empty() {
+            return None;
+        }
+        let target_cluster = self.clusters[target_nodes[0] as usize];
+
+        let mut distances = vec![u32::MAX; self.stations.len()];
+        let mut edges: Vec<(StopId, StopId)> = vec![];
+        let mut queue = BinaryHeap::new();
+        for source_node in source_nodes {
+            distances[source_node as usize] = 0;
+            queue.push(Reverse((0, source_node)));
+        }
+
+        let mut distance = None;
+        while let Some(Reverse((node_distance, node))) = queue.pop() {
+            if node_distance > distances[node as usize] {
+                continue;
+            }
+            if let Some(idx) = target_nodes.iter().position(|target_node| *target_node == node) {
+                distance.get_or_insert(node_distance);
+                target_nodes.swap_remove(idx);
+                if target_nodes.is_empty() {
+                    break;
+                }
+            }
+
+            for edge in self.edges(node) {
+                if arc_flags.is_some_and(|arc_flags| !arc_flags.is_set(edge, target_cluster)) {
+                    continue;
+                }
+                let next = self.edge_targets[edge];
+                edges.push((self.stations[node as usize], self.stations[next as usize]));
+
+                let next_distance = node_distance + self.edge_weights[edge];
+                if next_distance < distances[next as usize] {
+                    distances[next as usize] = next_distance;
+                    queue.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+
+        Some((distance, edges))
+    }
+
+    /// Distance from every node to `target`, following the edges backwards
+    fn distances_to(&self, target: u32, reverse_edges: &[Vec<(u32, usize)>]) -> Vec<u32> {
+        let mut distances = vec![u32::MAX; self.stations.len()];
+        let mut queue = BinaryHeap::from([Reverse((0, target))]);
+        distances[target as usize] = 0;
+
+        while let Some(Reverse((node_distance, node))) = queue.pop() {
+            if node_distance > distances[node as usize] {
+                continue;
+            }
+            for (previous, edge) in &reverse_edges[node as usize] {
+                let previous_distance = node_distance + self.edge_weights[*edge];
+                if previous_distance < distances[*previous as usize] {
+                    distances[*previous as usize] = previous_distance;
+                    queue.push(Reverse((previous_distance, *previous)));
+                }
+            }
+        }
+
+        distances
+    }
+}
+
+impl ArcFlags {
+    /// Computes the flags of each cluster from shortest path trees to its boundary stations, i.e.
+    /// the stations that are reached from other clusters. Edges within a cluster always have its
+    /// flag.
+    pub fn compute(layer: &LongDistanceLayer) -> Self {
+        let num_nodes = layer.num_stations();
+        let mut reverse_edges: Vec<Vec<(u32, usize)>> = vec![vec![]; num_nodes];
+        let mut edge_sources = vec![0u32; layer.num_edges()];
+        for node in 0..num_nodes as u32 {
+            for edge in layer.edges(node) {
+                reverse_edges[layer.edge_targets[edge] as usize].push((node, edge));
+                edge_sources[edge] = node;
+            }
+        }
+
+        let words_per_edge = (layer.num_clusters as usize).div_ceil(64).max(1);
+        let flagged_edges: Vec<(u32, Vec<usize>)> = (0..layer.num_clusters).into_par_iter()
+            .map(|cluster_id| {
+                let mut flagged = vec![false; layer.num_edges()];
+                for (edge, source) in edge_sources.iter().enumerate() {
+                    let target = layer.edge_targets[edge];
+                    if layer.clusters[*source as usize] == cluster_id && layer.clusters[target as usize] == cluster_id {
+                        flagged[edge] = true;
+                    }
+                }
+
+                let boundary_nodes = (0..num_nodes as u32).filter(|node| {
+                    layer.clusters[*node as usize] == cluster_id
+                        && reverse_edges[*node as usize].iter()
+                            .any(|(previous, _)| layer.clusters[*previous as usize] != cluster_id)
+                });
+                for boundary_node in boundary_nodes {
+                    let distances = layer.distances_to(boundary_node, &reverse_edges);
+                    for (edge, source) in edge_sources.iter().enumerate() {
+                        let to_target = distances[layer.edge_targets[edge] as usize];
+                        if to_target != u32::MAX && distances[*source as usize] == to_target + layer.edge_weights[edge] {
+                            flagged[edge] = true;
+                        }
+                    }
+                }
+
+                let flagged = flagged.into_iter().enumerate()
+                    .filter_map(|(edge, flagged)| flagged.then_some(edge))
+                    .collect();
+                (cluster_id, flagged)
+            })
+            .collect();
+
+        let mut flags = vec![0u64; layer.num_edges() * words_per_edge];
+        for (cluster_id, edges) in flagged_edges {
+            for edge in edges {
+                flags[edge * words_per_edge + cluster_id as usize / 64] |= 1 << (cluster_id % 64);
+            }
+        }
+
+        Self { words_per_edge, flags }
+    }
+
+    fn is_set(&self, edge: usize, cluster_id: u32) -> bool {
+        self.flags
+            .get(edge * self.words_per_edge + cluster_id as usize / 64)
+            .is_some_and(|word| word & (1 << (cluster_id % 64)) != 0)
+    }
+
+    /// Share of the flags that are set. The lower, the more the flags prune searches.
+    pub fn density(&self, num_clusters: u32) -> f32 {
+        let num_flags = self.flags.len() / self.words_per_edge * num_clusters as usize;
+        let num_set: u32 = self.flags.iter().map(|word| word.count_ones()).sum();
+        if num_flags == 0 { 0.0 } else { num_set as f32 / num_flags as f32 }
+    }
+}
+
+impl ArcFlagsBenchmark {
+    /// Runs up to `max_queries` long-distance queries between stops of different clusters, once
+    /// without and once with the arc flags of `algorithm`
+    pub fn run(algorithm: &ScalableTransferPatternsAlgorithm, max_queries: usize) -> Self {
+        let stops = algorithm.clusters.keys().copied().sorted().collect::<Vec<_>>();
+        let num_stops = stops.len();
+        // Spread the queries over all pairs of stops, without collecting them
+        let num_pairs = num_stops * num_stops;
+        let step = (num_pairs / max_queries.max(1)).max(1);
+        let queries: Vec<(StopId, StopId)> = (0..num_pairs)
+            .step_by(step)
+            .map(|pair| (stops[pair / num_stops], stops[pair % num_stops]))
+            .filter(|(source, target)| algorithm.clusters[source] != algorithm.clusters[target])
+            .take(max_queries)
+            .collect();
+
+        let run = |use_arc_flags: bool| {
+            let start = Instant::now();
+            let searches: Vec<LongDistanceSearch> = queries.iter()
+                .filter_map(|(source, target)| algorithm.long_distance_search(*source, *target, use_arc_flags))
+                .collect();
+            (searches, start.elapsed())
+        };
+        let (without, duration_without) = run(false);
+        let (with, duration_with) = run(true);
+
+        let avg_edges = |searches: &[LongDistanceSearch]| {
+            let num_edges: usize = searches.iter().map(|search| search.query_graph.edges().count()).sum();
+            num_edges as f32 / searches.len().max(1) as f32
+        };
+
+        Self {
+            num_queries: queries.len(),
+            num_different_distances: without.iter().zip(&with)
+                .filter(|(without, with)| without.distance != with.distance)
+                .count(),
+            avg_query_graph_edges_without: avg_edges(&without),
+            avg_query_graph_edges_with: avg_edges(&with),
+            duration_without,
+            duration_with,
+        }
+    }
+
+    /// The benchmark as a single row, e.g. to save it to disk. Durations are in seconds.
+    pub fn to_df(&self) -> PolarsResult<DataFrame> {
+        df!(
+            "num_queries" => [self.num_queries as u64],
+            "num_different_distances" => [self.num_different_distances as u64],
+            "avg_query_graph_edges_without" => [self.avg_query_graph_edges_without],
+            "avg_query_graph_edges_with" => [self.avg_query_graph_edges_with],
+            "duration_without" => [self.duration_without.as_secs_f64()],
+            "duration_with" => [self.duration_with.as_secs_f64()],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tp::transfer_pattern_ds::dag::TransferPatternsDags;
+    use crate::tp::transfer_pattern_ds::table::TransferPatternsTable;
+    use hashbrown::HashSet;
+
+    /// Two clusters along a main line 0 - 1 - 2 - 3, with a branch 1 - 4 - 5 that leads away
+    fn layer() -> LongDistanceLayer {
+        let line_progressions = df!(
+            "line_id" => [0u32, 0, 0, 0, 1, 1, 1, 2, 2, 2, 2],
+            "stop_id" => [0u32, 1, 2, 3, 1, 4, 5, 3, 2, 1, 0],
+            "stop_sequence" => [0u32, 1, 2, 3, 0, 1, 2, 0, 1, 2, 3],
+        ).unwrap();
+        let long_distance_stations = df!("stop_id" => [0u32, 1, 2, 3, 4, 5]).unwrap();
+        let stops = df!(
+            "stop_id" => [0u32, 1, 2, 3, 4, 5],
+            "lat" => [48.0f32, 48.5, 49.0, 49.5, 48.5, 48.5],
+            "lon" => [9.0f32, 9.0, 9.0, 9.0, 9.5, 10.0],
+        ).unwrap().lazy();
+        let stop_ids_with_clusters = df!(
+            "stop_id" => [0u32, 1, 2, 3, 4, 5],
+            "cluster_id" => [0u32, 0, 1, 1, 0, 0],
+        ).unwrap();
+
+        LongDistanceLayer::new(&line_progressions, &long_distance_stations, &stops, &stop_ids_with_clusters).unwrap()
+    }
+
+    #[test]
+    fn test_arc_flags() {
+        let layer = layer();
+        assert_eq!(layer.num_stations(), 6);
+        assert_eq!(layer.num_edges(), 8);
+
+        let arc_flags = ArcFlags::compute(&layer);
+
+        let without = layer.search(StopId(0), StopId(3), None).unwrap();
+        let with = layer.search(StopId(0), StopId(3), Some(&arc_flags)).unwrap();
+        assert_eq!(without.distance, with.distance);
+        assert!(without.distance.is_some());
+        // The branch doesn't lead to the other cluster, so it is not searched
+        assert!(without.query_graph.next_stops(StopId(1)).contains(&StopId(4)));
+        assert!(!with.query_graph.next_stops(StopId(1)).contains(&StopId(4)));
+        assert!(with.query_graph.edges().count() < without.query_graph.edges().count());
+    }
+
+    /// The layer with a local stop in each cluster: 6 reaches station 0, and 7 is reached from
+    /// stations 2 and 3
+    fn algorithm() -> ScalableTransferPatternsAlgorithm {
+        let layer = layer();
+        let arc_flags = ArcFlags::compute(&layer);
+        let local_transfer_patterns = TransferPatternsDags::from_table(&TransferPatternsTable(HashSet::from([
+            (StopId(6), vec![], StopId(0)),
+            (StopId(2), vec![], StopId(7)),
+            (StopId(3), vec![], StopId(7)),
+        ])));
+        let clusters = [(0, 0), (1, 0), (2, 1), (3, 1), (4, 0), (5, 0), (6, 0), (7, 1)]
+            .map(|(stop_id, cluster_id)| (StopId(stop_id), cluster_id));
+
+        ScalableTransferPatternsAlgorithm {
+            local_transfer_patterns,
+            clusters: HashMap::from(clusters),
+            long_distance_layer: layer,
+            arc_flags: Some(arc_flags),
+        }
+    }
+
+    #[test]
+    fn test_long_distance_search() {
+        let algorithm = algorithm();
+
+        let without = algorithm.long_distance_search(StopId(6), StopId(7), false).unwrap();
+        let with = algorithm.long_distance_search(StopId(6), StopId(7), true).unwrap();
+
+        assert_eq!(without.distance, with.distance);
+        assert!(without.distance.is_some());
+        // The local transfer patterns lead to and from the long-distance layer
+        for search in [&without, &with] {
+            assert_eq!(search.query_graph.next_stops(StopId(6)), &[StopId(0)]);
+            assert!(search.query_graph.next_stops(StopId(2)).contains(&StopId(7)));
+        }
+        assert!(with.query_graph.edges().count() < without.query_graph.edges().count());
+        assert_eq!(algorithm.query_graph(StopId(6), StopId(7), true), Some(with.query_graph));
+    }
+
+    #[test]
+    fn test_benchmark() {
+        let benchmark = ArcFlagsBenchmark::run(&algorithm(), 100);
+
+        // Five stops in one cluster, three in the other
+        assert_eq!(benchmark.num_queries, 2 * 5 * 3);
+        assert_eq!(benchmark.num_different_distances, 0);
+        assert!(benchmark.avg_query_graph_edges_with <= benchmark.avg_query_graph_edges_without);
+    }
+}
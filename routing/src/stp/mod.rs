@@ -1,8 +1,23 @@
 use crate::algorithms::RoutingAlgorithm;
+use crate::stp::arc_flags::{ArcFlags, LongDistanceLayer};
+use crate::tp::transfer_pattern_ds::dag::TransferPatternsDags;
+use common::types::StopId;
+use hashbrown::HashMap;
 
+pub mod arc_flags;
 pub(crate) mod preprocessing;
+mod querying;
 
 #[derive(Clone)]
-pub struct ScalableTransferPatternsAlgorithm;
+pub struct ScalableTransferPatternsAlgorithm {
+    /// The transfer patterns within each cluster, as DAGs of their origins
+    pub local_transfer_patterns: TransferPatternsDags,
+    /// Cluster of each stop
+    pub clusters: HashMap<StopId, u32>,
+    /// Connects the long-distance stations of the clusters
+    pub long_distance_layer: LongDistanceLayer,
+    /// Speeds up searches in the long-distance layer, if enabled in the config
+    pub arc_flags: Option<ArcFlags>,
+}
 
 impl RoutingAlgorithm for ScalableTransferPatternsAlgorithm {}
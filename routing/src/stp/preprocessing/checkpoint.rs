@@ -0,0 +1,164 @@
+use crate::algorithms::initialization::{PreprocessingInput, PreprocessingResult};
+use crate::tp::transfer_pattern_ds::dag::TransferPatternsDags;
+use common::util::df::{write_df_to_file, FileType};
+use polars::prelude::*;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Where the transfer patterns of finished clusters are written to, so that an interrupted
+/// preprocessing can resume. Relative to the data dir.
+pub(super) const CHECKPOINTS_DIR: &str = "preprocessing/stp/checkpoints";
+
+/// Part of the fingerprint, so that checkpoints are calculated again when the way they are
+/// written or calculated changes
+const FORMAT_VERSION: u32 = 2;
+
+/// Transfer patterns of the clusters that were finished already. Checkpoints are only valid for
+/// the input and clustering that they were created with, which is identified by a fingerprint.
+pub(super) struct Checkpoints {
+    dir: PathBuf,
+}
+
+impl Checkpoints {
+    /// Opens the checkpoints in `dir`. Checkpoints of another input or clustering than the one with
+    /// `fingerprint` are removed.
+    pub(super) fn open(dir: &Path, fingerprint: u64) -> PreprocessingResult<Self> {
+        let fingerprint_path = dir.join("fingerprint");
+        let previous_fingerprint = fs::read_to_string(&fingerprint_path).ok()
+            .and_then(|fingerprint| fingerprint.trim().parse::<u64>().ok());
+
+        if previous_fingerprint != Some(fingerprint) && dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
+        fs::write(&fingerprint_path, fingerprint.to_string())?;
+
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    /// The transfer patterns of `cluster_id`, if it was finished already
+    pub(super) fn load(&self, cluster_id: u32) -> PreprocessingResult<Option<TransferPatternsDags>> {
+        let cluster_dir = self.cluster_dir(cluster_id);
+        // Written last, so that clusters that were interrupted while saving are calculated again
+        if !cluster_dir.join("done").exists() {
+            return Ok(None);
+        }
+
+        let df = LazyFrame::scan_parquet(cluster_dir.join("transfer_patterns.parquet"), Default::default())?
+            .collect()?;
+        Ok(Some(TransferPatternsDags::from_df(&df)?))
+    }
+
+    pub(super) fn save(&self, cluster_id: u32, transfer_patterns: &TransferPatternsDags) -> PreprocessingResult<()> {
+        let cluster_dir = self.cluster_dir(cluster_id);
+        write_df_to_file(
+            cluster_dir.join("transfer_patterns.parquet"),
+            FileType::PARQUET,
+            transfer_patterns.to_df()?,
+        )?;
+        fs::write(cluster_dir.join("done"), "")?;
+
+        Ok(())
+    }
+
+    /// Removes all checkpoints, once they aren't needed anymore
+    pub(super) fn remove(self) -> PreprocessingResult<()> {
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+
+    fn cluster_dir(&self, cluster_id: u32) -> PathBuf {
+        self.dir.join(format!("cluster_id={cluster_id}"))
+    }
+}
+
+/// Identifies the clustering and the input by their content, along with the [`FORMAT_VERSION`]
+pub(super) fn fingerprint(stop_ids_with_clusters: &DataFrame, input: &PreprocessingInput) -> PreprocessingResult<u64> {
+    let mut hasher = DefaultHasher::new();
+    FORMAT_VERSION.hash(&mut hasher);
+
+    let mut clustering = stop_ids_with_clusters.sort(["stop_id"], Default::default())?;
+    hash_df(&mut clustering, &mut hasher)?;
+
+    for frame in [&input.services, &input.stops, &input.trips, &input.stop_times] {
+        hash_df(&mut frame.clone().collect()?, &mut hasher)?;
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Hashes the data frame as it would be written to an IPC file, without keeping it in memory
+fn hash_df(df: &mut DataFrame, hasher: &mut DefaultHasher) -> PreprocessingResult<()> {
+    IpcWriter::new(HashWriter(hasher)).finish(df)?;
+    Ok(())
+}
+
+struct HashWriter<'a>(&'a mut DefaultHasher);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tp::transfer_pattern_ds::table::TransferPatternsTable;
+    use common::types::StopId;
+    use hashbrown::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checkpoints() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("checkpoints");
+        let table = TransferPatternsTable(HashSet::from([(StopId(0), vec![StopId(2)], StopId(1))]));
+        let dags = TransferPatternsDags::from_table(&table);
+
+        let checkpoints = Checkpoints::open(&dir, 1).unwrap();
+        assert_eq!(checkpoints.load(0).unwrap(), None);
+        checkpoints.save(0, &dags).unwrap();
+
+        // Resuming with the same fingerprint reuses the cluster
+        let checkpoints = Checkpoints::open(&dir, 1).unwrap();
+        assert_eq!(checkpoints.load(0).unwrap(), Some(dags));
+
+        // Another input or clustering starts over
+        let checkpoints = Checkpoints::open(&dir, 2).unwrap();
+        assert_eq!(checkpoints.load(0).unwrap(), None);
+
+        checkpoints.remove().unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let input = |arrival: u32| PreprocessingInput {
+            services: df!("service_id" => [0u32]).unwrap().lazy(),
+            stops: df!("stop_id" => [0u32, 1]).unwrap().lazy(),
+            trips: df!("trip_id" => [0u32], "service_id" => [0u32]).unwrap().lazy(),
+            stop_times: df!(
+                "trip_id" => [0u32, 0],
+                "stop_id" => [0u32, 1],
+                "arrival_time" => [0u32, arrival],
+            ).unwrap().lazy(),
+        };
+        let clustering = df!("stop_id" => [1u32, 0], "cluster_id" => [0u32, 0]).unwrap();
+        let sorted_clustering = df!("stop_id" => [0u32, 1], "cluster_id" => [0u32, 0]).unwrap();
+
+        let fingerprint = super::fingerprint(&clustering, &input(60)).unwrap();
+
+        assert_eq!(super::fingerprint(&sorted_clustering, &input(60)).unwrap(), fingerprint);
+        // Same size, but another timetable
+        assert_ne!(super::fingerprint(&clustering, &input(120)).unwrap(), fingerprint);
+    }
+}
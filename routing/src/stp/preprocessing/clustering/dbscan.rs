@@ -3,44 +3,74 @@ use std::fmt::Display;
 
 use linfa::traits::Transformer;
 use linfa_clustering::Dbscan;
+use linfa_nn::distance::L2Dist;
+use linfa_nn::{CommonNearestNeighbour, NearestNeighbour};
+use ndarray::{Array2, Axis};
 use polars::frame::DataFrame;
 use polars::prelude::{col, Float32Type, IndexOrder, LazyFrame, Literal};
 use polars::series::Series;
 
-const MIN_CLUSTER_SIZE: usize = 500;
-
+/// `tolerance` is in degrees. Stops outside of dense areas (noise) are added to the cluster of
+/// their nearest clustered stop, since every stop needs a cluster.
 pub fn cluster(
     stops: &LazyFrame,
-) -> Result<DataFrame, DbscanClusterError> {
+    min_points: usize,
+    tolerance: f32,
+) -> Result<(DataFrame, u32), DbscanClusterError> {
     let stops_array = stops.clone()
         .select([col("lat"), col("lon")])
         .collect()?
         .to_ndarray::<Float32Type>(IndexOrder::default())?;
     let contiguous_stops_array = stops_array.as_standard_layout();
 
-    let clusters = Dbscan::params(MIN_CLUSTER_SIZE)
-        .tolerance(50e-2)
+    let clusters = Dbscan::params(min_points)
+        .tolerance(tolerance)
         .transform(&contiguous_stops_array)?;
 
-    let cluster_series: Series = clusters.into_iter()
-        .map(|x| match x {
-            Some(x) => {x as i32}
-            None => -1
-        })
+    let clustered_indices: Vec<usize> = clusters.iter()
+        .enumerate()
+        .filter_map(|(idx, cluster)| cluster.map(|_| idx))
+        .collect();
+    let num_clusters = clusters.iter().flatten().max().map_or(1, |max| *max as u32 + 1);
+
+    let cluster_ids: Vec<u32> = if clustered_indices.is_empty() {
+        // Nothing is dense enough, so all stops are in one cluster
+        vec![0; clusters.len()]
+    } else {
+        let clustered_stops: Array2<f32> = contiguous_stops_array.select(Axis(0), &clustered_indices);
+        let index = CommonNearestNeighbour::KdTree.from_batch(&clustered_stops, L2Dist)?;
+
+        clusters.iter()
+            .enumerate()
+            .map(|(idx, cluster)| match cluster {
+                Some(cluster) => Ok(*cluster as u32),
+                None => {
+                    let nearest = index.k_nearest(contiguous_stops_array.row(idx), 1)?;
+                    let (_, nearest_idx) = nearest[0];
+                    Ok(clusters[clustered_indices[nearest_idx]].unwrap() as u32)
+                }
+            })
+            .collect::<Result<Vec<u32>, DbscanClusterError>>()?
+    };
+
+    let cluster_series: Series = cluster_ids.into_iter()
         .collect::<Series>()
         .with_name("cluster_id".into());
-    
+
     let stop_ids_with_clusters = stops.clone()
+        .select([col("stop_id")])
         .with_column(cluster_series.lit())
         .collect()?;
 
-    Ok(stop_ids_with_clusters)
+    Ok((stop_ids_with_clusters, num_clusters))
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum DbscanClusterError {
     Polars(#[from] polars::error::PolarsError),
     Dbscan(#[from] linfa_clustering::DbscanParamsError),
+    NN(#[from] linfa_nn::NnError),
+    BuildIndex(#[from] linfa_nn::BuildError),
 }
 
 impl Display for DbscanClusterError {
@@ -48,7 +78,9 @@ impl Display for DbscanClusterError {
         let err: &dyn Display = match self {
             DbscanClusterError::Polars(err) => err,
             DbscanClusterError::Dbscan(err) => err,
+            DbscanClusterError::NN(err) => err,
+            DbscanClusterError::BuildIndex(err) => err,
         };
         write!(f, "{}", err)
     }
-}
\ No newline at end of file
+}
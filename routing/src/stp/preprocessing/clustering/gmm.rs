@@ -10,6 +10,7 @@ use polars::series::Series;
 
 pub fn cluster(
     stops: &LazyFrame,
+    stops_per_cluster: u32,
 ) -> Result<(DataFrame, u32), GmmClusterError> {
     let stops_array = stops.clone()
         .select([col("lat"), col("lon")])
@@ -18,7 +19,7 @@ pub fn cluster(
     let stops_data = DatasetBase::from(stops_array.clone());
 
     // determine the number of clusters roughly by looking at the numer of stops
-    let num_clusters = (stops_array.shape()[0] as f32 / 850.0).round() as usize;
+    let num_clusters = ((stops_array.shape()[0] as f32 / stops_per_cluster as f32).round() as usize).max(1);
 
     let model = GaussianMixtureModel::params(num_clusters)
         .n_runs(10)
@@ -33,6 +34,7 @@ pub fn cluster(
         .with_name("cluster_id".into());
 
     let stop_ids_with_clusters = stops.clone()
+        .select([col("stop_id")])
         .with_column(target_series.lit())
         .collect()?;
 
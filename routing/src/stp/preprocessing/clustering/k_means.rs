@@ -7,10 +7,9 @@ use polars::series::Series;
 use std::fmt;
 use std::fmt::Display;
 
-const NUM_CLUSTERS: u32 = 8;
-
 pub fn cluster(
     stops: &LazyFrame,
+    num_clusters: u32,
 ) -> Result<(DataFrame, u32), KmeansClusterError> {
     let stops_array = stops.clone()
         .select([ col("lat"), col("lon")])
@@ -18,7 +17,7 @@ pub fn cluster(
         .to_ndarray::<Float32Type>(IndexOrder::default())?;
     let stops_data = DatasetBase::from(stops_array.as_standard_layout().clone());
 
-    let k_means_model = KMeans::params(NUM_CLUSTERS as usize)
+    let k_means_model = KMeans::params(num_clusters as usize)
         .fit(&stops_data)?;
     let result = k_means_model.predict(stops_array);
 
@@ -32,7 +31,7 @@ pub fn cluster(
         .with_column(cluster_id_series.lit())
         .collect()?;
 
-    Ok((stop_ids_with_clusters, NUM_CLUSTERS))
+    Ok((stop_ids_with_clusters, num_clusters))
 }
 
 #[derive(thiserror::Error, Debug)]
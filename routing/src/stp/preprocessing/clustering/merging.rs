@@ -1,58 +1,193 @@
-use std::collections::hash_map::RandomState;
-use std::time::SystemTime;
-use dashmap::{DashMap, DashSet};
+use std::collections::BinaryHeap;
+use hashbrown::HashMap;
+use itertools::izip;
 use log::debug;
 use ordered_float::OrderedFloat;
 use polars::prelude::*;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 // Implements merge-based clustering as described in section 3.1.2 of "Scalable Transfer Patterns"
 
-const MAX_CLUSTER_SIZE: u32 = 1_500;
-
-type Map<K, V, S = RandomState> = DashMap<K, V, S>;
-
-// FIXME: Cluster on stop areas, rather than on individual stops (if areas are usually merged)
-
-pub async fn cluster(
+/// Clusters the stops by repeatedly merging the pair of adjacent clusters with the highest
+/// priority (see [`merge_priority`]), as long as the merged cluster has at most
+/// `max_cluster_size` stops. Two clusters are adjacent if a trip goes from one to the other, and
+/// their weight is how often that happens per year.
+///
+/// If the stops were grouped into stations (column `station_id`), stations are clustered instead
+/// of stops and `max_cluster_size` counts stations.
+pub fn cluster(
     services: &LazyFrame,
     stops: &LazyFrame,
     stop_times: &LazyFrame,
     trips: &LazyFrame,
-) -> Result<(), PolarsError> {
-    let stop_times = stop_times.clone()
-        // Only keep what we need for clustering
-        .select([
-            col("trip_id"), col("stop_sequence"), col("stop_id")
-        ]);
+    max_cluster_size: u32,
+) -> Result<(DataFrame, u32), PolarsError> {
+    // Cluster on stations rather than on individual stops, since the platforms of a station are
+    // always merged anyway
+    let by_station = stops.clone().collect_schema()?.contains("station_id");
+    let (nodes, stop_times) = if by_station {
+        let stop_times = stop_times.clone()
+            .join(
+                stops.clone().select([col("stop_id"), col("station_id")]),
+                [col("stop_id")],
+                [col("stop_id")],
+                JoinArgs::new(JoinType::Inner),
+            )
+            .select([
+                col("trip_id"), col("stop_sequence"), col("station_id").alias("node_id")
+            ]);
+        let stations = stops.clone()
+            .select([col("station_id").alias("node_id")])
+            .unique(None, UniqueKeepStrategy::First);
+        (stations, stop_times)
+    } else {
+        let stop_times = stop_times.clone()
+            // Only keep what we need for clustering
+            .select([
+                col("trip_id"), col("stop_sequence"), col("stop_id").alias("node_id")
+            ]);
+        (stops.clone().select([col("stop_id").alias("node_id")]), stop_times)
+    };
 
-    // See original paper linked above for explanation
-    let weight_function =
-        (lit(1) / col("s(u)"))
-        * (lit(1) / col("s(v)"))
-        // TODO: Can we optimize the sqrt?
-        * ((col("w(u,v)") / col("s(u)").sqrt()) + (col("w(u,v)") / col("s(v)").sqrt()));
+    let weighted_adjacency = weighted_adjacency(services, trips, &stop_times).collect()?;
 
+    let nodes = nodes.collect()?;
+    let node_ids: Vec<u32> = nodes.column("node_id")?.u32()?.into_iter().flatten().collect();
 
-    // A table of how each stop is connected to a following stop by some trip. Graph is directed.
-    let stops_adjacency = stop_times.clone()
+    // Put every node into its own cluster at the beginning. Clusters are identified by one of
+    // their nodes.
+    let mut clusters: HashMap<u32, Vec<u32>> = node_ids.iter()
+        .map(|node_id| (*node_id, vec![*node_id]))
+        .collect();
+
+    // Keep track of what clusters are adjacent. The weights of both directions are summed up,
+    // since a trip in either direction makes the clusters depend on each other.
+    let mut cluster_adjacency: HashMap<u32, HashMap<u32, u64>> = HashMap::with_capacity(clusters.len());
+    for (from, to, trips_per_year) in izip!(
+        weighted_adjacency.column("from_node_id")?.u32()?,
+        weighted_adjacency.column("to_node_id")?.u32()?,
+        weighted_adjacency.column("trips_per_year")?.u64()?,
+    ) {
+        let (Some(from), Some(to), Some(trips_per_year)) = (from, to, trips_per_year) else { continue };
+        if !clusters.contains_key(&from) || !clusters.contains_key(&to) {
+            continue;
+        }
+        *cluster_adjacency.entry(from).or_default().entry(to).or_default() += trips_per_year;
+        *cluster_adjacency.entry(to).or_default().entry(from).or_default() += trips_per_year;
+    }
+    drop(weighted_adjacency);
+
+    // Candidate merges, best first. Entries become outdated when one of their clusters changes,
+    // so their priority is checked again before merging.
+    let mut candidates: BinaryHeap<(OrderedFloat<f32>, u32, u32)> = BinaryHeap::new();
+    for (cluster_id, adjacent_clusters) in &cluster_adjacency {
+        for (adjacent_cluster_id, weight) in adjacent_clusters {
+            // Every pair is in the adjacency twice
+            if cluster_id < adjacent_cluster_id && 2 <= max_cluster_size {
+                candidates.push((OrderedFloat(merge_priority(1, 1, *weight)), *cluster_id, *adjacent_cluster_id));
+            }
+        }
+    }
+
+    let mut num_merges = 0;
+    while let Some((priority, cluster_a_id, cluster_b_id)) = candidates.pop() {
+        let (Some(cluster_a), Some(cluster_b)) = (clusters.get(&cluster_a_id), clusters.get(&cluster_b_id)) else {
+            // One of the clusters was merged into another one already
+            continue;
+        };
+        let size_a = cluster_a.len() as u32;
+        let size_b = cluster_b.len() as u32;
+        let Some(weight) = cluster_adjacency.get(&cluster_a_id).and_then(|adjacent| adjacent.get(&cluster_b_id)) else {
+            continue;
+        };
+        if size_a + size_b > max_cluster_size || merge_priority(size_a, size_b, *weight) != priority.0 {
+            continue;
+        }
+
+        // Merge the smaller cluster into the larger one
+        let (into_id, from_id) = if size_a >= size_b { (cluster_a_id, cluster_b_id) } else { (cluster_b_id, cluster_a_id) };
+        let from_nodes = clusters.remove(&from_id).unwrap();
+        clusters.get_mut(&into_id).unwrap().extend(from_nodes);
+
+        // Every cluster that was adjacent to the merged cluster is now adjacent to the cluster it
+        // was merged into
+        let adjacent_to_from = cluster_adjacency.remove(&from_id).unwrap_or_default();
+        for (adjacent_id, weight) in adjacent_to_from {
+            let adjacent = cluster_adjacency.get_mut(&adjacent_id).unwrap();
+            adjacent.remove(&from_id);
+            if adjacent_id == into_id {
+                continue;
+            }
+            *adjacent.entry(into_id).or_default() += weight;
+            *cluster_adjacency.entry(into_id).or_default().entry(adjacent_id).or_default() += weight;
+        }
+
+        // The priorities of all pairs with the merged cluster changed
+        let size_into = clusters[&into_id].len() as u32;
+        if let Some(adjacent_to_into) = cluster_adjacency.get(&into_id) {
+            for (adjacent_id, weight) in adjacent_to_into {
+                let size_adjacent = clusters[adjacent_id].len() as u32;
+                // Pairs that are too large now will never be merged, since clusters only grow
+                if size_into + size_adjacent <= max_cluster_size {
+                    let priority = merge_priority(size_into, size_adjacent, *weight);
+                    candidates.push((OrderedFloat(priority), into_id, *adjacent_id));
+                }
+            }
+        }
+
+        num_merges += 1;
+    }
+    debug!(target: "preprocessing", "Merged {} nodes into {} clusters in {num_merges} merges", node_ids.len(), clusters.len());
+
+    // Give the clusters continuous ids, ordered by their first node so that they are stable
+    let mut clusters: Vec<Vec<u32>> = clusters.into_values().collect();
+    clusters.iter_mut().for_each(|nodes| nodes.sort_unstable());
+    clusters.sort_unstable_by_key(|nodes| nodes[0]);
+    let num_clusters = clusters.len() as u32;
+
+    let (cluster_node_ids, cluster_ids): (Vec<u32>, Vec<u32>) = clusters.into_iter()
+        .enumerate()
+        .flat_map(|(cluster_id, nodes)| nodes.into_iter().map(move |node_id| (node_id, cluster_id as u32)))
+        .unzip();
+    let node_ids_with_clusters = df!(
+        "node_id" => cluster_node_ids,
+        "cluster_id" => cluster_ids,
+    )?.lazy();
+
+    let node_column = if by_station { "station_id" } else { "stop_id" };
+    let stop_ids_with_clusters = stops.clone()
+        .select([col("stop_id"), col(node_column).alias("node_id")])
         .join(
-            stop_times.clone(),
-            // Add one to the stop sequence number to select the next stop in the sequence
-            &[col("trip_id"), col("stop_sequence") + lit(1)],
-            &[col("trip_id"), col("stop_sequence")],
+            node_ids_with_clusters,
+            [col("node_id")],
+            [col("node_id")],
             JoinArgs::new(JoinType::Left),
         )
+        .select([col("stop_id"), col("cluster_id")])
+        .collect()?;
+
+    Ok((stop_ids_with_clusters, num_clusters))
+}
+
+/// How often per year trips go directly from one node to another, in the columns `from_node_id`,
+/// `to_node_id` and `trips_per_year`. `stop_times` has the node of each stop time in the column
+/// `node_id`, which is a stop or a station.
+pub(crate) fn weighted_adjacency(services: &LazyFrame, trips: &LazyFrame, stop_times: &LazyFrame) -> LazyFrame {
+    // A table of how each stop is connected to a following stop by some trip. Graph is directed.
+    let adjacency = stop_times.clone()
+        // Stop sequences only need to increase along a trip, so they can have gaps
+        .sort(["trip_id", "stop_sequence"], SortMultipleOptions::default())
+        .with_column(col("node_id").shift(lit(-1)).over([col("trip_id")]).alias("next_node_id"))
         // The last entry per trip has no following stop. It will be null, so drop it.
-        .drop_nulls(Some(vec![col("stop_id_right")]))
-        // Keep only relevant columns and rename them.
+        .drop_nulls(Some(vec![col("next_node_id")]))
+        // Trips between platforms of the same station don't connect different nodes
+        .filter(col("node_id").neq(col("next_node_id")))
         .select([
-            col("stop_id").alias("from_stop_id"),
-            col("stop_id_right").alias("to_stop_id"),
+            col("node_id").alias("from_node_id"),
+            col("next_node_id").alias("to_node_id"),
             col("trip_id")
         ]);
 
-    let weighted_stops_adjacency = stops_adjacency
+    adjacency
         .join(
             trips.clone(),
             [col("trip_id")],
@@ -60,7 +195,7 @@ pub async fn cluster(
             JoinArgs::new(JoinType::Inner)
         )
         .select([
-            col("from_stop_id"), col("to_stop_id"),
+            col("from_node_id"), col("to_node_id"),
             col("service_id")
         ])
         .join(
@@ -84,127 +219,14 @@ pub async fn cluster(
             JoinArgs::new(JoinType::Inner)
         )
         // Sum up number of trips from identical from-to-pairs
-        .group_by([ col("from_stop_id"), col("to_stop_id") ])
-        .agg([ sum("trips_per_year").alias("trips_per_year") ])
-        .collect()?;
-
-    let stop_count = {
-        let count = stops.clone().count()
-            .collect()?;
-        match count[0].get(0)? {
-            AnyValue::UInt32(count) => count,
-            _ => panic!("Count wasn't u32")
-        }
-    };
-    dbg!(&stop_count);
-
-    // Keep track of what clusters are adjacent
-    let cluster_adjacency: Map<u32, DashSet<(u32, u32)>> = Map::with_capacity(stop_count as usize);
-
-    let adjacency_count = weighted_stops_adjacency[0].len();
-    (0..adjacency_count).into_par_iter()
-        .for_each(|index| {
-            let row = &weighted_stops_adjacency.get_row(index).unwrap().0;
-            let from_cluster_id =
-                if let AnyValue::UInt32(int) = row.get(0).unwrap() {
-                    *int
-                } else { panic!("Wrong data type")};
-            let to_cluster_id =
-                if let AnyValue::UInt32(int) = row.get(1).unwrap() {
-                    *int
-                } else { panic!("Wrong data type")};
-            let trips_per_year: u32 =
-                if let AnyValue::UInt32(int) = row.get(2).unwrap() {
-                    *int
-                } else { panic!("Wrong data type")};
-
-            let from_cluster = cluster_adjacency.get_mut(&from_cluster_id);
-            if let Some(from_cluster) = from_cluster {
-                from_cluster.insert((to_cluster_id, trips_per_year));
-            } else {
-                let set = DashSet::with_capacity(1);
-                set.insert((to_cluster_id, trips_per_year));
-                cluster_adjacency.insert(from_cluster_id, set);
-            }
-        });
-
-    drop(weighted_stops_adjacency);
-
-    // Key is cluster-id, value are stop_ids
-    let clusters: Map<u32, DashSet<u32>> = Map::with_capacity(stop_count as usize);
-    // Put every stop into its own cluster at the beginning
-    (0..stop_count).into_par_iter()
-        .for_each(|index| {
-            let stops_in_this_cluster = DashSet::with_capacity(1);
-            stops_in_this_cluster.insert(index);
-            clusters.insert(index, stops_in_this_cluster);
-        });
-
-    loop {
-        let time = SystemTime::now();
-        let best_pair = clusters.par_iter()
-            // Get the best cluster to merge this with
-            .filter_map(|entry| {
-                let cluster_id = entry.key();
-                let cluster_size = entry.value().len();
-                let adjacent_clusters = cluster_adjacency.get(cluster_id);
-
-                return if let Some(adjacent_clusters) = adjacent_clusters {
-                    let best_adjacent_cluster = adjacent_clusters.iter()
-                        .map(|adjacent_cluster| {
-                            let (adjacent_cluster_id, trips_per_year) = adjacent_cluster.key();
-                            //dbg!(&adjacent_cluster_id);
-                            let adjacent_cluster_size = clusters.get(adjacent_cluster_id).unwrap()
-                                .len();
-                            let distance = evaluate_distance(cluster_size, adjacent_cluster_size, *trips_per_year);
-                            (*adjacent_cluster_id, distance)
-                        })
-                        .min_by_key(|(_, distance)| {
-                            OrderedFloat(*distance)
-                        });
-
-                    if let Some((best_to_cluster_with_id, distance)) = best_adjacent_cluster {
-                        Some((*cluster_id, best_to_cluster_with_id, distance))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .min_by_key(|(_,_,distance)| OrderedFloat(*distance));
-
-        if let Some((cluster_a_id, cluster_b_id, _)) = best_pair {
-            let cluster_b_stops = clusters.remove(&cluster_b_id).unwrap().1;
-            clusters.get_mut(&cluster_a_id).unwrap().extend(cluster_b_stops);
-
-            // Update stop adjacency:
-            // Remove item for cluster b
-            let clusters_after_b = cluster_adjacency.remove(&cluster_b_id).unwrap().1;
-            // Every cluster that was after b is now after cluster a, so add the trips to those
-            let clusters_after_a = cluster_adjacency.get_mut(&cluster_a_id).unwrap();
-            /*for (cluster_id, mut times_per_year_a) in &mut clusters_after_a {
-                let after_b = clusters_after_b.iter()
-                    .find(|after_b| after_b.0.clone() == cluster_id);
-                if let Some(after_b) = after_b {
-                    let (_, times_per_year_b) = *after_b;
-                    times_per_year_a += times_per_year_b;
-                }
-            }*/
-            // TODO: Further updates that are needed: Remove too large adjacencies (> MAX_CLUSTER_SIZE)
-
-            debug!("Merge took {:?}", time.elapsed().unwrap());
-        } else {
-            break;
-        }
-        break; // TODO: Remove this break to continue
-    }
-
-    Ok(())
+        .group_by([ col("from_node_id"), col("to_node_id") ])
+        .agg([ col("trips_per_year").cast(DataType::UInt64).sum().alias("trips_per_year") ])
 }
 
+/// How much merging two clusters improves the clustering. Clusters that are connected by many
+/// trips are merged first, and small clusters are preferred so that clusters grow evenly.
 #[inline]
-fn evaluate_distance(size_u: usize, size_v: usize, weight_between_u_v: u32) -> f32 {
+fn merge_priority(size_u: u32, size_v: u32, weight_between_u_v: u64) -> f32 {
     let size_u = size_u as f32;
     let size_v = size_v as f32;
     let weight_between_u_v = weight_between_u_v as f32;
@@ -214,4 +236,48 @@ fn evaluate_distance(size_u: usize, size_v: usize, weight_between_u_v: u32) -> f
             (weight_between_u_v / size_u.sqrt()) +
             (weight_between_u_v / size_v.sqrt())
         )
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster() {
+        // Two triangles of frequently connected stops, connected by a single rare trip
+        let stops = df!(
+            "stop_id" => [0u32, 1, 2, 3, 4, 5],
+            "lat" => [48.0f32, 48.0, 48.1, 49.0, 49.0, 49.1],
+            "lon" => [9.0f32, 9.1, 9.0, 10.0, 10.1, 10.0],
+        ).unwrap().lazy();
+        let services = df!(
+            "service_id" => [0u32, 1],
+            "monday" => [1u32, 0], "tuesday" => [1u32, 0], "wednesday" => [1u32, 0],
+            "thursday" => [1u32, 0], "friday" => [1u32, 0], "saturday" => [1u32, 0],
+            "sunday" => [1u32, 1],
+        ).unwrap().lazy();
+        let trips = df!(
+            "trip_id" => [0u32, 1, 2],
+            "service_id" => [0u32, 0, 1],
+        ).unwrap().lazy();
+        let stop_times = df!(
+            "trip_id" => [0u32, 0, 0, 1, 1, 1, 2, 2],
+            // Gaps in the stop sequence are allowed
+            "stop_sequence" => [0u32, 1, 2, 0, 5, 10, 0, 1],
+            "stop_id" => [0u32, 1, 2, 3, 4, 5, 2, 3],
+        ).unwrap().lazy();
+
+        let (stop_ids_with_clusters, num_clusters) =
+            cluster(&services, &stops, &stop_times, &trips, 3).unwrap();
+
+        assert_eq!(num_clusters, 2);
+        let stop_ids_with_clusters = stop_ids_with_clusters.sort(["stop_id"], Default::default()).unwrap();
+        let cluster_ids: Vec<u32> = stop_ids_with_clusters.column("cluster_id").unwrap().u32().unwrap()
+            .into_no_null_iter().collect();
+        assert_eq!(cluster_ids, vec![0, 0, 0, 1, 1, 1]);
+
+        // Without the size limit, everything ends up in one cluster
+        let (_, num_clusters) = cluster(&services, &stops, &stop_times, &trips, 6).unwrap();
+        assert_eq!(num_clusters, 1);
+    }
+}
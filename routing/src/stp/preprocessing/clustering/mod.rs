@@ -4,4 +4,42 @@ pub mod filter_for_cluster;
 pub use filter_for_cluster::filter_for_cluster as filter_for_cluster;
 pub mod dbscan;
 pub mod gmm;
-pub mod optics_geo;
\ No newline at end of file
+pub mod optics_geo;
+pub mod report;
+pub mod stations;
+pub use stations::cluster_by_station;
+
+use crate::algorithms::initialization::{PreprocessingError, PreprocessingInput, PreprocessingResult};
+use common::types::config::features::ClusteringConfig;
+use polars::frame::DataFrame;
+
+/// Clusters the stops with the configured strategy. Returns the `cluster_id` of each `stop_id`,
+/// as well as the number of clusters. Platforms of a station are always in the same cluster.
+pub fn cluster(config: &ClusteringConfig, input: &PreprocessingInput) -> PreprocessingResult<(DataFrame, u32)> {
+    let stops = &input.stops;
+    let result = match config {
+        ClusteringConfig::KMeans { num_clusters } => {
+            cluster_by_station(stops, |stops| k_means::cluster(stops, *num_clusters))
+                .map_err(|err| err.to_string())
+        }
+        ClusteringConfig::Dbscan { min_points, tolerance } => {
+            cluster_by_station(stops, |stops| dbscan::cluster(stops, *min_points, *tolerance))
+                .map_err(|err| err.to_string())
+        }
+        ClusteringConfig::Gmm { stops_per_cluster } => {
+            cluster_by_station(stops, |stops| gmm::cluster(stops, *stops_per_cluster))
+                .map_err(|err| err.to_string())
+        }
+        ClusteringConfig::OpticsGeo { min_points, tolerance, eps } => {
+            cluster_by_station(stops, |stops| optics_geo::cluster(stops, *min_points, *tolerance, *eps))
+                .map_err(|err| err.to_string())
+        }
+        // Handles stations itself, since it also needs to map the trips to stations
+        ClusteringConfig::Merging { max_cluster_size } => {
+            merging::cluster(&input.services, stops, &input.stop_times, &input.trips, *max_cluster_size)
+                .map_err(|err| err.to_string())
+        }
+    };
+
+    result.map_err(PreprocessingError::Clustering)
+}
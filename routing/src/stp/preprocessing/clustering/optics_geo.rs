@@ -10,9 +10,7 @@ use ndarray::{ArrayBase, ArrayView, CowRepr, Data, Dimension, Ix2};
 use polars::frame::{DataFrame, UniqueKeepStrategy};
 use polars::prelude::*;
 
-const MIN_POINTS: usize = 8;
 const MIN_CLUSTER_SIZE: u32 = 300;
-const MAX_CLUSTER_SIZE: u32 = 1_500;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct HaversineDist;
@@ -28,8 +26,13 @@ impl<F: Float> linfa_nn::distance::Distance<F> for HaversineDist {
     }
 }
 
+/// `tolerance` is the search radius and `eps` the maximum reachability distance within a cluster,
+/// both in meters
 pub fn cluster(
     stops: &LazyFrame,
+    min_points: usize,
+    tolerance: f32,
+    eps: f32,
 ) -> Result<(DataFrame, u32), OpticsClusterError> {
     let stops_array = stops.clone()
         .select([col("lat"), col("lon")])
@@ -39,14 +42,14 @@ pub fn cluster(
     //let stops_data = DatasetBase::from(stops_array.as_standard_layout().clone());
 
     let analysis = Optics::params_with(
-        MIN_POINTS,
+        min_points,
         HaversineDist,
         CommonNearestNeighbour::KdTree,
-    ).tolerance(2_000.0) // radius in meters (should be as small as possible for best runtime)
+    ).tolerance(tolerance) // should be as small as possible for best runtime
         .transform(contiguous_stops_array.view())?;
 
 
-    let clusters = extract_clusters(contiguous_stops_array.clone(), &analysis, 300.0)?;
+    let clusters = extract_clusters(contiguous_stops_array.clone(), &analysis, min_points, eps)?;
     let num_clusters = clusters.clone().lazy()
         .unique(Some(vec![String::from("cluster_id")]), UniqueKeepStrategy::Any)
         .select([len()])
@@ -64,7 +67,7 @@ pub fn cluster(
 }
 
 fn extract_clusters(
-    data: ArrayBase<CowRepr<f32>, Ix2>, analysis: &OpticsAnalysis<f32>, eps: f32,
+    data: ArrayBase<CowRepr<f32>, Ix2>, analysis: &OpticsAnalysis<f32>, min_points: usize, eps: f32,
 ) -> Result<DataFrame, OpticsClusterError> {
     let index = BallTreeIndex::new(&data, 4, HaversineDist)
         .expect("failed to construct ball tree index");
@@ -86,7 +89,7 @@ fn extract_clusters(
             current_cluster_size += 1;
         } else {
             let n = build_neighbourhood(&data, &index, sample, eps)?;
-            if n.len() >= MIN_POINTS && sample.core_distance().unwrap_or(f32::INFINITY) <= eps {
+            if n.len() >= min_points && sample.core_distance().unwrap_or(f32::INFINITY) <= eps {
                 clusters_cluster_column.push(current_cluster_id);
                 clusters_stop_column.push(sample.index() as u32);
                 current_cluster_size += 1;
@@ -0,0 +1,237 @@
+use crate::algorithms::initialization::{PreprocessingInput, PreprocessingResult};
+use crate::stp::preprocessing::clustering::merging::weighted_adjacency;
+use chrono::{DateTime, Utc};
+use common::types::config::features::ClusteringConfig;
+use common::types::config::work_dir;
+use hashbrown::{HashMap, HashSet};
+use itertools::izip;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, read_dir, remove_file, File};
+use std::path::{Path, PathBuf};
+
+/// Where the reports are written to, relative to the work dir
+pub const CLUSTERING_REPORTS_DIR: &str = "stp/clustering_reports";
+
+/// How many reports are kept, older ones are deleted when a report is saved
+const MAX_REPORTS: usize = 50;
+
+/// How well the stops were clustered, so that clustering strategies can be compared on a network.
+/// Reports are kept for the last [`MAX_REPORTS`] runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusteringReport {
+    pub created_at: DateTime<Utc>,
+    pub clustering: ClusteringConfig,
+    pub num_clusters: u32,
+    pub cluster_sizes: ClusterSizes,
+    /// Stops with a trip to or from a stop of another cluster
+    pub num_border_stops: u32,
+    /// How often per year trips go from one cluster to another. The lower, the fewer journeys
+    /// need long-distance transfer patterns.
+    pub cut_edge_weight: u64,
+    /// How often per year trips go from one stop to another, to relate the cut-edge weight to
+    pub total_edge_weight: u64,
+    /// Long-distance transfer patterns connect border stops of different clusters, so this is the
+    /// number of such pairs
+    pub estimated_long_distance_patterns: u64,
+}
+
+/// Number of stops per cluster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterSizes {
+    pub min: u32,
+    pub max: u32,
+    pub mean: f32,
+    pub median: f32,
+}
+
+impl ClusteringReport {
+    /// `stop_ids_with_clusters` has the columns `stop_id` and `cluster_id`
+    pub fn new(
+        clustering: &ClusteringConfig,
+        stop_ids_with_clusters: &DataFrame,
+        num_clusters: u32,
+        input: &PreprocessingInput,
+    ) -> PreprocessingResult<Self> {
+        let mut sizes = vec![0u32; num_clusters as usize];
+        let mut cluster_of_stop: HashMap<u32, u32> = HashMap::with_capacity(stop_ids_with_clusters.height());
+        for (stop_id, cluster_id) in izip!(
+            stop_ids_with_clusters.column("stop_id")?.u32()?,
+            stop_ids_with_clusters.column("cluster_id")?.u32()?,
+        ) {
+            let (Some(stop_id), Some(cluster_id)) = (stop_id, cluster_id) else { continue };
+            cluster_of_stop.insert(stop_id, cluster_id);
+            if let Some(size) = sizes.get_mut(cluster_id as usize) {
+                *size += 1;
+            }
+        }
+
+        let stop_times = input.stop_times.clone()
+            .select([col("trip_id"), col("stop_sequence"), col("stop_id").alias("node_id")]);
+        let adjacency = weighted_adjacency(&input.services, &input.trips, &stop_times).collect()?;
+
+        let mut total_edge_weight = 0;
+        let mut cut_edge_weight = 0;
+        let mut border_stops: HashSet<u32> = HashSet::new();
+        for (from, to, trips_per_year) in izip!(
+            adjacency.column("from_node_id")?.u32()?,
+            adjacency.column("to_node_id")?.u32()?,
+            adjacency.column("trips_per_year")?.u64()?,
+        ) {
+            let (Some(from), Some(to), Some(trips_per_year)) = (from, to, trips_per_year) else { continue };
+            let (Some(from_cluster), Some(to_cluster)) = (cluster_of_stop.get(&from), cluster_of_stop.get(&to)) else {
+                continue;
+            };
+            total_edge_weight += trips_per_year;
+            if from_cluster != to_cluster {
+                cut_edge_weight += trips_per_year;
+                border_stops.insert(from);
+                border_stops.insert(to);
+            }
+        }
+
+        let mut border_stops_per_cluster = vec![0u64; num_clusters as usize];
+        for stop_id in &border_stops {
+            if let Some(count) = border_stops_per_cluster.get_mut(cluster_of_stop[stop_id] as usize) {
+                *count += 1;
+            }
+        }
+        let num_border_stops = border_stops.len() as u64;
+        let estimated_long_distance_patterns = border_stops_per_cluster.iter()
+            .map(|in_cluster| in_cluster * (num_border_stops - in_cluster))
+            .sum();
+
+        Ok(Self {
+            created_at: Utc::now(),
+            clustering: clustering.clone(),
+            num_clusters,
+            cluster_sizes: ClusterSizes::of(sizes),
+            num_border_stops: num_border_stops as u32,
+            cut_edge_weight,
+            total_edge_weight,
+            estimated_long_distance_patterns,
+        })
+    }
+
+    /// Writes the report to [`CLUSTERING_REPORTS_DIR`] in the work dir of `data_dir`, next to the
+    /// clustered stops
+    pub fn save(&self, data_dir: &Path) -> PreprocessingResult<PathBuf> {
+        self.save_to(&work_dir(data_dir).join(CLUSTERING_REPORTS_DIR))
+    }
+
+    /// Writes the report to `reports_dir`, named by the time it was created, and deletes all but
+    /// the newest [`MAX_REPORTS`] reports
+    pub fn save_to(&self, reports_dir: &Path) -> PreprocessingResult<PathBuf> {
+        create_dir_all(reports_dir)?;
+
+        let path = reports_dir.join(format!("{}.json", self.created_at.timestamp_millis()));
+        serde_json::to_writer_pretty(File::create(&path)?, self).map_err(std::io::Error::from)?;
+        delete_old_reports(reports_dir, MAX_REPORTS)?;
+
+        Ok(path)
+    }
+}
+
+/// Report files are named by their creation time in milliseconds, other files are left alone
+fn delete_old_reports(reports_dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut report_files: Vec<(u64, PathBuf)> = vec![];
+    for report_file in read_dir(reports_dir)? {
+        let path = report_file?.path();
+        let timestamp = path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok());
+        if let Some(timestamp) = timestamp {
+            report_files.push((timestamp, path));
+        }
+    }
+    report_files.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    for (_, path) in report_files.into_iter().skip(keep) {
+        remove_file(path)?;
+    }
+    Ok(())
+}
+
+impl ClusterSizes {
+    fn of(mut sizes: Vec<u32>) -> Self {
+        if sizes.is_empty() {
+            return Self { min: 0, max: 0, mean: 0.0, median: 0.0 };
+        }
+
+        sizes.sort_unstable();
+        let middle = sizes.len() / 2;
+        let median = if sizes.len() % 2 == 0 {
+            (sizes[middle - 1] + sizes[middle]) as f32 / 2.0
+        } else {
+            sizes[middle] as f32
+        };
+
+        Self {
+            min: sizes[0],
+            max: sizes[sizes.len() - 1],
+            mean: sizes.iter().sum::<u32>() as f32 / sizes.len() as f32,
+            median,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_clustering_report() {
+        let input = PreprocessingInput {
+            services: df!(
+                "service_id" => [0u32],
+                "monday" => [1u32], "tuesday" => [0u32], "wednesday" => [0u32], "thursday" => [0u32],
+                "friday" => [0u32], "saturday" => [0u32], "sunday" => [0u32],
+            ).unwrap().lazy(),
+            stops: df!(
+                "stop_id" => [0u32, 1, 2, 3],
+                "lat" => [48.0f32, 48.1, 48.2, 48.3],
+                "lon" => [9.0f32, 9.1, 9.2, 9.3],
+            ).unwrap().lazy(),
+            trips: df!(
+                "trip_id" => [0u32],
+                "service_id" => [0u32],
+            ).unwrap().lazy(),
+            stop_times: df!(
+                "trip_id" => [0u32, 0, 0, 0],
+                "stop_sequence" => [0u32, 1, 2, 3],
+                "stop_id" => [0u32, 1, 2, 3],
+            ).unwrap().lazy(),
+        };
+        let stop_ids_with_clusters = df!(
+            "stop_id" => [0u32, 1, 2, 3],
+            "cluster_id" => [0u32, 0, 0, 1],
+        ).unwrap();
+
+        let report = ClusteringReport::new(&ClusteringConfig::default(), &stop_ids_with_clusters, 2, &input)
+            .unwrap();
+
+        assert_eq!(report.cluster_sizes, ClusterSizes { min: 1, max: 3, mean: 2.0, median: 2.0 });
+        // Only the trip from stop 2 to stop 3 leaves its cluster
+        assert_eq!(report.num_border_stops, 2);
+        assert_eq!(report.cut_edge_weight, 52);
+        assert_eq!(report.total_edge_weight, 3 * 52);
+        assert_eq!(report.estimated_long_distance_patterns, 2);
+    }
+
+    #[test]
+    fn test_delete_old_reports() {
+        let dir = TempDir::new().unwrap();
+        for timestamp in [3, 1, 4, 2] {
+            File::create(dir.path().join(format!("{timestamp}.json"))).unwrap();
+        }
+        File::create(dir.path().join("notes.txt")).unwrap();
+
+        delete_old_reports(dir.path(), 2).unwrap();
+
+        let remaining: HashSet<String> = read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(remaining, HashSet::from(["3.json".into(), "4.json".into(), "notes.txt".into()]));
+    }
+}
@@ -0,0 +1,73 @@
+use polars::error::PolarsError;
+use polars::frame::DataFrame;
+use polars::prelude::*;
+
+/// Runs `cluster` on stations instead of individual stops, so that all platforms of a station end
+/// up in the same cluster. Each station is located at the mean location of its stops. Stops that
+/// were not grouped into stations (column `station_id`) are clustered as they are.
+///
+/// `cluster` gets frames with the columns `stop_id`, `lat` and `lon` and returns the `cluster_id`
+/// of each `stop_id`, as well as the number of clusters.
+pub fn cluster_by_station<E: From<PolarsError>>(
+    stops: &LazyFrame,
+    cluster: impl FnOnce(&LazyFrame) -> Result<(DataFrame, u32), E>,
+) -> Result<(DataFrame, u32), E> {
+    if !stops.clone().collect_schema()?.contains("station_id") {
+        return cluster(stops);
+    }
+
+    let stations = stops.clone()
+        .group_by([col("station_id")])
+        .agg([col("lat").mean().cast(DataType::Float32), col("lon").mean().cast(DataType::Float32)])
+        // Keep the order stable, since clustering can depend on it
+        .sort(["station_id"], SortMultipleOptions::default())
+        .select([col("station_id").alias("stop_id"), col("lat"), col("lon")]);
+
+    let (station_ids_with_clusters, num_clusters) = cluster(&stations)?;
+
+    let stop_ids_with_clusters = stops.clone()
+        .select([col("stop_id"), col("station_id")])
+        .join(
+            station_ids_with_clusters.lazy().select([col("stop_id").alias("station_id"), col("cluster_id")]),
+            [col("station_id")],
+            [col("station_id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .select([col("stop_id"), col("cluster_id")])
+        .collect()?;
+
+    Ok((stop_ids_with_clusters, num_clusters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_by_station() {
+        let stops = df!(
+            "stop_id" => [0u32, 1, 2, 3],
+            "station_id" => [0u32, 1, 0, 1],
+            "lat" => [48.0f32, 52.0, 48.5, 52.5],
+            "lon" => [9.0f32, 13.0, 9.2, 13.2],
+        ).unwrap().lazy();
+
+        // Put each station into its own cluster
+        let (stop_ids_with_clusters, num_clusters) = cluster_by_station(&stops, |stations| {
+            let stations = stations.clone().collect()?;
+            assert_eq!(stations.height(), 2);
+            assert_eq!(stations.column("lat")?.f32()?.get(0), Some(48.25));
+
+            let clusters = stations.lazy()
+                .select([col("stop_id"), col("stop_id").alias("cluster_id")])
+                .collect()?;
+            Ok::<_, PolarsError>((clusters, 2))
+        }).unwrap();
+
+        assert_eq!(num_clusters, 2);
+        let stop_ids_with_clusters = stop_ids_with_clusters.sort(["stop_id"], Default::default()).unwrap();
+        let cluster_ids: Vec<u32> = stop_ids_with_clusters.column("cluster_id").unwrap().u32().unwrap()
+            .into_no_null_iter().collect();
+        assert_eq!(cluster_ids, vec![0, 1, 0, 1]);
+    }
+}
@@ -1,29 +1,60 @@
 use crate::direct_connections::{DirectConnections, LineProgressionFrame};
-use crate::stp::preprocessing::clustering::filter_for_cluster;
-use crate::stp::preprocessing::clustering::k_means::cluster;
+use crate::stp::preprocessing::clustering::{cluster, filter_for_cluster};
+use crate::stp::preprocessing::clustering::report::ClusteringReport;
+use crate::stp::preprocessing::checkpoint::{self, Checkpoints, CHECKPOINTS_DIR};
+use crate::stp::preprocessing::scheduler::{self, Scheduler};
+use crate::stp::arc_flags::{ArcFlags, ArcFlagsBenchmark, LongDistanceLayer};
 use crate::stp::ScalableTransferPatternsAlgorithm;
-use crate::tp::transfer_pattern_ds::table::TransferPatternsTable;
+use crate::tp::transfer_pattern_ds::dag::TransferPatternsDags;
 use crate::tp::TransferPatternsAlgorithm;
 use arrow_array::UInt32Array;
 use arrow_schema::{DataType, Field};
 use common::util::df::{write_df_to_file, write_geoarrow_to_file, FileType};
 use common::util::geoarrow_lines::build_geoarrow_lines;
 use common::util::logging::{run_with_pb, run_with_spinner};
+use common::util::metrics;
+use common::util::progress;
+use common::util::progress::Job;
+use polars::df;
 use polars::frame::{DataFrame, UniqueKeepStrategy};
 use polars::prelude::{col, lit, Column, IntoLazy, LazyFrame};
-use std::sync::Arc;
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use geo::{coord, point, Distance, Haversine};
-use hashbrown::HashSet;
-use log::debug;
+use hashbrown::{HashMap, HashSet};
+use indicatif::ProgressBar;
+use itertools::{izip, Itertools};
+use log::{debug, warn};
 use common::types::StopId;
+use common::types::config::features::PreprocessingConfig;
+use common::types::config::{work_dir, DEFAULT_DATA_DIR};
+use std::path::Path;
 use crate::algorithms::initialization::{ByPreprocessing, PreprocessingError, PreprocessingInput, PreprocessingResult};
 
+/// Where the direct connections of the clusters are written to, relative to the data dir. Like the
+/// checkpoints, they are kept outside the work dir.
+const DIRECT_CONNECTIONS_DIR: &str = "preprocessing/stp/direct_connections";
+
+/// Where the arc flags benchmark is written to, relative to the work dir
+const ARC_FLAGS_BENCHMARK_PATH: &str = "stp/arc_flags_benchmark.csv";
+
 // The minimum average distance between stations for a line to be considered long-distance. In
 // meters.
 const LONG_DISTANCE_AVG_DISTANCE: u32 = 10_000;
 
 impl ByPreprocessing for ScalableTransferPatternsAlgorithm {
     fn preprocess(input: PreprocessingInput, save_to_disk: bool) -> PreprocessingResult<Self> {
+        Self::preprocess_with_config(input, &PreprocessingConfig::default(), Path::new(DEFAULT_DATA_DIR), save_to_disk)
+    }
+
+    fn preprocess_with_config(
+        input: PreprocessingInput,
+        config: &PreprocessingConfig,
+        data_dir: &Path,
+        save_to_disk: bool,
+    ) -> PreprocessingResult<Self> {
         let direct_connections = run_with_spinner("preprocessing", "Calculating direct connections", || {
             let direct_connections = DirectConnections::try_from(input.clone())?;
             debug!(target: "preprocessing", "Direct connections built");
@@ -33,7 +64,7 @@ impl ByPreprocessing for ScalableTransferPatternsAlgorithm {
                 .to_geoarrow_lines(input.stops.clone())
                 .map_err(|e| PreprocessingError::BuildLines(e))?;
 
-            write_geoarrow_to_file("./data/tmp/global/lines.arrow".into(), FileType::IPC, geoarrow_table)
+            write_geoarrow_to_file(work_dir(data_dir).join("global/lines.arrow"), FileType::IPC, geoarrow_table)
                 .map_err(|e| PreprocessingError::GeoArrow(e))?;
             debug!(target: "preprocessing", "Geo-Arrow table of direct connections written");
 
@@ -42,10 +73,10 @@ impl ByPreprocessing for ScalableTransferPatternsAlgorithm {
         
         // TODO: Re-use direct connections in processing of clusters
 
-        let (stop_ids_with_clusters, num_clusters) =
+        let (stop_ids_with_clusters, num_clusters) = metrics::measure("clustering", || progress::track(Job::PreprocessingClustering, || {
             run_with_spinner("preprocessing", "Clustering stops", || {
-                let (stop_ids_with_clusters, num_clusters) =
-                    cluster(&input.stops).expect("Clustering failed");
+                debug!(target: "preprocessing", "Clustering with {:?}", config.clustering);
+                let (stop_ids_with_clusters, num_clusters) = cluster(&config.clustering, &input)?;
 
                 let stops_clustered = input.stops.clone()
                     .left_join(stop_ids_with_clusters.clone().lazy(), "stop_id", "stop_id")
@@ -53,92 +84,321 @@ impl ByPreprocessing for ScalableTransferPatternsAlgorithm {
 
                 // TODO: Switch to parquet
                 write_df_to_file(
-                    "data/tmp/stp/stops_clustered.csv".into(),
+                    work_dir(data_dir).join("stp/stops_clustered.csv"),
                     FileType::CSV,
                     stops_clustered,
                 )?;
 
+                // The clustering can still be used without a report
+                let report = ClusteringReport::new(&config.clustering, &stop_ids_with_clusters, num_clusters, &input)
+                    .and_then(|report| report.save(data_dir));
+                if let Err(err) = report {
+                    warn!(target: "preprocessing", "Unable to save clustering report: {err}");
+                }
+
                 Ok::<(DataFrame, u32), PreprocessingError>((stop_ids_with_clusters, num_clusters))
-            })?;
+            })
+        }))?;
 
         let message = format!("Calculating local transfers for {num_clusters} clusters");
-        run_with_pb("preprocessing", message.as_str(), num_clusters as u64, true, |pb| {
-            // Currently not parallelized, since individual clusters could take very different amounts
-            // of time and RAM usage is lower when only looking at a single cluster at a time.
-            // Therefore, we parallelize within one cluster.
-            for cluster_id in 0..num_clusters {
-                let cluster_result =
-                    Self::process_cluster(cluster_id, &stop_ids_with_clusters, &input)?;
-                if save_to_disk {
-                    Self::save_cluster(cluster_id, cluster_result)?;
-                }
-
-                pb.inc(1);
-            }
+        let local_transfer_patterns = metrics::measure("local_transfer_patterns", || progress::track(Job::PreprocessingLocalTransferPatterns, || {
+            run_with_pb("preprocessing", message.as_str(), num_clusters as u64, true, |pb| {
+                let (num_transfer_patterns, local_transfer_patterns) =
+                    Self::process_clusters(
+                        &stop_ids_with_clusters,
+                        num_clusters,
+                        &input,
+                        config,
+                        data_dir,
+                        save_to_disk,
+                        &pb,
+                    )?;
+
+                // Shown in the dashboard
+                let cluster_stats = df!(
+                    "cluster_id" => (0..num_clusters).collect::<Vec<u32>>(),
+                    "num_transfer_patterns" => num_transfer_patterns,
+                )?;
+                write_df_to_file(work_dir(data_dir).join("stp/cluster_stats.csv"), FileType::CSV, cluster_stats)?;
+
+                Ok::<TransferPatternsDags, PreprocessingError>(local_transfer_patterns)
+            })
+        }))?;
+
+        let (long_distance_layer, arc_flags) = metrics::measure("long_distance_transfer_patterns", || progress::track(Job::PreprocessingLongDistanceTransferPatterns, || {
+            let long_distance_stations =
+                run_with_spinner("preprocessing", "Finding long-distance stations", || {
+                    Ok::<DataFrame, PreprocessingError>(
+                        Self::find_long_distance_stations(
+                            direct_connections.line_progressions.clone(), input.stops.clone()
+                        )?.collect()?
+                    )
+                })?;
+            debug!(target: "preprocessing", "Found {} long distance stations", long_distance_stations.column("stop_id")?.len());
+            write_df_to_file(
+                work_dir(data_dir).join("stp/long_distance_stations.parquet"),
+                FileType::PARQUET,
+                long_distance_stations.clone(),
+            )?;
 
-            Ok::<(), PreprocessingError>(())
-        })?;
+            let border_stations =
+                run_with_spinner("preprocessing", "Finding border stations", || {
+                    Self::find_border_stations(
+                        direct_connections.line_progressions.clone(),
+                        &stop_ids_with_clusters
+                    )
+                })?;
+            debug!(target: "preprocessing", "Found {} border stations", border_stations.len());
+            write_df_to_file(
+                work_dir(data_dir).join("stp/border_stations.parquet"),
+                FileType::PARQUET,
+                df!("stop_id" => border_stations.iter().map(|stop| stop.0).sorted().collect::<Vec<u32>>())?,
+            )?;
 
-        let long_distance_stations =
-            run_with_spinner("preprocessing", "Finding long-distance stations", || {
-                Ok::<DataFrame, PreprocessingError>(
-                    Self::find_long_distance_stations(
-                        direct_connections.line_progressions.clone(), input.stops
-                    )?.collect()?
+            let long_distance_layer = run_with_spinner("preprocessing", "Building the long-distance layer", || {
+                LongDistanceLayer::new(
+                    &direct_connections.line_progressions,
+                    &long_distance_stations,
+                    &input.stops,
+                    &stop_ids_with_clusters,
                 )
             })?;
-        debug!(target: "preprocessing", "Found {} long distance stations", long_distance_stations.column("stop_id")?.len());
+            debug!(
+                target: "preprocessing",
+                "Long-distance layer has {} stations and {} edges",
+                long_distance_layer.num_stations(),
+                long_distance_layer.num_edges(),
+            );
 
-        let border_stations =
-            run_with_spinner("preprocessing", "Finding border stations", || {
-                Self::find_border_stations(
-                    direct_connections.line_progressions,
-                    &stop_ids_with_clusters
-                )
-            })?;
-        debug!(target: "preprocessing", "Found {} border stations", border_stations.len());
-        
-        let long_distance_transfer_patterns =
-            run_with_spinner("preprocessing", "Calculating long-distance transfer patterns", || {
-                
+            let arc_flags = config.long_distance.arc_flags.then(|| {
+                run_with_spinner("preprocessing", "Calculating arc flags of the long-distance layer", || {
+                    let arc_flags = ArcFlags::compute(&long_distance_layer);
+                    debug!(
+                        target: "preprocessing",
+                        "{:.1}% of the arc flags are set",
+                        arc_flags.density(num_clusters) * 100.0,
+                    );
+                    arc_flags
+                })
             });
 
+            let long_distance_transfer_patterns =
+                run_with_spinner("preprocessing", "Calculating long-distance transfer patterns", || {
+
+                });
+
+            Ok::<(LongDistanceLayer, Option<ArcFlags>), PreprocessingError>((long_distance_layer, arc_flags))
+        }))?;
+
+        let algorithm = Self {
+            local_transfer_patterns,
+            clusters: Self::clusters_of_stops(&stop_ids_with_clusters)?,
+            long_distance_layer,
+            arc_flags,
+        };
+        if algorithm.arc_flags.is_some() && config.long_distance.benchmark_queries > 0 {
+            algorithm.benchmark_arc_flags(config.long_distance.benchmark_queries, data_dir, save_to_disk);
+        }
+
         // TODO
-        Ok(Self {})
+        Ok(algorithm)
     }
 }
 
 impl ScalableTransferPatternsAlgorithm {
+    /// Compares long-distance queries with and without arc flags. If saving to disk, the benchmark
+    /// is written to [`ARC_FLAGS_BENCHMARK_PATH`] in the work dir of `data_dir`.
+    fn benchmark_arc_flags(&self, max_queries: usize, data_dir: &Path, save_to_disk: bool) {
+        let benchmark = run_with_spinner("preprocessing", "Comparing queries with and without arc flags", || {
+            ArcFlagsBenchmark::run(self, max_queries)
+        });
+        debug!(
+            target: "preprocessing",
+            "Arc flags reduce query graphs from {:.1} to {:.1} edges on average ({:?} -> {:?} for {} queries)",
+            benchmark.avg_query_graph_edges_without,
+            benchmark.avg_query_graph_edges_with,
+            benchmark.duration_without,
+            benchmark.duration_with,
+            benchmark.num_queries,
+        );
+        if benchmark.num_different_distances > 0 {
+            warn!(
+                target: "preprocessing",
+                "{} queries had other distances with arc flags",
+                benchmark.num_different_distances,
+            );
+        }
+
+        if save_to_disk {
+            let saved = benchmark.to_df().and_then(|df| {
+                write_df_to_file(work_dir(data_dir).join(ARC_FLAGS_BENCHMARK_PATH), FileType::CSV, df)
+            });
+            if let Err(e) = saved {
+                warn!(target: "preprocessing", "Could not save arc flags benchmark: {e}");
+            }
+        }
+    }
+
+    /// The cluster of each stop, from the columns `stop_id` and `cluster_id`
+    fn clusters_of_stops(stop_ids_with_clusters: &DataFrame) -> PreprocessingResult<HashMap<StopId, u32>> {
+        Ok(izip!(
+            stop_ids_with_clusters.column("stop_id")?.u32()?,
+            stop_ids_with_clusters.column("cluster_id")?.u32()?,
+        )
+            .filter_map(|(stop_id, cluster_id)| Some((StopId(stop_id?), cluster_id?)))
+            .collect())
+    }
+
+    /// Calculates the transfer patterns within each cluster and returns how many there are per
+    /// cluster, along with the transfer patterns of all clusters.
+    /// Clusters are processed in parallel within the memory budget, largest first, since those
+    /// take the longest. Everything is written within `data_dir`. If saving to disk, finished
+    /// clusters are checkpointed to [`CHECKPOINTS_DIR`] so that an interrupted preprocessing can
+    /// resume. The checkpoints are removed once all clusters are done.
+    fn process_clusters(
+        stop_ids_with_clusters: &DataFrame,
+        num_clusters: u32,
+        input: &PreprocessingInput,
+        config: &PreprocessingConfig,
+        data_dir: &Path,
+        save_to_disk: bool,
+        pb: &ProgressBar,
+    ) -> Result<(Vec<u32>, TransferPatternsDags), PreprocessingError> {
+        let config = &config.local_transfer_patterns;
+        let job = Job::PreprocessingLocalTransferPatterns;
+
+        let checkpoints = if save_to_disk && config.resume {
+            let fingerprint = checkpoint::fingerprint(stop_ids_with_clusters, input)?;
+            Some(Checkpoints::open(&data_dir.join(CHECKPOINTS_DIR), fingerprint)?)
+        } else {
+            None
+        };
+
+        let mut cluster_sizes = vec![0u32; num_clusters as usize];
+        for cluster_id in stop_ids_with_clusters.column("cluster_id")?.u32()?.into_iter().flatten() {
+            if let Some(size) = cluster_sizes.get_mut(cluster_id as usize) {
+                *size += 1;
+            }
+        }
+        let cluster_ids = (0..num_clusters)
+            .sorted_by_key(|cluster_id| Reverse(cluster_sizes[*cluster_id as usize]))
+            .collect_vec();
+
+        let scheduler = Scheduler::new(
+            config.memory_budget_mib * 1024 * 1024,
+            config.max_parallel_clusters.unwrap_or_else(rayon::current_num_threads),
+        );
+        let num_transfer_patterns = Mutex::new(vec![0u32; num_clusters as usize]);
+        let local_transfer_patterns = Mutex::new(TransferPatternsDags::default());
+        let num_done = AtomicU32::new(0);
+        let first_error: Mutex<Option<PreprocessingError>> = Mutex::new(None);
+
+        let finish_cluster = |cluster_id: u32, transfer_patterns: TransferPatternsDags| {
+            num_transfer_patterns.lock().unwrap()[cluster_id as usize] = transfer_patterns.num_patterns() as u32;
+            // Stops belong to exactly one cluster, so the DAGs of the clusters don't overlap
+            local_transfer_patterns.lock().unwrap().extend(transfer_patterns);
+            let done = num_done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress::finish_cluster(job, done);
+            pb.inc(1);
+        };
+
+        if let Some(first_cluster_id) = cluster_ids.first() {
+            progress::start_cluster(job, *first_cluster_id, 0, num_clusters);
+        }
+        thread::scope(|scope| {
+            for cluster_id in cluster_ids {
+                if first_error.lock().unwrap().is_some() {
+                    break;
+                }
+
+                let checkpoint = match checkpoints.as_ref().map(|checkpoints| checkpoints.load(cluster_id)) {
+                    Some(Ok(checkpoint)) => checkpoint,
+                    Some(Err(err)) => {
+                        warn!(target: "preprocessing", "Unable to read checkpoint of cluster {cluster_id}, calculating it again: {err}");
+                        None
+                    }
+                    None => None,
+                };
+                if let Some(transfer_patterns) = checkpoint {
+                    debug!(target: "preprocessing", "Resuming with the checkpoint of cluster {cluster_id}");
+                    finish_cluster(cluster_id, transfer_patterns);
+                    continue;
+                }
+
+                let memory = scheduler::estimate_memory(cluster_sizes[cluster_id as usize]);
+                let reservation = scheduler.reserve(memory);
+                let (checkpoints, first_error, finish_cluster, num_done) =
+                    (&checkpoints, &first_error, &finish_cluster, &num_done);
+                scope.spawn(move || {
+                    // The progress within the cluster is reported by the TP preprocessing
+                    progress::start_cluster(job, cluster_id, num_done.load(Ordering::Relaxed), num_clusters);
+
+                    let result = Self::process_cluster(cluster_id, stop_ids_with_clusters, input, data_dir)
+                        .and_then(|(transfer_patterns, direct_connections)| {
+                            if save_to_disk {
+                                Self::save_cluster(cluster_id, direct_connections, data_dir)?;
+                            }
+                            // Written after the other results of the cluster, so that it is only
+                            // reused if those are complete
+                            if let Some(checkpoints) = checkpoints {
+                                checkpoints.save(cluster_id, &transfer_patterns)?;
+                            }
+                            Ok(transfer_patterns)
+                        });
+                    drop(reservation);
+
+                    match result {
+                        Ok(transfer_patterns) => finish_cluster(cluster_id, transfer_patterns),
+                        Err(err) => {
+                            first_error.lock().unwrap().get_or_insert(err);
+                        }
+                    }
+                });
+            }
+        });
+
+        if let Some(err) = first_error.into_inner().unwrap() {
+            return Err(err);
+        }
+        if let Some(checkpoints) = checkpoints {
+            checkpoints.remove()?;
+        }
+        Ok((num_transfer_patterns.into_inner().unwrap(), local_transfer_patterns.into_inner().unwrap()))
+    }
+
     fn process_cluster(
         cluster_id: u32,
         stop_ids_with_clusters: &DataFrame,
         overall_input: &PreprocessingInput,
-    ) -> Result<(TransferPatternsTable, DirectConnections), PreprocessingError> {
+        data_dir: &Path,
+    ) -> Result<(TransferPatternsDags, DirectConnections), PreprocessingError> {
         let input = filter_for_cluster(cluster_id, stop_ids_with_clusters, overall_input)?;
+        let cluster_dir = work_dir(data_dir).join(format!("stp/clusters/{cluster_id}"));
 
         write_df_to_file(
-            format!("./data/tmp/stp/clusters/{cluster_id}/stops.parquet").into(),
+            cluster_dir.join("stops.parquet"),
             FileType::PARQUET,
             input.stops.clone().collect()?,
         )?;
         write_df_to_file(
-            format!("./data/tmp/stp/clusters/{cluster_id}/trips.parquet").into(),
+            cluster_dir.join("trips.parquet"),
             FileType::PARQUET,
             input.trips.clone().collect()?,
         )?;
         write_df_to_file(
-            format!("./data/tmp/stp/clusters/{cluster_id}/stop_times.parquet").into(),
+            cluster_dir.join("stop_times.parquet"),
             FileType::PARQUET,
             input.stop_times.clone().collect()?,
         )?;
 
         let result = TransferPatternsAlgorithm::preprocess(input.clone(), false)?;
 
-        let TransferPatternsAlgorithm { transfer_patterns, direct_connections } = result;
+        let TransferPatternsAlgorithm { dags: transfer_patterns, direct_connections } = result;
 
         // Build transfer patterns visualization
         {
-            let stop_chains = transfer_patterns.0.iter()
+            let patterns = transfer_patterns.patterns().collect_vec();
+            let stop_chains = patterns.iter()
                 .map(|tp| [vec![tp.0], tp.1.clone(), vec![tp.2]].concat());
 
             let mut table = build_geoarrow_lines(
@@ -149,16 +409,16 @@ impl ScalableTransferPatternsAlgorithm {
             let start_field = Field::new("start", DataType::UInt32, false);
             let target_field = Field::new("target", DataType::UInt32, false);
             let start_id_array = UInt32Array::from_iter(
-                transfer_patterns.0.iter().map(|tp| tp.0.0)
+                patterns.iter().map(|tp| tp.0.0)
             );
             let target_id_array = UInt32Array::from_iter(
-                transfer_patterns.0.iter().map(|tp| tp.2.0)
+                patterns.iter().map(|tp| tp.2.0)
             );
             table.append_column(start_field.into(), vec![Arc::new(start_id_array)])?;
             table.append_column(target_field.into(), vec![Arc::new(target_id_array)])?;
 
             write_geoarrow_to_file(
-                format!("./data/tmp/stp/clusters/{cluster_id}/transfer_patterns.arrow").into(),
+                cluster_dir.join("transfer_patterns.arrow"),
                 FileType::IPC,
                 table,
             )?;
@@ -169,7 +429,7 @@ impl ScalableTransferPatternsAlgorithm {
             let table = direct_connections.to_geoarrow_lines(input.stops)?;
 
             write_geoarrow_to_file(
-                format!("./data/tmp/stp/clusters/{cluster_id}/lines_geo.arrow").into(),
+                cluster_dir.join("lines_geo.arrow"),
                 FileType::IPC,
                 table,
             )?;
@@ -178,26 +438,23 @@ impl ScalableTransferPatternsAlgorithm {
         Ok((transfer_patterns, direct_connections))
     }
 
+    /// Writes the direct connections of a cluster. Its transfer patterns are written with the
+    /// checkpoints.
     fn save_cluster(
         cluster_id: u32,
-        (tp_table, direct_connections): (TransferPatternsTable, DirectConnections),
+        direct_connections: DirectConnections,
+        data_dir: &Path,
     ) -> Result<(), PreprocessingError> {
         // TODO: Switch to IPC as data format
 
-        /* TODO: write_file(
-            format!("./data/preprocessing/stp/transfer_patterns/cluster_id={cluster_id}/data.parquet").into(),
-            FileType::PARQUET,
-            tp_table.0
-        )?; */
-
         write_df_to_file(
-            format!("./data/preprocessing/stp/direct_connections/stop_incidence/cluster_id={cluster_id}/data.parquet").into(),
+            data_dir.join(format!("{DIRECT_CONNECTIONS_DIR}/stop_incidence/cluster_id={cluster_id}/data.parquet")),
             FileType::PARQUET,
             direct_connections.stop_incidence
         )?;
 
         write_df_to_file(
-            format!("./data/preprocessing/stp/direct_connections/expanded_lines/cluster_id={cluster_id}/data.parquet").into(),
+            data_dir.join(format!("{DIRECT_CONNECTIONS_DIR}/expanded_lines/cluster_id={cluster_id}/data.parquet")),
             FileType::PARQUET,
             direct_connections.expanded_lines
         )?;
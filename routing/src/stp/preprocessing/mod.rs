@@ -1,2 +1,4 @@
+mod checkpoint;
 pub mod clustering;
-mod init;
\ No newline at end of file
+mod init;
+mod scheduler;
@@ -0,0 +1,102 @@
+use std::sync::{Condvar, Mutex};
+
+/// Memory that the preprocessing of any cluster needs, e.g. for the RAPTOR structures. In bytes.
+const BASE_MEMORY: u64 = 64 * 1024 * 1024;
+/// Memory per pair of stops in a cluster. The range queries from each stop find transfer patterns
+/// to most other stops, so memory grows quadratically with the cluster size. In bytes.
+const MEMORY_PER_STOP_PAIR: u64 = 512;
+
+/// Rough estimate of the peak memory usage while calculating the transfer patterns of a cluster
+/// with `num_stops` stops, in bytes
+pub(super) fn estimate_memory(num_stops: u32) -> u64 {
+    BASE_MEMORY + (num_stops as u64).pow(2) * MEMORY_PER_STOP_PAIR
+}
+
+/// Limits how many clusters are processed at the same time, by their estimated memory usage and
+/// by their number
+pub(super) struct Scheduler {
+    memory_budget: u64,
+    max_parallel: usize,
+    state: Mutex<SchedulerState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    used_memory: u64,
+    running: usize,
+}
+
+/// A cluster that may run. Frees its memory when it is dropped.
+pub(super) struct Reservation<'a> {
+    scheduler: &'a Scheduler,
+    memory: u64,
+}
+
+impl Scheduler {
+    pub(super) fn new(memory_budget: u64, max_parallel: usize) -> Self {
+        Self {
+            memory_budget,
+            max_parallel: max_parallel.max(1),
+            state: Mutex::new(SchedulerState::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a cluster that needs `memory` bytes can run. Clusters that need more than the
+    /// whole budget still run, but only when no other cluster is running.
+    pub(super) fn reserve(&self, memory: u64) -> Reservation<'_> {
+        let memory = memory.min(self.memory_budget);
+
+        let mut state = self.state.lock().unwrap();
+        while state.running >= self.max_parallel || state.used_memory + memory > self.memory_budget {
+            state = self.released.wait(state).unwrap();
+        }
+        state.used_memory += memory;
+        state.running += 1;
+
+        Reservation { scheduler: self, memory }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        state.used_memory -= self.memory;
+        state.running -= 1;
+        self.scheduler.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_memory_budget() {
+        let scheduler = Scheduler::new(100, 8);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            // Only two clusters of this size fit into the budget at once, and the large one runs
+            // alone
+            for memory in [40, 40, 40, 40, 1_000] {
+                let reservation = scheduler.reserve(memory);
+                let (running, max_running) = (&running, &max_running);
+                scope.spawn(move || {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    drop(reservation);
+                });
+            }
+        });
+
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+    }
+}
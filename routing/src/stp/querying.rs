@@ -0,0 +1,64 @@
+use crate::stp::arc_flags::LongDistanceSearch;
+use crate::stp::ScalableTransferPatternsAlgorithm;
+use crate::tp::transfer_pattern_ds::dag::QueryGraph;
+use common::types::StopId;
+
+impl ScalableTransferPatternsAlgorithm {
+    /// Builds the query graph of the journeys from `source` to `target`. Within a cluster, it is
+    /// the query graph of the local transfer patterns, across clusters it combines them with the
+    /// long-distance layer (see [`Self::long_distance_search`]). `None` if a stop has no cluster.
+    pub fn query_graph(&self, source: StopId, target: StopId, use_arc_flags: bool) -> Option<QueryGraph> {
+        if self.clusters.get(&source)? == self.clusters.get(&target)? {
+            return self.local_transfer_patterns.query_graph(source, target).ok();
+        }
+        self.long_distance_search(source, target, use_arc_flags)
+            .map(|search| search.query_graph)
+    }
+
+    /// Combines the local transfer patterns from `source` to the long-distance stations of its
+    /// cluster, a search in the long-distance layer from those to the long-distance stations of
+    /// the cluster of `target`, and the local transfer patterns from there to `target`. With
+    /// `use_arc_flags`, the search only follows edges towards the cluster of `target`. The
+    /// distance is the one in the long-distance layer. `None` if a stop has no cluster or a
+    /// cluster has no long-distance stations.
+    pub fn long_distance_search(&self, source: StopId, target: StopId, use_arc_flags: bool) -> Option<LongDistanceSearch> {
+        let source_cluster = *self.clusters.get(&source)?;
+        let target_cluster = *self.clusters.get(&target)?;
+        let mut edges: Vec<(StopId, StopId)> = vec![];
+
+        // Long-distance stations that are reached from the source with local transfer patterns
+        let mut access_stations = vec![];
+        for station in self.long_distance_layer.stations_of_cluster(source_cluster) {
+            if station == source {
+                access_stations.push(station);
+                continue;
+            }
+            let Ok(local) = self.local_transfer_patterns.query_graph(source, station) else { continue };
+            if !local.is_empty() {
+                edges.extend(local.edges());
+                access_stations.push(station);
+            }
+        }
+
+        // Long-distance stations from which the target is reached with local transfer patterns
+        let mut egress_stations = vec![];
+        for station in self.long_distance_layer.stations_of_cluster(target_cluster) {
+            if station == target {
+                egress_stations.push(station);
+                continue;
+            }
+            let Ok(local) = self.local_transfer_patterns.query_graph(station, target) else { continue };
+            if !local.is_empty() {
+                edges.extend(local.edges());
+                egress_stations.push(station);
+            }
+        }
+
+        let arc_flags = self.arc_flags.as_ref().filter(|_| use_arc_flags);
+        let (distance, long_distance_edges) =
+            self.long_distance_layer.search_between(&access_stations, &egress_stations, arc_flags)?;
+        edges.extend(long_distance_edges);
+
+        Some(LongDistanceSearch { distance, query_graph: QueryGraph::from_edges(source, target, edges) })
+    }
+}
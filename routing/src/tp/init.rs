@@ -1,37 +1,64 @@
-use crate::algorithms::initialization::{ByPreprocessing, PreprocessingInput, PreprocessingResult};
+use crate::algorithms::initialization::{ByPreprocessing, PreprocessingError, PreprocessingInput, PreprocessingResult};
 use crate::direct_connections::DirectConnections;
 use crate::raptor::RaptorAlgorithm;
+use crate::tp::transfer_pattern_ds::dag::TransferPatternsDags;
 use crate::tp::transfer_pattern_ds::graph::TransferPatternsGraphs;
 use crate::tp::transfer_pattern_ds::table::TransferPatternsTable;
 use crate::tp::TransferPatternsAlgorithm;
 use async_trait::async_trait;
 use chrono::{DateTime, Duration};
 use common::util::logging::run_with_pb;
+use common::util::progress;
+use common::util::progress::Job;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use log::warn;
+#[cfg(debug_assertions)]
+use common::types::config::work_dir;
+use common::types::config::DEFAULT_DATA_DIR;
+use common::types::config::features::PreprocessingConfig;
+use log::{debug, warn};
 use crate::algorithms::queries::cardinality::All;
 use crate::algorithms::queries::Queryable;
 use crate::algorithms::queries::range::{Range, RangeInput};
+use crate::algorithms::queries::options::QueryOptions;
+
+/// Where the transfer pattern graphs are written to in debug builds, one DOT file per stop.
+/// Relative to the work dir.
+#[cfg(debug_assertions)]
+const DAGS_DIR: &str = "tp/dags";
 
 #[async_trait]
 impl ByPreprocessing for TransferPatternsAlgorithm {
     fn preprocess(input: PreprocessingInput, save_to_disk: bool) -> PreprocessingResult<Self> {
+        Self::preprocess_with_config(input, &PreprocessingConfig::default(), Path::new(DEFAULT_DATA_DIR), save_to_disk)
+    }
+
+    #[allow(unused_variables)] // data_dir is only used in debug builds
+    fn preprocess_with_config(
+        input: PreprocessingInput,
+        _config: &PreprocessingConfig,
+        data_dir: &Path,
+        save_to_disk: bool,
+    ) -> PreprocessingResult<Self> {
         if save_to_disk {
-            warn!(target: "preprocessing", "Saving to disk is not yet implemented, ignoring");
+            warn!(target: "preprocessing", "Saving the transfer patterns to disk is not yet implemented, ignoring");
         }
 
         let direct_connections = DirectConnections::try_from(input.clone())?;
         let raptor = RaptorAlgorithm::preprocess_with_direct_connections(input.clone(), direct_connections.clone())?;
         let raptor = Arc::new(raptor);
 
-        let tp_table = Arc::new(Mutex::new(TransferPatternsTable::new()));
+        let dags = Arc::new(Mutex::new(TransferPatternsDags::default()));
 
         // Also keep a graph representation when in debugging mode. This is useful for checking the
         // validity of what we build.
         #[allow(unused_variables)] // for the regular compiler, where this is not used at all
         let tp_graph = Arc::new(Mutex::new(TransferPatternsGraphs::new(raptor.stop_mapping.0.clone())));
 
+        // Transfer patterns are collected over the first week of service
+        let first_departure = raptor.calendar.first_service_day_start().unwrap_or(DateTime::UNIX_EPOCH);
+
         let total = raptor.num_stops() as u64;
         run_with_pb("preprocessing", "Calculating local transfers in a single cluster", total, false, |pb| {
             raptor.stop_mapping.0.par_iter()
@@ -39,9 +66,10 @@ impl ByPreprocessing for TransferPatternsAlgorithm {
                     Queryable::<Range, All>::query(
                         &*Arc::clone(&raptor), // TODO: This looks bad
                         RangeInput {
-                            earliest_departure: DateTime::from_timestamp_millis(0).unwrap(),
-                            start: *stop,
+                            earliest_departure: first_departure,
+                            start: (*stop).into(),
                             range: Duration::weeks(1),
+                            options: QueryOptions::default(),
                         },
                         All {}
                     )
@@ -57,16 +85,20 @@ impl ByPreprocessing for TransferPatternsAlgorithm {
                         drop(tp_graph);
                     }
 
-                    // Add the collected results to the table of transfer patterns
-                    let tp_table = Arc::clone(&tp_table);
-                    let mut tp_table = tp_table.lock().unwrap();
-                    let res = tp_table.add(range_out);
+                    // Collect the transfer patterns of this origin, and only keep them as a DAG
+                    let mut tp_table = TransferPatternsTable::new();
+                    tp_table.add(range_out)?;
+                    let origin_dags = TransferPatternsDags::from_table(&tp_table);
                     drop(tp_table);
 
-                    res
+                    let dags = Arc::clone(&dags);
+                    dags.lock().unwrap().extend(origin_dags);
+
+                    Ok::<(), PreprocessingError>(())
                 })
                 .for_each(|_| {
                     pb.inc(1);
+                    progress::set_cluster_progress(Job::PreprocessingLocalTransferPatterns, pb.position(), total);
                 });
         });
 
@@ -76,14 +108,18 @@ impl ByPreprocessing for TransferPatternsAlgorithm {
                 .expect("Lock is still owned by others").into_inner().unwrap();
             // Check that graphs are acyclic. Expensive to compute, so only do that in debug.
             tp_graph.validate();
+            if save_to_disk {
+                tp_graph.write_dot_files(&work_dir(data_dir).join(DAGS_DIR))?;
+            }
         }
 
-        let tp_table = Arc::try_unwrap(tp_table)
+        let dags = Arc::try_unwrap(dags)
             .expect("Lock is still owned by others").into_inner().unwrap();
+        debug!(target: "preprocessing", "Built transfer pattern DAGs with {} nodes and {} edges", dags.num_nodes(), dags.num_edges());
 
         Ok(Self {
             direct_connections,
-            transfer_patterns: tp_table,
+            dags,
         })
     }
 }
@@ -142,7 +178,7 @@ mod tests {
         logging::init(LevelFilter::Debug);
 
         let actual_patterns = TransferPatternsAlgorithm::preprocess(input.clone(), false)
-            .unwrap().transfer_patterns;
+            .unwrap().dags.to_table();
 
         assert_eq!(expected_patterns, actual_patterns);
         // This test does not include the correctness of direct connections, this is done in tests
@@ -1,16 +1,17 @@
 use crate::algorithms::RoutingAlgorithm;
 use crate::direct_connections::DirectConnections;
-use crate::tp::transfer_pattern_ds::table::TransferPatternsTable;
+use crate::tp::transfer_pattern_ds::dag::TransferPatternsDags;
 
 mod init;
 mod querying;
-pub(crate) mod transfer_pattern_ds;
+pub mod transfer_pattern_ds;
 
 /// https://ad.informatik.uni-freiburg.de/files/transferpatterns.pdf
 
 pub struct TransferPatternsAlgorithm {
     pub direct_connections: DirectConnections,
-    pub transfer_patterns: TransferPatternsTable,
+    /// The transfer patterns as DAGs, from which the query graphs are built
+    pub dags: TransferPatternsDags,
 }
 
 impl RoutingAlgorithm for TransferPatternsAlgorithm {}
@@ -1,19 +1,68 @@
-use crate::algorithms::errors::QueryResult;
+use crate::algorithms::errors::{QueryError, QueryResult};
 use crate::algorithms::queries::earliest_arrival::{
     EarliestArrival, EarliestArrivalInput, EarliestArrivalOutput,
 };
 use crate::algorithms::queries::{cardinality, Queryable};
+use crate::journey::{Journey, Leg};
 use crate::tp::TransferPatternsAlgorithm;
+use chrono::{DateTime, Utc};
+use common::types::station::Place;
+use common::types::StopId;
+use hashbrown::HashMap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 
 impl Queryable<EarliestArrival, cardinality::Single> for TransferPatternsAlgorithm {
+    /// Runs a time-dependent Dijkstra on the query graph of the transfer patterns, as in section 3.3
+    /// of the paper. Each connection of the query graph is a direct ride.
     fn query(
         &self,
         input: EarliestArrivalInput,
         cardinality: cardinality::Single,
     ) -> QueryResult<EarliestArrivalOutput> {
-        drop(input);
-        drop(cardinality);
-        todo!()
+        let (Place::Stop(source), Place::Stop(target)) = (input.start, cardinality.target) else {
+            return Err(QueryError::InvalidInput("Transfer patterns can only be queried between stops".into()));
+        };
+        // Stops without transfer patterns can't reach anything
+        let query_graph = self.dags.query_graph(source, target)
+            .map_err(|_| QueryError::NoRouteFound)?;
+
+        // The arrival at each stop, with the ride that arrives there
+        let mut arrivals: HashMap<StopId, (DateTime<Utc>, Option<Leg>)> =
+            HashMap::from([(source, (input.earliest_departure, None))]);
+        let mut queue = BinaryHeap::from([Reverse((input.earliest_departure, source))]);
+        while let Some(Reverse((arrival, stop))) = queue.pop() {
+            if stop == target {
+                break;
+            }
+            if arrivals[&stop].0 < arrival {
+                continue;
+            }
+
+            for next_stop in query_graph.next_stops(stop) {
+                let Some(ride) = self.direct_connections.earliest_ride(stop, *next_stop, arrival)? else {
+                    continue;
+                };
+                let Leg::Ride { alight_time, .. } = ride else { unreachable!() };
+                if arrivals.get(next_stop).is_none_or(|(best, _)| alight_time < *best) {
+                    arrivals.insert(*next_stop, (alight_time, Some(ride)));
+                    queue.push(Reverse((alight_time, *next_stop)));
+                }
+            }
+        }
+
+        let mut legs = vec![];
+        let mut stop = target;
+        while stop != source {
+            let Some((_, Some(ride))) = arrivals.remove(&stop) else {
+                return Err(QueryError::NoRouteFound);
+            };
+            stop = *ride.start();
+            legs.push(ride);
+        }
+        legs.reverse();
+
+        Ok(EarliestArrivalOutput { journey: Journey::from(legs) })
     }
 }
 
@@ -28,6 +77,7 @@ mod tests {
     use crate::algorithms::initialization::ByPreprocessing;
     use crate::algorithms::queries::earliest_arrival::{EarliestArrivalInput, EarliestArrivalOutput};
     use crate::algorithms::queries::{cardinality, Queryable};
+    use crate::algorithms::queries::options::QueryOptions;
 
     #[test]
     fn single_ea_case_1() {
@@ -38,9 +88,10 @@ mod tests {
             .query(
                 EarliestArrivalInput {
                     earliest_departure: DateTime::UNIX_EPOCH,
-                    start: StopId(0),
+                    start: StopId(0).into(),
+                    options: QueryOptions::default(),
                 },
-                cardinality::Single { target: StopId(1) },
+                cardinality::Single { target: StopId(1).into() },
             )
             .unwrap();
         let expected = EarliestArrivalOutput {
@@ -48,8 +99,8 @@ mod tests {
                 trip: OneOffTripId(0).into(),
                 boarding_stop: StopId(0),
                 alight_stop: StopId(1),
-                boarding_time: DateTime::from_timestamp_millis(100).unwrap(),
-                alight_time: DateTime::from_timestamp_millis(500).unwrap(),
+                boarding_time: DateTime::from_timestamp(100, 0).unwrap(),
+                alight_time: DateTime::from_timestamp(500, 0).unwrap(),
             }]),
         };
         assert_eq!(expected, actual);
@@ -0,0 +1,375 @@
+use crate::tp::transfer_pattern_ds::table::TransferPatternsTable;
+use common::types::errors::UnknownStopIdError;
+use common::types::StopId;
+use hashbrown::{HashMap, HashSet};
+use itertools::izip;
+use polars::prelude::*;
+
+/// https://ad.informatik.uni-freiburg.de/files/transferpatterns.pdf
+/// The transfer patterns of each origin stop as a DAG, as in section 3.2 of the paper. Unlike
+/// [`TransferPatternsGraphs`](super::graph::TransferPatternsGraphs), stops have their real
+/// (global) ids and the DAGs are stored compactly, so that they can be kept for querying.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TransferPatternsDags {
+    dags: HashMap<StopId, TransferPatternsDag>,
+}
+
+/// The transfer patterns that start at one origin. Each pattern is a path from the node of its
+/// target stop to the root, so edges point in the opposite direction of travel. Patterns with the
+/// same first stops share the nodes of those, i.e. the paths share their suffixes. Every target
+/// stop has exactly one node, so that the query graph can be built from it directly.
+///
+/// Nodes are numbered continuously, with the root as node 0. The edges of node `n` are
+/// `edges[edge_offsets[n]..edge_offsets[n + 1]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferPatternsDag {
+    /// Stop of each node
+    stops: Vec<StopId>,
+    edge_offsets: Vec<u32>,
+    edges: Vec<u32>,
+    /// Node of each target stop, sorted by stop
+    targets: Vec<(StopId, u32)>,
+}
+
+/// The stops and connections between them that optimal journeys from `source` to `target` can
+/// use, according to the transfer patterns. Queries only need to look at these connections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryGraph {
+    pub source: StopId,
+    pub target: StopId,
+    /// Stops that can follow each stop, in the direction of travel
+    next_stops: HashMap<StopId, Vec<StopId>>,
+}
+
+const ROOT: u32 = 0;
+
+impl TransferPatternsDags {
+    pub(crate) fn from_table(table: &TransferPatternsTable) -> Self {
+        let mut builders: HashMap<StopId, DagBuilder> = HashMap::new();
+        for (start, intermediates, target) in &table.0 {
+            builders.entry(*start)
+                .or_insert_with(|| DagBuilder::new(*start))
+                .add(intermediates, *target);
+        }
+
+        Self {
+            dags: builders.into_iter()
+                .map(|(origin, builder)| (origin, builder.build()))
+                .collect(),
+        }
+    }
+
+    /// Adds the DAGs of other origins, e.g. of another cluster
+    pub fn extend(&mut self, other: TransferPatternsDags) {
+        self.dags.extend(other.dags);
+    }
+
+    pub fn get(&self, origin: StopId) -> Option<&TransferPatternsDag> {
+        self.dags.get(&origin)
+    }
+
+    /// Builds the query graph of the journeys from `source` to `target`. It is empty if there are
+    /// no transfer patterns between them.
+    pub fn query_graph(&self, source: StopId, target: StopId) -> Result<QueryGraph, UnknownStopIdError> {
+        self.dags.get(&source)
+            .map(|dag| dag.query_graph(target))
+            .ok_or(UnknownStopIdError(source))
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.dags.values().map(|dag| dag.stops.len()).sum()
+    }
+
+    pub fn num_edges(&self) -> usize {
+        self.dags.values().map(|dag| dag.edges.len()).sum()
+    }
+
+    pub fn num_patterns(&self) -> usize {
+        self.dags.values().map(TransferPatternsDag::num_patterns).sum()
+    }
+
+    /// All transfer patterns as `(origin, intermediates, target)`
+    pub fn patterns(&self) -> impl Iterator<Item = (StopId, Vec<StopId>, StopId)> + '_ {
+        self.dags.values().flat_map(TransferPatternsDag::patterns)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn to_table(&self) -> TransferPatternsTable {
+        TransferPatternsTable(self.patterns().collect())
+    }
+
+    /// The DAGs with one row per origin, in the columns `origin`, `stops`, `edge_offsets`, `edges`,
+    /// `target_stops` and `target_nodes` (lists of the arrays of the DAG), e.g. to save them to disk
+    pub(crate) fn to_df(&self) -> PolarsResult<DataFrame> {
+        let dags = self.dags.values().collect::<Vec<_>>();
+
+        DataFrame::new(vec![
+            Column::new("origin".into(), dags.iter().map(|dag| dag.origin().0).collect::<Vec<u32>>()),
+            u32_lists("stops", dags.iter().map(|dag| dag.stops.iter().map(|stop| stop.0).collect())),
+            u32_lists("edge_offsets", dags.iter().map(|dag| dag.edge_offsets.clone())),
+            u32_lists("edges", dags.iter().map(|dag| dag.edges.clone())),
+            u32_lists("target_stops", dags.iter().map(|dag| dag.targets.iter().map(|(stop, _)| stop.0).collect())),
+            u32_lists("target_nodes", dags.iter().map(|dag| dag.targets.iter().map(|(_, node)| *node).collect())),
+        ])
+    }
+
+    /// Reads DAGs that were written with [`TransferPatternsDags::to_df`]
+    pub(crate) fn from_df(df: &DataFrame) -> PolarsResult<Self> {
+        fn u32s(list: Series) -> PolarsResult<Vec<u32>> {
+            Ok(list.u32()?.into_no_null_iter().collect())
+        }
+
+        let mut dags = HashMap::with_capacity(df.height());
+        for (origin, stops, edge_offsets, edges, target_stops, target_nodes) in izip!(
+            df.column("origin")?.u32()?,
+            df.column("stops")?.list()?,
+            df.column("edge_offsets")?.list()?,
+            df.column("edges")?.list()?,
+            df.column("target_stops")?.list()?,
+            df.column("target_nodes")?.list()?,
+        ) {
+            let (Some(origin), Some(stops), Some(edge_offsets), Some(edges), Some(target_stops), Some(target_nodes)) =
+                (origin, stops, edge_offsets, edges, target_stops, target_nodes) else {
+                continue;
+            };
+            let dag = TransferPatternsDag {
+                stops: u32s(stops)?.into_iter().map(StopId).collect(),
+                edge_offsets: u32s(edge_offsets)?,
+                edges: u32s(edges)?,
+                targets: izip!(u32s(target_stops)?, u32s(target_nodes)?)
+                    .map(|(stop, node)| (StopId(stop), node))
+                    .collect(),
+            };
+            dags.insert(StopId(origin), dag);
+        }
+
+        Ok(Self { dags })
+    }
+}
+
+fn u32_lists(name: &str, lists: impl Iterator<Item = Vec<u32>>) -> Column {
+    let lists: ListChunked = lists.map(|list| Some(Series::from_iter(list))).collect();
+    lists.into_series().with_name(name.into()).into()
+}
+
+impl TransferPatternsDag {
+    pub fn origin(&self) -> StopId {
+        self.stops[ROOT as usize]
+    }
+
+    fn edges(&self, node: u32) -> &[u32] {
+        let start = self.edge_offsets[node as usize] as usize;
+        let end = self.edge_offsets[node as usize + 1] as usize;
+        &self.edges[start..end]
+    }
+
+    fn target_node(&self, target: StopId) -> Option<u32> {
+        self.targets.binary_search_by_key(&target, |(stop, _)| *stop).ok()
+            .map(|idx| self.targets[idx].1)
+    }
+
+    /// Target nodes have an edge per pattern, all other nodes have exactly one edge
+    pub fn num_patterns(&self) -> usize {
+        self.targets.iter().map(|(_, node)| self.edges(*node).len()).sum()
+    }
+
+    /// The transfer patterns as `(origin, intermediates, target)`, by following the single edge
+    /// of each prefix node back to the root
+    pub fn patterns(&self) -> impl Iterator<Item = (StopId, Vec<StopId>, StopId)> + '_ {
+        self.targets.iter().flat_map(move |&(target, target_node)| {
+            self.edges(target_node).iter().map(move |&prefix_node| {
+                let mut intermediates = vec![];
+                let mut node = prefix_node;
+                while node != ROOT {
+                    intermediates.push(self.stops[node as usize]);
+                    node = self.edges(node)[0];
+                }
+                intermediates.reverse();
+                (self.origin(), intermediates, target)
+            })
+        })
+    }
+
+    /// Follows all paths from the node of `target` to the root. Nodes that are shared by several
+    /// paths are only followed once.
+    pub fn query_graph(&self, target: StopId) -> QueryGraph {
+        let mut next_stops: HashMap<StopId, Vec<StopId>> = HashMap::new();
+
+        if let Some(target_node) = self.target_node(target) {
+            let mut visited: HashSet<u32> = HashSet::from([target_node]);
+            let mut stack = vec![target_node];
+            while let Some(node) = stack.pop() {
+                for &previous_node in self.edges(node) {
+                    // The edge goes back towards the origin, so the journey travels the other way
+                    let next = next_stops.entry(self.stops[previous_node as usize]).or_default();
+                    if !next.contains(&self.stops[node as usize]) {
+                        next.push(self.stops[node as usize]);
+                    }
+                    if visited.insert(previous_node) {
+                        stack.push(previous_node);
+                    }
+                }
+            }
+        }
+
+        QueryGraph { source: self.origin(), target, next_stops }
+    }
+}
+
+impl QueryGraph {
+    /// Builds the query graph from its connections, in the direction of travel
+    pub(crate) fn from_edges(source: StopId, target: StopId, edges: impl IntoIterator<Item = (StopId, StopId)>) -> Self {
+        let mut next_stops: HashMap<StopId, Vec<StopId>> = HashMap::new();
+        for (from, to) in edges {
+            let next = next_stops.entry(from).or_default();
+            if !next.contains(&to) {
+                next.push(to);
+            }
+        }
+
+        Self { source, target, next_stops }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_stops.is_empty()
+    }
+
+    /// Stops that can follow `stop` on an optimal journey
+    pub fn next_stops(&self, stop: StopId) -> &[StopId] {
+        self.next_stops.get(&stop).map_or(&[], Vec::as_slice)
+    }
+
+    /// All connections, in the direction of travel
+    pub fn edges(&self) -> impl Iterator<Item = (StopId, StopId)> + '_ {
+        self.next_stops.iter()
+            .flat_map(|(from, next_stops)| next_stops.iter().map(move |to| (*from, *to)))
+    }
+}
+
+/// Collects the transfer patterns of an origin, before they are stored compactly
+struct DagBuilder {
+    stops: Vec<StopId>,
+    edges: Vec<Vec<u32>>,
+    /// Prefix node that follows a node with a stop, so that patterns share their first stops
+    prefixes: HashMap<(u32, StopId), u32>,
+    targets: HashMap<StopId, u32>,
+}
+
+impl DagBuilder {
+    fn new(origin: StopId) -> Self {
+        Self {
+            stops: vec![origin],
+            edges: vec![vec![]],
+            prefixes: HashMap::new(),
+            targets: HashMap::new(),
+        }
+    }
+
+    fn add_node(&mut self, stop: StopId, edge_to: u32) -> u32 {
+        let node = self.stops.len() as u32;
+        self.stops.push(stop);
+        self.edges.push(vec![edge_to]);
+        node
+    }
+
+    fn add(&mut self, intermediates: &[StopId], target: StopId) {
+        let mut current = ROOT;
+        for stop in intermediates {
+            current = match self.prefixes.get(&(current, *stop)) {
+                Some(node) => *node,
+                None => {
+                    let node = self.add_node(*stop, current);
+                    self.prefixes.insert((current, *stop), node);
+                    node
+                }
+            };
+        }
+
+        match self.targets.get(&target) {
+            Some(target_node) => {
+                let edges = &mut self.edges[*target_node as usize];
+                if !edges.contains(&current) {
+                    edges.push(current);
+                }
+            }
+            None => {
+                let target_node = self.add_node(target, current);
+                self.targets.insert(target, target_node);
+            }
+        }
+    }
+
+    fn build(self) -> TransferPatternsDag {
+        let mut edge_offsets = Vec::with_capacity(self.edges.len() + 1);
+        edge_offsets.push(0);
+        for edges in &self.edges {
+            edge_offsets.push(edge_offsets.last().unwrap() + edges.len() as u32);
+        }
+
+        let mut targets: Vec<(StopId, u32)> = self.targets.into_iter().collect();
+        targets.sort_unstable();
+
+        TransferPatternsDag {
+            stops: self.stops,
+            edge_offsets,
+            edges: self.edges.into_iter().flatten().collect(),
+            targets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_query_graph() {
+        let [a, b, c, d, e] = [StopId(0), StopId(1), StopId(2), StopId(3), StopId(4)];
+        // The example of Fig. 1 in the paper
+        let table = TransferPatternsTable(HashSet::from([
+            (a, vec![], e),
+            (a, vec![b], e),
+            (a, vec![], b),
+            (a, vec![b], c),
+            (a, vec![b, d], e),
+            (a, vec![b, c, d], e),
+        ]));
+
+        let dags = TransferPatternsDags::from_table(&table);
+
+        // Root, the prefixes B, B-C, B-D and B-C-D, and the targets B, C and E
+        let dag = dags.get(a).unwrap();
+        assert_eq!(dag.stops.len(), 8);
+        assert_eq!(dags.num_edges(), 10);
+
+        let query_graph = dags.query_graph(a, e).unwrap();
+        assert_eq!(
+            query_graph.edges().sorted().collect_vec(),
+            vec![(a, b), (a, e), (b, c), (b, d), (b, e), (c, d), (d, e)],
+        );
+        assert_eq!(query_graph.next_stops(a).iter().sorted().collect_vec(), vec![&b, &e]);
+
+        assert_eq!(dags.query_graph(a, c).unwrap().edges().sorted().collect_vec(), vec![(a, b), (b, c)]);
+        assert!(dags.query_graph(a, d).unwrap().is_empty());
+        assert!(matches!(dags.query_graph(e, a), Err(UnknownStopIdError(stop)) if stop == e));
+
+        assert_eq!(dags.num_patterns(), 6);
+        assert_eq!(dags.to_table(), table);
+    }
+
+    #[test]
+    fn test_df_roundtrip() {
+        let table = TransferPatternsTable(HashSet::from([
+            (StopId(0), vec![], StopId(1)),
+            (StopId(0), vec![StopId(1), StopId(3)], StopId(2)),
+            (StopId(2), vec![], StopId(1)),
+        ]));
+        let dags = TransferPatternsDags::from_table(&table);
+
+        let df = dags.to_df().unwrap();
+
+        assert_eq!(df.height(), 2);
+        assert_eq!(TransferPatternsDags::from_df(&df).unwrap(), dags);
+    }
+}
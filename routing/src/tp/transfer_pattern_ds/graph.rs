@@ -8,11 +8,16 @@ use petgraph::dot::{Config, Dot};
 use petgraph::graph::NodeIndex;
 use petgraph::{Directed, Graph, Incoming};
 use std::fmt::Debug;
+use std::fs;
+use std::fs::create_dir_all;
+use std::path::Path;
+use common::types::errors::UnknownStopIdError;
 use crate::algorithms::queries::range::RangeOutput;
 
 /// https://ad.informatik.uni-freiburg.de/files/transferpatterns.pdf
-/// This graph only exists for debugging purposes. In the real world, we use the transfer pattern
-/// table to make easier to store them. Be careful: Stops are not mapped to their real ids here!
+/// This graph only exists for debugging purposes. For querying, the transfer patterns are kept in
+/// [`TransferPatternsDags`](super::dag::TransferPatternsDags). Be careful: Stops are not mapped to
+/// their real ids here!
 
 #[derive(Debug, PartialEq, PartialOrd, Ord, Eq)]
 enum NodeType {
@@ -66,6 +71,27 @@ impl TransferPatternsGraphs {
         Self { dags }
     }
 
+    /// Formats the graph of the transfer patterns that start at `stop_id` in the DOT language of
+    /// Graphviz
+    pub(crate) fn to_dot(&self, stop_id: StopId) -> Result<String, UnknownStopIdError> {
+        match self.dags.get(&stop_id) {
+            Some(graph) => Ok(format!("{:?}", format_graph(graph))),
+            None => Err(UnknownStopIdError(stop_id)),
+        }
+    }
+
+    /// Writes the graph of each stop to `<dir>/<stop_id>.dot`, so that it can be inspected in the
+    /// dashboard
+    pub(crate) fn write_dot_files(&self, dir: &Path) -> std::io::Result<()> {
+        create_dir_all(dir)?;
+        for stop_id in self.dags.keys() {
+            if let Ok(dot) = self.to_dot(*stop_id) {
+                fs::write(dir.join(format!("{}.dot", stop_id.0)), dot)?;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn add(&mut self, result: RangeOutput) {
         for journey in result.journeys {
             self.add_journey(journey);
@@ -196,7 +222,7 @@ impl TransferPatternsGraphs {
 
 #[cfg(test)]
 mod tests {
-    use super::{format_graph, NodeType, TpGraph, TransferPatternsGraphs};
+    use super::{NodeType, TransferPatternsGraphs};
     use crate::journey::Journey;
     use crate::journey::Leg::Ride;
     use chrono::{DateTime, TimeDelta};
@@ -204,20 +230,12 @@ mod tests {
     use common::types::StopId;
     use itertools::{assert_equal, Itertools};
     use petgraph::{Directed, Graph};
-    use petgraph::dot::Dot;
     use common::types::trip::OneOffTripId;
 
     impl TransferPatternsGraphs {
-        
-        pub(self) fn format_as_dot<'a>(&self, stop_id: StopId) -> Result<Dot<'a, &TpGraph>, UnknownStopIdError> {
-            match self.dags.get(&stop_id) {
-                Some(graph) => Ok(format_graph(graph)),
-                None => Err(UnknownStopIdError(stop_id))
-            }
-        }
 
         pub(crate) fn print(&self, stop_id: StopId) {
-            println!("{:?}", self.format_as_dot(stop_id));
+            println!("{:?}", self.to_dot(stop_id));
         }
 
         pub(self) fn nodes(&self, root: StopId) -> Option<impl Iterator<Item=&(StopId, NodeType)> + Sized> {
@@ -398,5 +416,25 @@ mod tests {
             expected_edges.into_iter().sorted()
         );
     }
-}
 
+    #[test]
+    fn test_to_dot() {
+        let mut tp = TransferPatternsGraphs::new(vec![StopId(0), StopId(1)]);
+        tp.add_journey(Journey::from(vec![
+            Ride {
+                trip: OneOffTripId(0).into(),
+                boarding_stop: StopId(0),
+                alight_stop: StopId(1),
+                boarding_time: DateTime::UNIX_EPOCH,
+                alight_time: DateTime::UNIX_EPOCH + TimeDelta::seconds(42),
+            }
+        ]));
+
+        let dot = tp.to_dot(StopId(0)).unwrap();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("shape = diamond"));
+        assert!(dot.contains("1 -> 0"));
+
+        assert!(matches!(tp.to_dot(StopId(7)), Err(UnknownStopIdError(StopId(7)))));
+    }
+}
@@ -1,2 +1,3 @@
+pub mod dag;
 pub mod graph;
 pub(crate) mod table;
\ No newline at end of file
@@ -82,4 +82,4 @@ impl TransferPatternsTable {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
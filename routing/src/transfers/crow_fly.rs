@@ -81,4 +81,9 @@ impl CrowFlyTransferProvider {
 
         Ok(<Self as From<Vec<Coord<f32>>>>::from(coords))
     }
+
+    /// Use a different speed for the transfers, e.g. to account for wheelchair users
+    pub fn with_speed(self, speed: Speed) -> Self {
+        Self { speed, ..self }
+    }
 }
\ No newline at end of file
@@ -1,11 +1,16 @@
 use crate::AppData;
 use axum::extract::State;
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use routing::algorithms::errors::{QueryError, QueryResult};
 use routing::algorithms::queries::cardinality::{All, Multiple, Single, TargetCardinality};
-use routing::algorithms::queries::range::{Range, RangeOutput};
+use routing::algorithms::queries::isochrone::Isochrone;
+use routing::algorithms::queries::matrix::Matrix;
+use routing::algorithms::queries::range::Range;
+use routing::algorithms::queries::via::Via;
 use routing::algorithms::queries::{QueryType, Queryable};
+use common::util::time::InTimezone;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -76,8 +81,10 @@ impl<'a> TryFrom<AnyTargetCardinality<'a>> for All {
 pub(crate) async fn endpoint(
     State(app_data): State<Arc<AppData>>,
     axum::extract::Query(query): axum::extract::Query<Query<'_, Range>>,
-) -> Result<Json<RangeOutput>, (StatusCode, String)> {
-    let algorithm = &app_data.algorithm;
+) -> Result<Response, (StatusCode, String)> {
+    // Keep the timetable for the whole query, even if the data is reloaded in the meantime
+    let timetable = &*app_data.timetable.load_full();
+    let algorithm = &timetable.algorithm;
 
     /*let result = match query.0 {
         //AnyQuery::EaSingle(q) => to_responder(run::<EarliestArrival, Single, _>(algorithm, q)),
@@ -93,7 +100,112 @@ pub(crate) async fn endpoint(
 
     let result = run2::<Range, All, _>(algorithm, query);
 
-    result.map(|r| Json(r)).map_err(|err| convert_error(err))
+    result
+        .map(|r| {
+            let datasets = algorithm.datasets(r.journeys());
+            let r = r.in_timezone(algorithm.timezone());
+            Json(timetable.attribute(r, &datasets)).into_response()
+        })
+        .map_err(|err| convert_error(err))
+}
+
+pub(crate) async fn via_endpoint(
+    State(app_data): State<Arc<AppData>>,
+    axum::extract::Query(query): axum::extract::Query<Query<'_, Via>>,
+) -> Result<Response, (StatusCode, String)> {
+    let timetable = &*app_data.timetable.load_full();
+    let algorithm = &timetable.algorithm;
+    let result = run2::<Via, Single, _>(algorithm, query);
+
+    result
+        .map(|r| {
+            let datasets = algorithm.datasets(&r.sections);
+            let r = r.in_timezone(algorithm.timezone());
+            Json(timetable.attribute(r, &datasets)).into_response()
+        })
+        .map_err(|err| convert_error(err))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IsochroneFormat {
+    #[default]
+    Json,
+    /// Walking buffers around the reachable stops
+    Geojson,
+}
+
+#[derive(Deserialize)]
+pub struct IsochroneFormatParam {
+    #[serde(default)]
+    format: IsochroneFormat,
+}
+
+pub(crate) async fn isochrone_endpoint(
+    State(app_data): State<Arc<AppData>>,
+    axum::extract::Query(IsochroneFormatParam { format }): axum::extract::Query<IsochroneFormatParam>,
+    axum::extract::Query(query): axum::extract::Query<Query<'_, Isochrone>>,
+) -> Result<Response, (StatusCode, String)> {
+    let timetable = &*app_data.timetable.load_full();
+    let algorithm = &timetable.algorithm;
+    let output = run2::<Isochrone, All, _>(algorithm, query).map_err(convert_error)?
+        .in_timezone(algorithm.timezone());
+
+    let response = match format {
+        IsochroneFormat::Json => Json(timetable.attribute_all(output)).into_response(),
+        IsochroneFormat::Geojson => {
+            // Foreign members are allowed in feature collections
+            let feature_collection = output.to_geojson(|stop| algorithm.stop_location(stop));
+            Json(timetable.attribute_all(feature_collection)).into_response()
+        }
+    };
+    Ok(response)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MatrixFormat {
+    #[default]
+    Json,
+    /// Long format (origin, destination, travel time) as Arrow IPC stream
+    Arrow,
+}
+
+#[derive(Deserialize)]
+pub struct MatrixFormatParam {
+    #[serde(default)]
+    format: MatrixFormat,
+}
+
+pub(crate) async fn matrix_endpoint(
+    State(app_data): State<Arc<AppData>>,
+    axum::extract::Query(MatrixFormatParam { format }): axum::extract::Query<MatrixFormatParam>,
+    Json(query): Json<Query<'static, Matrix>>,
+) -> Result<Response, (StatusCode, String)> {
+    let timetable = app_data.timetable.load_full();
+    // The matrix runs many queries on the rayon thread pool, which must not block the async runtime
+    let (timetable, output) = tokio::task::spawn_blocking(move || {
+        let output = match query.target_cardinality {
+            AnyTargetCardinality::All(_) => run2::<Matrix, All, _>(&timetable.algorithm, query),
+            _ => run2::<Matrix, Multiple, _>(&timetable.algorithm, query),
+        };
+        (timetable, output)
+    })
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    let output = output.map_err(convert_error)?;
+
+    let response = match format {
+        MatrixFormat::Json => Json(timetable.attribute_all(output)).into_response(),
+        // The attributions can't be part of the table, so they are left to the JSON format
+        MatrixFormat::Arrow => {
+            let bytes = output
+                .to_arrow_ipc()
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+            ([(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream")], bytes).into_response()
+        }
+    };
+    Ok(response)
 }
 
 // Utility function to run a generic query on an algorithm
@@ -115,5 +227,6 @@ fn convert_error(err: QueryError) -> (StatusCode, String) {
             StatusCode::BAD_REQUEST,
             QueryError::InvalidTargetCardinality.to_string(),
         ),
+        QueryError::InvalidInput(reason) => (StatusCode::BAD_REQUEST, reason),
     }
 }
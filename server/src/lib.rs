@@ -1,42 +1,254 @@
 mod api;
 
-use axum::routing::get;
+use axum::http::{header, HeaderValue, Method};
+use axum::routing::{get, post};
+use arc_swap::ArcSwap;
 use axum::Router;
-use common::types::config::Config;
+use axum_server::tls_rustls::RustlsConfig;
+use common::types::attribution::DatasetAttribution;
+use common::types::config::servers::{is_valid_cors_origin, ApiServerConfig};
+use common::types::config::{Config, ConfigV1};
+use common::util::tls;
+use common::util::tls::TlsError;
 use routing::raptor::RaptorAlgorithm;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::future::Future;
 use std::sync::Arc;
 use tokio::net::TcpListener;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
 type ALGORITHM = RaptorAlgorithm;
 
 struct AppData {
-    algorithm: ALGORITHM,
+    /// Swapped when the timetable data is reloaded. Queries keep using the timetable they started
+    /// with, so they finish on the old data.
+    timetable: Arc<ArcSwap<Timetable>>,
     config: Config,
 }
 
+/// The data that queries are answered with. Algorithm and attributions are swapped together when
+/// the timetable data is reloaded, so results are attributed to the data they were computed from.
+pub struct Timetable {
+    pub algorithm: ALGORITHM,
+    /// By dataset ID
+    pub attributions: HashMap<String, DatasetAttribution>,
+}
+
+impl Timetable {
+    pub fn new(algorithm: ALGORITHM, attributions: Vec<DatasetAttribution>) -> Self {
+        let attributions = attributions.into_iter()
+            .map(|attribution| (attribution.dataset_id.clone(), attribution))
+            .collect();
+        Self { algorithm, attributions }
+    }
+
+    /// Adds the attributions of the given datasets to the output. Datasets without a known
+    /// attribution are still listed.
+    pub fn attribute<T>(&self, output: T, dataset_ids: &[&str]) -> Attributed<T> {
+        let datasets = dataset_ids.iter()
+            .map(|dataset_id| {
+                self.attributions.get(*dataset_id)
+                    .cloned()
+                    .unwrap_or_else(|| DatasetAttribution::unknown(dataset_id))
+            })
+            .collect();
+        Attributed { output, datasets }
+    }
+
+    /// Adds the attributions of all datasets to the output. For outputs that don't consist of
+    /// journeys, like isochrones, the datasets that contributed can't be told apart.
+    pub fn attribute_all<T>(&self, output: T) -> Attributed<T> {
+        let mut datasets: Vec<DatasetAttribution> = self.attributions.values().cloned().collect();
+        datasets.sort_by(|a, b| a.dataset_id.cmp(&b.dataset_id));
+        Attributed { output, datasets }
+    }
+}
+
+/// Output of a query, together with the attributions of the datasets it is based on
+#[derive(Serialize)]
+pub struct Attributed<T> {
+    #[serde(flatten)]
+    pub output: T,
+    pub datasets: Vec<DatasetAttribution>,
+}
+
+/// Socket the API server accepts connections on
+pub enum Listener {
+    Plain(TcpListener),
+    Tls(std::net::TcpListener, RustlsConfig),
+}
+
 pub async fn build<'a>(
-    algorithm: ALGORITHM,
+    timetable: Arc<ArcSwap<Timetable>>,
     config: Config,
-) -> Result<(TcpListener, Router), ServerError> {
-    let app_data = Arc::new(AppData { algorithm, config });
+) -> Result<(Listener, Router), ServerError> {
+    let server_config = config.servers().api.clone();
+    if server_config.commercial {
+        check_commercial_use(&config)?;
+    }
+    let app_data = Arc::new(AppData { timetable, config });
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/api/v1/routing", get(api::v1::routing::endpoint))
+        .route("/api/v1/routing/via", get(api::v1::routing::via_endpoint))
+        .route("/api/v1/isochrone", get(api::v1::routing::isochrone_endpoint))
+        .route("/api/v1/matrix", post(api::v1::routing::matrix_endpoint))
         .with_state(app_data);
 
-    let listener = TcpListener::bind("0.0.0.0:8080").await?;
+    if let Some(cors) = cors_layer(&server_config.cors_origins)? {
+        app = app.layer(cors);
+    }
+
+    let listener = bind(&server_config).await?;
 
     Ok((listener, app))
 }
 
+async fn bind(server_config: &ApiServerConfig) -> Result<Listener, ServerError> {
+    let listener = TcpListener::bind(server_config.listen).await?;
+
+    match &server_config.tls {
+        None => Ok(Listener::Plain(listener)),
+        Some(tls_config) => {
+            let rustls_config = RustlsConfig::from_config(Arc::new(tls::load_server_config(tls_config)?));
+            Ok(Listener::Tls(listener.into_std()?, rustls_config))
+        }
+    }
+}
+
+/// Refuses datasets whose license doesn't allow commercial use
+fn check_commercial_use(config: &Config) -> Result<(), ServerError> {
+    let Config::Version1(ConfigV1 { datasets, .. }) = config;
+    let non_commercial: Vec<String> = datasets.iter()
+        .filter(|dataset| dataset.license.as_ref().is_some_and(|license| license.is_non_commercial()))
+        .map(|dataset| dataset.id.clone())
+        .collect();
+
+    match non_commercial.is_empty() {
+        true => Ok(()),
+        false => Err(ServerError::NonCommercialLicense(non_commercial)),
+    }
+}
+
+/// Without origins, no CORS headers are sent and browsers only allow same-origin requests
+fn cors_layer(origins: &[String]) -> Result<Option<CorsLayer>, ServerError> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins.iter()
+            .map(|origin| match is_valid_cors_origin(origin) {
+                true => HeaderValue::from_str(origin).map_err(|_| ServerError::InvalidCorsOrigin(origin.clone())),
+                false => Err(ServerError::InvalidCorsOrigin(origin.clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    // The matrix is queried with a JSON body, which needs a preflight
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST])
+            .allow_headers([header::CONTENT_TYPE])
+    ))
+}
+
+/// Serves `app` until `shutdown` completes. Open connections are allowed to finish.
+pub async fn serve(
+    listener: Listener,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), ServerError> {
+    match listener {
+        Listener::Plain(listener) => {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await?;
+        }
+        Listener::Tls(listener, rustls_config) => {
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown.await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+
+            axum_server::from_tcp_rustls(listener, rustls_config)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ServerError {
     Io(#[from] std::io::Error),
+    Tls(#[from] TlsError),
+    InvalidCorsOrigin(String),
+    NonCommercialLicense(Vec<String>),
 }
 
 impl Display for ServerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            ServerError::Tls(err) => write!(f, "{err}"),
+            ServerError::InvalidCorsOrigin(origin) => write!(f, "Invalid CORS origin {origin:?}"),
+            ServerError::NonCommercialLicense(datasets) => write!(
+                f,
+                "The API is configured for commercial use, but the licenses of these datasets forbid it: {}",
+                datasets.join(", ")
+            ),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_cors_layer() {
+        assert!(cors_layer(&[]).unwrap().is_none());
+        assert!(cors_layer(&["*".into()]).unwrap().is_some());
+        assert!(cors_layer(&["https://example.org".into()]).unwrap().is_some());
+        assert!(matches!(cors_layer(&["https://example.org\n".into()]), Err(ServerError::InvalidCorsOrigin(_))));
+        assert!(matches!(cors_layer(&["https://example.org/".into()]), Err(ServerError::InvalidCorsOrigin(_))));
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        let app = Router::new()
+            .route("/api/v1/matrix", post(|| async {}))
+            .layer(cors_layer(&["https://example.org".into()]).unwrap().unwrap());
+
+        let response = app.oneshot(
+            Request::options("/api/v1/matrix")
+                .header(header::ORIGIN, "https://example.org")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+                .body(Body::empty())
+                .unwrap()
+        ).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://example.org");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("POST"));
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap().contains("content-type"));
     }
 }
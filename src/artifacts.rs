@@ -0,0 +1,151 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use log::{debug, info, warn};
+use polars::prelude::IntoLazy;
+use data_harvester::step5_simplify as simplify;
+use routing::algorithms::initialization::{ByPreprocessing, PreprocessingInput};
+use common::types::attribution::DatasetAttribution;
+use common::types::config::DEFAULT_DATA_DIR;
+use common::util::logging;
+use server::Timetable;
+use crate::{DrinoError, ALGORITHM};
+
+pub use common::types::config::work_dir;
+
+/// Where the preprocessing writes its artifacts to with the default data dir. Commands that
+/// read artifacts use it, unless they are given another directory.
+pub fn default_work_dir() -> PathBuf {
+    work_dir(Path::new(DEFAULT_DATA_DIR))
+}
+
+/// Subdirectories of the [`work_dir`] that every preprocessing writes and that are needed to serve
+/// and query the data later on
+const REQUIRED_ARTIFACT_DIRS: [&str; 2] = [simplify::SIMPLIFY_DIR, "attribution"];
+
+/// Subdirectories of the [`work_dir`] that are only used to inspect the data. Which of them exist
+/// depends on the algorithm.
+const OPTIONAL_ARTIFACT_DIRS: [&str; 3] = ["stats", "global", "stp"];
+
+/// Where the license and attribution of each dataset is written to, relative to the [`work_dir`]
+const ATTRIBUTIONS_PATH: &str = "attribution/datasets.json";
+
+/// Copies the artifacts of the last preprocessing in `data_dir` to `out_dir`, keeping the layout
+/// of the [`work_dir`]
+pub fn export(data_dir: &Path, out_dir: &Path) -> io::Result<()> {
+    let work_dir = work_dir(data_dir);
+    if out_dir.exists() && fs::canonicalize(out_dir)? == fs::canonicalize(&work_dir)? {
+        debug!(target: "artifacts", "Artifacts are already in {out_dir:?}");
+        return Ok(());
+    }
+
+    // Checked before copying anything, so that no incomplete export is left behind
+    if let Some(dir) = REQUIRED_ARTIFACT_DIRS.iter().find(|dir| !work_dir.join(dir).exists()) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No {dir:?} artifacts in {work_dir:?}, the preprocessing didn't write them there"),
+        ));
+    }
+
+    for dir in REQUIRED_ARTIFACT_DIRS.into_iter().chain(OPTIONAL_ARTIFACT_DIRS) {
+        let from = work_dir.join(dir);
+        if from.exists() {
+            copy_dir(&from, &out_dir.join(dir))?;
+        }
+    }
+
+    info!(target: "artifacts", "Artifacts written to {out_dir:?}");
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the attributions of the datasets to the work dir in `data_dir`, so that they can be
+/// served together with the preprocessed timetable later on
+pub fn save_attributions(data_dir: &Path, attributions: &[DatasetAttribution]) -> io::Result<()> {
+    let path = work_dir(data_dir).join(ATTRIBUTIONS_PATH);
+    fs::create_dir_all(path.parent().expect("the path has got a directory"))?;
+    fs::write(path, serde_json::to_string_pretty(attributions)?)
+}
+
+/// Reads the attributions written by [`save_attributions`]. Artifacts without attributions can
+/// still be served, but responses will only name the datasets.
+fn load_attributions(data_dir: &Path) -> Result<Vec<DatasetAttribution>, DrinoError> {
+    let path = data_dir.join(ATTRIBUTIONS_PATH);
+    if !path.exists() {
+        warn!(target: "artifacts", "No attributions in {data_dir:?}, responses will lack the licenses of the datasets");
+        return Ok(vec![]);
+    }
+
+    let attributions = serde_json::from_str(&fs::read_to_string(path)?).map_err(io::Error::from)?;
+    Ok(attributions)
+}
+
+/// Builds the timetable from the simplified data in `data_dir`, skipping fetching, validation and
+/// merging of the datasets
+pub fn load(data_dir: &Path) -> Result<Timetable, DrinoError> {
+    let simplify_dir = data_dir.join(simplify::SIMPLIFY_DIR);
+    if !simplify_dir.exists() {
+        return Err(DrinoError::IO(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No preprocessed timetable in {data_dir:?}. Run `drino preprocess` first."),
+        )));
+    }
+
+    let input = logging::run_with_spinner("artifacts", "Reading timetable data", || {
+        let input = simplify::load(&simplify_dir)?;
+
+        // Cache important (and small) tables, like the preprocessing does
+        Ok::<PreprocessingInput, DrinoError>(PreprocessingInput {
+            stops: input.stops.collect()?.lazy(),
+            stop_times: input.stop_times.collect()?.lazy(),
+            ..input
+        })
+    })?;
+
+    let algorithm = ALGORITHM::preprocess(input, false)?;
+    Ok(Timetable::new(algorithm, load_attributions(data_dir)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_export_without_simplified_data() {
+        let data_dir = TempDir::new().unwrap();
+        fs::create_dir_all(work_dir(data_dir.path()).join("attribution")).unwrap();
+        let out = TempDir::new().unwrap();
+
+        let result = export(data_dir.path(), &out.path().join("artifacts"));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(!out.path().join("artifacts").exists());
+    }
+
+    #[test]
+    fn test_copy_dir() {
+        let from = TempDir::new().unwrap();
+        fs::create_dir_all(from.path().join("nested")).unwrap();
+        fs::write(from.path().join("a.parquet"), "a").unwrap();
+        fs::write(from.path().join("nested/b.csv"), "b").unwrap();
+
+        let to = TempDir::new().unwrap();
+        copy_dir(from.path(), &to.path().join("copy")).unwrap();
+
+        assert_eq!(fs::read_to_string(to.path().join("copy/a.parquet")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(to.path().join("copy/nested/b.csv")).unwrap(), "b");
+    }
+}
@@ -1,13 +1,76 @@
 use log::LevelFilter;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use chrono::{DateTime, FixedOffset};
+use common::types::station::Place;
+use routing::algorithms::queries::via::ViaStop;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use crate::artifacts::default_work_dir;
 
 #[derive(Parser, Clone)]
 #[command(version, about)]
 pub struct BootstrapConfig {
-    #[clap(short('c'), long("config"), env("DRINO_CONFIG"), default_value_os = "config.json")]
-    pub config_file: String,
+    /// Config files in YAML or JSON. If several are given (repeated or comma separated), later
+    /// files override the values of earlier ones.
+    #[clap(short('c'), long("config"), env("DRINO_CONFIG"), value_delimiter(','), default_value = "config.json")]
+    pub config_files: Vec<PathBuf>,
     #[clap(short('l'), long("log-level"), env("DRINO_LOG_LEVEL"), default_value_t, value_enum)]
     pub log_level: LogLevel,
+    /// Overrides `servers.api.listen` of the config file
+    #[clap(long("api-listen"), env("DRINO_API_LISTEN"))]
+    pub api_listen: Option<SocketAddr>,
+    /// Overrides `servers.visualization.listen` of the config file
+    #[clap(long("visualization-listen"), env("DRINO_VISUALIZATION_LISTEN"))]
+    pub visualization_listen: Option<SocketAddr>,
+    /// Don't launch the visualization server
+    #[clap(long("no-visualization"), env("DRINO_NO_VISUALIZATION"))]
+    pub no_visualization: bool,
+    /// Without a command, the datasets are preprocessed and then served until Ctrl-C is pressed
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Preprocess the datasets, write the artifacts and exit
+    Preprocess {
+        /// Directory to write the artifacts to
+        #[clap(long("out"), default_value_os_t = default_work_dir())]
+        out: PathBuf,
+    },
+    /// Serve the artifacts of an earlier preprocessing, without fetching the datasets
+    Serve {
+        /// Directory with the artifacts of `drino preprocess`
+        #[clap(long("data"), default_value_os_t = default_work_dir())]
+        data: PathBuf,
+    },
+    /// Print the earliest arrival journey between two stops as JSON
+    Query {
+        /// Directory with the artifacts of `drino preprocess`
+        #[clap(long("data"), default_value_os_t = default_work_dir())]
+        data: PathBuf,
+        /// Stop ID, or `st:<station_id>` to start at any stop of a station
+        #[clap(long("from"))]
+        from: Place,
+        /// Stop ID, or `st:<station_id>` to arrive at any stop of a station
+        #[clap(long("to"))]
+        to: Place,
+        /// Earliest departure as RFC 3339, e.g. `2024-12-24T08:00:00+01:00`
+        #[clap(long("at"))]
+        at: DateTime<FixedOffset>,
+        /// Stops or stations to visit on the way, optionally with the minimum stay in seconds
+        /// (`<stop_id>:<seconds>` or `st:<station_id>:<seconds>`)
+        #[clap(long("via"))]
+        via: Vec<ViaStop>,
+    },
+    /// Print the JSON Schema of the config file
+    Schema,
+    /// Print statistics of the datasets and the last preprocessing as JSON
+    Inspect {
+        /// Directory with the artifacts of `drino preprocess`
+        #[clap(long("data"), default_value_os_t = default_work_dir())]
+        data: PathBuf,
+    },
 }
 
 impl BootstrapConfig {
@@ -1,60 +1,240 @@
+mod interpolation;
+mod location;
+
 use std::fmt::Display;
-use common::types::config::Config;
+use common::types::config::{Config, ConfigV1};
 use log::{debug, info};
-use std::fs::File;
+use serde_json::{Map, Value};
+use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::bootstrap_config::BootstrapConfig;
+use crate::config::interpolation::interpolate;
+use crate::config::location::{locate, ConfigPath, Location, Segment};
 
+/// Reads the config files and merges them in order, so that later files override earlier ones.
+/// Mappings are merged key by key, all other values are replaced.
 pub(super) fn load_config(bootstrap_config: BootstrapConfig) -> Result<Config, ConfigError> {
-    let path: &Path = &Path::new(&bootstrap_config.config_file);
-    
-    let config_file = File::open(path)?;
-    let config_extension = path.extension();
-    
-    if let Some(extension) = config_extension {
-        let config: Config = match extension.to_str() { 
-            Some("yml") | Some("yaml") => {
-                serde_yml::from_reader(config_file)?
-            },
-            Some("json") => {
-                serde_json::from_reader(config_file)?
-            },
-            _ => {
-                return Err(ConfigError::UnknownFileExtension())
-            }
+    let layers = bootstrap_config.config_files.iter()
+        .map(|path| Layer::read(path))
+        .collect::<Result<Vec<Layer>, ConfigError>>()?;
+
+    let merged = layers.iter()
+        .fold(Value::Object(Map::new()), |merged, layer| merge(merged, layer.value.clone()));
+
+    let mut config = parse(merged, &layers)?;
+
+    apply_overrides(&mut config, &bootstrap_config);
+
+    info!(target: "main", "Config read successfully from {:?}", bootstrap_config.config_files);
+    // Interpolated values may be secrets, so only log the config as it is written in the files
+    let uninterpolated = layers.iter()
+        .fold(Value::Object(Map::new()), |merged, layer| merge(merged, layer.uninterpolated.clone()));
+    debug!(target: "main", "Using config (before interpolation): {uninterpolated}");
+
+    Ok(config)
+}
+
+/// A single config file
+struct Layer {
+    path: PathBuf,
+    text: String,
+    value: Value,
+    /// The value before environment variables were interpolated, which is safe to log
+    uninterpolated: Value,
+}
+
+impl Layer {
+    fn read(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|err| ConfigError::Read(path.to_path_buf(), err))?;
+
+        let parsed = match path.extension().map(|extension| extension.to_str()) {
+            None => return Err(ConfigError::MissingFileExtension()),
+            Some(Some("yml")) | Some(Some("yaml")) => serde_yml::from_str(&text).map_err(|err| err.to_string()),
+            Some(Some("json")) => serde_json::from_str(&text).map_err(|err| err.to_string()),
+            Some(_) => return Err(ConfigError::UnknownFileExtension()),
         };
+        // Syntax errors already contain the line and column
+        let mut value: Value = parsed.map_err(|message| ConfigError::Invalid {
+            file: path.to_path_buf(),
+            location: None,
+            path: None,
+            message,
+        })?;
+
+        let uninterpolated = value.clone();
+        let layer = Self { path: path.to_path_buf(), text, value: Value::Null, uninterpolated };
+        interpolate(&mut value, &ConfigPath::default())
+            .map_err(|err| layer.invalid(&err.path, err.kind.to_string()))?;
+
+        Ok(Self { value, ..layer })
+    }
+
+    fn invalid(&self, path: &ConfigPath, message: String) -> ConfigError {
+        ConfigError::Invalid {
+            file: self.path.clone(),
+            location: locate(&self.text, path).map(|(_, location)| location),
+            path: Some(path.clone()),
+            message,
+        }
+    }
+}
+
+fn merge(base: Value, overrides: Value) -> Value {
+    match (base, overrides) {
+        (Value::Object(mut base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                let merged = match base.remove(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Object(base)
+        }
+        (_, overrides) => overrides,
+    }
+}
+
+/// Checks the merged config against the config types. Errors point to the file that defined the
+/// invalid value last.
+fn parse(merged: Value, layers: &[Layer]) -> Result<Config, ConfigError> {
+    let invalid = |path: ConfigPath, message: String| {
+        // The later file wins if several files define the value
+        let layer = layers.iter()
+            .enumerate()
+            .max_by_key(|(i, layer)| (locate(&layer.text, &path).map(|(depth, _)| depth), *i))
+            .map(|(_, layer)| layer)
+            .expect("at least one config file is read");
+        layer.invalid(&path, message)
+    };
+
+    let Value::Object(mut fields) = merged else {
+        return Err(invalid(ConfigPath::default(), "Expected a mapping at the top level".into()));
+    };
 
-        info!(target: "main", "Config read successfully from {path:?}");
-        debug!(target: "main", "Using config: {:?}", config);
+    // Versions are written as numbers in YAML more often than not
+    match fields.remove("version") {
+        Some(Value::String(version)) if version == "1" => {}
+        Some(Value::Number(version)) if version.as_u64() == Some(1) => {}
+        Some(version) => {
+            let path = ConfigPath::default().join(Segment::Key("version".into()));
+            return Err(invalid(path, format!("Unknown version {version}, expected \"1\"")));
+        }
+        None => return Err(invalid(ConfigPath::default(), "Missing field `version`".into())),
+    }
+
+    let config: ConfigV1 = serde_path_to_error::deserialize(Value::Object(fields))
+        .map_err(|err| invalid(ConfigPath::from(err.path()), err.inner().to_string()))?;
+
+    Ok(Config::Version1(config))
+}
 
-        Ok(config)
-    } else {
-        Err(ConfigError::MissingFileExtension())
+/// Options given on the command line or in the environment take precedence over the config file
+fn apply_overrides(config: &mut Config, bootstrap_config: &BootstrapConfig) {
+    let servers = config.servers_mut();
+    if let Some(listen) = bootstrap_config.api_listen {
+        servers.api.listen = listen;
+    }
+    if let Some(listen) = bootstrap_config.visualization_listen {
+        servers.visualization.listen = listen;
+    }
+    if bootstrap_config.no_visualization {
+        servers.visualization.enabled = false;
     }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ConfigError {
-    IO(#[from] io::Error),
-    DeserializationYaml(#[from] serde_yml::Error),
-    DeserializationJson(#[from] serde_json::Error),
+    Read(PathBuf, io::Error),
+    Invalid {
+        file: PathBuf,
+        location: Option<Location>,
+        path: Option<ConfigPath>,
+        message: String,
+    },
     MissingFileExtension(),
     UnknownFileExtension(),
-    NoDatasets()
+    NoDatasets(),
+    MultipleDatasets(),
 }
 
 impl Display for ConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            ConfigError::IO(err) => write!(f, "{}", err),
-            ConfigError::DeserializationYaml(err) => write!(f, "{}", err),
-            ConfigError::DeserializationJson(err) => write!(f, "{}", err),
+            ConfigError::Read(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Invalid { file, location, path, message } => {
+                write!(f, "{}", file.display())?;
+                if let Some(location) = location {
+                    write!(f, ":{location}")?;
+                }
+                write!(f, ": {message}")?;
+                if let Some(path) = path {
+                    write!(f, " (at {path})")?;
+                }
+                Ok(())
+            }
             ConfigError::MissingFileExtension() => write!(f, "File extension not provided. Please provide .yml, .yaml or .json in the file path."),
             ConfigError::UnknownFileExtension() => write!(f, "File extension not recognized. Please provide .yml, .yaml or .json in the file path."),
             ConfigError::NoDatasets() => write!(f, "No datasets provided."),
+            ConfigError::MultipleDatasets() => write!(f, "Using multiple datasets is not yet supported. Please provide a single dataset."),
         }?;
-        
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, text: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn load(files: Vec<PathBuf>) -> Result<Config, ConfigError> {
+        let layers = files.iter().map(|path| Layer::read(path)).collect::<Result<Vec<_>, _>>()?;
+        let merged = layers.iter().fold(Value::Object(Map::new()), |merged, layer| merge(merged, layer.value.clone()));
+        parse(merged, &layers)
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = json!({ "version": "1", "servers": { "api": { "listen": "0.0.0.0:80", "cors_origins": ["a"] } } });
+        let overrides = json!({ "servers": { "api": { "cors_origins": ["b"] } } });
+        assert_eq!(
+            merge(base, overrides),
+            json!({ "version": "1", "servers": { "api": { "listen": "0.0.0.0:80", "cors_origins": ["b"] } } })
+        );
+    }
+
+    #[test]
+    fn test_layers() {
+        let dir = TempDir::new().unwrap();
+        let base = write(&dir, "base.yaml", "version: 1\ndatasets:\n  - id: a\n    format: gtfs\n    src: { path: a.zip }\n");
+        let overrides = write(&dir, "prod.json", r#"{ "servers": { "visualization": { "enabled": false } } }"#);
+
+        let config = load(vec![base, overrides]).unwrap();
+        assert!(!config.servers().visualization.enabled);
+        let Config::Version1(ConfigV1 { datasets, .. }) = config;
+        assert_eq!(datasets.len(), 1);
+    }
+
+    #[test]
+    fn test_error_location() {
+        let dir = TempDir::new().unwrap();
+        let base = write(&dir, "base.yaml", "version: 1\ndatasets:\n  - id: a\n    format: gtfs\n    src: { path: a.zip }\n");
+        let overrides = write(&dir, "prod.yaml", "servers:\n  api:\n    listen: not-an-address\n");
+
+        let Err(ConfigError::Invalid { file, location, path, .. }) = load(vec![base, overrides.clone()]) else {
+            panic!("Expected an invalid config");
+        };
+        assert_eq!(file, overrides);
+        assert_eq!(location, Some(Location { line: 3, column: 5 }));
+        assert_eq!(path.unwrap().to_string(), "servers.api.listen");
+    }
+}
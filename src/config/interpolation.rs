@@ -0,0 +1,124 @@
+use crate::config::location::{ConfigPath, Segment};
+use serde_json::Value;
+
+/// Replaces `${NAME}` in all strings of the config with the environment variable `NAME`, so that
+/// secrets don't have to be written into the file. `${NAME:-default}` falls back to `default` if
+/// the variable is not set, and `$${` is a literal `${`.
+///
+/// Only strings are interpolated, so values from the environment can't change the structure of
+/// the config.
+pub fn interpolate(value: &mut Value, path: &ConfigPath) -> Result<(), InterpolationError> {
+    interpolate_with(value, path, &|name| std::env::var(name).ok())
+}
+
+fn interpolate_with(
+    value: &mut Value,
+    path: &ConfigPath,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<(), InterpolationError> {
+    match value {
+        Value::String(text) => {
+            *text = interpolate_str(text, lookup)
+                .map_err(|kind| InterpolationError { path: path.clone(), kind })?;
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                interpolate_with(value, &path.join(Segment::Index(index)), lookup)?;
+            }
+        }
+        Value::Object(values) => {
+            for (key, value) in values.iter_mut() {
+                interpolate_with(value, &path.join(Segment::Key(key.clone())), lookup)?;
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(text: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String, InterpolationErrorKind> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(variable) = rest.strip_prefix("${") {
+            let end = variable.find('}')
+                .ok_or_else(|| InterpolationErrorKind::Unclosed(rest.to_string()))?;
+            let (name, default) = match variable[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&variable[..end], None),
+            };
+
+            let replacement = lookup(name)
+                .or_else(|| default.map(str::to_string))
+                .ok_or_else(|| InterpolationErrorKind::Unset(name.to_string()))?;
+            result.push_str(&replacement);
+            rest = &variable[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[derive(Debug)]
+pub struct InterpolationError {
+    pub path: ConfigPath,
+    pub kind: InterpolationErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InterpolationErrorKind {
+    Unset(String),
+    Unclosed(String),
+}
+
+impl std::fmt::Display for InterpolationErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpolationErrorKind::Unset(name) => {
+                write!(f, "Environment variable {name} is not set. Use ${{{name}:-default}} to fall back to a default.")
+            }
+            InterpolationErrorKind::Unclosed(text) => write!(f, "Missing closing }} in {text:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "API_KEY").then(|| "secret".to_string())
+    }
+
+    #[test]
+    fn test_interpolate_str() {
+        assert_eq!(interpolate_str("Bearer ${API_KEY}", &lookup), Ok("Bearer secret".into()));
+        assert_eq!(interpolate_str("${MISSING:-fallback}/${API_KEY}", &lookup), Ok("fallback/secret".into()));
+        assert_eq!(interpolate_str("$${API_KEY} costs $5", &lookup), Ok("${API_KEY} costs $5".into()));
+        assert_eq!(interpolate_str("${MISSING}", &lookup), Err(InterpolationErrorKind::Unset("MISSING".into())));
+        assert_eq!(interpolate_str("${API_KEY", &lookup), Err(InterpolationErrorKind::Unclosed("${API_KEY".into())));
+    }
+
+    #[test]
+    fn test_interpolate_reports_path() {
+        let mut value = json!({ "datasets": [{ "src": { "headers": { "Authorization": "${MISSING}" } } }] });
+        let err = interpolate_with(&mut value, &ConfigPath::default(), &lookup).unwrap_err();
+        assert_eq!(err.path.to_string(), "datasets[0].src.headers.Authorization");
+
+        let mut value = json!({ "retention": 3, "headers": { "key": "${API_KEY}" } });
+        interpolate_with(&mut value, &ConfigPath::default(), &lookup).unwrap();
+        assert_eq!(value, json!({ "retention": 3, "headers": { "key": "secret" } }));
+    }
+}
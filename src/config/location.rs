@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use yaml_rust2::parser::{Event, Parser};
+
+/// Position of a value in a config file, e.g. `datasets[0].src.url`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ConfigPath(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl ConfigPath {
+    pub fn join(&self, segment: Segment) -> Self {
+        let mut segments = self.0.clone();
+        segments.push(segment);
+        Self(segments)
+    }
+
+    /// The path, followed by the paths of its parents up to the root
+    fn ancestors(&self) -> impl Iterator<Item = ConfigPath> + '_ {
+        (0..=self.0.len()).rev().map(|len| Self(self.0[..len].to_vec()))
+    }
+}
+
+impl From<&serde_path_to_error::Path> for ConfigPath {
+    fn from(path: &serde_path_to_error::Path) -> Self {
+        Self(
+            path.iter()
+                .filter_map(|segment| match segment {
+                    serde_path_to_error::Segment::Seq { index } => Some(Segment::Index(*index)),
+                    serde_path_to_error::Segment::Map { key } => Some(Segment::Key(key.clone())),
+                    // Externally tagged variants are keys in the file
+                    serde_path_to_error::Segment::Enum { variant } => Some(Segment::Key(variant.clone())),
+                    serde_path_to_error::Segment::Unknown => None,
+                })
+                .collect()
+        )
+    }
+}
+
+impl Display for ConfigPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, ".");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{key}")?,
+                Segment::Key(key) => write!(f, ".{key}")?,
+                Segment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Line and column in a config file, both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Finds where `path` is defined in `text`, which is YAML or JSON. If the path doesn't exist, the
+/// closest parent that does is returned, together with the number of segments that were found.
+pub fn locate(text: &str, path: &ConfigPath) -> Option<(usize, Location)> {
+    let locations = locations(text);
+    path.ancestors()
+        .find_map(|ancestor| locations.get(&ancestor).map(|location| (ancestor.0.len(), *location)))
+}
+
+/// Where each value starts. For values in mappings, that is where their key starts.
+fn locations(text: &str) -> HashMap<ConfigPath, Location> {
+    enum Container {
+        Mapping { key: Option<String> },
+        Sequence { index: usize },
+    }
+
+    let mut locations = HashMap::new();
+    let mut containers: Vec<Container> = vec![];
+    let mut path = ConfigPath::default();
+    let mut parser = Parser::new_from_str(text);
+
+    // A file that can't be parsed has no locations beyond where parsing failed
+    while let Ok((event, marker)) = parser.next_token() {
+        let location = Location { line: marker.line(), column: marker.col() + 1 };
+
+        // Keys of mappings are scalars that don't start a value
+        if let (Event::Scalar(key, ..), Some(Container::Mapping { key: current @ None })) = (&event, containers.last_mut()) {
+            *current = Some(key.clone());
+            path = path.join(Segment::Key(key.clone()));
+            locations.insert(path.clone(), location);
+            continue;
+        }
+
+        let starts_value = matches!(
+            event,
+            Event::Scalar(..) | Event::Alias(_) | Event::MappingStart(..) | Event::SequenceStart(..)
+        );
+        if starts_value {
+            if let Some(Container::Sequence { index }) = containers.last() {
+                path = path.join(Segment::Index(*index));
+                locations.entry(path.clone()).or_insert(location);
+            }
+        }
+        if path.0.is_empty() && starts_value {
+            locations.entry(path.clone()).or_insert(location);
+        }
+
+        match event {
+            Event::MappingStart(..) => {
+                containers.push(Container::Mapping { key: None });
+                continue;
+            }
+            Event::SequenceStart(..) => {
+                containers.push(Container::Sequence { index: 0 });
+                continue;
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                containers.pop();
+            }
+            Event::StreamEnd => break,
+            Event::Scalar(..) | Event::Alias(_) => {}
+            _ => continue,
+        }
+
+        // A value has ended, so leave it and advance the container it was in
+        match containers.last_mut() {
+            Some(Container::Mapping { key }) if key.is_some() => {
+                *key = None;
+                path.0.pop();
+            }
+            Some(Container::Sequence { index }) => {
+                *index += 1;
+                path.0.pop();
+            }
+            _ => {}
+        }
+    }
+
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[Segment]) -> ConfigPath {
+        ConfigPath(segments.to_vec())
+    }
+
+    #[test]
+    fn test_locate_yaml() {
+        let text = "version: 1\ndatasets:\n  - id: a\n    src:\n      url: https://example.org\n  - id: b\n";
+        let url = path(&[Segment::Key("datasets".into()), Segment::Index(0), Segment::Key("src".into()), Segment::Key("url".into())]);
+        assert_eq!(url.to_string(), "datasets[0].src.url");
+        assert_eq!(locate(text, &url), Some((4, Location { line: 5, column: 7 })));
+
+        let second = path(&[Segment::Key("datasets".into()), Segment::Index(1), Segment::Key("format".into())]);
+        assert_eq!(locate(text, &second), Some((2, Location { line: 6, column: 7 })));
+    }
+
+    #[test]
+    fn test_locate_json() {
+        let text = "{\n  \"version\": \"1\",\n  \"datasets\": [\n    { \"id\": \"a\", \"src\": { \"path\": \"a.zip\" } }\n  ]\n}";
+        let src = path(&[Segment::Key("datasets".into()), Segment::Index(0), Segment::Key("src".into())]);
+        assert_eq!(locate(text, &src), Some((3, Location { line: 4, column: 18 })));
+    }
+}
@@ -1,26 +1,39 @@
+mod artifacts;
 pub mod bootstrap_config;
 mod config;
 mod preprocessing;
+mod reload;
 
 use crate::config::load_config;
-use bootstrap_config::BootstrapConfig;
-use common::types::config::Config;
+use actix_web::dev::ServerHandle;
+use bootstrap_config::{BootstrapConfig, Command};
+use common::types::config::{Config, ConfigV1};
 use common::util::logging;
 use common::util::speed::Speed;
+use common::util::time::InTimezone;
 use data_harvester::step1_fetch::FetchError;
 use data_harvester::step2_import::ImportError;
 use data_harvester::step3_validate::ValidateError;
 use data_harvester::step4_merge::MergeError;
 use data_harvester::step5_simplify::SimplifyError;
+use arc_swap::ArcSwap;
 use log::{debug, error, info};
 use polars::error::PolarsError;
 use preprocessing::preprocess;
 use routing::stp::ScalableTransferPatternsAlgorithm;
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::path::Path;
+use visualization::DataPaths;
 use tokio::signal;
 use routing::algorithms::initialization::PreprocessingError;
+use routing::algorithms::errors::QueryError;
+use routing::algorithms::queries::cardinality::Single;
+use routing::algorithms::queries::via::ViaInput;
+use routing::algorithms::queries::Queryable;
 use routing::raptor::RaptorAlgorithm;
 use routing::tp::TransferPatternsAlgorithm;
+use server::Timetable;
 
 type ALGORITHM = RaptorAlgorithm;
 
@@ -30,9 +43,11 @@ pub const MAX_SPEED: Speed = Speed(500.0);
 
 #[tokio::main]
 async fn main() {
-    let _ = run()
-        .await
-        .inspect_err(|err| error!(target: "main", "{}", err));
+    if let Err(err) = run().await {
+        error!(target: "main", "{}", err);
+        // Batch jobs and CI rely on the exit code to detect failures
+        std::process::exit(1);
+    }
 }
 
 async fn run() -> Result<(), DrinoError> {
@@ -43,38 +58,117 @@ async fn run() -> Result<(), DrinoError> {
 
     debug!(target: "main", "Using temporary folder at {}", std::env::temp_dir().to_str().unwrap());
 
-    let config = load_config(bootstrap_config)?;
+    match bootstrap_config.command.clone() {
+        None => preprocess_and_serve(load_config(bootstrap_config)?).await,
+        Some(Command::Preprocess { out }) => {
+            let config = load_config(bootstrap_config)?;
+            let Config::Version1(ConfigV1 { datasets, features, data_dir, .. }) = &config;
+            preprocess(datasets, &features.preprocessing, data_dir).await?;
+            artifacts::export(data_dir, &out)?;
+            Ok(())
+        }
+        Some(Command::Serve { data }) => {
+            let config = load_config(bootstrap_config)?;
+            let vis_server_handle = launch_visualization(&config, &data).await?;
+            let timetable = Arc::new(ArcSwap::from_pointee(artifacts::load(&data)?));
+            serve(config, timetable, vis_server_handle).await
+        }
+        Some(Command::Query { data, from, to, at, via }) => {
+            let timetable = artifacts::load(&data)?;
+            let algorithm = &timetable.algorithm;
+            let output = algorithm.query(ViaInput::new(at.to_utc(), from, via), Single { target: to })?;
+            let datasets = algorithm.datasets(&output.sections);
+            let output = timetable.attribute(output.in_timezone(algorithm.timezone()), &datasets);
+            let json = serde_json::to_string_pretty(&output).map_err(std::io::Error::from)?;
+            println!("{json}");
+            Ok(())
+        }
+        Some(Command::Schema) => {
+            println!("{}", serde_json::to_string_pretty(&Config::json_schema()).map_err(std::io::Error::from)?);
+            Ok(())
+        }
+        Some(Command::Inspect { data }) => {
+            let stats = visualization::api::v1::stats::collect_stats(&data)?;
+            println!("{}", serde_json::to_string_pretty(&stats).map_err(std::io::Error::from)?);
+            Ok(())
+        }
+    }
+}
+
+/// Preprocesses the datasets and serves them until Ctrl-C is pressed. The visualization server is
+/// launched first, so that it can show the progress of the preprocessing.
+async fn preprocess_and_serve(config: Config) -> Result<(), DrinoError> {
+    let vis_server_handle = launch_visualization(&config, &artifacts::work_dir(config.data_dir())).await?;
+
+    let timetable = match &config {
+        Config::Version1(ConfigV1 { datasets, features, data_dir, .. }) => {
+            let (timetable, version) = preprocess(datasets, &features.preprocessing, data_dir).await?;
+            let timetable = Arc::new(ArcSwap::from_pointee(timetable));
+
+            if features.reload.enabled {
+                reload::spawn_reloader(
+                    datasets.clone(),
+                    features.preprocessing.clone(),
+                    data_dir.clone(),
+                    timetable.clone(),
+                    version,
+                    features.reload.check_interval,
+                )?;
+            }
+
+            timetable
+        }
+    };
 
-    info!(target: "visualization", "Launching visualization server");
-    let vis_server = visualization::build_server(config.clone(), "./data".into(), true).await?;
+    serve(config, timetable, vis_server_handle).await
+}
+
+/// Launches the visualization server, which shows the artifacts in `work_dir`
+async fn launch_visualization(config: &Config, work_dir: &Path) -> Result<Option<ServerHandle>, DrinoError> {
+    if !config.servers().visualization.enabled {
+        info!(target: "visualization", "Visualization server is disabled");
+        return Ok(None);
+    }
+
+    info!(target: "visualization", "Launching visualization server on {}", config.servers().visualization.listen);
+    let vis_server = visualization::build_server(
+        config.clone(),
+        DataPaths::new(config.data_dir().to_path_buf(), work_dir.to_path_buf()),
+        true,
+    ).await?;
     let vis_server_handle = vis_server.handle();
     tokio::spawn(vis_server);
-    
-    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    Ok(Some(vis_server_handle))
+}
 
-    let (api_listener, api_app) = match &config {
-        Config::Version1 { datasets, .. } => {
-            let algorithm = preprocess(datasets).await?;
+/// Runs the API server until Ctrl-C is pressed, then shuts down both servers
+async fn serve(
+    config: Config,
+    timetable: Arc<ArcSwap<Timetable>>,
+    vis_server_handle: Option<ServerHandle>,
+) -> Result<(), DrinoError> {
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
-            server::build(algorithm, config).await?
+    let api_listen = config.servers().api.listen;
+    let (api_listener, api_app) = server::build(timetable, config).await?;
+    let api_server_handle = tokio::spawn(async move {
+        info!(target: "server", "Launching API server on {api_listen}");
+        let shutdown = async move {
+            _ = shutdown_rx.await;
+        };
+        if let Err(err) = server::serve(api_listener, api_app, shutdown).await {
+            error!(target: "server", "{err}");
         }
-    };
-    let api_server_handle = tokio::spawn(async {
-        info!(target: "server", "Launching API server");
-        axum::serve(api_listener, api_app)
-            .with_graceful_shutdown(async move { 
-                _ = shutdown_rx.await;
-            })
-            .await
-            .unwrap();
     });
 
     signal::ctrl_c().await?;
     info!(target: "main", "Received shutdown signal");
 
     logging::run_with_spinner_async("main", "Shutting down servers", async || {
-        vis_server_handle.stop(true).await;
-        debug!(target: "main", "Visualization server stopped");
+        if let Some(vis_server_handle) = vis_server_handle {
+            vis_server_handle.stop(true).await;
+            debug!(target: "main", "Visualization server stopped");
+        }
         _ = shutdown_tx.send(());
         _ = api_server_handle.await;
         debug!(target: "main", "API server stopped");
@@ -100,6 +194,7 @@ pub enum DrinoError {
     Preprocessing(#[from] PreprocessingError),
     IO(#[from] std::io::Error),
     Server(#[from] server::ServerError),
+    Query(#[from] QueryError),
 }
 
 impl Display for DrinoError {
@@ -115,6 +210,7 @@ impl Display for DrinoError {
             DrinoError::Preprocessing(err) => err,
             DrinoError::IO(err) => err,
             DrinoError::Server(err) => err,
+            DrinoError::Query(err) => err,
         };
         let prefix = match self {
             DrinoError::Config(_) => "Reading config file",
@@ -127,6 +223,7 @@ impl Display for DrinoError {
             DrinoError::Preprocessing(_) => "Preprocessing data",
             DrinoError::IO(_) => "Error during IO",
             DrinoError::Server(_) => "Error in server",
+            DrinoError::Query(_) => "Querying journey",
         };
         write!(f, "{}: {}", prefix, err)
     }
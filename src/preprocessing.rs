@@ -1,87 +1,158 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use futures::StreamExt;
-use log::{debug, info};
+use log::{debug, info, warn};
 use polars::prelude::IntoLazy;
 use tempfile::TempPath;
 use common::types::config::dataset::Dataset;
+use common::types::config::features::PreprocessingConfig;
 use common::util::logging;
-use data_harvester::step1_fetch::fetch_dataset;
-use data_harvester::step2_import::{import_data, ImportStepExtra};
+use common::util::metrics;
+use common::util::progress;
+use common::util::progress::Job;
+use data_harvester::step1_fetch::{fetch_dataset, FetchError, FetchStepOutput};
+use data_harvester::step2_import::{import_data, read_attribution, ImportStepExtra};
 use data_harvester::step3_validate::{validate_data, ValidateStepOutput};
+use data_harvester::step3_validate::repair::repair_data;
+use data_harvester::step3_validate::report::ValidationReport;
 use data_harvester::step4_merge::merge;
 use data_harvester::step5_simplify::simplify;
 use routing::algorithms::initialization::{ByPreprocessing, PreprocessingInput};
-use crate::{DrinoError, ALGORITHM};
+use server::Timetable;
+use crate::{artifacts, DrinoError, ALGORITHM};
 use crate::config::ConfigError;
+use crate::reload::DataVersion;
+
+/// Where the duration and memory usage of the preprocessing stages are written to, relative to
+/// the work dir
+const STAGE_METRICS_PATH: &str = "stats/stages.json";
+
+/// Fetches the datasets and preprocesses them, writing to `data_dir`. Also returns the version of
+/// the fetched data, so that changes can be detected later on.
+pub async fn preprocess(
+    datasets: &[Dataset],
+    config: &PreprocessingConfig,
+    data_dir: &Path,
+) -> Result<(Timetable, DataVersion), DrinoError> {
+    let fetched =
+        logging::run_with_spinner_async("preprocessing", "Fetching datasets", async || fetch(datasets, data_dir).await)
+            .await?;
+    let version = DataVersion::of(fetched.iter().map(FetchStepOutput::path));
+
+    let timetable = preprocess_fetched(fetched, config, data_dir).await?;
+
+    Ok((timetable, version))
+}
+
+/// Fetches all datasets into `data_dir`
+pub async fn fetch<'a>(datasets: &'a [Dataset], data_dir: &Path) -> Result<Vec<FetchStepOutput<'a>>, DrinoError> {
+    metrics::measure_async("fetch", progress::track_async(Job::HarvestData, fetch_inner(datasets, data_dir))).await
+}
+
+async fn fetch_inner<'a>(datasets: &'a [Dataset], data_dir: &Path) -> Result<Vec<FetchStepOutput<'a>>, DrinoError> {
+    match datasets.len() {
+        0 => {
+            Err(DrinoError::Config(ConfigError::NoDatasets()))
+        }
+        2.. => {
+            Err(DrinoError::Config(ConfigError::MultipleDatasets()))
+        },
+        1 => {
+            let fetched = futures::stream::iter(datasets)
+                .then(|dataset| fetch_dataset(dataset, data_dir))
+                .collect::<Vec<Result<FetchStepOutput, FetchError>>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<FetchStepOutput>, FetchError>>()?;
+
+            Ok(fetched)
+        }
+    }
+}
 
 /// Wrapper for `preprocess_inner` that handles cleaning up temporary files, even if error was
 /// thrown.
-pub async fn preprocess(datasets: &Vec<Dataset>) -> Result<ALGORITHM, DrinoError> {
+pub async fn preprocess_fetched(
+    fetched: Vec<FetchStepOutput<'_>>,
+    config: &PreprocessingConfig,
+    data_dir: &Path,
+) -> Result<Timetable, DrinoError> {
+    // The attributions are read before the feeds are consumed by the import
+    let attributions = fetched.iter().map(read_attribution).collect::<Result<Vec<_>, _>>()?;
+
     let mut files_to_clean_up: Vec<PathBuf> = vec![];
 
-    let result = preprocess_inner(datasets, &mut files_to_clean_up)
+    let result = metrics::measure_async("preprocessing", preprocess_inner(fetched, config, data_dir, &mut files_to_clean_up))
         .await;
 
     clean_up(files_to_clean_up);
 
-    result
+    // Stats are only shown in the dashboard, so preprocessing doesn't fail without them
+    if let Err(err) = metrics::save(&artifacts::work_dir(data_dir).join(STAGE_METRICS_PATH)) {
+        warn!(target: "preprocessing", "Unable to save stage metrics: {err}");
+    }
+
+    let algorithm = result?;
+    artifacts::save_attributions(data_dir, &attributions)?;
+    Ok(Timetable::new(algorithm, attributions))
 }
 
 async fn preprocess_inner(
-    datasets: &Vec<Dataset>,
+    fetched: Vec<FetchStepOutput<'_>>,
+    config: &PreprocessingConfig,
+    data_dir: &Path,
     files_to_clean_up: &mut Vec<PathBuf>,
 ) -> Result<ALGORITHM, DrinoError> {
+    let datasets: Vec<Dataset> = fetched.iter().map(|fetch_out| fetch_out.dataset().clone()).collect();
+    let validation = config.validation();
+    let repair = config.repair();
     info!(target: "preprocessing", "Starting preprocessing");
     let preprocessing_start_time = SystemTime::now();
 
     let preprocessing_input =
-        logging::run_with_spinner_async("preprocessing", "Fetching and importing datasets", async || {
-            if datasets.len() > 1 {
-                todo!("Using multiple datasets is not yet supported")
-            }
-            match datasets.len() {
-                0 => {
-                    Err(DrinoError::Config(ConfigError::NoDatasets()))
-                }
-                2.. => {
-                    todo!("Using multiple datasets is not yet supported")
-                },
-                1 => {
-                    let datasets = datasets.into_iter().take(1);
-
-                    let results = futures::stream::iter(datasets)
-                        .then(|dataset| async move {
-                            let fetch_out = fetch_dataset(dataset).await?;
-                            let import_out = import_data(fetch_out).await?;
-                            let validated = validate_data(import_out).await?;
-                            Ok::<ValidateStepOutput, DrinoError>(validated)
-                        })
-                        .collect::<Vec<Result<ValidateStepOutput, DrinoError>>>()
-                        .await
-                        .into_iter()
-                        .collect::<Result<Vec<ValidateStepOutput>, DrinoError>>()?;
-
-                    results.iter().for_each(|result| match &result.extra {
-                        ImportStepExtra::Gtfs {
-                            temporary_files, ..
-                        } => temporary_files
-                            .iter()
-                            .for_each(|f| files_to_clean_up.push(f.clone())),
+        logging::run_with_spinner_async("preprocessing", "Importing datasets", async || {
+            let results = futures::stream::iter(fetched)
+                .then(|fetch_out| async {
+                    let import_out = metrics::measure_async(
+                        "import",
+                        progress::track_async(Job::ImportData, import_data(fetch_out))
+                    ).await?;
+
+                    let validate = progress::track_async(Job::ValidateData, async {
+                        let validated = validate_data(import_out, validation.as_ref()).await?;
+                        let validated = repair_data(validated, repair.as_ref(), validation.as_ref()).await?;
+
+                        // The dataset can still be used without a report
+                        if let Err(err) = ValidationReport::new(&validated).save(data_dir) {
+                            warn!(target: "validation", "Unable to save validation report: {err}");
+                        }
+                        Ok::<ValidateStepOutput, DrinoError>(validated)
                     });
-
-                    let merged = merge(results).await?;
-                    let simplified = simplify(merged).await?;
-
-                    Ok::<PreprocessingInput, DrinoError>(simplified)
-                }
-            }
+                    metrics::measure_async("validate", validate).await
+                })
+                .collect::<Vec<Result<ValidateStepOutput, DrinoError>>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<ValidateStepOutput>, DrinoError>>()?;
+
+            results.iter().for_each(|result| match &result.extra {
+                ImportStepExtra::Gtfs {
+                    temporary_files, ..
+                } => temporary_files
+                    .iter()
+                    .for_each(|f| files_to_clean_up.push(f.clone())),
+            });
+
+            let merged = metrics::measure_async("merge", merge(results)).await?;
+            let simplified = metrics::measure_async("simplify", simplify(merged, &datasets, data_dir)).await?;
+
+            Ok::<PreprocessingInput, DrinoError>(simplified)
         }).await?;
 
     // TODO: Merge datasets (with deduplication) and frequency reduce calender times
 
     // Cache important (and small) tables like stops to speed up computation
-    let cached_input = logging::run_with_spinner(
+    let cached_input = metrics::measure("cache", || logging::run_with_spinner(
         "preprocessing",
         "Reading and caching timetable data",
         move || {
@@ -91,9 +162,9 @@ async fn preprocess_inner(
                 ..preprocessing_input
             })
         },
-    )?;
+    ))?;
 
-    let preprocessing_result = ALGORITHM::preprocess(cached_input, true)?;
+    let preprocessing_result = metrics::measure("algorithm", || ALGORITHM::preprocess_with_config(cached_input, config, data_dir, true))?;
 
     let elapsed = indicatif::HumanDuration(preprocessing_start_time.elapsed().unwrap());
     info!(target: "preprocessing", "Preprocessing finished in {}", elapsed);
@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use arc_swap::ArcSwap;
+use log::{debug, error, info};
+use common::types::config::dataset::Dataset;
+use common::types::config::features::PreprocessingConfig;
+use data_harvester::step1_fetch::FetchStepOutput;
+use crate::preprocessing::{fetch, preprocess_fetched};
+use server::Timetable;
+use crate::DrinoError;
+
+/// Identifies the data that an algorithm was preprocessed from: the fetched file of each dataset
+/// and when it was last modified. New imports of URL datasets have a new path, while updated
+/// files of file datasets have a new modification time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataVersion(Vec<(PathBuf, Option<SystemTime>)>);
+
+impl DataVersion {
+    pub fn of<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        Self(
+            paths.into_iter()
+                .map(|path| {
+                    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+                    (path.to_path_buf(), modified)
+                })
+                .collect()
+        )
+    }
+}
+
+/// Starts a background thread that checks the datasets for changes every `check_interval`. If
+/// they have changed, they are preprocessed again and the new timetable replaces the one in
+/// `timetable`. Queries that are in flight keep the old timetable until they are finished.
+///
+/// Preprocessing runs on its own thread, so that it doesn't slow down the API server.
+pub fn spawn_reloader(
+    datasets: Vec<Dataset>,
+    config: PreprocessingConfig,
+    data_dir: PathBuf,
+    timetable: Arc<ArcSwap<Timetable>>,
+    mut version: DataVersion,
+    check_interval: Duration,
+) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("reload".into())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Unable to start runtime for reloading");
+
+            loop {
+                thread::sleep(check_interval);
+
+                match runtime.block_on(reload_if_changed(&datasets, &config, &data_dir, &timetable, &version)) {
+                    Ok(Some(new_version)) => {
+                        info!(target: "reload", "Timetable data reloaded");
+                        version = new_version;
+                    }
+                    Ok(None) => debug!(target: "reload", "Timetable data has not changed"),
+                    Err(err) => error!(target: "reload", "Keeping the current timetable data. {err}"),
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Runs the preprocessing again and swaps the timetable, if the fetched data differs from
+/// `version`. Returns the version of the new data in that case.
+async fn reload_if_changed(
+    datasets: &[Dataset],
+    config: &PreprocessingConfig,
+    data_dir: &Path,
+    timetable: &ArcSwap<Timetable>,
+    version: &DataVersion,
+) -> Result<Option<DataVersion>, DrinoError> {
+    let fetched = fetch(datasets, data_dir).await?;
+    let new_version = DataVersion::of(fetched.iter().map(FetchStepOutput::path));
+
+    if &new_version == version {
+        return Ok(None);
+    }
+
+    info!(target: "reload", "Timetable data has changed, preprocessing it again");
+    let new_timetable = preprocess_fetched(fetched, config, data_dir).await?;
+    timetable.store(Arc::new(new_timetable));
+
+    Ok(Some(new_version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_data_version() {
+        let dir = TempDir::new().unwrap();
+        let first = dir.path().join("1");
+        let second = dir.path().join("2");
+        fs::write(&first, "v1").unwrap();
+
+        let version = DataVersion::of([first.as_path()]);
+        assert_eq!(version, DataVersion::of([first.as_path()]));
+
+        // A file that was modified in place
+        let modified = SystemTime::now() + Duration::from_secs(60);
+        File::options().write(true).open(&first).unwrap().set_modified(modified).unwrap();
+        assert_ne!(version, DataVersion::of([first.as_path()]));
+
+        // A new import
+        fs::write(&second, "v2").unwrap();
+        assert_ne!(version, DataVersion::of([second.as_path()]));
+    }
+}
@@ -0,0 +1,69 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{get, web, Responder, Result};
+use actix_web_lab::__reexports::serde_json;
+use log::warn;
+use serde::Deserialize;
+use std::fs::{read_dir, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::DataPaths;
+
+#[derive(Deserialize)]
+pub(crate) struct ClusteringQuery {
+    /// Maximum number of reports to return, newest first
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+/// Clustering reports of the preprocessings, newest first, so that clustering strategies can be
+/// compared. Reports are written by the preprocessing to
+/// `<work_dir>/stp/clustering_reports/<timestamp>.json`. Reports that can't be read are left out.
+#[get("/api/v1/clustering")]
+pub(crate) async fn clustering(
+    data_paths: web::Data<DataPaths>,
+    query: web::Query<ClusteringQuery>,
+) -> Result<impl Responder> {
+    let reports = read_reports(&data_paths.work_dir.join("stp/clustering_reports"), query.limit)
+        .map_err(|err| ErrorInternalServerError(format!("Unable to read clustering reports: {err}")))?;
+
+    Ok(web::Json(reports))
+}
+
+fn read_reports(reports_dir: &Path, limit: usize) -> io::Result<Vec<serde_json::Value>> {
+    if !reports_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    // Report files are named by their creation time in milliseconds
+    let mut report_files: Vec<(u64, PathBuf)> = vec![];
+    for report_file in read_dir(reports_dir)? {
+        let path = report_file?.path();
+        let timestamp = path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok());
+        if let Some(timestamp) = timestamp {
+            report_files.push((timestamp, path));
+        }
+    }
+    report_files.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let reports = report_files.into_iter()
+        .filter_map(|(_, path)| match read_report(&path) {
+            Ok(report) => Some(report),
+            Err(err) => {
+                warn!(target: "visualization", "Skipping clustering report {path:?}: {err}");
+                None
+            }
+        })
+        .take(limit)
+        .collect();
+    Ok(reports)
+}
+
+fn read_report(path: &Path) -> io::Result<serde_json::Value> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
@@ -1,7 +1,15 @@
+pub mod clustering;
 pub mod config;
 pub mod stats;
 pub mod status;
+pub mod stops;
+pub mod transfer_patterns;
+pub mod validation;
 
+pub use clustering::clustering as clustering_api;
 pub use config::config as config_api;
 pub use stats::stats as stats_api;
-pub use status::status as status_api;
\ No newline at end of file
+pub use status::status as status_api;
+pub use stops::stops as stops_api;
+pub use transfer_patterns::transfer_patterns as transfer_patterns_api;
+pub use validation::validation as validation_api;
\ No newline at end of file
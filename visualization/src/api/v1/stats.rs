@@ -1,52 +1,199 @@
-use actix_web::error::ErrorInternalServerError;
+use actix_web::error::{ErrorInternalServerError, ErrorServiceUnavailable};
 use actix_web::{get, web, Responder, Result};
+use actix_web_lab::__reexports::serde_json;
 use common::util::df;
+use common::util::metrics::StageMetrics;
 use polars::error::PolarsError;
-use polars::frame::UniqueKeepStrategy;
-use polars::prelude::{col, LazyCsvReader, LazyFileListReader, LazyFrame};
+use polars::frame::{DataFrame, UniqueKeepStrategy};
+use polars::prelude::{col, len, lit, DataType, IntoLazy, LazyCsvReader, LazyFileListReader, LazyFrame};
 use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+use crate::DataPaths;
 
 #[derive(Serialize)]
-struct Stats {
+pub struct Stats {
     num_stops: u32,
-    num_clusters: u32,
     num_trips: u32,
+    /// Only available if the data was preprocessed with scalable transfer patterns
+    num_clusters: Option<u32>,
+    num_border_stations: Option<u32>,
+    num_long_distance_stations: Option<u32>,
+    datasets: Vec<DatasetStats>,
+    clusters: Vec<ClusterStats>,
+    /// Duration and memory usage of the stages of the last preprocessing
+    stages: Vec<StageMetrics>,
+}
+
+#[derive(Serialize)]
+struct DatasetStats {
+    dataset_id: String,
+    num_stops: u32,
+    num_lines: u32,
+    num_trips: u32,
+    num_services: u32,
+    num_stop_times: u32,
+}
+
+#[derive(Serialize)]
+struct ClusterStats {
+    cluster_id: u32,
+    num_stops: u32,
+    num_transfer_patterns: Option<u32>,
 }
 
 #[get("/api/v1/stats")]
-pub(crate) async fn stats() -> Result<impl Responder> {
-    fn collect_stats() -> Result<Stats, PolarsError> {
-        let clustered_stops = LazyCsvReader::new("../data/tmp/stp/stops_clustered.csv").finish()?;
-        let num_stops = df::count(clustered_stops.clone())?;
-
-        let clusters = clustered_stops
-            .select([col("cluster_id")])
-            .unique(None, UniqueKeepStrategy::Any);
-        let num_clusters = df::count(clusters)?;
-
-        let trips =
-            LazyFrame::scan_parquet("../data/tmp/simplify/trips.parquet", Default::default())?;
-        let num_trips = df::count(trips)?;
-
-        Ok(Stats {
-            num_stops,
-            num_clusters,
-            num_trips,
-        })
+pub(crate) async fn stats(data_paths: web::Data<DataPaths>) -> Result<impl Responder> {
+    let tmp_dir = &data_paths.work_dir;
+
+    // TODO: Use a better way to determine whether service is ready
+    if !tmp_dir.join("simplify/stops.parquet").exists() {
+        return Err(ErrorServiceUnavailable("Timetable data has not been preprocessed yet"));
+    }
+
+    let result = collect_stats(tmp_dir)
+        .map_err(|err| ErrorInternalServerError(format!("Unable to collect stats: {err}")))?;
+
+    Ok(web::Json(result))
+}
+
+/// Collects the stats from the artifacts that the preprocessing wrote to `tmp_dir`
+pub fn collect_stats(tmp_dir: &Path) -> Result<Stats, PolarsError> {
+    let simplify_dir = tmp_dir.join("simplify");
+    let stops = LazyFrame::scan_parquet(simplify_dir.join("stops.parquet"), Default::default())?;
+    let trips = LazyFrame::scan_parquet(simplify_dir.join("trips.parquet"), Default::default())?;
+    let services = LazyFrame::scan_parquet(simplify_dir.join("services.parquet"), Default::default())?;
+    let stop_times = LazyFrame::scan_parquet(simplify_dir.join("stop_times.parquet"), Default::default())?;
+
+    let num_stops = df::count(stops.clone())?;
+    let num_trips = df::count(trips.clone())?;
+    let datasets = dataset_stats(stops, trips, services, stop_times)?;
+
+    let stp_dir = tmp_dir.join("stp");
+    let clusters = match stp_dir.join("stops_clustered.csv") {
+        path if path.exists() => cluster_stats(&path, &stp_dir.join("cluster_stats.csv"))?,
+        _ => vec![],
+    };
+    let num_clusters = (!clusters.is_empty()).then_some(clusters.len() as u32);
+    let num_border_stations = count_if_exists(&stp_dir.join("border_stations.parquet"))?;
+    let num_long_distance_stations = count_if_exists(&stp_dir.join("long_distance_stations.parquet"))?;
+
+    // Stats of a preprocessing that was interrupted are still useful without stages
+    let stages = File::open(tmp_dir.join("stats/stages.json")).ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+        .unwrap_or_default();
+
+    Ok(Stats {
+        num_stops,
+        num_trips,
+        num_clusters,
+        num_border_stations,
+        num_long_distance_stations,
+        datasets,
+        clusters,
+        stages,
+    })
+}
+
+/// Counts the entities of each dataset. Lines are the routes that have trips.
+fn dataset_stats(
+    stops: LazyFrame,
+    trips: LazyFrame,
+    services: LazyFrame,
+    stop_times: LazyFrame,
+) -> Result<Vec<DatasetStats>, PolarsError> {
+    fn count_by_dataset(frame: LazyFrame, name: &str) -> LazyFrame {
+        frame
+            .group_by([col("dataset_id")])
+            .agg([len().alias(name)])
     }
 
-    match collect_stats() {
-        Ok(stats) => Ok(web::Json(stats)),
-        Err(err) => {
-            match err {
-                PolarsError::IO { .. } => {
-                    // TODO: Use a better way to determine whether service is ready
-                    Err(actix_web::Error::from(ErrorInternalServerError(
-                        "Unable to read stops_clustered.csv",
-                    )))
-                }
-                _ => Err(actix_web::Error::from(ErrorInternalServerError(""))),
-            }
-        }
+    let lines = trips.clone()
+        .select([col("dataset_id"), col("route_id_in_dataset")])
+        .unique(None, UniqueKeepStrategy::Any);
+
+    let counts = count_by_dataset(stops, "num_stops")
+        .left_join(count_by_dataset(lines, "num_lines"), col("dataset_id"), col("dataset_id"))
+        .left_join(count_by_dataset(trips, "num_trips"), col("dataset_id"), col("dataset_id"))
+        .left_join(count_by_dataset(services, "num_services"), col("dataset_id"), col("dataset_id"))
+        .left_join(count_by_dataset(stop_times, "num_stop_times"), col("dataset_id"), col("dataset_id"))
+        .with_columns([
+            col("dataset_id").cast(DataType::String),
+            col("num_stops").cast(DataType::UInt32),
+            col("num_lines").fill_null(lit(0)).cast(DataType::UInt32),
+            col("num_trips").fill_null(lit(0)).cast(DataType::UInt32),
+            col("num_services").fill_null(lit(0)).cast(DataType::UInt32),
+            col("num_stop_times").fill_null(lit(0)).cast(DataType::UInt32),
+        ])
+        .sort(["dataset_id"], Default::default())
+        .collect()?;
+
+    let dataset_ids = counts.column("dataset_id")?.str()?;
+    let column = |name: &str| -> Result<Vec<u32>, PolarsError> {
+        Ok(counts.column(name)?.u32()?.into_iter().map(Option::unwrap_or_default).collect())
+    };
+    let (num_stops, num_lines, num_trips) = (column("num_stops")?, column("num_lines")?, column("num_trips")?);
+    let (num_services, num_stop_times) = (column("num_services")?, column("num_stop_times")?);
+
+    Ok(
+        (0..counts.height())
+            .map(|i| DatasetStats {
+                dataset_id: dataset_ids.get(i).unwrap_or_default().to_string(),
+                num_stops: num_stops[i],
+                num_lines: num_lines[i],
+                num_trips: num_trips[i],
+                num_services: num_services[i],
+                num_stop_times: num_stop_times[i],
+            })
+            .collect()
+    )
+}
+
+/// Number of stops and transfer patterns in each cluster. Transfer patterns are only counted once
+/// all clusters are processed.
+fn cluster_stats(stops_clustered: &Path, cluster_stats: &Path) -> Result<Vec<ClusterStats>, PolarsError> {
+    let stops_per_cluster = LazyCsvReader::new(stops_clustered).finish()?
+        .group_by([col("cluster_id")])
+        .agg([len().alias("num_stops")]);
+
+    let transfer_patterns = if cluster_stats.exists() {
+        LazyCsvReader::new(cluster_stats).finish()?
+    } else {
+        DataFrame::empty_with_schema(&[
+            ("cluster_id".into(), DataType::UInt32),
+            ("num_transfer_patterns".into(), DataType::UInt32),
+        ].into_iter().collect()).lazy()
+    };
+
+    let clusters = stops_per_cluster
+        .left_join(transfer_patterns, col("cluster_id"), col("cluster_id"))
+        .select([
+            col("cluster_id").cast(DataType::UInt32),
+            col("num_stops").cast(DataType::UInt32),
+            col("num_transfer_patterns").cast(DataType::UInt32),
+        ])
+        .sort(["cluster_id"], Default::default())
+        .collect()?;
+
+    let cluster_ids = clusters.column("cluster_id")?.u32()?;
+    let num_stops = clusters.column("num_stops")?.u32()?;
+    let num_transfer_patterns = clusters.column("num_transfer_patterns")?.u32()?;
+
+    Ok(
+        (0..clusters.height())
+            .map(|i| ClusterStats {
+                cluster_id: cluster_ids.get(i).unwrap_or_default(),
+                num_stops: num_stops.get(i).unwrap_or_default(),
+                num_transfer_patterns: num_transfer_patterns.get(i),
+            })
+            .collect()
+    )
+}
+
+fn count_if_exists(path: &Path) -> Result<Option<u32>, PolarsError> {
+    if !path.exists() {
+        return Ok(None);
     }
+    let frame = LazyFrame::scan_parquet(path, Default::default())?;
+    Ok(Some(df::count(frame)?))
 }
@@ -2,62 +2,13 @@ use actix_web::{get, web, Responder};
 use actix_web_lab::__reexports::futures_util::future;
 use actix_web_lab::__reexports::serde_json;
 use actix_web_lab::sse;
-use log::debug;
-use serde::Serialize;
-use std::collections::HashMap;
+use common::util::progress;
+use common::util::progress::PipelineState;
+use log::{debug, warn};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::mpsc;
-use tokio::time::interval;
-
-#[derive(Serialize, Debug, Clone)]
-pub enum JobStatus {
-    Queued,
-    Running,
-    Succeeded,
-    Failed,
-}
-
-impl Default for JobStatus {
-    fn default() -> Self {
-        JobStatus::Queued
-    }
-}
-
-#[derive(Serialize, Debug, Hash, Clone, Eq, PartialEq)]
-pub enum Job {
-    HarvestData,
-    ImportData,
-    ValidateData,
-    PreprocessingClustering,
-    PreprocessingLocalTransferPatterns,
-    PreprocessingLongDistanceTransferPatterns,
-}
-
-#[derive(Serialize, Clone, Debug)]
-struct State(HashMap<Job, JobStatus>);
-
-impl State {
-    pub fn update(&mut self, job: Job, new_status: JobStatus) {
-        self.0.insert(job, new_status);
-    }
-}
-
-impl Default for State {
-    fn default() -> Self {
-        Self(HashMap::from([
-            (Job::HarvestData, JobStatus::Queued),
-            (Job::ImportData, JobStatus::Queued),
-            (Job::ValidateData, JobStatus::Queued),
-            (Job::PreprocessingClustering, JobStatus::Queued),
-            (Job::PreprocessingLocalTransferPatterns, JobStatus::Queued),
-            (
-                Job::PreprocessingLongDistanceTransferPatterns,
-                JobStatus::Queued,
-            ),
-        ]))
-    }
-}
+use tokio::sync::{mpsc, watch};
+use tokio::time::{interval, sleep};
 
 pub struct StatusBroadcaster {
     inner: Mutex<StatusBroadcasterInner>,
@@ -66,15 +17,20 @@ pub struct StatusBroadcaster {
 #[derive(Debug, Clone, Default)]
 pub struct StatusBroadcasterInner {
     clients: Vec<mpsc::Sender<sse::Event>>,
-    state: State,
+    state: PipelineState,
 }
 
 impl StatusBroadcaster {
     pub fn create() -> Arc<Self> {
+        let receiver = progress::subscribe();
         let broadcaster = Arc::new(StatusBroadcaster {
-            inner: Mutex::new(StatusBroadcasterInner::default()),
+            inner: Mutex::new(StatusBroadcasterInner {
+                clients: vec![],
+                state: receiver.borrow().clone(),
+            }),
         });
         StatusBroadcaster::spawn_ping(Arc::clone(&broadcaster));
+        StatusBroadcaster::spawn_forward_progress(Arc::clone(&broadcaster), receiver);
 
         broadcaster
     }
@@ -92,6 +48,21 @@ impl StatusBroadcaster {
         });
     }
 
+    /// Broadcasts the progress of the preprocessing pipeline whenever it changes.
+    fn spawn_forward_progress(this: Arc<Self>, mut receiver: watch::Receiver<PipelineState>) {
+        actix_web::rt::spawn(async move {
+            while receiver.changed().await.is_ok() {
+                this.inner.lock().unwrap().state = receiver.borrow_and_update().clone();
+                if let Err(err) = this.broadcast().await {
+                    warn!(target: "status endpoint", "Unable to broadcast status: {err}");
+                }
+
+                // Progress changes with every processed stop, clients don't need all of these updates
+                sleep(Duration::from_millis(250)).await;
+            }
+        });
+    }
+
     /// Removes all non-responsive clients from broadcast list.
     async fn remove_stale_clients(&self) {
         let clients = self.inner.lock().unwrap().clients.clone();
@@ -129,16 +100,13 @@ impl StatusBroadcaster {
         rx
     }
 
-    pub fn update_silently(&self, job: Job, new_job_status: JobStatus) {
-        self.inner.lock().unwrap().state.update(job, new_job_status);
-    }
-
     /// Broadcasts currently set status to all clients.
     pub async fn broadcast(&self) -> Result<(), serde_json::Error> {
         let inner_locked = self.inner.lock().unwrap();
         let clients = inner_locked.clients.clone();
-
         let data = sse::Data::new_json(inner_locked.state.clone())?;
+        // Don't block registering clients while sending
+        drop(inner_locked);
 
         let send_futures = clients
             .iter()
@@ -148,8 +116,6 @@ impl StatusBroadcaster {
         // disconnected clients will get swept up by `remove_stale_clients`
         let _ = future::join_all(send_futures).await;
 
-        drop(inner_locked);
-
         Ok(())
     }
 }
@@ -0,0 +1,77 @@
+use actix_web::error::{ErrorInternalServerError, ErrorServiceUnavailable};
+use actix_web::{get, web, Responder, Result};
+use polars::error::PolarsError;
+use polars::prelude::{col, lit, DataType, LazyFrame, NULL};
+use serde::Serialize;
+use std::path::Path;
+use crate::DataPaths;
+
+#[derive(Serialize)]
+struct Stop {
+    stop_id: u32,
+    lat: f32,
+    lon: f32,
+    /// ID of the stop in the dataset it was imported from
+    stop_id_in_dataset: Option<String>,
+    dataset_id: Option<String>,
+    /// Station the stop belongs to. Queries can start at all of its stops with `st:<station_id>`.
+    station_id: Option<u32>,
+}
+
+/// All stops with the IDs that the routing API uses, so that stops can be picked for queries
+#[get("/api/v1/stops")]
+pub(crate) async fn stops(data_paths: web::Data<DataPaths>) -> Result<impl Responder> {
+    let path = data_paths.work_dir.join("simplify/stops.parquet");
+    if !path.exists() {
+        return Err(ErrorServiceUnavailable("Timetable data has not been preprocessed yet"));
+    }
+
+    let result = read_stops(&path)
+        .map_err(|err| ErrorInternalServerError(format!("Unable to read stops: {err}")))?;
+
+    Ok(web::Json(result))
+}
+
+fn read_stops(path: &Path) -> Result<Vec<Stop>, PolarsError> {
+    let mut frame = LazyFrame::scan_parquet(path, Default::default())?;
+    // Stops of earlier preprocessings weren't grouped into stations
+    let station_id = if frame.collect_schema()?.contains("station_id") {
+        col("station_id").cast(DataType::UInt32)
+    } else {
+        lit(NULL).cast(DataType::UInt32).alias("station_id")
+    };
+
+    let frame = frame
+        .select([
+            col("stop_id").cast(DataType::UInt32),
+            col("lat").cast(DataType::Float32),
+            col("lon").cast(DataType::Float32),
+            col("stop_id_in_dataset").cast(DataType::String),
+            col("dataset_id").cast(DataType::String),
+            station_id,
+        ])
+        .collect()?;
+
+    let stop_ids = frame.column("stop_id")?.u32()?;
+    let lats = frame.column("lat")?.f32()?;
+    let lons = frame.column("lon")?.f32()?;
+    let ids_in_dataset = frame.column("stop_id_in_dataset")?.str()?;
+    let dataset_ids = frame.column("dataset_id")?.str()?;
+    let station_ids = frame.column("station_id")?.u32()?;
+
+    let result = (0..frame.height())
+        // Stops without an ID or location cannot be used for queries
+        .filter_map(|i| {
+            Some(Stop {
+                stop_id: stop_ids.get(i)?,
+                lat: lats.get(i)?,
+                lon: lons.get(i)?,
+                stop_id_in_dataset: ids_in_dataset.get(i).map(String::from),
+                dataset_id: dataset_ids.get(i).map(String::from),
+                station_id: station_ids.get(i),
+            })
+        })
+        .collect();
+
+    Ok(result)
+}
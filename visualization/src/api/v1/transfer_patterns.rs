@@ -0,0 +1,29 @@
+use actix_web::error::{ErrorInternalServerError, ErrorNotFound, ErrorNotImplemented};
+use actix_web::{get, web, HttpResponse, Result};
+use std::fs;
+use std::io;
+use crate::DataPaths;
+
+/// Graph of the transfer patterns that start at a stop, in the DOT language of Graphviz. The
+/// graphs are written to `<work_dir>/tp/dags/<stop_id>.dot` by debug builds of the transfer
+/// patterns preprocessing. Other algorithms don't write them, which is reported as
+/// `501 Not Implemented`, so the UI can tell it apart from a stop without transfer patterns.
+#[get("/api/v1/transfer-patterns/{stop_id}")]
+pub(crate) async fn transfer_patterns(
+    data_paths: web::Data<DataPaths>,
+    stop_id: web::Path<u32>,
+) -> Result<HttpResponse> {
+    let dags_dir = data_paths.work_dir.join("tp/dags");
+    if !dags_dir.is_dir() {
+        return Err(ErrorNotImplemented(
+            "The transfer pattern graphs are not available for the active routing algorithm. They are only written by debug builds that use transfer patterns."
+        ));
+    }
+
+    let dot = fs::read_to_string(dags_dir.join(format!("{stop_id}.dot"))).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => ErrorNotFound(format!("No transfer patterns for stop {stop_id}.")),
+        _ => ErrorInternalServerError(format!("Unable to read transfer patterns: {err}")),
+    })?;
+
+    Ok(HttpResponse::Ok().content_type("text/vnd.graphviz").body(dot))
+}
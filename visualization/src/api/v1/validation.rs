@@ -0,0 +1,90 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{get, web, Responder, Result};
+use actix_web_lab::__reexports::serde_json;
+use log::warn;
+use serde::Deserialize;
+use std::fs::{read_dir, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::DataPaths;
+
+#[derive(Deserialize)]
+pub(crate) struct ValidationQuery {
+    /// Only return reports of this dataset
+    dataset_id: Option<String>,
+    /// Maximum number of reports to return, newest first
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+/// Validation reports of the dataset imports, newest first. Reports are written by the
+/// preprocessing to `<data_dir>/datasets/<dataset_id>/validation/<timestamp>.json`. Reports that
+/// can't be read are left out.
+#[get("/api/v1/validation")]
+pub(crate) async fn validation(
+    data_paths: web::Data<DataPaths>,
+    query: web::Query<ValidationQuery>,
+) -> Result<impl Responder> {
+    let reports = read_reports(&data_paths.data_dir.join("datasets"), query.dataset_id.as_deref(), query.limit)
+        .map_err(|err| ErrorInternalServerError(format!("Unable to read validation reports: {err}")))?;
+
+    Ok(web::Json(reports))
+}
+
+fn read_reports(
+    datasets_dir: &Path,
+    dataset_id: Option<&str>,
+    limit: usize,
+) -> io::Result<Vec<serde_json::Value>> {
+    if !datasets_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    // Report files are named by their creation time in milliseconds
+    let mut report_files: Vec<(u64, PathBuf)> = vec![];
+    for dataset_dir in read_dir(datasets_dir)? {
+        let reports_dir = dataset_dir?.path().join("validation");
+        if !reports_dir.is_dir() {
+            continue;
+        }
+
+        for report_file in read_dir(reports_dir)? {
+            let path = report_file?.path();
+            let timestamp = path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            if let Some(timestamp) = timestamp {
+                report_files.push((timestamp, path));
+            }
+        }
+    }
+    report_files.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut reports = vec![];
+    for (_, path) in report_files {
+        if reports.len() >= limit {
+            break;
+        }
+
+        let report = match read_report(&path) {
+            Ok(report) => report,
+            Err(err) => {
+                warn!(target: "visualization", "Skipping validation report {path:?}: {err}");
+                continue;
+            }
+        };
+        if dataset_id.is_none_or(|dataset_id| report["dataset_id"] == dataset_id) {
+            reports.push(report);
+        }
+    }
+
+    Ok(reports)
+}
+
+fn read_report(path: &Path) -> io::Result<serde_json::Value> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
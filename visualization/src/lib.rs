@@ -6,80 +6,95 @@ use actix_files::Files;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
 use actix_web_static_files::ResourceFiles;
-use api::v1::status::{Job, JobStatus, StatusBroadcaster};
-use api::v1::{config_api, stats_api, status_api};
+use api::v1::status::StatusBroadcaster;
+use api::v1::{clustering_api, config_api, stats_api, status_api, stops_api, transfer_patterns_api, validation_api};
+use common::types::config::servers::is_valid_cors_origin;
 use common::types::config::Config;
+use common::util::tls;
 use std::sync::Arc;
-use std::time::Duration;
 use actix_web::middleware::Logger;
-use tokio::time::interval;
 
 // Import the statically built dashboard files
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
+/// Where the dashboard reads the data written during preprocessing from
+#[derive(Debug, Clone)]
+pub struct DataPaths {
+    /// The configured data dir, with the datasets and their validation reports
+    pub data_dir: PathBuf,
+    /// The artifacts of the preprocessing: `<data_dir>/tmp`, unless the artifacts are served from
+    /// another directory
+    pub work_dir: PathBuf,
+}
+
+impl DataPaths {
+    pub fn new(data_dir: PathBuf, work_dir: PathBuf) -> Self {
+        Self { data_dir, work_dir }
+    }
+}
+
 pub async fn build_server(
     config: Config,
-    data_path: PathBuf,
+    data_paths: DataPaths,
     disable_signals: bool
 ) -> std::io::Result<Server> {
+    let server_config = config.servers().visualization.clone();
+    let tls_config = server_config.tls.as_ref()
+        .map(tls::load_server_config)
+        .transpose()
+        .map_err(std::io::Error::other)?;
+    let cors_origins = server_config.cors_origins.clone();
+    // Same as for the API. Invalid origins would make actix panic in every worker.
+    if let Some(origin) = cors_origins.iter().find(|origin| !is_valid_cors_origin(origin)) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid CORS origin {origin:?}")));
+    }
+
     let mut http_server = HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:5173")
-            .allowed_origin("https://hoppscotch.io");
+        let cors = cors(&cors_origins);
 
         let frontend_files = generate();
 
+        // Forwards the progress that the preprocessing reports to the dashboard
         let status_broadcaster = StatusBroadcaster::create();
 
-        let broadcaster: Arc<StatusBroadcaster> = Arc::clone(&status_broadcaster);
-        actix_web::rt::spawn(async move {
-            let mut interval = interval(Duration::from_secs(6));
-
-            broadcaster.update_silently(Job::HarvestData, JobStatus::Running);
-            broadcaster.broadcast().await.unwrap();
-
-            interval.tick().await;
-            broadcaster.update_silently(Job::HarvestData, JobStatus::Succeeded);
-            broadcaster.update_silently(Job::ImportData, JobStatus::Running);
-            broadcaster.broadcast().await.unwrap();
-
-            interval.tick().await;
-            broadcaster.update_silently(Job::ImportData, JobStatus::Succeeded);
-            broadcaster.update_silently(Job::ValidateData, JobStatus::Running);
-            broadcaster.broadcast().await.unwrap();
-
-            interval.tick().await;
-            broadcaster.update_silently(Job::ValidateData, JobStatus::Succeeded);
-            broadcaster.update_silently(Job::PreprocessingClustering, JobStatus::Running);
-            broadcaster.broadcast().await.unwrap();
-
-            interval.tick().await;
-            interval.tick().await;
-            broadcaster.update_silently(Job::PreprocessingClustering, JobStatus::Failed);
-            broadcaster.broadcast().await.unwrap();
-        });
-
         App::new()
             .wrap(Logger::default().log_target("visualization"))
             .wrap(cors)
             // Make config available in all handlers
             .app_data(web::Data::new(config.clone()))
-            // Build a global channel to send status data
+            // Channel to send status data
             .app_data(web::Data::new(Arc::clone(&status_broadcaster)))
+            // Directories with the data written during preprocessing
+            .app_data(web::Data::new(data_paths.clone()))
             // API endpoints
             .service(stats_api)
             .service(config_api)
             .service(status_api)
+            .service(validation_api)
+            .service(clustering_api)
+            .service(stops_api)
+            .service(transfer_patterns_api)
             // Static files
-            .service(Files::new("/data-files", data_path.clone()).prefer_utf8(true))
+            .service(Files::new("/data-files/tmp", data_paths.work_dir.clone()).prefer_utf8(true))
             // Serve the frontend. This is a catchall, so it must be defined last.
             .service(ResourceFiles::new("/", frontend_files).resolve_not_found_to_root())
-    })
-        .bind("127.0.0.1:3001")?;
-    
+    });
+
+    http_server = match tls_config {
+        None => http_server.bind(server_config.listen)?,
+        Some(tls_config) => http_server.bind_rustls_0_23(server_config.listen, tls_config)?,
+    };
+
     if disable_signals {
         http_server = http_server.disable_signals();
     }
     
     Ok(http_server.run())
+}
+
+fn cors(origins: &[String]) -> Cors {
+    origins.iter().fold(Cors::default(), |cors, origin| match origin.as_str() {
+        "*" => cors.allow_any_origin(),
+        origin => cors.allowed_origin(origin),
+    })
 }
\ No newline at end of file
@@ -1,13 +1,14 @@
 extern crate drino_visualization;
 
-use common::types::config::Config;
+use common::types::config::{work_dir, Config, ConfigV1};
 use common::util::logging;
 use log::{info, LevelFilter};
+use std::path::Path;
 use std::str::FromStr;
 use common::types::config::dataset::{DataSource, Dataset, DatasetConsistency, DatasetFormat, DatasetGroup, GeoPointConsistency, IdConsistency, License};
 use url::Url;
 use common::util::distance::Distance;
-use drino_visualization::build_server;
+use drino_visualization::{build_server, DataPaths};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -15,28 +16,34 @@ async fn main() -> std::io::Result<()> {
 
     build_server(
         // TODO
-        Config::Version1 {
+        Config::Version1(ConfigV1 {
             datasets: vec![
                 Dataset {
                     id: "dataset-1".into(),
                     format: DatasetFormat::Gtfs,
                     group_ids: vec![ "group-a".into() ],
                     license: Some(License::Cc0_1_0),
-                    src: DataSource::URL { url: Url::from_str("https://asdf.com").unwrap(), headers: Default::default() }
+                    attribution: None,
+                    clip: None,
+                    src: DataSource::URL { url: Url::from_str("https://asdf.com").unwrap(), headers: Default::default(), fetch_interval: None, retention: 3 }
                 },
                 Dataset {
                     id: "dataset-2".into(),
                     format: DatasetFormat::Gtfs,
                     group_ids: vec![ "group-a".into(), "group-b".into() ],
                     license: Some(License::Cc0_1_0),
-                    src: DataSource::URL { url: Url::from_str("https://asdf.com").unwrap(), headers: Default::default() }
+                    attribution: None,
+                    clip: None,
+                    src: DataSource::URL { url: Url::from_str("https://asdf.com").unwrap(), headers: Default::default(), fetch_interval: None, retention: 3 }
                 },
                 Dataset {
                     id: "dataset-3".into(),
                     format: DatasetFormat::GtfsRt,
                     group_ids: vec![ "group-b".into() ],
                     license: Some(License::Cc0_1_0),
-                    src: DataSource::URL { url: Url::from_str("https://asdf.com").unwrap(), headers: Default::default() }
+                    attribution: None,
+                    clip: None,
+                    src: DataSource::URL { url: Url::from_str("https://asdf.com").unwrap(), headers: Default::default(), fetch_interval: None, retention: 3 }
                 },
             ],
             dataset_groups: vec![
@@ -52,8 +59,10 @@ async fn main() -> std::io::Result<()> {
                 }
             ],
             features: Default::default(),
-        },
-        "../data".into(),
+            servers: Default::default(),
+            data_dir: "../data".into(),
+        }),
+        DataPaths::new("../data".into(), work_dir(Path::new("../data"))),
         false
     ).await?
     .await?;
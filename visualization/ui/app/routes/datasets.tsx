@@ -30,10 +30,27 @@ interface DatasetGroup {
     id: string;
 }
 
+interface ValidationReport {
+    dataset_id: string;
+    created_at: string;
+    skipped: boolean;
+    counts: Record<string, { errors: number, warnings: number }>;
+    findings: {
+        rule: string;
+        severity: "error" | "warning";
+        file: string;
+        message: string;
+        count: number;
+        samples: { location: string }[];
+    }[];
+    repairs: { message: string, file: string, count: number }[];
+}
+
 export default function DatasetsPage() {
     let [configLoaded, setConfigLoaded] = useState(false);
     let [datasets, setDatasets] = useState<Dataset[]>([]);
     let [datasetGroups, setDatasetGroups] = useState<DatasetGroup[]>([]);
+    let [validationReports, setValidationReports] = useState<ValidationReport[]>([]);
 
     useEffect(() => {
         fetchData<Config>("http://localhost:3001/api/v1/config")
@@ -44,8 +61,18 @@ export default function DatasetsPage() {
                 }
             })
             .finally(() => setConfigLoaded(true));
+
+        fetchData<ValidationReport[]>("http://localhost:3001/api/v1/validation")
+            .then(data => {
+                if (data) {
+                    setValidationReports(data);
+                }
+            });
     }, []);
 
+    const total = (report: ValidationReport, severity: "errors" | "warnings") =>
+        Object.values(report.counts).reduce((sum, counts) => sum + counts[severity], 0);
+
     return (
         <div className="grow-0 grid flex-1 items-_start gap-4 px-4 sm:px-6 md:gap-8">
             <Card>
@@ -158,6 +185,58 @@ export default function DatasetsPage() {
                     </div>
                 </CardFooter>
             </Card>
+            <Card>
+                <CardHeader>
+                    <CardTitle>Validation</CardTitle>
+                    <CardDescription>Feed quality of the latest imports</CardDescription>
+                </CardHeader>
+                <CardContent>
+                    <Table>
+                        <TableHeader>
+                            <TableRow>
+                                <TableHead>Dataset</TableHead>
+                                <TableHead>Imported</TableHead>
+                                <TableHead>Errors</TableHead>
+                                <TableHead>Warnings</TableHead>
+                                <TableHead>Repairs</TableHead>
+                                <TableHead>Findings</TableHead>
+                            </TableRow>
+                        </TableHeader>
+                        <TableBody>
+                            {validationReports.map(report => (
+                                <TableRow key={report.dataset_id + report.created_at}>
+                                    <TableCell>
+                                        {report.dataset_id}
+                                        {report.skipped && <Badge variant="destructive" className="ml-2">skipped</Badge>}
+                                    </TableCell>
+                                    <TableCell>{new Date(report.created_at).toLocaleString()}</TableCell>
+                                    <TableCell>{total(report, "errors")}</TableCell>
+                                    <TableCell>{total(report, "warnings")}</TableCell>
+                                    <TableCell>{report.repairs.reduce((sum, repair) => sum + repair.count, 0)}</TableCell>
+                                    <TableCell>
+                                        <div className="flex flex-col gap-1">
+                                            {report.findings.map(finding => (
+                                                <div key={finding.file + finding.message} className="text-xs">
+                                                    <Badge variant={finding.severity == "error" ? "destructive" : "outline"}>
+                                                        {finding.rule}
+                                                    </Badge>
+                                                    {" "}{finding.message} ({finding.count}×, e.g.{" "}
+                                                    <code>{finding.samples.map(sample => sample.location).join(", ")}</code>)
+                                                </div>
+                                            ))}
+                                        </div>
+                                    </TableCell>
+                                </TableRow>
+                            ))}
+                        </TableBody>
+                    </Table>
+                </CardContent>
+                <CardFooter>
+                    <div className="text-xs text-muted-foreground">
+                        <b>{validationReports.length} reports</b> of past imports
+                    </div>
+                </CardFooter>
+            </Card>
         </div>
     );
 }
@@ -7,6 +7,8 @@ import {Button} from "~/components/ui/button";
 import {useEffect, useState} from "react";
 import {LoadingSpinner} from "~/components/ui/spinner";
 import {Skeleton} from "~/components/ui/skeleton";
+import {Progress} from "~/components/ui/progress";
+import {Table, TableBody, TableCell, TableHead, TableHeader, TableRow} from "~/components/ui/table";
 
 export function meta({}: Route.MetaArgs) {
     return [
@@ -18,9 +20,25 @@ interface Job {
     title: string;
     id: string;
     status: JobStatus;
+    /** Percentage that is done, if the job reports it */
+    progress?: number | null;
+    clusters?: ClusterProgress | null;
     cards: JobCard[];
 }
 
+interface ClusterProgress {
+    done: number;
+    total: number;
+    current: number | null;
+    current_progress: number;
+}
+
+interface JobState {
+    status: keyof typeof JobStatus;
+    progress: number | null;
+    clusters: ClusterProgress | null;
+}
+
 enum JobStatus {
     Queued,
     Running,
@@ -77,7 +95,57 @@ const JOBS: Job[] = [
 
 interface Stats {
     num_stops: number,
-    num_trips: number
+    num_trips: number,
+    num_clusters: number | null,
+    num_border_stations: number | null,
+    num_long_distance_stations: number | null,
+    datasets: DatasetStats[],
+    clusters: ClusterStats[],
+    stages: StageMetrics[],
+}
+
+interface DatasetStats {
+    dataset_id: string,
+    num_stops: number,
+    num_lines: number,
+    num_trips: number,
+    num_services: number,
+    num_stop_times: number,
+}
+
+interface ClusterStats {
+    cluster_id: number,
+    num_stops: number,
+    num_transfer_patterns: number | null,
+}
+
+interface ClusteringReport {
+    created_at: string,
+    clustering: { strategy: string } & Record<string, number | string>,
+    num_clusters: number,
+    cluster_sizes: { min: number, max: number, mean: number, median: number },
+    num_border_stops: number,
+    cut_edge_weight: number,
+    total_edge_weight: number,
+    estimated_long_distance_patterns: number,
+}
+
+interface StageMetrics {
+    stage: string,
+    /** In seconds */
+    duration: number,
+    /** In bytes */
+    peak_memory: number | null,
+}
+
+function formatBytes(bytes: number): string {
+    const units = ["B", "KiB", "MiB", "GiB"];
+    let unit = 0;
+    while (bytes >= 1024 && unit < units.length - 1) {
+        bytes /= 1024;
+        unit++;
+    }
+    return `${bytes.toFixed(1)} ${units[unit]}`;
 }
 
 export default function Home() {
@@ -98,6 +166,20 @@ export default function Home() {
             .finally(() => setStatsLoading(false));
     }, []);
 
+    let [clusteringReports, setClusteringReports] = useState<ClusteringReport[]>([]);
+
+    useEffect(() => {
+        fetch("http://localhost:3001/api/v1/clustering")
+            .then(response => {
+                if (response.ok) {
+                    return response.json();
+                }
+                throw response;
+            })
+            .then(data => setClusteringReports(data))
+            .catch(() => setClusteringReports([]));
+    }, []);
+
     useEffect(() => {
         const sse = new EventSource('http://localhost:3001/api/v1/status');
 
@@ -108,18 +190,20 @@ export default function Home() {
         };
 
         sse.onmessage = (msg) => {
-            const data = JSON.parse(msg.data);
-
-            for (const [jobId, newStatus] of Object.entries(data)) {
-                const newJobs = jobs.map((j) => {
-                    if (j.id === jobId) {
-                        j.status = JobStatus[newStatus as keyof typeof JobStatus];
-                    }
-                    return j;
-                });
-
-                setJobs(newJobs);
-            }
+            const data: Record<string, JobState> = JSON.parse(msg.data);
+
+            setJobs((jobs) => jobs.map((job) => {
+                const state = data[job.id];
+                if (!state) {
+                    return job;
+                }
+                return {
+                    ...job,
+                    status: JobStatus[state.status],
+                    progress: state.progress,
+                    clusters: state.clusters,
+                };
+            }));
         }
 
         return () => {
@@ -138,7 +222,7 @@ export default function Home() {
                 <CardStats
                     title="Lines" subtitle="Lines accross datasets"
                     valueLoading={statsLoading}
-                    value="42,4242"
+                    value={stats?.datasets?.reduce((sum, dataset) => sum + dataset.num_lines, 0)?.toString()}
                     icon={<Waypoints/>}/>
                 <CardStats
                     title="Trips" subtitle="Trips places accross datasets"
@@ -146,8 +230,117 @@ export default function Home() {
                     value={stats?.num_trips?.toString()}
                     icon={<Navigation/>}/>
             </div>
+            {stats && (
+                <div className="grid gap-4 md:grid-cols-2 md:gap-8">
+                    <Card>
+                        <CardHeader>
+                            <CardTitle>Datasets</CardTitle>
+                            <CardDescription>
+                                {stats.num_clusters != null
+                                    ? `${stats.num_clusters} clusters, ${stats.num_border_stations ?? "-"} border stations, ${stats.num_long_distance_stations ?? "-"} long-distance stations`
+                                    : "Timetable data after simplification"}
+                            </CardDescription>
+                        </CardHeader>
+                        <Table>
+                            <TableHeader>
+                                <TableRow>
+                                    <TableHead>ID</TableHead>
+                                    <TableHead>Stops</TableHead>
+                                    <TableHead>Lines</TableHead>
+                                    <TableHead>Trips</TableHead>
+                                    <TableHead>Services</TableHead>
+                                    <TableHead>Stop times</TableHead>
+                                </TableRow>
+                            </TableHeader>
+                            <TableBody>
+                                {stats.datasets.map(dataset => (
+                                    <TableRow key={dataset.dataset_id}>
+                                        <TableCell>{dataset.dataset_id}</TableCell>
+                                        <TableCell>{dataset.num_stops}</TableCell>
+                                        <TableCell>{dataset.num_lines}</TableCell>
+                                        <TableCell>{dataset.num_trips}</TableCell>
+                                        <TableCell>{dataset.num_services}</TableCell>
+                                        <TableCell>{dataset.num_stop_times}</TableCell>
+                                    </TableRow>
+                                ))}
+                            </TableBody>
+                        </Table>
+                    </Card>
+                    <Card>
+                        <CardHeader>
+                            <CardTitle>Preprocessing</CardTitle>
+                            <CardDescription>Duration and peak memory usage of the last run</CardDescription>
+                        </CardHeader>
+                        <Table>
+                            <TableHeader>
+                                <TableRow>
+                                    <TableHead>Stage</TableHead>
+                                    <TableHead>Duration</TableHead>
+                                    <TableHead>Peak memory</TableHead>
+                                </TableRow>
+                            </TableHeader>
+                            <TableBody>
+                                {stats.stages.map(stage => (
+                                    <TableRow key={stage.stage}>
+                                        <TableCell>{stage.stage}</TableCell>
+                                        <TableCell>{stage.duration.toFixed(2)} s</TableCell>
+                                        <TableCell>{stage.peak_memory != null ? formatBytes(stage.peak_memory) : "-"}</TableCell>
+                                    </TableRow>
+                                ))}
+                            </TableBody>
+                        </Table>
+                    </Card>
+                </div>
+            )}
+            {clusteringReports.length > 0 && (
+                <Card>
+                    <CardHeader>
+                        <CardTitle>Clustering</CardTitle>
+                        <CardDescription>Quality of the clusterings of recent runs, to compare strategies. Lower cut-edge weight and fewer long-distance patterns make queries faster.</CardDescription>
+                    </CardHeader>
+                    <Table>
+                        <TableHeader>
+                            <TableRow>
+                                <TableHead>Run</TableHead>
+                                <TableHead>Strategy</TableHead>
+                                <TableHead>Clusters</TableHead>
+                                <TableHead>Stops per cluster (min / median / max)</TableHead>
+                                <TableHead>Border stops</TableHead>
+                                <TableHead>Cut-edge weight</TableHead>
+                                <TableHead>Est. long-distance patterns</TableHead>
+                            </TableRow>
+                        </TableHeader>
+                        <TableBody>
+                            {clusteringReports.map(report => {
+                                const {strategy, ...parameters} = report.clustering;
+                                return (
+                                    <TableRow key={report.created_at}>
+                                        <TableCell>{new Date(report.created_at).toLocaleString()}</TableCell>
+                                        <TableCell>
+                                            {strategy}
+                                            <span className="text-muted-foreground">
+                                                {Object.entries(parameters).map(([key, value]) => ` ${key}=${value}`).join(",")}
+                                            </span>
+                                        </TableCell>
+                                        <TableCell>{report.num_clusters}</TableCell>
+                                        <TableCell>{report.cluster_sizes.min} / {report.cluster_sizes.median} / {report.cluster_sizes.max}</TableCell>
+                                        <TableCell>{report.num_border_stops}</TableCell>
+                                        <TableCell>
+                                            {report.cut_edge_weight}
+                                            {report.total_edge_weight > 0 && (
+                                                <span className="text-muted-foreground"> ({(100 * report.cut_edge_weight / report.total_edge_weight).toFixed(1)}%)</span>
+                                            )}
+                                        </TableCell>
+                                        <TableCell>{report.estimated_long_distance_patterns}</TableCell>
+                                    </TableRow>
+                                );
+                            })}
+                        </TableBody>
+                    </Table>
+                </Card>
+            )}
             <div className="flex flex-col gap-4 md:gap-8">
-                {JOBS.map((job) => (
+                {jobs.map((job) => (
                     <div key={job.id}>
                         <h2 className="text-lg font-semibold leading-none tracking-tight flex items-center gap-3 h-5">
                             {job.status === JobStatus.Running && <LoadingSpinner size={20}/>}
@@ -160,6 +353,17 @@ export default function Home() {
                             </div>}
                             {job.title}
                         </h2>
+                        {job.status === JobStatus.Running && job.progress != null && (
+                            <div className="flex flex-col gap-1 mt-3 mb-2">
+                                <Progress value={job.progress}/>
+                                <p className="text-sm text-muted-foreground">
+                                    {job.progress.toFixed(1)}%
+                                    {job.clusters && job.clusters.current != null && (
+                                        <> &middot; Cluster {job.clusters.current} ({job.clusters.done} of {job.clusters.total} done, {job.clusters.current_progress.toFixed(0)}% of the current one)</>
+                                    )}
+                                </p>
+                            </div>
+                        )}
                         {job.cards.map((card) => (
                             <NavLink to={card.link} key={card.link}>
                                 <Card className="flex flex-row items-center">
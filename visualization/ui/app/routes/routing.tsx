@@ -1,9 +1,10 @@
 "use client"
+import * as React from "react";
+import {useEffect, useMemo, useState} from "react";
 import {Card, CardContent, CardDescription, CardHeader, CardTitle} from "~/components/ui/card";
 import {EmptyState} from "~/components/ui/empty-state";
 import type {Route} from "../../.react-router/types/app/routes/+types/home";
-import {useState} from "react";
-import {Ellipsis} from "lucide-react";
+import {Download, Ellipsis, ExternalLink, X} from "lucide-react";
 import {Input} from "~/components/ui/input";
 import {Button} from "~/components/ui/button";
 import {z} from "zod"
@@ -13,10 +14,15 @@ import {Form, FormControl, FormField, FormItem, FormLabel} from "~/components/ui
 import {Tabs, TabsList, TabsTrigger} from "~/components/ui/tabs";
 import {LoadingSpinner} from "~/components/ui/spinner";
 import {Skeleton} from "~/components/ui/skeleton";
-import {fetchData} from "~/lib/utils";
+import {Badge} from "~/components/ui/badge";
 import {TabsContent} from "@radix-ui/react-tabs";
-import {Checkbox} from "~/components/ui/checkbox";
-import {DatePicker} from "~/components/ui/date-picker";
+import {FullscreenControl, Map, NavigationControl, ScaleControl, useControl} from "react-map-gl/dist/es5/exports-maplibre";
+import {MapboxOverlay} from '@deck.gl/mapbox';
+import type {DeckProps} from '@deck.gl/core';
+import {type Color, type PickingInfo, type Position} from '@deck.gl/core';
+import {LineLayer, ScatterplotLayer} from '@deck.gl/layers';
+import 'maplibre-gl/dist/maplibre-gl.css';
+import {toast} from "sonner";
 
 export function meta({}: Route.MetaArgs) {
     return [
@@ -24,212 +30,463 @@ export function meta({}: Route.MetaArgs) {
     ];
 }
 
+const ROUTING_API = "http://localhost:8080/api/v1";
+const VISUALIZATION_API = "http://localhost:3001/api/v1";
 
-enum QueryType {
-    EarliestArrival = "earliest-arrival",
-    LatestDeparture = "latest-departure",
-    Range = "range"
+function DeckGLOverlay(props: DeckProps) {
+    const overlay = useControl<MapboxOverlay>(() => new MapboxOverlay(props));
+    overlay.setProps(props);
+    return null;
 }
 
-const EarliestArrivalQuerySchema = z.object({
-    origin: z.string(),
-    destination: z.string(),
-    datetime: z.coerce.date(), // Ensures proper date parsing
-});
+enum QueryType {
+    Range = "range",
+    Via = "via",
+}
 
-const RangeQuerySchema = z.object({
-    start: z.number(),
-    target: z.number(),
-    earliest_departure: z.coerce.date(), // Ensures proper date parsing
-    range: z.number(),
+const QuerySchema = z.object({
+    departure: z.string().min(1),
+    /** In minutes */
+    range: z.coerce.number().positive(),
+    /** Comma separated stop IDs, optionally with a dwell time, e.g. `42:1800,7` */
+    via: z.string(),
 });
 
-const AnyQuerySchema = z.union([RangeQuerySchema, EarliestArrivalQuerySchema]);
-
+type Stop = {
+    stop_id: number,
+    lat: number,
+    lon: number,
+    stop_id_in_dataset: string | null,
+    dataset_id: string | null,
+};
 
-type Result = {
-    journeys: Journey[];
+type Leg =
+    | {
+    ride: {
+        trip: unknown,
+        boarding_stop: number,
+        alight_stop: number,
+        boarding_time: string,
+        alight_time: string,
+    }
 }
+    | {
+    transfer: {
+        start: number,
+        end: number,
+        /** In seconds */
+        duration: number,
+    }
+};
 
 type Journey = {
     legs: Leg[];
 }
 
-interface Leg {
-    leg: LegType;
+type RangeOutput = {
+    journeys: Journey[];
 }
 
-enum LegType {
-    RIDE = "ride",
-    TRANSFER = "transfer",
+type ViaOutput = {
+    sections: Journey[];
 }
 
-interface State {
-}
+type DrawnLeg = {
+    start: Position,
+    end: Position,
+    ride: boolean,
+};
 
-class EnterQueryState implements State {
+type Result =
+    | { state: "empty" }
+    | { state: "loading" }
+    | { state: "done", journeys: Journey[], title: (index: number) => string };
+
+const RIDE_COLOR: Color = [37, 99, 235];
+const TRANSFER_COLOR: Color = [120, 120, 120];
+const SELECTED_COLOR: Color = [220, 38, 38];
+
+function legStops(leg: Leg): [number, number] {
+    if ("ride" in leg) {
+        return [leg.ride.boarding_stop, leg.ride.alight_stop];
+    }
+    return [leg.transfer.start, leg.transfer.end];
 }
 
-class LoadingState implements State {
+function formatTime(time: string): string {
+    return new Date(time).toLocaleString();
 }
 
-class ResultState implements State {
-    result: Result;
+function toRfc3339(localDateTime: string): string {
+    return new Date(localDateTime).toISOString();
+}
 
-    constructor(result: Result) {
-        this.result = result;
+async function fetchJson<T>(url: string): Promise<T | undefined> {
+    try {
+        const res = await fetch(url);
+        if (!res.ok) {
+            toast.error("Query failed", {description: await res.text()});
+            return undefined;
+        }
+        return await res.json();
+    } catch (err) {
+        toast.error("Error while sending request", {description: (err as Error).message});
+        return undefined;
     }
 }
 
 export default function RoutingPage() {
+    const [stops, setStops] = useState<Stop[]>([]);
+    const [origin, setOrigin] = useState<Stop | null>(null);
+    const [destination, setDestination] = useState<Stop | null>(null);
+    const [queryType, setQueryType] = useState<QueryType>(QueryType.Range);
+    const [result, setResult] = useState<Result>({state: "empty"});
+    const [selectedJourney, setSelectedJourney] = useState<number | null>(null);
+    const [dot, setDot] = useState<string | null>(null);
+    // Reason why the transfer pattern graphs can't be shown for the active algorithm
+    const [dotUnavailable, setDotUnavailable] = useState<string | null>(null);
 
-    const form = useForm<z.infer<typeof AnyQuerySchema>>({
-        resolver: zodResolver(AnyQuerySchema),
+    const form = useForm<z.infer<typeof QuerySchema>>({
+        resolver: zodResolver(QuerySchema),
         defaultValues: {
-            origin: "",
-            destination: "",
-            datetime: new Date(),
+            departure: new Date().toISOString().slice(0, 16),
+            range: 60,
+            via: "",
         }
     });
 
-    const onSubmit = async (query: z.infer<typeof AnyQuerySchema>) => {
-        setState(new LoadingState());
+    useEffect(() => {
+        fetchJson<Stop[]>(`${VISUALIZATION_API}/stops`).then(stops => setStops(stops ?? []));
+    }, []);
 
-        const res = await fetchData<Result>("http://localhost:8080/api/v1/routing?" + new URLSearchParams({
-            start: query.origin.toString(),
-            target_type: "all",
-            earliest_departure: new Date().getUTCSeconds().toString(),
-            range: String(70_000),
-        }));
+    const stopsById = useMemo(() => {
+        const byId: Record<number, Stop> = {};
+        stops.forEach(stop => byId[stop.stop_id] = stop);
+        return byId;
+    }, [stops]);
 
-        if (res) {
-            console.log(res);
-            setState(new ResultState(res));
+    const onStopClick = (stop: Stop) => {
+        if (origin === null) {
+            setOrigin(stop);
         } else {
-            setState(new EnterQueryState());
+            setDestination(stop);
         }
-    }
+    };
+
+    const onSubmit = async (query: z.infer<typeof QuerySchema>) => {
+        if (origin === null) {
+            toast.error("Pick a start stop on the map first");
+            return;
+        }
+        setResult({state: "loading"});
+        setSelectedJourney(null);
 
-    const [state, setState] = useState<State>(new EnterQueryState());
-    const [queryType, setQueryType] = useState<QueryType>(QueryType.EarliestArrival);
+        const earliestDeparture = toRfc3339(query.departure);
+        if (queryType === QueryType.Range) {
+            const params = new URLSearchParams({
+                start: origin.stop_id.toString(),
+                earliest_departure: earliestDeparture,
+                range: String(query.range * 60),
+                ...(destination !== null
+                    ? {target_type: "single", target: destination.stop_id.toString()}
+                    : {target_type: "all"}),
+            });
+            const output = await fetchJson<RangeOutput>(`${ROUTING_API}/routing?${params}`);
+            setResult(output
+                ? {state: "done", journeys: output.journeys, title: (i) => `Journey ${i + 1}`}
+                : {state: "empty"});
+        } else {
+            if (destination === null) {
+                toast.error("Pick a destination stop on the map for via queries");
+                setResult({state: "empty"});
+                return;
+            }
+            const params = new URLSearchParams({
+                start: origin.stop_id.toString(),
+                earliest_departure: earliestDeparture,
+                via: query.via,
+                target_type: "single",
+                target: destination.stop_id.toString(),
+            });
+            const output = await fetchJson<ViaOutput>(`${ROUTING_API}/routing/via?${params}`);
+            setResult(output
+                ? {state: "done", journeys: output.sections, title: (i) => `Section ${i + 1}`}
+                : {state: "empty"});
+        }
+    };
+
+    const showTransferPatterns = async () => {
+        if (origin === null) return;
+
+        const res = await fetch(`${VISUALIZATION_API}/transfer-patterns/${origin.stop_id}`);
+        if (res.ok) {
+            setDot(await res.text());
+        } else if (res.status === 501) {
+            setDot(null);
+            setDotUnavailable(await res.text());
+        } else {
+            setDot(null);
+            toast.error("No transfer patterns", {description: await res.text()});
+        }
+    };
+
+    const drawnLegs: DrawnLeg[] = useMemo(() => {
+        if (result.state !== "done") return [];
+
+        const journeys = selectedJourney !== null ? [result.journeys[selectedJourney]] : result.journeys;
+        return journeys.flatMap(journey => journey.legs.flatMap(leg => {
+            const [start, end] = legStops(leg).map(id => stopsById[id]);
+            if (!start || !end) return [];
+            return [{start: [start.lon, start.lat], end: [end.lon, end.lat], ride: "ride" in leg}];
+        }));
+    }, [result, selectedJourney, stopsById]);
+
+    const layers = [
+        new ScatterplotLayer<Stop>({
+            id: "stops",
+            data: stops,
+            getPosition: (s: Stop): Position => [s.lon, s.lat],
+            getFillColor: (s: Stop): Color => (
+                s.stop_id === origin?.stop_id || s.stop_id === destination?.stop_id
+                    ? SELECTED_COLOR
+                    : [0, 0, 0, 120]
+            ),
+            getRadius: 14,
+            radiusMinPixels: 2,
+            radiusMaxPixels: 8,
+            pickable: true,
+            onClick: ({object}) => onStopClick(object),
+            updateTriggers: {getFillColor: [origin, destination]},
+        }),
+        new LineLayer<DrawnLeg>({
+            id: "journey-legs",
+            data: drawnLegs,
+            getSourcePosition: (l: DrawnLeg) => l.start,
+            getTargetPosition: (l: DrawnLeg) => l.end,
+            getColor: (l: DrawnLeg) => l.ride ? RIDE_COLOR : TRANSFER_COLOR,
+            getWidth: (l: DrawnLeg) => l.ride ? 4 : 2,
+            widthUnits: "pixels",
+        }),
+    ];
+
+    const getTooltip = React.useCallback(({object}: PickingInfo<Stop>) => {
+        return object && {
+            html: `<b>Stop ID:</b> ${object.stop_id}<br/><b>In dataset:</b> ${object.stop_id_in_dataset ?? "-"} (${object.dataset_id ?? "-"})`
+        };
+    }, []);
+
+    const stopLabel = (stopId: number) => {
+        const stop = stopsById[stopId];
+        return stop?.stop_id_in_dataset ? `${stopId} (${stop.stop_id_in_dataset})` : stopId.toString();
+    };
 
     return (
         <div className="flex items-start flex-row mx-4 sm:mx-6 gap-4 mb-6">
-            <Card className="w-96 h-auto">
-                <CardHeader>
-                    <CardTitle>Routing Playground</CardTitle>
-                    <CardDescription>Run queries on the transit network</CardDescription>
-                </CardHeader>
-                <CardContent>
-                    <Form {...form}>
-                        <form
-                            onSubmit={form.handleSubmit(onSubmit)}
-                            className="space-y-4">
-                            <Tabs
-                                className="space-y-4"
-                                defaultValue={QueryType.EarliestArrival}
-                                onValueChange={(value) => setQueryType(value as QueryType)}>
-                                <TabsList>
-                                    <TabsTrigger value={QueryType.EarliestArrival}>Earliest Arrival</TabsTrigger>
-                                    <TabsTrigger value={QueryType.LatestDeparture}>Latest Departure</TabsTrigger>
-                                    <TabsTrigger value={QueryType.Range}>Range</TabsTrigger>
-                                </TabsList>
-
-                                <TabsContent value={QueryType.EarliestArrival} className="space-y-4">
-                                    <div className="space-y-2">
+            <div className="flex flex-col gap-4 w-96">
+                <Card className="w-96 h-auto">
+                    <CardHeader>
+                        <CardTitle>Journey Explorer</CardTitle>
+                        <CardDescription>Pick stops on the map and run queries on the transit network</CardDescription>
+                    </CardHeader>
+                    <CardContent>
+                        <div className="flex flex-col gap-2 mb-4">
+                            <div className="flex flex-row items-center gap-2">
+                                <span className="text-sm font-medium w-12">From</span>
+                                {origin
+                                    ? <Button size="sm" variant="secondary" className="gap-1" onClick={() => setOrigin(null)}>
+                                        {stopLabel(origin.stop_id)}<X className="h-4 w-4"/>
+                                    </Button>
+                                    : <span className="text-sm text-muted-foreground">Click a stop on the map</span>}
+                            </div>
+                            <div className="flex flex-row items-center gap-2">
+                                <span className="text-sm font-medium w-12">To</span>
+                                {destination
+                                    ? <Button size="sm" variant="secondary" className="gap-1" onClick={() => setDestination(null)}>
+                                        {stopLabel(destination.stop_id)}<X className="h-4 w-4"/>
+                                    </Button>
+                                    : <span className="text-sm text-muted-foreground">All stops</span>}
+                            </div>
+                        </div>
+                        <Form {...form}>
+                            <form
+                                onSubmit={form.handleSubmit(onSubmit)}
+                                className="space-y-4">
+                                <Tabs
+                                    className="space-y-4"
+                                    defaultValue={QueryType.Range}
+                                    onValueChange={(value) => setQueryType(value as QueryType)}>
+                                    <TabsList>
+                                        <TabsTrigger value={QueryType.Range}>Range</TabsTrigger>
+                                        <TabsTrigger value={QueryType.Via}>Via</TabsTrigger>
+                                    </TabsList>
+
+                                    <FormField
+                                        control={form.control}
+                                        name="departure"
+                                        render={({field}) => (
+                                            <FormItem>
+                                                <FormLabel>Earliest departure</FormLabel>
+                                                <FormControl>
+                                                    <Input {...field} type="datetime-local"/>
+                                                </FormControl>
+                                            </FormItem>
+                                        )}/>
+
+                                    <TabsContent value={QueryType.Range} className="space-y-4">
                                         <FormField
                                             control={form.control}
-                                            name="origin"
+                                            name="range"
                                             render={({field}) => (
                                                 <FormItem>
-                                                    <FormLabel>From</FormLabel>
+                                                    <FormLabel>Departure range (minutes)</FormLabel>
                                                     <FormControl>
-                                                        <Input {...field} placeholder="Departure Station"/>
+                                                        <Input {...field} type="number" min={1}/>
                                                     </FormControl>
                                                 </FormItem>
                                             )}/>
-                                        <div className="flex flex-row gap-2 items-center">
-                                            <FormField
-                                                control={form.control}
-                                                name="destination"
-                                                render={({field}) => (
-                                                    <FormItem>
-                                                        <FormLabel>To</FormLabel>
-                                                        <FormControl>
-                                                            <Input {...field} placeholder="Destination Station"/>
-                                                        </FormControl>
-                                                    </FormItem>
-                                                )}/>
-                                            <Checkbox id="all"/>
-                                            <label
-                                                htmlFor="all"
-                                                className="text-sm font-medium leading-none peer-disabled:cursor-not-allowed peer-disabled:opacity-70"
-                                            >
-                                                All
-                                            </label>
-                                        </div>
-                                    </div>
-                                    <FormField
-                                        control={form.control}
-                                        name="datetime"
-                                        render={({field}) => (
-                                            <FormItem className="grow">
-                                                <FormLabel>Departure</FormLabel>
-                                                <FormControl>
-                                                    <DatePicker className="w-full" />
-                                                </FormControl>
-                                            </FormItem>
-                                        )}/>
-                                </TabsContent>
-
-                            </Tabs>
-
-                            <Button type="submit" className="w-full">
-                                {!(state instanceof LoadingState) ?
-                                    <>Search</> :
-                                    <LoadingSpinner/>
-                                }
-                            </Button>
-                        </form>
-                    </Form>
-                </CardContent>
-            </Card>
-            <div className="grow">
-                {state instanceof EnterQueryState && (
+                                    </TabsContent>
+
+                                    <TabsContent value={QueryType.Via} className="space-y-4">
+                                        <FormField
+                                            control={form.control}
+                                            name="via"
+                                            render={({field}) => (
+                                                <FormItem>
+                                                    <FormLabel>Via stops</FormLabel>
+                                                    <FormControl>
+                                                        <Input {...field} placeholder="42:1800,7"/>
+                                                    </FormControl>
+                                                </FormItem>
+                                            )}/>
+                                    </TabsContent>
+                                </Tabs>
+
+                                <Button type="submit" className="w-full">
+                                    {result.state !== "loading" ? <>Search</> : <LoadingSpinner/>}
+                                </Button>
+                            </form>
+                        </Form>
+                    </CardContent>
+                </Card>
+
+                <Card className="w-96 h-auto">
+                    <CardHeader>
+                        <CardTitle>Transfer Patterns</CardTitle>
+                        <CardDescription>
+                            Graph of the transfer patterns from the start stop. Only available for debug builds
+                            that use transfer patterns.
+                        </CardDescription>
+                    </CardHeader>
+                    <CardContent className="space-y-4">
+                        {dotUnavailable !== null && (
+                            <p className="text-sm text-muted-foreground">{dotUnavailable}</p>
+                        )}
+                        <Button variant="secondary" className="w-full"
+                                disabled={origin === null || dotUnavailable !== null}
+                                onClick={showTransferPatterns}>
+                            Show graph of the start stop
+                        </Button>
+                        {dot !== null && (
+                            <>
+                                <div className="flex flex-row gap-2">
+                                    <Button asChild size="sm" variant="outline" className="gap-1">
+                                        <a href={`data:text/vnd.graphviz;charset=utf-8,${encodeURIComponent(dot)}`}
+                                           download={`transfer-patterns-${origin?.stop_id}.dot`}>
+                                            <Download className="h-4 w-4"/> DOT file
+                                        </a>
+                                    </Button>
+                                    <Button asChild size="sm" variant="outline" className="gap-1">
+                                        <a href={`https://dreampuf.github.io/GraphvizOnline/#${encodeURIComponent(dot)}`}
+                                           target="_blank" rel="noreferrer">
+                                            <ExternalLink className="h-4 w-4"/> Render
+                                        </a>
+                                    </Button>
+                                </div>
+                                <pre className="text-xs bg-muted/50 rounded p-2 max-h-64 overflow-auto">{dot}</pre>
+                            </>
+                        )}
+                    </CardContent>
+                </Card>
+            </div>
+
+            <div className="grow flex flex-col gap-4">
+                <div className="relative h-[28rem] rounded-xl overflow-hidden">
+                    <Map
+                        initialViewState={{
+                            longitude: 0,
+                            latitude: 0,
+                            zoom: 1
+                        }}
+                        mapStyle="https://basemaps.cartocdn.com/gl/positron-gl-style/style.json"
+                        style={{width: "100%", height: "100%"}}>
+
+                        <NavigationControl position="top-right"/>
+                        <FullscreenControl position="top-right"/>
+                        <ScaleControl/>
+
+                        <DeckGLOverlay
+                            layers={layers}
+                            controller
+                            getTooltip={getTooltip}/>
+                    </Map>
+                </div>
+
+                {result.state === "empty" && (
                     <EmptyState
                         icon={<Ellipsis/>}
                         title={"No query"}
                         description={"Enter a query to get started"}/>
                 )}
-                {(state instanceof LoadingState || state instanceof ResultState) && (
-                    <div className="flex flex-col gap-2 w-full max-w-screen-md mx-auto">
-                        {(state instanceof LoadingState) && (
-                            <>
-                                <Skeleton className="h-24 w-full delay-0"/>
-                                <Skeleton className="h-24 w-full delay-100"/>
-                                <Skeleton className="h-24 w-full delay-200"/>
-                            </>
-                        )}
-                        {(state instanceof ResultState) && (
-                            (state as ResultState).result.journeys.map((journey) => (
-                                <Card key={journey} className="w-full">
-                                    <CardHeader>
-                                        <CardTitle>
-                                            {(journey.legs).map((leg) => {
-                                                switch (leg.leg) {
-                                                    case LegType.RIDE:
-                                                        return leg.start;
-                                                    case LegType.TRANSFER:
-                                                        return "Transfer";
-                                                }
-                                            })}
-                                        </CardTitle>
-                                    </CardHeader>
-                                </Card>
-                            ))
-                        )}
+                {result.state === "loading" && (
+                    <div className="flex flex-col gap-2 w-full">
+                        <Skeleton className="h-24 w-full delay-0"/>
+                        <Skeleton className="h-24 w-full delay-100"/>
+                        <Skeleton className="h-24 w-full delay-200"/>
                     </div>
                 )}
+                {result.state === "done" && result.journeys.length === 0 && (
+                    <EmptyState
+                        icon={<Ellipsis/>}
+                        title={"No journeys"}
+                        description={"No journeys were found for this query"}/>
+                )}
+                {result.state === "done" && result.journeys.map((journey, i) => (
+                    <Card key={i}
+                          className={`w-full cursor-pointer ${selectedJourney === i ? "ring-2 ring-primary" : ""}`}
+                          onClick={() => setSelectedJourney(selectedJourney === i ? null : i)}>
+                        <CardHeader>
+                            <CardTitle>{result.title(i)}</CardTitle>
+                            <CardDescription>{journey.legs.length} legs</CardDescription>
+                        </CardHeader>
+                        <CardContent>
+                            <ol className="flex flex-col gap-1 text-sm">
+                                {journey.legs.map((leg, j) => (
+                                    <li key={j} className="flex flex-row items-center gap-2">
+                                        {"ride" in leg ? (
+                                            <>
+                                                <Badge>Ride</Badge>
+                                                {stopLabel(leg.ride.boarding_stop)} @ {formatTime(leg.ride.boarding_time)}
+                                                {" → "}
+                                                {stopLabel(leg.ride.alight_stop)} @ {formatTime(leg.ride.alight_time)}
+                                                <span className="text-muted-foreground">
+                                                    (trip {JSON.stringify(leg.ride.trip)})
+                                                </span>
+                                            </>
+                                        ) : (
+                                            <>
+                                                <Badge variant="secondary">Transfer</Badge>
+                                                {stopLabel(leg.transfer.start)} → {stopLabel(leg.transfer.end)}
+                                                <span className="text-muted-foreground">
+                                                    ({Math.round(leg.transfer.duration / 60)} min)
+                                                </span>
+                                            </>
+                                        )}
+                                    </li>
+                                ))}
+                            </ol>
+                        </CardContent>
+                    </Card>
+                ))}
             </div>
         </div>
     );